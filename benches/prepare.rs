@@ -49,6 +49,10 @@ fn run_bench(ctx: &mut Criterion) {
             "Arabic - Single Text Area",
             vec![include_str!("../samples/arabic.txt")],
         ),
+        (
+            "Emoji - Single Text Area",
+            vec![include_str!("../samples/emoji.txt")],
+        ),
         (
             "Latin - Many Text Areas",
             include_str!("../samples/latin.txt")
@@ -92,7 +96,35 @@ fn run_bench(ctx: &mut Criterion) {
                             bottom: 1000,
                         },
                         default_color: Color::rgb(0, 0, 0),
+                        opacity: 1.0,
                         custom_glyphs: &[],
+                        shadow: None,
+                        z_order: 0,
+                        caret: None,
+                        pattern: None,
+                        drop_shadow: None,
+                        outline: None,
+                        glow: None,
+                        background: None,
+                        gradient: None,
+                        glyph_colors: None,
+                        selection: None,
+                        decorations: None,
+                        shadow_style: None,
+                        transform: None,
+                        world_transform: None,
+                        faux_bold: None,
+                        thickness: 0.0,
+                        oblique: None,
+                        sdf: false,
+                        msdf: false,
+                        corner_radii: None,
+                        stencil_reference: 0,
+                        path: None,
+                        glyph_modifier: None,
+                        color_glyphs: None,
+                        rasterization: None,
+                        depth: 0.0,
                     })
                     .collect();
 
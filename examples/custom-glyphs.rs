@@ -242,6 +242,7 @@ impl winit::application::ApplicationHandler for Application {
                                 bottom: 180,
                             },
                             default_color: Color::rgb(255, 255, 255),
+                            opacity: 1.0,
                             custom_glyphs: &[
                                 CustomGlyph {
                                     id: 0,
@@ -251,6 +252,7 @@ impl winit::application::ApplicationHandler for Application {
                                     height: 64.0,
                                     color: Some(Color::rgb(200, 200, 255)),
                                     snap_to_physical_pixel: true,
+                                    content_type_hint: None,
                                     metadata: 0,
                                 },
                                 CustomGlyph {
@@ -261,6 +263,7 @@ impl winit::application::ApplicationHandler for Application {
                                     height: 64.0,
                                     color: None,
                                     snap_to_physical_pixel: true,
+                                    content_type_hint: None,
                                     metadata: 0,
                                 },
                                 CustomGlyph {
@@ -271,6 +274,7 @@ impl winit::application::ApplicationHandler for Application {
                                     height: 64.0,
                                     color: Some(Color::rgb(200, 255, 200)),
                                     snap_to_physical_pixel: true,
+                                    content_type_hint: None,
                                     metadata: 0,
                                 },
                                 CustomGlyph {
@@ -281,9 +285,37 @@ impl winit::application::ApplicationHandler for Application {
                                     height: 64.0,
                                     color: None,
                                     snap_to_physical_pixel: true,
+                                    content_type_hint: None,
                                     metadata: 0,
                                 },
                             ],
+                            shadow: None,
+                            z_order: 0,
+                            caret: None,
+                            pattern: None,
+                            drop_shadow: None,
+                            outline: None,
+                            glow: None,
+                            background: None,
+                            gradient: None,
+                            glyph_colors: None,
+                            selection: None,
+                            decorations: None,
+                            shadow_style: None,
+                            transform: None,
+                            world_transform: None,
+                            faux_bold: None,
+                            thickness: 0.0,
+                            oblique: None,
+                            sdf: false,
+                            msdf: false,
+                            corner_radii: None,
+                            stencil_reference: 0,
+                            path: None,
+                            glyph_modifier: None,
+                            color_glyphs: None,
+                            rasterization: None,
+                            depth: 0.0,
                         }],
                         swash_cache,
                         rasterize_svg,
@@ -244,7 +244,35 @@ impl winit::application::ApplicationHandler for Application {
                                 bottom: top.floor() as i32 + physical_size.height,
                             },
                             default_color: FONT_COLOR,
+                            opacity: 1.0,
                             custom_glyphs: &[],
+                            shadow: None,
+                            z_order: 0,
+                            caret: None,
+                            pattern: None,
+                            drop_shadow: None,
+                            outline: None,
+                            glow: None,
+                            background: None,
+                            gradient: None,
+                            glyph_colors: None,
+                            selection: None,
+                            decorations: None,
+                            shadow_style: None,
+                            transform: None,
+                            world_transform: None,
+                            faux_bold: None,
+                            thickness: 0.0,
+                            oblique: None,
+                            sdf: false,
+                            msdf: false,
+                            corner_radii: None,
+                            stencil_reference: 0,
+                            path: None,
+                            glyph_modifier: None,
+                            color_glyphs: None,
+                            rasterization: None,
+                            depth: 0.0,
                         };
 
                         let total_lines = b
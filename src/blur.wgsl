@@ -0,0 +1,56 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+struct BlurParams {
+    direction: vec2<f32>,
+    radius: f32,
+    _pad: u32,
+};
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> blur: BlurParams;
+
+// Covers the target with a single triangle
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_idx: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_idx << 1u) & 2u), f32(vertex_idx & 2u));
+
+    var vert_output: VertexOutput;
+    vert_output.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    vert_output.uv = uv;
+    return vert_output;
+}
+
+// Blurs the premultiplied source along `blur.direction` with a Gaussian kernel whose radius
+// spans three standard deviations
+@fragment
+fn fs_blur(in_frag: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel_step = blur.direction / vec2<f32>(textureDimensions(source_texture));
+    let sigma = max(blur.radius / 3.0, 1e-3);
+    let taps = i32(ceil(blur.radius));
+
+    var color = vec4<f32>(0.0);
+    var total_weight = 0.0;
+    for (var i: i32 = -taps; i <= taps; i = i + 1) {
+        let offset = f32(i);
+        let weight = exp(-(offset * offset) / (2.0 * sigma * sigma));
+        color += weight * textureSampleLevel(source_texture, source_sampler, in_frag.uv + offset * pixel_step, 0.0);
+        total_weight += weight;
+    }
+
+    return color / total_weight;
+}
+
+// Copies the premultiplied source onto the target
+@fragment
+fn fs_composite(in_frag: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(source_texture, source_sampler, in_frag.uv, 0.0);
+}
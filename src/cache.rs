@@ -1,21 +1,133 @@
-use crate::{GlyphToRender, Params};
+use crate::{
+    ClipParams, GlyphToRender, GradientParams, PackedGlyph, Params, RendererParams, TextTransform,
+    TransferParams, TransformParams, MAX_CLIPS, MAX_GRADIENTS, MAX_TRANSFORMS,
+};
 use std::{
     borrow::Cow,
     mem,
-    num::NonZeroU64,
-    ops::Deref,
-    sync::{Arc, Mutex},
+    num::{NonZeroU32, NonZeroU64},
+    ops::{Deref, Range},
+    sync::{Arc, Mutex, OnceLock},
 };
 use wgpu::{
-    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry,
-    BindingResource, BindingType, BlendState, Buffer, BufferBindingType, ColorTargetState,
-    ColorWrites, DepthStencilState, Device, FilterMode, FragmentState, MultisampleState,
-    PipelineCompilationOptions, PipelineLayout, PipelineLayoutDescriptor, PrimitiveState,
-    PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
-    SamplerDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages,
-    TextureFormat, TextureSampleType, TextureView, TextureViewDimension, VertexFormat, VertexState,
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendFactor, BlendState, Buffer,
+    BufferBindingType, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites,
+    CommandEncoderDescriptor, DepthStencilState, Device, Extent3d, Features, FilterMode,
+    FragmentState, LoadOp, MultisampleState, Operations, PipelineCache, PipelineCompilationOptions,
+    PipelineLayout, PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, PushConstantRange,
+    Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, StoreOp, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDescriptor, TextureViewDimension, VertexFormat, VertexState,
 };
 
+/// The declaration of the viewport parameters in the text shader, which is replaced by
+/// [`PUSH_CONSTANT_PARAMS`] on backends with push constants.
+const UNIFORM_PARAMS: &str = "@group(1) @binding(0)\nvar<uniform> params: Params;";
+const PUSH_CONSTANT_PARAMS: &str = "var<push_constant> params: Params;";
+
+/// The fragment hook of the embedded text shader, which is replaced by the snippet of a
+/// [`TextShader::FragmentHook`].
+const DEFAULT_FRAGMENT_HOOK: &str = concat!(
+    "fn glyphon_fragment(color: vec4<f32>, in_frag: VertexOutput) -> vec4<f32> {\n",
+    "    return color;\n",
+    "}",
+);
+
+/// The vertex inputs and view index of the embedded text shader, which are replaced in multiview
+/// pipelines to read the index of the view being rendered.
+const VERTEX_INDEX_INPUT: &str = "@builtin(vertex_index) vertex_idx: u32,";
+const MULTIVIEW_INPUT: &str =
+    "@builtin(vertex_index) vertex_idx: u32,\n    @builtin(view_index) view_index: u32,";
+const DEFAULT_VIEW_INDEX: &str = "fn view_index(in_vert: VertexInput) -> u32 {\n    return 0u;\n}";
+const MULTIVIEW_VIEW_INDEX: &str =
+    "fn view_index(in_vert: VertexInput) -> u32 {\n    return in_vert.view_index;\n}";
+
+/// Returns the source of a variant of the text shader, with a fragment entry point for
+/// dual-source blending if `subpixel` is set and reading the index of the view being rendered if
+/// `multiview` is set.
+fn variant_source(source: &str, subpixel: bool, multiview: bool) -> String {
+    let mut source = source.to_owned();
+    if multiview {
+        source = source
+            .replace(VERTEX_INDEX_INPUT, MULTIVIEW_INPUT)
+            .replace(DEFAULT_VIEW_INDEX, MULTIVIEW_VIEW_INDEX);
+    }
+    if subpixel {
+        source = format!(
+            "enable dual_source_blending;\n{source}{}",
+            include_str!("subpixel.wgsl")
+        );
+    }
+    source
+}
+
+/// Returns the source of the text shader, reading the viewport parameters from push constants if
+/// `push_constants` is set. The pattern and instances bind groups then move up by one group, into
+/// the place of the uniforms bind group.
+fn text_shader_source(source: Cow<'_, str>, push_constants: bool) -> Cow<'_, str> {
+    if push_constants {
+        Cow::Owned(
+            source
+                .replace(UNIFORM_PARAMS, PUSH_CONSTANT_PARAMS)
+                .replace("@group(2)", "@group(1)")
+                .replace("@group(3)", "@group(2)"),
+        )
+    } else {
+        source
+    }
+}
+
+/// The fragment shader of the text pipelines of a [`Cache`], see [`Cache::with_shader`].
+#[derive(Clone, Copy, Debug, Default)]
+pub enum TextShader<'a> {
+    /// The shader embedded in glyphon.
+    #[default]
+    Embedded,
+    /// The embedded shader with a WGSL snippet that defines the function
+    /// `fn glyphon_fragment(color: vec4<f32>, in_frag: VertexOutput) -> vec4<f32>`, which is
+    /// called with the color of each fragment and returns the color to draw instead, for effects
+    /// like scanlines, palette remapping or dissolve transitions.
+    ///
+    /// The color holds the coverage of the glyph in its alpha channel, before the opacity of the
+    /// text area is applied. For glyphs drawn from subpixel masks, it is passed without coverage.
+    /// `in_frag` holds the instance data, such as `flags`, `uv` and `local_position`, and the
+    /// snippet may use all functions and bindings of the embedded shader.
+    FragmentHook(&'a str),
+    /// A replacement for the embedded shader, which must keep its bindings, vertex inputs and
+    /// entry points. With dual-source blending, the contents of `subpixel.wgsl` are appended to
+    /// it. Without the `vs_packed` entry point, instances are always drawn in the full layout.
+    Replacement(&'a str),
+}
+
+/// The layout of the instances read by a text pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum InstanceLayout {
+    /// [`GlyphToRender`] instances in a vertex buffer, read by the `vs_main` entry point.
+    Full,
+    /// [`PackedGlyph`] instances in a vertex buffer, read by the `vs_packed` entry point.
+    Packed,
+    /// [`GlyphToRender`] instances in a storage buffer, which the `vs_pulled` entry point indexes
+    /// by the vertex index to draw all of them with a single triangle list.
+    Pulled,
+    /// Pulled instances drawn once for each [`TextTransform`] in a vertex buffer, read by the
+    /// `vs_placed` entry point.
+    ///
+    /// [`TextTransform`]: crate::TextTransform
+    Placed,
+}
+
+/// The texture views bound to the atlas bind group.
+pub(crate) struct AtlasViews<'a> {
+    pub color: &'a TextureView,
+    pub mask: &'a TextureView,
+    pub sdf: &'a TextureView,
+    pub prebaked_color: &'a TextureView,
+    pub prebaked_mask: &'a TextureView,
+}
+
 /// A cache to share common resources (e.g., pipelines, layouts, shaders) between multiple text
 /// renderers.
 #[derive(Debug, Clone)]
@@ -24,24 +136,123 @@ pub struct Cache(Arc<Inner>);
 #[derive(Debug)]
 struct Inner {
     sampler: Sampler,
+    mipmap_sampler: Sampler,
     shader: ShaderModule,
+    /// The source of `shader`, from which `variants` are created.
+    shader_source: String,
+    /// Whether the viewport parameters are passed through push constants instead of the uniforms
+    /// bind group.
+    push_constants: bool,
+    /// The shaders with a fragment entry point for dual-source blending, with the index of the
+    /// rendered view for multiview pipelines, or with both, created when first requested.
+    variants: [OnceLock<ShaderModule>; 3],
     vertex_buffers: [wgpu::VertexBufferLayout<'static>; 1],
+    /// The layout of the compact instances read by the `vs_packed` entry point.
+    packed_vertex_buffers: [wgpu::VertexBufferLayout<'static>; 1],
+    /// The layout of the placements read by the `vs_placed` entry point.
+    placed_vertex_buffers: [wgpu::VertexBufferLayout<'static>; 1],
     atlas_layout: BindGroupLayout,
     uniforms_layout: BindGroupLayout,
+    pattern_layout: BindGroupLayout,
+    pattern_sampler: Sampler,
+    default_pattern: TextureView,
     pipeline_layout: PipelineLayout,
+    /// The layout of the instances bind group and the pipeline layout of pipelines that pull
+    /// instances from it, created when first requested, as not all devices support storage
+    /// buffers in vertex shaders.
+    pulled_layouts: OnceLock<(BindGroupLayout, PipelineLayout)>,
+    /// The cache of compiled pipelines passed to [`Cache::with_pipeline_cache`].
+    pipeline_cache: Option<PipelineCache>,
     cache: Mutex<
         Vec<(
-            TextureFormat,
+            ColorTargetState,
             MultisampleState,
             Option<DepthStencilState>,
+            Option<NonZeroU32>,
+            InstanceLayout,
             RenderPipeline,
         )>,
     >,
+    mipmap_shader: ShaderModule,
+    mipmap_layout: BindGroupLayout,
+    mipmap_pipeline_layout: PipelineLayout,
+    mipmap_pipelines: Mutex<Vec<(TextureFormat, RenderPipeline)>>,
+    /// The vertex buffer shared by renderers, see [`TextRenderer::set_shared_vertices`].
+    ///
+    /// [`TextRenderer::set_shared_vertices`]: crate::TextRenderer::set_shared_vertices
+    vertex_pool: Mutex<VertexPool>,
+}
+
+/// A vertex buffer whose ranges are allocated by the renderers of a [`Cache`].
+#[derive(Debug, Default)]
+struct VertexPool {
+    buffer: Option<Buffer>,
+    /// The unallocated ranges of `buffer`, sorted by their start.
+    free: Vec<Range<u64>>,
+}
+
+impl VertexPool {
+    /// Returns the given range to the free ranges, merging it with its neighbors.
+    fn free(&mut self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let index = self.free.partition_point(|free| free.start < range.start);
+        self.free.insert(index, range);
+        if index + 1 < self.free.len() && self.free[index].end == self.free[index + 1].start {
+            self.free[index].end = self.free.remove(index + 1).end;
+        }
+        if index > 0 && self.free[index - 1].end == self.free[index].start {
+            self.free[index - 1].end = self.free.remove(index).end;
+        }
+    }
+
+    /// Takes `size` bytes from the first free range that is large enough.
+    fn take(&mut self, size: u64) -> Option<Range<u64>> {
+        let index = self
+            .free
+            .iter()
+            .position(|free| free.end - free.start >= size)?;
+        let free = &mut self.free[index];
+        let range = free.start..free.start + size;
+        free.start = range.end;
+        if free.is_empty() {
+            self.free.remove(index);
+        }
+        Some(range)
+    }
 }
 
 impl Cache {
     /// Creates a new `Cache` with the given `device`.
     pub fn new(device: &Device) -> Self {
+        Self::with_shader(device, TextShader::Embedded)
+    }
+
+    /// Creates a new `Cache` with the given `device`, whose text pipelines use the given fragment
+    /// `shader`.
+    ///
+    /// The shader is compiled when the `Cache` is created, so invalid WGSL is reported through
+    /// the error handler of the device.
+    pub fn with_shader(device: &Device, shader: TextShader<'_>) -> Self {
+        Self::with_pipeline_cache(device, shader, None)
+    }
+
+    /// Creates a new `Cache` like [`Cache::with_shader`], whose pipelines are compiled through
+    /// the given [`PipelineCache`].
+    ///
+    /// A pipeline cache created from the data of a previous run, see [`PipelineCache::get_data`],
+    /// skips most of the shader compilation when the pipelines are first created. Together with
+    /// [`TextRendererBuilder::precompile`], this moves the remaining cost to startup instead of
+    /// the first frames that render text.
+    ///
+    /// [`TextRendererBuilder::precompile`]: crate::TextRendererBuilder::precompile
+    pub fn with_pipeline_cache(
+        device: &Device,
+        shader: TextShader<'_>,
+        pipeline_cache: Option<PipelineCache>,
+    ) -> Self {
         let sampler = device.create_sampler(&SamplerDescriptor {
             label: Some("glyphon sampler"),
             min_filter: FilterMode::Nearest,
@@ -52,9 +263,33 @@ impl Cache {
             ..Default::default()
         });
 
+        // Samples mipmapped atlases trilinearly
+        let mipmap_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("glyphon mipmap sampler"),
+            min_filter: FilterMode::Linear,
+            mag_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        // Passing the viewport parameters through push constants saves binding a uniform buffer
+        // for every renderer
+        let push_constants = device.features().contains(Features::PUSH_CONSTANTS)
+            && device.limits().max_push_constant_size as usize >= mem::size_of::<Params>();
+
+        let shader_source = match shader {
+            TextShader::Embedded => include_str!("shader.wgsl").to_owned(),
+            TextShader::FragmentHook(snippet) => {
+                include_str!("shader.wgsl").replace(DEFAULT_FRAGMENT_HOOK, snippet)
+            }
+            TextShader::Replacement(source) => source.to_owned(),
+        };
         let shader = device.create_shader_module(ShaderModuleDescriptor {
             label: Some("glyphon shader"),
-            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
+            source: ShaderSource::Wgsl(text_shader_source(
+                Cow::Borrowed(&shader_source),
+                push_constants,
+            )),
         });
 
         let vertex_buffer_layout = wgpu::VertexBufferLayout {
@@ -101,6 +336,111 @@ impl Cache {
                     offset: mem::size_of::<u32>() as u64 * 8,
                     shader_location: 7,
                 },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Uint32,
+                    offset: mem::size_of::<u32>() as u64 * 9,
+                    shader_location: 8,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Float32,
+                    offset: mem::size_of::<u32>() as u64 * 10,
+                    shader_location: 9,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Uint32,
+                    offset: mem::size_of::<u32>() as u64 * 11,
+                    shader_location: 10,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Float32,
+                    offset: mem::size_of::<u32>() as u64 * 12,
+                    shader_location: 11,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Float32x2,
+                    offset: mem::size_of::<u32>() as u64 * 13,
+                    shader_location: 12,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Float32,
+                    offset: mem::size_of::<u32>() as u64 * 15,
+                    shader_location: 13,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: mem::size_of::<u32>() as u64 * 16,
+                    shader_location: 14,
+                },
+            ],
+        };
+
+        let packed_vertex_buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<PackedGlyph>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Sint16x2,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Uint32,
+                    offset: mem::size_of::<u32>() as u64,
+                    shader_location: 1,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Uint32,
+                    offset: mem::size_of::<u32>() as u64 * 2,
+                    shader_location: 2,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Uint32,
+                    offset: mem::size_of::<u32>() as u64 * 3,
+                    shader_location: 3,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Uint32,
+                    offset: mem::size_of::<u32>() as u64 * 4,
+                    shader_location: 4,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Float32,
+                    offset: mem::size_of::<u32>() as u64 * 5,
+                    shader_location: 5,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Uint32,
+                    offset: mem::size_of::<u32>() as u64 * 6,
+                    shader_location: 6,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Uint32,
+                    offset: mem::size_of::<u32>() as u64 * 7,
+                    shader_location: 7,
+                },
+            ],
+        };
+
+        // The columns of a `TextTransform`
+        let placed_vertex_buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<TextTransform>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Float32x2,
+                    offset: mem::size_of::<f32>() as u64 * 2,
+                    shader_location: 1,
+                },
+                wgpu::VertexAttribute {
+                    format: VertexFormat::Float32x2,
+                    offset: mem::size_of::<f32>() as u64 * 4,
+                    shader_location: 2,
+                },
             ],
         };
 
@@ -111,7 +451,7 @@ impl Cache {
                     visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
                     ty: BindingType::Texture {
                         multisampled: false,
-                        view_dimension: TextureViewDimension::D2,
+                        view_dimension: TextureViewDimension::D2Array,
                         sample_type: TextureSampleType::Float { filterable: true },
                     },
                     count: None,
@@ -121,7 +461,7 @@ impl Cache {
                     visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
                     ty: BindingType::Texture {
                         multisampled: false,
-                        view_dimension: TextureViewDimension::D2,
+                        view_dimension: TextureViewDimension::D2Array,
                         sample_type: TextureSampleType::Float { filterable: true },
                     },
                     count: None,
@@ -132,6 +472,52 @@ impl Cache {
                     ty: BindingType::Sampler(SamplerBindingType::Filtering),
                     count: None,
                 },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(mem::size_of::<TransferParams>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
             ],
             label: Some("glyphon atlas bind group layout"),
         });
@@ -150,49 +536,313 @@ impl Cache {
             label: Some("glyphon uniforms bind group layout"),
         });
 
-        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[&atlas_layout, &uniforms_layout],
+        let pattern_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(
+                            (mem::size_of::<GradientParams>() * MAX_GRADIENTS) as u64,
+                        ),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(
+                            (mem::size_of::<TransformParams>() * MAX_TRANSFORMS) as u64,
+                        ),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(
+                            (mem::size_of::<ClipParams>() * MAX_CLIPS) as u64,
+                        ),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(mem::size_of::<RendererParams>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("glyphon pattern bind group layout"),
+        });
+
+        let pattern_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("glyphon pattern sampler"),
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            min_filter: FilterMode::Linear,
+            mag_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: 0f32,
+            lod_max_clamp: 0f32,
+            ..Default::default()
+        });
+
+        // Bound when no pattern is set, it is never sampled
+        let default_pattern = device.create_texture(&TextureDescriptor {
+            label: Some("glyphon default pattern"),
+            size: Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let default_pattern = default_pattern.create_view(&TextureViewDescriptor::default());
+
+        let pipeline_layout = if push_constants {
+            device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some("glyphon pipeline layout"),
+                bind_group_layouts: &[&atlas_layout, &pattern_layout],
+                push_constant_ranges: &[PushConstantRange {
+                    stages: ShaderStages::VERTEX_FRAGMENT,
+                    range: 0..mem::size_of::<Params>() as u32,
+                }],
+            })
+        } else {
+            device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some("glyphon pipeline layout"),
+                bind_group_layouts: &[&atlas_layout, &uniforms_layout, &pattern_layout],
+                push_constant_ranges: &[],
+            })
+        };
+
+        let mipmap_shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("glyphon mipmap shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("mipmap.wgsl"))),
+        });
+
+        let mipmap_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("glyphon mipmap bind group layout"),
+        });
+
+        let mipmap_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("glyphon mipmap pipeline layout"),
+            bind_group_layouts: &[&mipmap_layout],
             push_constant_ranges: &[],
         });
 
         Self(Arc::new(Inner {
             sampler,
+            mipmap_sampler,
             shader,
+            shader_source,
+            push_constants,
+            variants: Default::default(),
             vertex_buffers: [vertex_buffer_layout],
+            packed_vertex_buffers: [packed_vertex_buffer_layout],
+            placed_vertex_buffers: [placed_vertex_buffer_layout],
             uniforms_layout,
             atlas_layout,
+            pattern_layout,
+            pattern_sampler,
+            default_pattern,
             pipeline_layout,
+            pulled_layouts: OnceLock::new(),
+            pipeline_cache,
             cache: Mutex::new(Vec::new()),
+            mipmap_shader,
+            mipmap_layout,
+            mipmap_pipeline_layout,
+            mipmap_pipelines: Mutex::new(Vec::new()),
+            vertex_pool: Mutex::new(VertexPool::default()),
         }))
     }
 
     pub(crate) fn create_atlas_bind_group(
         &self,
         device: &Device,
-        color_atlas: &TextureView,
-        mask_atlas: &TextureView,
+        views: AtlasViews,
+        transfer_params: &Buffer,
     ) -> BindGroup {
         device.create_bind_group(&BindGroupDescriptor {
             layout: &self.0.atlas_layout,
             entries: &[
                 BindGroupEntry {
                     binding: 0,
-                    resource: BindingResource::TextureView(color_atlas),
+                    resource: BindingResource::TextureView(views.color),
                 },
                 BindGroupEntry {
                     binding: 1,
-                    resource: BindingResource::TextureView(mask_atlas),
+                    resource: BindingResource::TextureView(views.mask),
                 },
                 BindGroupEntry {
                     binding: 2,
                     resource: BindingResource::Sampler(&self.0.sampler),
                 },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::Sampler(&self.0.mipmap_sampler),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: transfer_params.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::TextureView(views.prebaked_color),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::TextureView(views.prebaked_mask),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: BindingResource::TextureView(views.sdf),
+                },
             ],
             label: Some("glyphon atlas bind group"),
         })
     }
 
+    /// Returns the cache of compiled pipelines passed to [`Cache::with_pipeline_cache`], whose
+    /// data can be saved to speed up the next run once the pipelines were created.
+    pub fn pipeline_cache(&self) -> Option<&PipelineCache> {
+        self.0.pipeline_cache.as_ref()
+    }
+
+    /// Returns whether both caches share the same resources.
+    pub(crate) fn ptr_eq(&self, other: &Cache) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Moves `range` of the shared vertex buffer to a range of at least `size` bytes, unless it
+    /// already is large enough, and returns the buffer.
+    ///
+    /// The buffer grows when no free range is large enough, keeping the contents of the
+    /// allocated ranges. Renderers keep the buffer they last prepared with, so the previous
+    /// buffer stays valid until they prepare again.
+    pub(crate) fn allocate_vertices(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        range: &mut Range<u64>,
+        size: u64,
+    ) -> Buffer {
+        let mut pool = self.0.vertex_pool.lock().expect("Lock vertex pool");
+
+        if let Some(buffer) = pool
+            .buffer
+            .as_ref()
+            .filter(|_| range.end - range.start >= size)
+        {
+            return buffer.clone();
+        }
+
+        pool.free(range.clone());
+        // Ranges get room to grow, so that they don't move on every prepare
+        let size = next_pool_size(size);
+        *range = match pool.take(size) {
+            Some(free) => free,
+            None => {
+                let old_size = pool.buffer.as_ref().map_or(0, |buffer| buffer.size());
+                let new_size = next_pool_size(old_size + size);
+                let buffer = device.create_buffer(&BufferDescriptor {
+                    label: Some("glyphon shared vertices"),
+                    size: new_size,
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                });
+
+                if let Some(old_buffer) = &pool.buffer {
+                    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+                        label: Some("glyphon shared vertices grow"),
+                    });
+                    encoder.copy_buffer_to_buffer(old_buffer, 0, &buffer, 0, old_size);
+                    queue.submit(Some(encoder.finish()));
+                }
+
+                pool.buffer = Some(buffer);
+                pool.free(old_size..new_size);
+                pool.take(size).expect("Allocate from grown vertex pool")
+            }
+        };
+
+        pool.buffer.clone().expect("Vertex pool buffer")
+    }
+
+    /// Returns a range allocated by [`Cache::allocate_vertices`] to the shared vertex buffer.
+    pub(crate) fn free_vertices(&self, range: Range<u64>) {
+        let mut pool = self.0.vertex_pool.lock().expect("Lock vertex pool");
+        pool.free(range);
+    }
+
+    /// Returns `true` if the viewport parameters are passed through push constants.
+    pub(crate) fn push_constants(&self) -> bool {
+        self.0.push_constants
+    }
+
+    /// The index of the pattern bind group, which takes the place of the uniforms bind group if
+    /// the viewport parameters are passed through push constants.
+    pub(crate) fn pattern_group(&self) -> u32 {
+        if self.0.push_constants {
+            1
+        } else {
+            2
+        }
+    }
+
     pub(crate) fn create_uniforms_bind_group(&self, device: &Device, buffer: &Buffer) -> BindGroup {
         device.create_bind_group(&BindGroupDescriptor {
             layout: &self.0.uniforms_layout,
@@ -204,61 +854,414 @@ impl Cache {
         })
     }
 
+    /// Creates the bind group of a renderer's pattern texture, the gradients, transforms and
+    /// rounded clips of its text areas and its own parameters.
+    pub(crate) fn create_pattern_bind_group(
+        &self,
+        device: &Device,
+        pattern: Option<&TextureView>,
+        gradients: &Buffer,
+        transforms: &Buffer,
+        clips: &Buffer,
+        renderer_params: &Buffer,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            layout: &self.0.pattern_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(
+                        pattern.unwrap_or(&self.0.default_pattern),
+                    ),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.0.pattern_sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: gradients.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: transforms.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: clips.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: renderer_params.as_entire_binding(),
+                },
+            ],
+            label: Some("glyphon pattern bind group"),
+        })
+    }
+
+    /// Returns whether the text shader has the `vs_packed` entry point, which draws instances in
+    /// the compact layout of [`PackedGlyph`].
+    pub(crate) fn supports_packed_instances(&self) -> bool {
+        self.0.shader_source.contains("fn vs_packed(")
+    }
+
+    /// Returns whether the text shader has the `vs_pulled` entry point, which reads instances
+    /// from a storage buffer.
+    pub(crate) fn supports_pulled_instances(&self) -> bool {
+        self.0.shader_source.contains("fn vs_pulled(")
+    }
+
+    /// Returns whether the text shader has the `vs_placed` entry point, which draws pulled
+    /// instances at each of a number of placements.
+    pub(crate) fn supports_placed_instances(&self) -> bool {
+        self.0.shader_source.contains("fn vs_placed(")
+    }
+
+    /// Returns the index of the bind group of the storage buffer read by pipelines that pull
+    /// their instances.
+    pub(crate) fn instances_group(&self) -> u32 {
+        self.pattern_group() + 1
+    }
+
+    fn pulled_layouts(&self, device: &Device) -> &(BindGroupLayout, PipelineLayout) {
+        let Inner {
+            pulled_layouts,
+            atlas_layout,
+            uniforms_layout,
+            pattern_layout,
+            push_constants,
+            ..
+        } = self.0.deref();
+
+        pulled_layouts.get_or_init(|| {
+            let instances_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: NonZeroU64::new(
+                                mem::size_of::<GlyphToRender>() as u64
+                            ),
+                        },
+                        count: None,
+                    }],
+                    label: Some("glyphon instances bind group layout"),
+                });
+
+            let pipeline_layout = if *push_constants {
+                device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: Some("glyphon pulled pipeline layout"),
+                    bind_group_layouts: &[atlas_layout, pattern_layout, &instances_layout],
+                    push_constant_ranges: &[PushConstantRange {
+                        stages: ShaderStages::VERTEX_FRAGMENT,
+                        range: 0..mem::size_of::<Params>() as u32,
+                    }],
+                })
+            } else {
+                device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: Some("glyphon pulled pipeline layout"),
+                    bind_group_layouts: &[
+                        atlas_layout,
+                        uniforms_layout,
+                        pattern_layout,
+                        &instances_layout,
+                    ],
+                    push_constant_ranges: &[],
+                })
+            };
+
+            (instances_layout, pipeline_layout)
+        })
+    }
+
+    /// Creates the bind group of the storage buffer that pipelines pulling their instances read
+    /// them from.
+    pub(crate) fn create_instances_bind_group(
+        &self,
+        device: &Device,
+        buffer: &Buffer,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            layout: &self.pulled_layouts(device).0,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("glyphon instances bind group"),
+        })
+    }
+
+    /// Returns the text pipeline with the given state, reading instances in the given layout.
     pub(crate) fn get_or_create_pipeline(
         &self,
         device: &Device,
-        format: TextureFormat,
+        target: ColorTargetState,
         multisample: MultisampleState,
         depth_stencil: Option<DepthStencilState>,
+        multiview: Option<NonZeroU32>,
+        instances: InstanceLayout,
     ) -> RenderPipeline {
         let Inner {
             cache,
             pipeline_layout,
+            pipeline_cache,
             shader,
+            shader_source,
+            push_constants,
+            variants,
             vertex_buffers,
+            packed_vertex_buffers,
+            placed_vertex_buffers,
             ..
         } = self.0.deref();
 
+        // Dual-source blending reads the second output of the subpixel entry point, which needs
+        // the `dual_source_blending` extension, and multiview pipelines read the index of the
+        // rendered view, so both need separate shader modules
+        let blend = target.blend.unwrap_or(BlendState::REPLACE);
+        let subpixel = is_dual_source(&blend);
+        let shader = match (subpixel, multiview.is_some()) {
+            (false, false) => shader,
+            (subpixel, multiview) => {
+                let variant = subpixel as usize + 2 * multiview as usize - 1;
+                variants[variant].get_or_init(|| {
+                    device.create_shader_module(ShaderModuleDescriptor {
+                        label: Some("glyphon shader variant"),
+                        source: ShaderSource::Wgsl(text_shader_source(
+                            Cow::Owned(variant_source(shader_source, subpixel, multiview)),
+                            *push_constants,
+                        )),
+                    })
+                })
+            }
+        };
+        let (fs_entry_point, label) = if subpixel {
+            ("fs_subpixel", "glyphon subpixel pipeline")
+        } else if blend.color.src_factor == BlendFactor::SrcAlpha {
+            ("fs_main", "glyphon pipeline")
+        } else {
+            // Blend states that don't multiply by the source alpha expect premultiplied colors
+            ("fs_premultiplied", "glyphon premultiplied pipeline")
+        };
+
         let mut cache = cache.lock().expect("Write pipeline cache");
 
         cache
             .iter()
-            .find(|(fmt, ms, ds, _)| fmt == &format && ms == &multisample && ds == &depth_stencil)
-            .map(|(_, _, _, p)| p.clone())
+            .find(|(tg, ms, ds, mv, il, _)| {
+                tg == &target
+                    && ms == &multisample
+                    && ds == &depth_stencil
+                    && mv == &multiview
+                    && il == &instances
+            })
+            .map(|(_, _, _, _, _, p)| p.clone())
             .unwrap_or_else(|| {
+                let (layout, vs_entry_point, buffers, topology) = match instances {
+                    InstanceLayout::Full => (
+                        pipeline_layout,
+                        "vs_main",
+                        &vertex_buffers[..],
+                        PrimitiveTopology::TriangleStrip,
+                    ),
+                    InstanceLayout::Packed => (
+                        pipeline_layout,
+                        "vs_packed",
+                        &packed_vertex_buffers[..],
+                        PrimitiveTopology::TriangleStrip,
+                    ),
+                    InstanceLayout::Pulled => (
+                        &self.pulled_layouts(device).1,
+                        "vs_pulled",
+                        &[][..],
+                        PrimitiveTopology::TriangleList,
+                    ),
+                    InstanceLayout::Placed => (
+                        &self.pulled_layouts(device).1,
+                        "vs_placed",
+                        &placed_vertex_buffers[..],
+                        PrimitiveTopology::TriangleList,
+                    ),
+                };
                 let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-                    label: Some("glyphon pipeline"),
-                    layout: Some(pipeline_layout),
+                    label: Some(label),
+                    layout: Some(layout),
                     vertex: VertexState {
                         module: shader,
-                        entry_point: Some("vs_main"),
-                        buffers: vertex_buffers,
+                        entry_point: Some(vs_entry_point),
+                        buffers,
                         compilation_options: PipelineCompilationOptions::default(),
                     },
                     fragment: Some(FragmentState {
                         module: shader,
-                        entry_point: Some("fs_main"),
-                        targets: &[Some(ColorTargetState {
-                            format,
-                            blend: Some(BlendState::ALPHA_BLENDING),
-                            write_mask: ColorWrites::default(),
-                        })],
+                        entry_point: Some(fs_entry_point),
+                        targets: &[Some(target.clone())],
                         compilation_options: PipelineCompilationOptions::default(),
                     }),
                     primitive: PrimitiveState {
-                        topology: PrimitiveTopology::TriangleStrip,
+                        topology,
                         ..Default::default()
                     },
                     depth_stencil: depth_stencil.clone(),
                     multisample,
-                    multiview: None,
-                    cache: None,
+                    multiview,
+                    cache: pipeline_cache.as_ref(),
                 });
 
-                cache.push((format, multisample, depth_stencil, pipeline.clone()));
+                cache.push((
+                    target,
+                    multisample,
+                    depth_stencil,
+                    multiview,
+                    instances,
+                    pipeline.clone(),
+                ));
 
                 pipeline
             })
             .clone()
     }
+
+    /// Regenerates all mip levels of every layer of `texture` from its first mip level.
+    pub(crate) fn generate_mipmaps(&self, device: &Device, queue: &Queue, texture: &Texture) {
+        let pipeline = self.get_or_create_mipmap_pipeline(device, texture.format());
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("glyphon mipmap encoder"),
+        });
+
+        for layer in 0..texture.depth_or_array_layers() {
+            let level_view = |mip_level| {
+                texture.create_view(&TextureViewDescriptor {
+                    dimension: Some(TextureViewDimension::D2),
+                    base_mip_level: mip_level,
+                    mip_level_count: Some(1),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            };
+
+            let mut source = level_view(0);
+            for mip_level in 1..texture.mip_level_count() {
+                let target = level_view(mip_level);
+                let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                    layout: &self.0.mipmap_layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(&source),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::Sampler(&self.0.mipmap_sampler),
+                        },
+                    ],
+                    label: Some("glyphon mipmap bind group"),
+                });
+
+                {
+                    let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                        label: Some("glyphon mipmap pass"),
+                        color_attachments: &[Some(RenderPassColorAttachment {
+                            view: &target,
+                            resolve_target: None,
+                            ops: Operations {
+                                load: LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                                store: StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+                    pass.set_pipeline(&pipeline);
+                    pass.set_bind_group(0, &bind_group, &[]);
+                    pass.draw(0..3, 0..1);
+                }
+
+                source = target;
+            }
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+
+    fn get_or_create_mipmap_pipeline(
+        &self,
+        device: &Device,
+        format: TextureFormat,
+    ) -> RenderPipeline {
+        let Inner {
+            mipmap_pipelines,
+            mipmap_pipeline_layout,
+            mipmap_shader,
+            pipeline_cache,
+            ..
+        } = self.0.deref();
+
+        let mut mipmap_pipelines = mipmap_pipelines
+            .lock()
+            .expect("Write mipmap pipeline cache");
+
+        if let Some((_, pipeline)) = mipmap_pipelines.iter().find(|(fmt, _)| fmt == &format) {
+            return pipeline.clone();
+        }
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("glyphon mipmap pipeline"),
+            layout: Some(mipmap_pipeline_layout),
+            vertex: VertexState {
+                module: mipmap_shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: mipmap_shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::default(),
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: pipeline_cache.as_ref(),
+        });
+
+        mipmap_pipelines.push((format, pipeline.clone()));
+
+        pipeline
+    }
+}
+
+/// Returns `true` if `blend` reads the second output of the fragment shader.
+fn is_dual_source(blend: &BlendState) -> bool {
+    [blend.color, blend.alpha].iter().any(|component| {
+        [component.src_factor, component.dst_factor]
+            .iter()
+            .any(|factor| {
+                matches!(
+                    factor,
+                    BlendFactor::Src1
+                        | BlendFactor::OneMinusSrc1
+                        | BlendFactor::Src1Alpha
+                        | BlendFactor::OneMinusSrc1Alpha
+                )
+            })
+    })
+}
+
+/// Returns the power of two, and multiple of the copy alignment, that a range of the shared
+/// vertex buffer of the given size is rounded up to.
+fn next_pool_size(size: u64) -> u64 {
+    size.next_power_of_two().max(wgpu::COPY_BUFFER_ALIGNMENT)
 }
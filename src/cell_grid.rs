@@ -0,0 +1,202 @@
+use crate::{fontdb, Color, GlyphToRender, GlyphonCacheKey};
+use wgpu::Buffer;
+
+/// The instances drawn for each cell: its background, its glyph, its underline and its
+/// strikethrough.
+pub(crate) const INSTANCES_PER_CELL: usize = 4;
+
+/// The angle in radians that the glyphs of italic cells are slanted by.
+pub(crate) const ITALIC_OBLIQUE: f32 = 0.2;
+
+/// The style of a [`Cell`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CellFlags {
+    /// Thickens the glyph by [`CellMetrics::faux_bold`].
+    pub bold: bool,
+    /// Slants the glyph as a synthetic italic.
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    /// Swaps the foreground and background colors.
+    pub inverse: bool,
+}
+
+/// A cell of a [`CellGrid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cell {
+    /// The glyph drawn in the cell, by its id in the font of the grid. Cells with the glyph id
+    /// `0`, the `.notdef` glyph, are drawn without a glyph.
+    pub glyph_id: u16,
+    /// The color of the glyph and its decorations.
+    pub fg: Color,
+    /// The color the cell is filled with, which isn't drawn if it's fully transparent.
+    pub bg: Color,
+    pub flags: CellFlags,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            glyph_id: 0,
+            fg: Color::rgb(255, 255, 255),
+            bg: Color::rgba(0, 0, 0, 0),
+            flags: CellFlags::default(),
+        }
+    }
+}
+
+/// The font and the size of the cells of a [`CellGrid`], in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellMetrics {
+    /// The font the glyphs of the cells are drawn with, which is usually monospace.
+    pub font: fontdb::ID,
+    pub font_size: f32,
+    pub cell_width: f32,
+    pub cell_height: f32,
+    /// The distance from the top of a cell to the baseline of its glyph.
+    pub baseline: f32,
+    /// How many pixels the glyphs of bold cells are thickened by.
+    pub faux_bold: f32,
+}
+
+/// A grid of monospace cells, such as the screen of a terminal emulator, which is drawn without
+/// shaping or laying out any text.
+///
+/// The grid keeps the instances of its cells in a buffer of its own, and
+/// [`TextRenderer::prepare_grid`] only prepares and uploads the cells that changed since it was
+/// last prepared. The grid is drawn with [`TextRenderer::render_grid`].
+///
+/// [`TextRenderer::prepare_grid`]: crate::TextRenderer::prepare_grid
+/// [`TextRenderer::render_grid`]: crate::TextRenderer::render_grid
+#[derive(Debug)]
+pub struct CellGrid {
+    pub(crate) metrics: CellMetrics,
+    /// The position of the top left corner of the grid in physical pixels.
+    pub(crate) position: [f32; 2],
+    columns: usize,
+    rows: usize,
+    pub(crate) cells: Vec<Cell>,
+    /// Whether each cell changed since the grid was last prepared.
+    pub(crate) dirty: Vec<bool>,
+    /// Whether all cells must be prepared again, e.g. after the grid was resized.
+    pub(crate) all_dirty: bool,
+    /// The instances of all backgrounds, followed by those of all glyphs and those of all
+    /// decorations, in the order of the cells.
+    pub(crate) instances: Vec<GlyphToRender>,
+    /// The glyph each cell samples, which is kept in the atlas while the grid is drawn.
+    pub(crate) glyph_keys: Vec<Option<GlyphonCacheKey>>,
+    /// The generation of the atlas the instances were prepared with.
+    pub(crate) atlas_generation: u64,
+    pub(crate) buffer: Option<Buffer>,
+    pub(crate) buffer_size: u64,
+}
+
+impl CellGrid {
+    /// Creates a grid of `columns` by `rows` empty cells.
+    pub fn new(metrics: CellMetrics, columns: usize, rows: usize) -> Self {
+        Self {
+            metrics,
+            position: [0.0; 2],
+            columns,
+            rows,
+            cells: vec![Cell::default(); columns * rows],
+            dirty: vec![false; columns * rows],
+            all_dirty: true,
+            instances: Vec::new(),
+            glyph_keys: Vec::new(),
+            atlas_generation: 0,
+            buffer: None,
+            buffer_size: 0,
+        }
+    }
+
+    /// Returns the number of columns of the grid.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Returns the number of rows of the grid.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the cell at the given column and row, or `None` if it's outside of the grid.
+    pub fn cell(&self, column: usize, row: usize) -> Option<&Cell> {
+        self.index(column, row).map(|index| &self.cells[index])
+    }
+
+    /// Sets the cell at the given column and row, which is prepared again if it changed. Cells
+    /// outside of the grid are ignored.
+    pub fn set_cell(&mut self, column: usize, row: usize, cell: Cell) {
+        let Some(index) = self.index(column, row) else {
+            return;
+        };
+        if self.cells[index] != cell {
+            self.cells[index] = cell;
+            self.dirty[index] = true;
+        }
+    }
+
+    /// Sets all cells of the grid to `cell`.
+    pub fn fill(&mut self, cell: Cell) {
+        for (index, current) in self.cells.iter_mut().enumerate() {
+            if *current != cell {
+                *current = cell;
+                self.dirty[index] = true;
+            }
+        }
+    }
+
+    /// Resizes the grid to `columns` by `rows` cells, keeping the cells that remain within it.
+    /// New cells are empty.
+    pub fn resize(&mut self, columns: usize, rows: usize) {
+        if columns == self.columns && rows == self.rows {
+            return;
+        }
+
+        let mut cells = vec![Cell::default(); columns * rows];
+        for row in 0..rows.min(self.rows) {
+            let count = columns.min(self.columns);
+            let start = row * self.columns;
+            cells[row * columns..row * columns + count]
+                .copy_from_slice(&self.cells[start..start + count]);
+        }
+
+        self.cells = cells;
+        self.dirty = vec![false; columns * rows];
+        self.columns = columns;
+        self.rows = rows;
+        self.all_dirty = true;
+    }
+
+    /// Returns the font and the size of the cells.
+    pub fn metrics(&self) -> CellMetrics {
+        self.metrics
+    }
+
+    /// Sets the font and the size of the cells, which prepares all cells again if they changed.
+    pub fn set_metrics(&mut self, metrics: CellMetrics) {
+        if self.metrics != metrics {
+            self.metrics = metrics;
+            self.all_dirty = true;
+        }
+    }
+
+    /// Returns the position of the top left corner of the grid in physical pixels.
+    pub fn position(&self) -> [f32; 2] {
+        self.position
+    }
+
+    /// Moves the top left corner of the grid to the given position in physical pixels, which
+    /// prepares all cells again if it changed.
+    pub fn set_position(&mut self, left: f32, top: f32) {
+        if self.position != [left, top] {
+            self.position = [left, top];
+            self.all_dirty = true;
+        }
+    }
+
+    fn index(&self, column: usize, row: usize) -> Option<usize> {
+        (column < self.columns && row < self.rows).then(|| row * self.columns + column)
+    }
+}
@@ -25,6 +25,14 @@ pub struct CustomGlyph {
     /// pixel and the resulting `SubpixelBin`'s in `RasterizationRequest` will always
     /// be `Zero` (useful for images and other large glyphs).
     pub snap_to_physical_pixel: bool,
+    /// The atlas this glyph should be stored in, regardless of the type of data produced by the
+    /// rasterizer.
+    ///
+    /// Setting this to `Some(ContentType::Mask)` stores glyphs that are rasterized as color
+    /// images in the mask atlas instead (using the alpha channel as the mask), so they are tinted
+    /// with [`CustomGlyph::color`]. This is useful for monochrome icons to avoid filling up the
+    /// color atlas. Set to `None` to use the content type produced by the rasterizer.
+    pub content_type_hint: Option<ContentType>,
     /// Additional metadata about the glyph
     pub metadata: usize,
 }
@@ -51,6 +59,12 @@ pub struct RasterizeCustomGlyphRequest {
     /// The scaling factor applied to the text area (Note that `width` and
     /// `height` are already scaled by this factor.)
     pub scale: f32,
+    /// The content type the rasterized glyph will be stored as (see
+    /// [`CustomGlyph::content_type_hint`]).
+    ///
+    /// Rasterizers may use this to produce data of the hinted type directly, otherwise the
+    /// output is converted.
+    pub content_type_hint: Option<ContentType>,
 }
 
 /// A rasterized custom glyph
@@ -63,15 +77,7 @@ pub struct RasterizedCustomGlyph {
 }
 
 impl RasterizedCustomGlyph {
-    pub(crate) fn validate(
-        &self,
-        input: &RasterizeCustomGlyphRequest,
-        expected_type: Option<ContentType>,
-    ) {
-        if let Some(expected_type) = expected_type {
-            assert_eq!(self.content_type, expected_type, "Custom glyph rasterizer must always produce the same content type for a given input. Expected {:?}, got {:?}. Input: {:?}", expected_type, self.content_type, input);
-        }
-
+    pub(crate) fn validate(&self, input: &RasterizeCustomGlyphRequest) {
         assert_eq!(
             self.data.len(),
             input.width as usize * input.height as usize * self.content_type.bytes_per_pixel(),
@@ -81,8 +87,31 @@ impl RasterizedCustomGlyph {
             input,
         );
     }
+
+    /// Converts the rasterized data to the hinted content type, if any.
+    pub(crate) fn with_content_type_hint(self, content_type_hint: Option<ContentType>) -> Self {
+        let Some(content_type) = content_type_hint else {
+            return self;
+        };
+
+        let data = match (self.content_type, content_type) {
+            (ContentType::Color, ContentType::Mask) => {
+                // Use the alpha channel as the mask
+                self.data.iter().skip(3).step_by(4).copied().collect()
+            }
+            (ContentType::Mask, ContentType::Color) => self
+                .data
+                .iter()
+                .flat_map(|&alpha| [255, 255, 255, alpha])
+                .collect(),
+            _ => return self,
+        };
+
+        Self { data, content_type }
+    }
 }
 
+/// The key of a rasterized custom glyph in the atlas.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct CustomGlyphCacheKey {
     /// Font ID
@@ -95,23 +124,41 @@ pub struct CustomGlyphCacheKey {
     pub x_bin: SubpixelBin,
     /// Binning of fractional Y offset
     pub y_bin: SubpixelBin,
+    /// The atlas the glyph is stored in
+    pub content_type_hint: Option<ContentType>,
 }
 
 /// The type of image data contained in a rasterized glyph
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum ContentType {
     /// Each pixel contains 32 bits of rgba data
     Color,
     /// Each pixel contains a single 8 bit channel
     Mask,
+    /// Each pixel contains 32 bits with the coverage of the red, green and blue subpixels,
+    /// followed by the coverage of the whole pixel. See [`AtlasConfig::subpixel`].
+    ///
+    /// [`AtlasConfig::subpixel`]: crate::AtlasConfig::subpixel
+    SubpixelMask,
+    /// Each pixel contains a single 8 bit channel with the signed distance to the outline of the
+    /// glyph, `0.5` on the outline and increasing towards its inside. See [`TextArea::sdf`].
+    ///
+    /// [`TextArea::sdf`]: crate::TextArea::sdf
+    Sdf,
+    /// Each pixel contains 32 bits with three signed distances encoded like [`ContentType::Sdf`]
+    /// whose median is the distance to the outline of the glyph, followed by the median. See
+    /// [`TextArea::msdf`].
+    ///
+    /// [`TextArea::msdf`]: crate::TextArea::msdf
+    Msdf,
 }
 
 impl ContentType {
     /// The number of bytes per pixel for this content type
     pub fn bytes_per_pixel(&self) -> usize {
         match self {
-            Self::Color => 4,
-            Self::Mask => 1,
+            Self::Color | Self::SubpixelMask | Self::Msdf => 4,
+            Self::Mask | Self::Sdf => 1,
         }
     }
 }
@@ -1,3 +1,4 @@
+use crate::{ContentType, GlyphonCacheKey};
 use std::{
     error::Error,
     fmt::{self, Display, Formatter},
@@ -6,22 +7,177 @@ use std::{
 /// An error that occurred while preparing text for rendering.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum PrepareError {
-    AtlasFull,
+    Atlas(AtlasError),
+    /// A glyph couldn't be allocated in the atlas of its content type.
+    AtlasFull(AtlasFullError),
 }
 
 impl Display for PrepareError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "Prepare error: glyph texture atlas is full")
+        match self {
+            PrepareError::Atlas(error) => write!(f, "Prepare error: {error}"),
+            PrepareError::AtlasFull(error) => write!(f, "Prepare error: {error}"),
+        }
+    }
+}
+
+impl Error for PrepareError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PrepareError::Atlas(error) => Some(error),
+            PrepareError::AtlasFull(error) => Some(error),
+        }
+    }
+}
+
+impl From<AtlasError> for PrepareError {
+    fn from(error: AtlasError) -> Self {
+        PrepareError::Atlas(error)
+    }
+}
+
+impl From<AtlasFullError> for PrepareError {
+    fn from(error: AtlasFullError) -> Self {
+        PrepareError::AtlasFull(error)
+    }
+}
+
+/// A glyph that couldn't be allocated in the atlas of its content type, along with the state of
+/// that atlas, e.g. to decide whether to trim the atlas, shrink the text or split it across
+/// renderers.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AtlasFullError {
+    /// The content type of the glyph, which selects the atlas it's stored in.
+    pub content_type: ContentType,
+    /// The glyph that couldn't be allocated.
+    pub glyph: GlyphonCacheKey,
+    /// The width and height of the glyph, without the padding around it.
+    pub requested: (u32, u32),
+    /// The width and height of the atlas texture.
+    pub atlas_size: (u32, u32),
+    /// The number of layers of the atlas texture.
+    pub layers: u32,
+    /// The width and height the atlas texture can grow to.
+    pub max_size: u32,
+    /// The number of layers the atlas texture can grow to.
+    pub max_layers: u32,
+    /// Why the glyph couldn't be allocated, either [`AtlasError::MaxSizeReached`] or
+    /// [`AtlasError::AllocationFailed`].
+    pub error: AtlasError,
+}
+
+impl Display for AtlasFullError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} glyph of size {}x{} doesn't fit into the {:?} atlas of size {}x{} with {} layers, which can grow to {}x{} with {} layers",
+            self.glyph,
+            self.requested.0,
+            self.requested.1,
+            self.content_type,
+            self.atlas_size.0,
+            self.atlas_size.1,
+            self.layers,
+            self.max_size,
+            self.max_size,
+            self.max_layers
+        )
+    }
+}
+
+impl Error for AtlasFullError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// An error that occurred while rendering text into a texture, see
+/// [`TextRenderer::render_to_texture`](crate::TextRenderer::render_to_texture).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RenderToTextureError {
+    Prepare(PrepareError),
+    Render(RenderError),
+}
+
+impl Display for RenderToTextureError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RenderToTextureError::Prepare(error) => write!(f, "Render to texture error: {error}"),
+            RenderToTextureError::Render(error) => write!(f, "Render to texture error: {error}"),
+        }
+    }
+}
+
+impl Error for RenderToTextureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RenderToTextureError::Prepare(error) => Some(error),
+            RenderToTextureError::Render(error) => Some(error),
+        }
+    }
+}
+
+impl From<PrepareError> for RenderToTextureError {
+    fn from(error: PrepareError) -> Self {
+        RenderToTextureError::Prepare(error)
+    }
+}
+
+impl From<RenderError> for RenderToTextureError {
+    fn from(error: RenderError) -> Self {
+        RenderToTextureError::Render(error)
+    }
+}
+
+/// An error that occurred while allocating space for a glyph in the texture atlas.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AtlasError {
+    /// The atlas is full and has reached its maximum size and number of layers.
+    MaxSizeReached,
+    /// The custom glyph rasterizer returned `None` or a different content type for a glyph that
+    /// had to be uploaded again, even though it rasterized the same input before.
+    RasterizerMismatch,
+    /// The glyph is larger than the atlas can ever hold. `requested` is the width and height of
+    /// the glyph and `available` the largest width and height that can be allocated.
+    AllocationFailed {
+        requested: (u32, u32),
+        available: (u32, u32),
+    },
+}
+
+impl Display for AtlasError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AtlasError::MaxSizeReached => write!(
+                f,
+                "Atlas error: glyph texture atlas is full and reached its maximum size"
+            ),
+            AtlasError::RasterizerMismatch => write!(
+                f,
+                "Atlas error: custom glyph rasterizer returned a different result for the same input"
+            ),
+            AtlasError::AllocationFailed {
+                requested,
+                available,
+            } => write!(
+                f,
+                "Atlas error: glyph of size {}x{} doesn't fit into the atlas, the largest available size is {}x{}",
+                requested.0, requested.1, available.0, available.1
+            ),
+        }
     }
 }
 
-impl Error for PrepareError {}
+impl Error for AtlasError {}
 
 /// An error that occurred while rendering text.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum RenderError {
     RemovedFromAtlas,
     ScreenResolutionChanged,
+    /// Placed text was rendered by a renderer that doesn't pull its instances from a storage
+    /// buffer, see [`TextRenderer::set_vertex_pulling`](crate::TextRenderer::set_vertex_pulling).
+    PlacementUnsupported,
 }
 
 impl Display for RenderError {
@@ -37,8 +193,47 @@ impl Display for RenderError {
                 f,
                 "Render error: screen resolution changed since last `prepare` call"
             ),
+            RenderError::PlacementUnsupported => write!(
+                f,
+                "Render error: placed text requires a renderer that pulls its instances"
+            ),
         }
     }
 }
 
 impl Error for RenderError {}
+
+/// An error that occurred while downloading the atlas textures.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DownloadError {
+    MapFailed,
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Download error: failed to map the atlas texture for reading"
+        )
+    }
+}
+
+impl Error for DownloadError {}
+
+/// An error that occurred while loading a glyph cache saved with
+/// [`TextAtlas::save_glyph_cache`](crate::TextAtlas::save_glyph_cache).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LoadGlyphCacheError {
+    InvalidData,
+}
+
+impl Display for LoadGlyphCacheError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Load glyph cache error: data is malformed or was saved by an incompatible version"
+        )
+    }
+}
+
+impl Error for LoadGlyphCacheError {}
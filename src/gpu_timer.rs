@@ -0,0 +1,142 @@
+use std::{mem, sync::mpsc, time::Duration};
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device, Features, MapMode, PollType,
+    QuerySet, QuerySetDescriptor, QueryType, Queue, RenderPass, QUERY_SET_MAX_QUERIES, QUERY_SIZE,
+};
+
+/// Measures the GPU time of [`TextRenderer::render_timed`](crate::TextRenderer::render_timed)
+/// calls with timestamp queries.
+///
+/// The timestamps are written into the render pass of each call, which requires the
+/// [`Features::TIMESTAMP_QUERY`] and [`Features::TIMESTAMP_QUERY_INSIDE_PASSES`] features. Once
+/// the passes are recorded, the queries are resolved into a command encoder with
+/// [`GpuTimer::resolve`], and the times are read with [`GpuTimer::read`] after submitting it.
+pub struct GpuTimer {
+    query_set: QuerySet,
+    /// The buffer the queries are resolved into.
+    resolve_buffer: Buffer,
+    /// The buffer the resolved queries are copied into to be mapped.
+    read_buffer: Buffer,
+    /// The number of queries of `query_set`, two per render call.
+    capacity: u32,
+    /// The number of queries written since the last call to `resolve`.
+    written: u32,
+    /// The number of queries copied into `read_buffer` by the last call to `resolve`.
+    resolved: u32,
+    /// The number of nanoseconds per timestamp tick.
+    period: f32,
+}
+
+impl GpuTimer {
+    /// Creates a timer for up to `max_renders` timed render calls between calls to
+    /// [`GpuTimer::resolve`]. Returns `None` if the device lacks the features to write
+    /// timestamps inside render passes.
+    pub fn new(device: &Device, queue: &Queue, max_renders: u32) -> Option<Self> {
+        let features = Features::TIMESTAMP_QUERY | Features::TIMESTAMP_QUERY_INSIDE_PASSES;
+        if !device.features().contains(features) {
+            return None;
+        }
+
+        let capacity = max_renders
+            .max(1)
+            .saturating_mul(2)
+            .min(QUERY_SET_MAX_QUERIES);
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some("glyphon timestamps"),
+            ty: QueryType::Timestamp,
+            count: capacity,
+        });
+        let size = capacity as u64 * QUERY_SIZE as u64;
+        let resolve_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("glyphon timestamps resolve"),
+            size,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let read_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("glyphon timestamps read"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            read_buffer,
+            capacity,
+            written: 0,
+            resolved: 0,
+            period: queue.get_timestamp_period(),
+        })
+    }
+
+    /// Writes the timestamp before a render call. Returns the index of the query to write
+    /// after it, or `None` if the timer is full.
+    pub(crate) fn begin(&mut self, pass: &mut RenderPass<'_>) -> Option<u32> {
+        if self.written + 2 > self.capacity {
+            return None;
+        }
+
+        pass.write_timestamp(&self.query_set, self.written);
+        self.written += 2;
+        Some(self.written - 1)
+    }
+
+    /// Writes the timestamp after a render call.
+    pub(crate) fn end(&self, pass: &mut RenderPass<'_>, query: u32) {
+        pass.write_timestamp(&self.query_set, query);
+    }
+
+    /// Resolves the timestamps written since the last call, to be read with
+    /// [`GpuTimer::read`] once the encoder was submitted.
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder) {
+        self.resolved = self.written;
+        self.written = 0;
+        if self.resolved == 0 {
+            return;
+        }
+
+        let size = self.resolved as u64 * QUERY_SIZE as u64;
+        encoder.resolve_query_set(&self.query_set, 0..self.resolved, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.read_buffer, 0, size);
+    }
+
+    /// Returns the GPU time of each timed render call resolved by the last call to
+    /// [`GpuTimer::resolve`], in the order they were recorded. This blocks until the GPU has
+    /// finished them.
+    ///
+    /// Returns `None` if the timestamps couldn't be read.
+    pub fn read(&mut self, device: &Device) -> Option<Vec<Duration>> {
+        let resolved = mem::take(&mut self.resolved);
+        if resolved == 0 {
+            return Some(Vec::new());
+        }
+
+        let slice = self
+            .read_buffer
+            .slice(..resolved as u64 * QUERY_SIZE as u64);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(PollType::Wait).ok()?;
+        receiver.recv().ok()?.ok()?;
+
+        let times = {
+            let mapped = slice.get_mapped_range();
+            mapped
+                .chunks_exact(2 * QUERY_SIZE as usize)
+                .map(|pair| {
+                    let start = u64::from_le_bytes(pair[..8].try_into().unwrap());
+                    let end = u64::from_le_bytes(pair[8..].try_into().unwrap());
+                    let nanos = end.saturating_sub(start) as f64 * self.period as f64;
+                    Duration::from_nanos(nanos as u64)
+                })
+                .collect()
+        };
+        self.read_buffer.unmap();
+
+        Some(times)
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{
+    cache::InstanceLayout,
+    shadow_blur::{begin_pass, create_fullscreen_pipeline, create_texture_layout},
+    text_render::create_oversized_buffer,
+    GlyphToRender, Resolution, TextAtlas, Viewport,
+};
+use std::{borrow::Cow, mem, ops::Range, slice};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource,
+    BlendComponent, BlendFactor, BlendOperation, BlendState, Buffer, BufferDescriptor,
+    BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, CompareFunction,
+    DepthStencilState, Device, Extent3d, FilterMode, LoadOp, MultisampleState,
+    PipelineLayoutDescriptor, Queue, RenderBundleEncoder, RenderPass, RenderPipeline, Sampler,
+    SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+/// Scales the color and alpha of the target by one minus the alpha of the glyphs, which cuts
+/// them out of what was drawn before.
+const ERASE_BLENDING: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::Zero,
+        dst_factor: BlendFactor::OneMinusSrcAlpha,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::Zero,
+        dst_factor: BlendFactor::OneMinusSrcAlpha,
+        operation: BlendOperation::Add,
+    },
+};
+
+/// The instances of a text area whose glyphs are cut out of its background.
+#[derive(Clone)]
+pub(crate) struct KnockoutArea {
+    pub(crate) background: Range<u32>,
+    pub(crate) glyphs: Range<u32>,
+}
+
+/// Renders the backgrounds of text areas with a knockout [`TextBackground`] into an offscreen
+/// target, cuts their glyphs out of them and composites the result beneath the text.
+///
+/// [`TextBackground`]: crate::TextBackground
+pub(crate) struct Knockout {
+    /// The format of the render target, which the offscreen target shares.
+    format: TextureFormat,
+    texture_layout: BindGroupLayout,
+    sampler: Sampler,
+    background_pipeline: RenderPipeline,
+    erase_pipeline: RenderPipeline,
+    composite_pipeline: RenderPipeline,
+    vertex_buffer: Buffer,
+    vertex_buffer_size: u64,
+    target: Option<(Resolution, TextureView, BindGroup)>,
+    areas: Vec<KnockoutArea>,
+}
+
+impl Knockout {
+    /// Creates the knockout resources for a renderer whose text is drawn with the given
+    /// `multisample` and `depth_stencil` states.
+    pub(crate) fn new(
+        device: &Device,
+        atlas: &TextAtlas,
+        multisample: MultisampleState,
+        depth_stencil: Option<DepthStencilState>,
+    ) -> Self {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("glyphon knockout shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("blur.wgsl"))),
+        });
+
+        let texture_layout = create_texture_layout(device);
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("glyphon knockout sampler"),
+            min_filter: FilterMode::Nearest,
+            mag_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let background_pipeline = atlas.cache.get_or_create_pipeline(
+            device,
+            ColorTargetState {
+                format: atlas.format,
+                blend: Some(BlendState::ALPHA_BLENDING),
+                write_mask: ColorWrites::default(),
+            },
+            MultisampleState::default(),
+            None,
+            None,
+            InstanceLayout::Full,
+        );
+        let erase_pipeline = atlas.cache.get_or_create_pipeline(
+            device,
+            ColorTargetState {
+                format: atlas.format,
+                blend: Some(ERASE_BLENDING),
+                write_mask: ColorWrites::default(),
+            },
+            MultisampleState::default(),
+            None,
+            None,
+            InstanceLayout::Full,
+        );
+
+        // The backgrounds lie beneath all text of the renderer, so they neither test nor write
+        // depth
+        let depth_stencil = depth_stencil.map(|depth_stencil| DepthStencilState {
+            depth_write_enabled: false,
+            depth_compare: CompareFunction::Always,
+            ..depth_stencil
+        });
+        let composite_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("glyphon knockout composite pipeline layout"),
+            bind_group_layouts: &[&texture_layout],
+            push_constant_ranges: &[],
+        });
+        let composite_pipeline = create_fullscreen_pipeline(
+            device,
+            "glyphon knockout composite pipeline",
+            &shader,
+            &composite_layout,
+            "fs_composite",
+            ColorTargetState {
+                format: atlas.format,
+                blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                write_mask: ColorWrites::default(),
+            },
+            multisample,
+            depth_stencil,
+            atlas.cache.pipeline_cache(),
+        );
+
+        let vertex_buffer_size = 4096;
+        let vertex_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("glyphon knockout vertices"),
+            size: vertex_buffer_size,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            format: atlas.format,
+            texture_layout,
+            sampler,
+            background_pipeline,
+            erase_pipeline,
+            composite_pipeline,
+            vertex_buffer,
+            vertex_buffer_size,
+            target: None,
+            areas: Vec::new(),
+        }
+    }
+
+    /// Uploads the instances of the text areas with a knockout background, which are indexed by
+    /// `areas`.
+    pub(crate) fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        resolution: Resolution,
+        vertices: &[GlyphToRender],
+        areas: &mut Vec<KnockoutArea>,
+    ) {
+        self.areas.clear();
+        if vertices.is_empty() || resolution.width == 0 || resolution.height == 0 {
+            areas.clear();
+            return;
+        }
+        self.areas.append(areas);
+
+        let vertices_raw = unsafe {
+            slice::from_raw_parts(
+                vertices as *const _ as *const u8,
+                mem::size_of_val(vertices),
+            )
+        };
+
+        if self.vertex_buffer_size >= vertices_raw.len() as u64 {
+            queue.write_buffer(&self.vertex_buffer, 0, vertices_raw);
+        } else {
+            self.vertex_buffer.destroy();
+
+            let (buffer, buffer_size) = create_oversized_buffer(
+                device,
+                Some("glyphon knockout vertices"),
+                vertices_raw,
+                BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            );
+
+            self.vertex_buffer = buffer;
+            self.vertex_buffer_size = buffer_size;
+        }
+
+        if self.target.as_ref().map(|(resolution, ..)| *resolution) != Some(resolution) {
+            self.target = Some(self.create_target(device, resolution));
+        }
+    }
+
+    /// Renders the backgrounds that were previously provided to `prepare` into the offscreen
+    /// target, each with its glyphs cut out.
+    pub(crate) fn render(
+        &self,
+        encoder: &mut CommandEncoder,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pattern_bind_group: &BindGroup,
+    ) {
+        let Some((_, view, _)) = &self.target else {
+            return;
+        };
+        if self.areas.is_empty() {
+            return;
+        }
+
+        let mut pass = begin_pass(
+            encoder,
+            "glyphon knockout pass",
+            view,
+            LoadOp::Clear(wgpu::Color::TRANSPARENT),
+        );
+        pass.set_bind_group(0, &atlas.bind_group, &[]);
+        viewport.bind(&mut pass);
+        pass.set_bind_group(atlas.cache.pattern_group(), pattern_bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        for area in &self.areas {
+            pass.set_pipeline(&self.background_pipeline);
+            pass.draw(0..4, area.background.clone());
+            pass.set_pipeline(&self.erase_pipeline);
+            pass.draw(0..4, area.glyphs.clone());
+        }
+    }
+
+    /// Composites the backgrounds with their glyphs cut out onto the render target of `pass`.
+    pub(crate) fn composite(&self, pass: &mut RenderPass<'_>) {
+        let Some((_, _, bind_group)) = &self.target else {
+            return;
+        };
+        if self.areas.is_empty() {
+            return;
+        }
+
+        pass.set_pipeline(&self.composite_pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Records the compositing of the backgrounds into a render bundle, like `composite`.
+    pub(crate) fn composite_bundle<'a>(&'a self, encoder: &mut RenderBundleEncoder<'a>) {
+        let Some((_, _, bind_group)) = &self.target else {
+            return;
+        };
+        if self.areas.is_empty() {
+            return;
+        }
+
+        encoder.set_pipeline(&self.composite_pipeline);
+        encoder.set_bind_group(0, bind_group, &[]);
+        encoder.draw(0..3, 0..1);
+    }
+
+    fn create_target(
+        &self,
+        device: &Device,
+        resolution: Resolution,
+    ) -> (Resolution, TextureView, BindGroup) {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("glyphon knockout target"),
+            size: Extent3d {
+                width: resolution.width,
+                height: resolution.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: self.format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &self.texture_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("glyphon knockout target bind group"),
+        });
+
+        (resolution, view, bind_group)
+    }
+}
@@ -4,22 +4,59 @@
 //! [cosmic-text]: https://github.com/pop-os/cosmic-text
 //! [etagere]: https://github.com/nical/etagere
 
+/// Enters a `tracing` span at the debug level until the end of the enclosing scope. Does nothing
+/// without the `tracing` feature.
+macro_rules! trace_span {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($($args)*).entered();
+    };
+}
+
+/// Emits a `tracing` event at the debug level. Does nothing without the `tracing` feature.
+macro_rules! trace_event {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($args)*);
+    };
+}
+
 mod cache;
+mod cell_grid;
 mod custom_glyph;
 mod error;
+mod gpu_timer;
+mod knockout;
+mod persist;
+mod sdf;
+mod shadow_blur;
 mod text_atlas;
 mod text_render;
 mod viewport;
 
-pub use cache::Cache;
+pub use cache::{Cache, TextShader};
+pub use cell_grid::{Cell, CellFlags, CellGrid, CellMetrics};
 pub use custom_glyph::{
-    ContentType, CustomGlyph, CustomGlyphId, RasterizeCustomGlyphRequest, RasterizedCustomGlyph,
+    ContentType, CustomGlyph, CustomGlyphCacheKey, CustomGlyphId, RasterizeCustomGlyphRequest,
+    RasterizedCustomGlyph,
+};
+pub use error::{
+    AtlasError, AtlasFullError, DownloadError, LoadGlyphCacheError, PrepareError, RenderError,
+    RenderToTextureError,
+};
+pub use gpu_timer::GpuTimer;
+pub use text_atlas::{
+    AtlasBudget, AtlasConfig, AtlasImage, AtlasRect, AtlasSnapshot, AtlasStats, AtlasTextureStats,
+    ColorMode, ExternalAtlasTexture, ExternalAtlasTextures, GrowCost, PrebakedAtlasPage,
+    PrebakedGlyph, TextAtlas, TransferFunction, TrimPolicy,
+};
+pub use text_render::{
+    BlendMode, DebugMode, DepthConvention, GlyphQuad, GlyphRect, GlyphonCacheKey, Hit,
+    PrepareStats, RasterizedText, TextAreaHandle, TextId, TextRenderer, TextRendererBuilder,
 };
-pub use error::{PrepareError, RenderError};
-pub use text_atlas::{ColorMode, TextAtlas};
-pub use text_render::TextRenderer;
 pub use viewport::Viewport;
 
+/// The default padding around each glyph in the atlas, in pixels. See [`AtlasConfig::padding`].
 pub const SHADOW_MARGIN_PX: u16 = 8;
 
 // Re-export all top-level types from `cosmic-text` for convenience.
@@ -33,11 +70,14 @@ pub use cosmic_text::{
 };
 
 use etagere::AllocId;
+use std::hash::{Hash, Hasher};
 
 pub(crate) enum GpuCacheStatus {
     InAtlas {
         x: u16,
         y: u16,
+        /// The layer of the atlas texture array
+        layer: u16,
         content_type: ContentType,
     },
     SkipRasterization,
@@ -50,6 +90,8 @@ pub(crate) struct GlyphDetails {
     atlas_id: Option<AllocId>,
     top: i16,
     left: i16,
+    /// The number of the last trim the glyph was in use before.
+    last_used_trim: u64,
 }
 
 #[repr(C)]
@@ -63,6 +105,76 @@ pub(crate) struct GlyphToRender {
     depth: f32,
     shadow_radius: f32,
     shadow_intensity: f32,
+    flags: u32,
+    opacity: f32,
+    /// The index of the transform of the instance in the transforms of its renderer, plus one, or
+    /// zero if it isn't transformed. [`WORLD_TRANSFORM_BIT`] is set if the transform maps to clip
+    /// space. The bits from [`CLIP_SHIFT`] hold the index of its rounded clip likewise.
+    transform: u32,
+    /// The distance in physical pixels by which the coverage of a mask glyph is dilated, or eroded
+    /// if negative.
+    embolden: f32,
+    /// The horizontal shift per physical pixel above the baseline, followed by the physical y
+    /// coordinate of the baseline, by which the instance is slanted.
+    oblique: [f32; 2],
+    /// The atlas texels per physical pixel of the instance, which is `1.0` except for glyphs
+    /// rendered from signed distance fields.
+    texel_scale: f32,
+    /// The cosine and sine of the angle by which the instance is rotated, followed by the physical
+    /// position it is rotated around.
+    rotation: [f32; 4],
+}
+
+/// The compact layout of a [`GlyphToRender`] that doesn't use shadows, emboldening, slanting,
+/// rotation or distance fields, read by the `vs_packed` entry point of the text shader.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PackedGlyph {
+    pos: [i16; 2],
+    dim: [u16; 2],
+    uv: [u16; 2],
+    color: u32,
+    /// The content type and the sRGB flag in the two low bytes, followed by the opacity as a
+    /// 16-bit normalized integer.
+    content_type_srgb_opacity: u32,
+    depth: f32,
+    flags: u32,
+    transform: u32,
+}
+
+impl GlyphToRender {
+    /// Returns the instance in the compact layout, or `None` if it uses a field the compact layout
+    /// lacks or doesn't fit into it.
+    pub(crate) fn pack(&self) -> Option<PackedGlyph> {
+        let [content_type, srgb] = self.content_type_with_srgb;
+        if self.shadow_radius != 0.0
+            || self.shadow_intensity != 0.0
+            || self.embolden != 0.0
+            || self.oblique != [0.0; 2]
+            || self.texel_scale != 1.0
+            || self.rotation != [1.0, 0.0, 0.0, 0.0]
+            || content_type > 0xff
+            || srgb > 0xff
+            || !(0.0..=1.0).contains(&self.opacity)
+        {
+            return None;
+        }
+
+        let opacity = (self.opacity * 65535.0).round() as u32;
+        Some(PackedGlyph {
+            pos: [
+                i16::try_from(self.pos[0]).ok()?,
+                i16::try_from(self.pos[1]).ok()?,
+            ],
+            dim: self.dim,
+            uv: self.uv,
+            color: self.color,
+            content_type_srgb_opacity: content_type as u32 | (srgb as u32) << 8 | opacity << 16,
+            depth: self.depth,
+            flags: self.flags,
+            transform: self.transform,
+        })
+    }
 }
 
 /// The screen resolution to use when rendering text.
@@ -79,6 +191,220 @@ pub struct Resolution {
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub(crate) struct Params {
     screen_resolution: Resolution,
+    edge_feather: f32,
+    gamma: f32,
+    contrast: f32,
+    zoom: f32,
+    offset: [f32; 2],
+}
+
+/// The coefficients of the transfer function of [`ColorMode::Custom`], see
+/// [`TransferFunction::Parametric`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct TransferParams {
+    g: f32,
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    _pad: [u32; 3],
+}
+
+/// The most text areas with a [`TextGradient`] or [`GlyphColors`] a [`TextRenderer`] draws at
+/// once. The gradients of further text areas are ignored.
+pub(crate) const MAX_GRADIENTS: usize = 63;
+
+/// The most stops of a [`TextGradient`] that are used.
+pub(crate) const MAX_GRADIENT_STOPS: usize = 8;
+
+/// The most text areas with a [`TextTransform`] or a [`TextArea::world_transform`] a
+/// [`TextRenderer`] draws at once. The transforms of further text areas are ignored.
+pub(crate) const MAX_TRANSFORMS: usize = 255;
+
+/// Set in `GlyphToRender::transform` if the transform of the instance maps to clip space rather
+/// than to physical pixels.
+pub(crate) const WORLD_TRANSFORM_BIT: u32 = 1 << 31;
+
+/// The most text areas with [`TextArea::corner_radii`] a [`TextRenderer`] draws at once. The
+/// bounds of further text areas keep square corners.
+pub(crate) const MAX_CLIPS: usize = 255;
+
+/// The most views of a multiview render pass whose projections a [`TextRenderer`] applies, see
+/// [`TextRenderer::set_view_projections`].
+pub(crate) const MAX_VIEWS: usize = 4;
+
+/// The shift of the index of the rounded clip of an instance in `GlyphToRender::transform`.
+pub(crate) const CLIP_SHIFT: u32 = 8;
+
+/// The rounded bounds of a text area as applied by the shader, in physical pixels before the
+/// transform of the text area.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ClipParams {
+    /// The left, top, right and bottom edges of the bounds.
+    rect: [f32; 4],
+    /// The radii of the top-left, top-right, bottom-right and bottom-left corners.
+    radii: [f32; 4],
+}
+
+impl ClipParams {
+    pub(crate) fn new(bounds: TextBounds, radii: CornerRadii) -> Self {
+        // Each radius is at most half of the shorter side of the bounds
+        let width = bounds.right as f32 - bounds.left as f32;
+        let height = bounds.bottom as f32 - bounds.top as f32;
+        let max_radius = 0.5 * width.min(height);
+        let radius = |radius: f32| radius.clamp(0.0, max_radius.max(0.0));
+
+        Self {
+            rect: [
+                bounds.left as f32,
+                bounds.top as f32,
+                bounds.right as f32,
+                bounds.bottom as f32,
+            ],
+            radii: [
+                radius(radii.top_left),
+                radius(radii.top_right),
+                radius(radii.bottom_right),
+                radius(radii.bottom_left),
+            ],
+        }
+    }
+}
+
+/// The parameters of a [`TextRenderer`] that apply to all of its text areas.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RendererParams {
+    /// The projections of the views of a multiview render pass.
+    pub(crate) projections: [[[f32; 4]; 4]; MAX_VIEWS],
+    pub(crate) brightness: f32,
+    /// Whether depths are mapped for a reverse-Z depth buffer, see [`DepthConvention`].
+    pub(crate) reverse_z: u32,
+    /// The debug visualizations drawn by the shader, see [`DebugMode`].
+    pub(crate) debug: u32,
+    pub(crate) _pad: u32,
+}
+
+impl Default for RendererParams {
+    fn default() -> Self {
+        let identity = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+
+        Self {
+            projections: [identity; MAX_VIEWS],
+            brightness: 1.0,
+            reverse_z: 0,
+            debug: 0,
+            _pad: 0,
+        }
+    }
+}
+
+/// The transform of a text area as applied by the shader: a column-major matrix that maps the
+/// physical position and depth of a vertex to physical pixels, or to clip space for a world
+/// transform.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct TransformParams {
+    matrix: [[f32; 4]; 4],
+}
+
+impl TransformParams {
+    /// A [`TextTransform`] around the `origin` of a text area, which keeps the depth of vertices.
+    pub(crate) fn new(transform: TextTransform, origin: [f32; 2]) -> Self {
+        let [a, b, c, d, e, f] = transform.0;
+        let [x, y] = origin;
+
+        Self {
+            matrix: [
+                [a, b, 0.0, 0.0],
+                [c, d, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [x + e - (a * x + c * y), y + f - (b * x + d * y), 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// A [`TextArea::world_transform`], which places vertices at `z = 0` regardless of their
+    /// depth.
+    pub(crate) fn world(matrix: [[f32; 4]; 4]) -> Self {
+        let [x, y, _, w] = matrix;
+
+        Self {
+            matrix: [x, y, [0.0; 4], w],
+        }
+    }
+}
+
+/// A [`TextGradient`] as evaluated by the shader, or the [`GlyphColors`] of a text area.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct GradientParams {
+    /// The left, top, width and height of the content box of the text area in physical pixels.
+    rect: [f32; 4],
+    /// The start and end points of a linear gradient, or the center and radii of a radial one.
+    geometry: [f32; 4],
+    /// `0` for a linear gradient, `1` for a radial one and `2` for glyph colors.
+    kind: u32,
+    stop_count: u32,
+    _pad: [u32; 2],
+    offsets: [f32; MAX_GRADIENT_STOPS],
+    colors: [u32; MAX_GRADIENT_STOPS],
+}
+
+impl GradientParams {
+    pub(crate) fn new(gradient: &TextGradient, rect: [f32; 4]) -> Self {
+        let (kind, geometry) = match gradient.kind {
+            GradientKind::Linear { start, end } => (0, [start[0], start[1], end[0], end[1]]),
+            GradientKind::Radial { center, radius } => {
+                (1, [center[0], center[1], radius[0], radius[1]])
+            }
+        };
+
+        let mut offsets = [0.0; MAX_GRADIENT_STOPS];
+        let mut colors = [0; MAX_GRADIENT_STOPS];
+        let stops = &gradient.stops[..gradient.stops.len().min(MAX_GRADIENT_STOPS)];
+        for (i, stop) in stops.iter().enumerate() {
+            offsets[i] = stop.offset;
+            colors[i] = stop.color.0;
+        }
+
+        Self {
+            rect,
+            geometry,
+            kind,
+            stop_count: stops.len() as u32,
+            _pad: [0; 2],
+            offsets,
+            colors,
+        }
+    }
+
+    pub(crate) fn glyph_colors(glyph_colors: GlyphColors) -> Self {
+        let mut colors = [0; MAX_GRADIENT_STOPS];
+        colors[..4].copy_from_slice(&[
+            glyph_colors.top_left.0,
+            glyph_colors.top_right.0,
+            glyph_colors.bottom_left.0,
+            glyph_colors.bottom_right.0,
+        ]);
+
+        Self {
+            rect: [0.0; 4],
+            geometry: [0.0; 4],
+            kind: 2,
+            stop_count: 4,
+            _pad: [0; 2],
+            offsets: [0.0; MAX_GRADIENT_STOPS],
+            colors,
+        }
+    }
 }
 
 /// Controls the visible area of the text. Any text outside of the visible area will be clipped.
@@ -106,6 +432,48 @@ impl Default for TextBounds {
     }
 }
 
+impl TextBounds {
+    /// Returns the overlapping area of `self` and `other`.
+    pub(crate) fn intersect(&self, other: &TextBounds) -> TextBounds {
+        TextBounds {
+            left: self.left.max(other.left),
+            top: self.top.max(other.top),
+            right: self.right.min(other.right),
+            bottom: self.bottom.min(other.bottom),
+        }
+    }
+
+    /// Returns `true` if the bounds don't contain any pixels.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.left >= self.right || self.top >= self.bottom
+    }
+}
+
+/// The radii of the corners of [`TextArea::bounds`] in physical pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CornerRadii {
+    /// The radius of the top-left corner.
+    pub top_left: f32,
+    /// The radius of the top-right corner.
+    pub top_right: f32,
+    /// The radius of the bottom-right corner.
+    pub bottom_right: f32,
+    /// The radius of the bottom-left corner.
+    pub bottom_left: f32,
+}
+
+impl CornerRadii {
+    /// The same radius for all corners.
+    pub fn uniform(radius: f32) -> Self {
+        Self {
+            top_left: radius,
+            top_right: radius,
+            bottom_right: radius,
+            bottom_left: radius,
+        }
+    }
+}
+
 /// A text area containing text to be rendered along with its overflow behavior.
 #[derive(Clone)]
 pub struct TextArea<'a> {
@@ -122,10 +490,513 @@ pub struct TextArea<'a> {
     pub bounds: TextBounds,
     /// The default color of the text area.
     pub default_color: Color,
+    /// The opacity of the text area, from `0.0` (invisible) to `1.0` (opaque), which multiplies
+    /// the alpha of everything drawn for it, including color glyphs.
+    pub opacity: f32,
     /// Additional custom glyphs to render.
     pub custom_glyphs: &'a [CustomGlyph],
 
     pub shadow: Option<TextShadow>,
+
+    /// The draw order of the text area. Text areas with a higher `z_order` are drawn on top of
+    /// text areas with a lower one, and text areas with the same `z_order` are drawn in the order
+    /// they were provided.
+    pub z_order: i32,
+
+    /// A caret to draw in the text area, if any.
+    pub caret: Option<TextCaret>,
+
+    /// Fills the glyphs of the text area with the pattern set with
+    /// [`TextRenderer::set_pattern`], mapped with the given [`PatternMapping`]. The pattern is
+    /// multiplied with the color of the glyphs.
+    pub pattern: Option<PatternMapping>,
+
+    /// A shadow drawn behind all glyphs of the text area, like the CSS `text-shadow` property.
+    pub drop_shadow: Option<DropShadow>,
+
+    /// An outline drawn around all glyphs of the text area, behind their fill.
+    pub outline: Option<TextOutline>,
+
+    /// An additive halo centered on all glyphs of the text area.
+    pub glow: Option<TextGlow>,
+
+    /// A filled rectangle drawn behind the laid-out content of the text area, if any.
+    pub background: Option<TextBackground>,
+
+    /// Fills the glyphs of the text area with a gradient instead of their color, if any.
+    pub gradient: Option<TextGradient<'a>>,
+
+    /// Colors the corners of each glyph of the text area, interpolated across the glyph. Ignored
+    /// if the text area has a `gradient`.
+    pub glyph_colors: Option<GlyphColors>,
+
+    /// A selection highlighted beneath the glyphs of the text area, if any.
+    pub selection: Option<TextSelection>,
+
+    /// Maps the metadata of each glyph to the decorations drawn along it, if any.
+    ///
+    /// `cosmic-text` doesn't carry decoration attributes, so spans are decorated by giving them
+    /// distinct [`Attrs::metadata`]. Consecutive glyphs of a line with the same decoration share a
+    /// single stroke, positioned and sized according to the metrics of their font.
+    pub decorations: Option<&'a dyn Fn(usize) -> TextDecoration>,
+
+    /// A soft shadow of any radius drawn beneath the text, blurred on the GPU. Unlike a
+    /// [`DropShadow`], it isn't limited by the padding around glyphs in the atlas, but it has to
+    /// be rendered with [`TextRenderer::render_shadows`].
+    pub shadow_style: Option<ShadowStyle>,
+
+    /// A 2D affine transform applied to everything drawn for the text area, around its `left` and
+    /// `top` origin, in physical pixels.
+    ///
+    /// The text is clipped to `bounds` before it is transformed, so the bounds are transformed
+    /// along with the text.
+    pub transform: Option<TextTransform>,
+
+    /// A column-major model-view-projection matrix that places the text area in a 3D scene,
+    /// replacing its screen-space placement. Takes precedence over `transform`.
+    ///
+    /// The matrix maps the physical positions of the text area, as given by `left`, `top` and
+    /// `scale` with the y axis pointing down and `z = 0`, to clip space. The resulting depth is
+    /// used for depth testing instead of `metadata_to_depth`. The text is clipped to `bounds`
+    /// before it is transformed, and isn't clipped by the scissor rect of
+    /// [`TextRenderer::render_with_scissor`]. The projections set with
+    /// [`TextRenderer::set_view_projections`] are applied after the matrix, e.g. to render the
+    /// text area for both eyes of a multiview render pass.
+    pub world_transform: Option<[[f32; 4]; 4]>,
+
+    /// Synthesizes bold text where a font has no bold face, by thickening glyphs by this many
+    /// logical pixels.
+    ///
+    /// This only applies to glyphs with a requested weight of [`Weight::SEMIBOLD`] or more whose
+    /// font face is lighter than that, such as bold text in a terminal whose font only has a
+    /// regular face. The thickening can't exceed the padding around glyphs in the atlas.
+    pub faux_bold: Option<f32>,
+
+    /// Thickens the glyphs of the text area by this many logical pixels, or thins them if
+    /// negative, such as `0.3` to match the heavier rendering of another platform. `0.0` leaves
+    /// glyphs unchanged.
+    ///
+    /// This dilates or erodes the coverage of mask glyphs, and moves the outline of glyphs
+    /// rendered from distance fields. It adds to [`TextArea::faux_bold`] and likewise can't
+    /// exceed the padding around glyphs in the atlas.
+    pub thickness: f32,
+
+    /// Slants the glyphs of the text area by this angle in radians around their baseline, such
+    /// as `0.2` for a synthetic italic when a font has no italic face.
+    ///
+    /// The glyphs are sheared when they are drawn, so they keep sharing their atlas entries with
+    /// upright text.
+    pub oblique: Option<f32>,
+
+    /// Whether the glyphs of the text area are rendered from signed distance fields instead of
+    /// bitmaps.
+    ///
+    /// A distance field is rasterized once at a fixed size and stays crisp at any size, scale or
+    /// transform, so this suits text that is animated in size, rotated or magnified. Small text
+    /// looks softer than with bitmaps, since distance fields aren't hinted. Color glyphs such as
+    /// emoji are scaled bitmaps.
+    pub sdf: bool,
+
+    /// Whether the glyphs of the text area are rendered from multi-channel signed distance
+    /// fields, which keep the corners of large or heavily scaled text sharp where
+    /// [`TextArea::sdf`] rounds them. The fields are stored in the color atlas and generated from
+    /// the outlines of the glyphs, which is slower than rasterizing a single-channel field.
+    ///
+    /// Requires the `msdf` feature, without which the glyphs are rendered as with
+    /// [`TextArea::sdf`].
+    pub msdf: bool,
+
+    /// Rounds the corners of `bounds`, so that text is clipped with antialiased edges inside of
+    /// rounded cards or buttons.
+    ///
+    /// Each radius is limited to half of the shorter side of the bounds. The rounded bounds of up
+    /// to 255 text areas are drawn at once; further text areas keep square corners.
+    pub corner_radii: Option<CornerRadii>,
+
+    /// The stencil reference value the text area is drawn with, for text to be clipped to a
+    /// mask that the caller drew into the stencil buffer, such as an oddly shaped scrollable
+    /// panel.
+    ///
+    /// This only applies if the [`TextRenderer`] was created with a depth-stencil state whose
+    /// stencil test is enabled, which sets the compare function and masks. The text areas are
+    /// then drawn one by one, each after setting its reference on the render pass.
+    pub stencil_reference: u32,
+
+    /// Lays the glyphs of each line of the text area along a path instead of a straight line.
+    ///
+    /// The distance of the center of a glyph from the left edge of the text area becomes its
+    /// distance along the path, and the glyph is rotated so that its baseline follows the
+    /// direction of the path there. Glyphs beyond either end of the path are hidden. The glyphs
+    /// are clipped to `bounds` before they are rotated, and backgrounds, selections, carets and
+    /// decorations aren't laid along the path.
+    pub path: Option<&'a TextPath>,
+
+    /// Called for each glyph of the text area during `prepare` to perturb its offset, color and
+    /// opacity, for effects like typewriter reveals, wavy text or shaking dialogue.
+    ///
+    /// Animate the effect by preparing the text area again each frame with a modifier that
+    /// depends on the time.
+    pub glyph_modifier: Option<&'a dyn Fn(GlyphIndex, &mut GlyphInstance)>,
+
+    /// Changes how the color glyphs of the text area are drawn, or `None` to draw them as they
+    /// are.
+    pub color_glyphs: Option<ColorGlyphStyle>,
+
+    /// How the outlines of the glyphs of the text area are rasterized, or `None` to rasterize
+    /// them like [`SwashCache`] does. Doesn't apply to glyphs drawn from distance fields.
+    pub rasterization: Option<GlyphRasterization>,
+
+    /// The depth the text area is drawn at, which is added to the depth returned by
+    /// `metadata_to_depth` and written into the Z coordinate of its vertices.
+    ///
+    /// With a [`TextRenderer`] created with a depth-stencil state, this interleaves the text
+    /// area with other depth-tested geometry, such as labels occluded by 3D objects, without
+    /// splitting it into several render passes. For reverse-Z depth buffers, see
+    /// [`TextRenderer::set_depth_convention`].
+    pub depth: f32,
+}
+
+impl<'a> TextArea<'a> {
+    /// Creates a text area that draws `buffer` at the origin in white, without bounds, scaling or
+    /// any effects.
+    pub fn new(buffer: &'a Buffer) -> Self {
+        Self {
+            buffer,
+            left: 0.0,
+            top: 0.0,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            default_color: Color::rgb(255, 255, 255),
+            opacity: 1.0,
+            custom_glyphs: &[],
+            shadow: None,
+            z_order: 0,
+            caret: None,
+            pattern: None,
+            drop_shadow: None,
+            outline: None,
+            glow: None,
+            background: None,
+            gradient: None,
+            glyph_colors: None,
+            selection: None,
+            decorations: None,
+            shadow_style: None,
+            transform: None,
+            world_transform: None,
+            faux_bold: None,
+            thickness: 0.0,
+            oblique: None,
+            sdf: false,
+            msdf: false,
+            corner_radii: None,
+            stencil_reference: 0,
+            path: None,
+            glyph_modifier: None,
+            color_glyphs: None,
+            rasterization: None,
+            depth: 0.0,
+        }
+    }
+
+    /// Returns a builder of a text area that draws `buffer`, starting from the defaults of
+    /// [`TextArea::new`].
+    pub fn builder(buffer: &'a Buffer) -> TextAreaBuilder<'a> {
+        TextAreaBuilder(Self::new(buffer))
+    }
+}
+
+/// A builder of a [`TextArea`], see [`TextArea::builder`].
+///
+/// The fields without a method of their own can be set on the built text area.
+#[derive(Clone)]
+pub struct TextAreaBuilder<'a>(TextArea<'a>);
+
+impl<'a> TextAreaBuilder<'a> {
+    /// Sets the left and top edges of the buffer.
+    pub fn position(mut self, left: f32, top: f32) -> Self {
+        self.0.left = left;
+        self.0.top = top;
+        self
+    }
+
+    /// Sets the visible bounds of the text area.
+    pub fn bounds(mut self, bounds: TextBounds) -> Self {
+        self.0.bounds = bounds;
+        self
+    }
+
+    /// Sets the scaling to apply to the buffer.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.0.scale = scale;
+        self
+    }
+
+    /// Sets the default color of the text area.
+    pub fn default_color(mut self, color: Color) -> Self {
+        self.0.default_color = color;
+        self
+    }
+
+    /// Sets the opacity of the text area.
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        self.0.opacity = opacity;
+        self
+    }
+
+    /// Sets the custom glyphs to render along with the buffer.
+    pub fn custom_glyphs(mut self, custom_glyphs: &'a [CustomGlyph]) -> Self {
+        self.0.custom_glyphs = custom_glyphs;
+        self
+    }
+
+    /// Sets the draw order of the text area.
+    pub fn z_order(mut self, z_order: i32) -> Self {
+        self.0.z_order = z_order;
+        self
+    }
+
+    /// Sets the transform of the text area in physical pixels.
+    pub fn transform(mut self, transform: TextTransform) -> Self {
+        self.0.transform = Some(transform);
+        self
+    }
+
+    /// Sets the depth the text area is drawn at.
+    pub fn depth(mut self, depth: f32) -> Self {
+        self.0.depth = depth;
+        self
+    }
+
+    /// Returns the text area.
+    pub fn build(self) -> TextArea<'a> {
+        self.0
+    }
+}
+
+/// A 2D affine transform `[a, b, c, d, e, f]`, which maps a point `(x, y)` to
+/// `(a * x + c * y + e, b * x + d * y + f)`.
+///
+/// A [`TextRenderer`] draws the transforms of up to 255 text areas at once; further text areas
+/// aren't transformed.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextTransform(pub [f32; 6]);
+
+impl TextTransform {
+    /// The transform that leaves points unchanged.
+    pub const IDENTITY: Self = Self([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    /// A translation by `x` and `y`.
+    pub fn translation(x: f32, y: f32) -> Self {
+        Self([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    /// A scale by `x` horizontally and `y` vertically.
+    pub fn scale(x: f32, y: f32) -> Self {
+        Self([x, 0.0, 0.0, y, 0.0, 0.0])
+    }
+
+    /// A clockwise rotation on screen by `angle` radians.
+    pub fn rotation(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self([cos, sin, -sin, cos, 0.0, 0.0])
+    }
+
+    /// A skew by `x` radians along the horizontal axis and `y` radians along the vertical one.
+    pub fn skew(x: f32, y: f32) -> Self {
+        Self([1.0, y.tan(), x.tan(), 1.0, 0.0, 0.0])
+    }
+
+    /// Returns the transform that applies `self`, followed by `other`.
+    pub fn then(self, other: Self) -> Self {
+        let [a, b, c, d, e, f] = self.0;
+        let [oa, ob, oc, od, oe, of] = other.0;
+
+        Self([
+            oa * a + oc * b,
+            ob * a + od * b,
+            oa * c + oc * d,
+            ob * c + od * d,
+            oa * e + oc * f + oe,
+            ob * e + od * f + of,
+        ])
+    }
+
+    /// Transforms a point.
+    pub fn transform_point(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let [a, b, c, d, e, f] = self.0;
+        [a * x + c * y + e, b * x + d * y + f]
+    }
+
+    /// Returns the bytes of the given transforms, to be written into a buffer of placements for
+    /// [`TextRenderer::render_placed`].
+    pub fn slice_as_bytes(transforms: &[Self]) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                transforms.as_ptr() as *const u8,
+                std::mem::size_of_val(transforms),
+            )
+        }
+    }
+}
+
+impl Default for TextTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// A path in physical pixels that the lines of a [`TextArea`] are laid along, such as a curved
+/// street label. Curves are flattened into line segments as they are added.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextPath {
+    points: Vec<[f32; 2]>,
+    /// The length of the path up to each point.
+    lengths: Vec<f32>,
+}
+
+impl TextPath {
+    /// A path starting at the given point.
+    pub fn new(start: [f32; 2]) -> Self {
+        Self {
+            points: vec![start],
+            lengths: vec![0.0],
+        }
+    }
+
+    /// A polyline through the given points, or `None` if there are none.
+    pub fn polyline(points: impl IntoIterator<Item = [f32; 2]>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let mut path = Self::new(points.next()?);
+        for point in points {
+            path.line_to(point);
+        }
+        Some(path)
+    }
+
+    /// Adds a straight segment to the given point.
+    pub fn line_to(&mut self, point: [f32; 2]) -> &mut Self {
+        let [x, y] = self.end();
+        let segment = (point[0] - x).hypot(point[1] - y);
+        // Segments without length have no direction
+        if segment > 0.0 {
+            self.points.push(point);
+            self.lengths.push(self.length() + segment);
+        }
+        self
+    }
+
+    /// Adds a quadratic Bézier curve with the given control point to the given point.
+    pub fn quadratic_to(&mut self, control: [f32; 2], point: [f32; 2]) -> &mut Self {
+        let start = self.end();
+        self.curve_to([start, control, point], |[p0, p1, p2], t| {
+            let u = 1.0 - t;
+            [0, 1].map(|i| u * u * p0[i] + 2.0 * u * t * p1[i] + t * t * p2[i])
+        })
+    }
+
+    /// Adds a cubic Bézier curve with the given control points to the given point.
+    pub fn cubic_to(
+        &mut self,
+        control1: [f32; 2],
+        control2: [f32; 2],
+        point: [f32; 2],
+    ) -> &mut Self {
+        let start = self.end();
+        self.curve_to([start, control1, control2, point], |[p0, p1, p2, p3], t| {
+            let u = 1.0 - t;
+            [0, 1].map(|i| {
+                u * u * u * p0[i]
+                    + 3.0 * u * u * t * p1[i]
+                    + 3.0 * u * t * t * p2[i]
+                    + t * t * t * p3[i]
+            })
+        })
+    }
+
+    /// Returns the length of the path.
+    pub fn length(&self) -> f32 {
+        self.lengths[self.lengths.len() - 1]
+    }
+
+    /// Returns the point at the given distance along the path and the unit direction of the path
+    /// there, or `None` if the distance is outside of the path.
+    pub fn point_at(&self, distance: f32) -> Option<([f32; 2], [f32; 2])> {
+        if !(0.0..=self.length()).contains(&distance) || self.points.len() < 2 {
+            return None;
+        }
+
+        let end = self
+            .lengths
+            .partition_point(|&length| length < distance)
+            .max(1);
+        let ([x0, y0], [x1, y1]) = (self.points[end - 1], self.points[end]);
+        let segment = self.lengths[end] - self.lengths[end - 1];
+
+        let t = (distance - self.lengths[end - 1]) / segment;
+        let direction = [(x1 - x0) / segment, (y1 - y0) / segment];
+        Some(([x0 + (x1 - x0) * t, y0 + (y1 - y0) * t], direction))
+    }
+
+    fn end(&self) -> [f32; 2] {
+        self.points[self.points.len() - 1]
+    }
+
+    /// Flattens a Bézier curve with the given control points into line segments about 4 pixels
+    /// long.
+    fn curve_to<const N: usize>(
+        &mut self,
+        points: [[f32; 2]; N],
+        evaluate: impl Fn([[f32; 2]; N], f32) -> [f32; 2],
+    ) -> &mut Self {
+        // The control polygon is at least as long as the curve
+        let length: f32 = points
+            .windows(2)
+            .map(|w| (w[1][0] - w[0][0]).hypot(w[1][1] - w[0][1]))
+            .sum();
+        let segments = (length / 4.0).ceil().clamp(1.0, 64.0) as u32;
+        for i in 1..=segments {
+            self.line_to(evaluate(points, i as f32 / segments as f32));
+        }
+        self
+    }
+}
+
+/// Identifies a glyph passed to a [`TextArea::glyph_modifier`] by its position in the text of the
+/// buffer, like a [`Cursor`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GlyphIndex {
+    /// The index of the buffer line of the glyph.
+    pub line: usize,
+    /// The byte offset of the start of the text of the glyph in its line.
+    pub start: usize,
+    /// The byte offset of the end of the text of the glyph in its line.
+    pub end: usize,
+}
+
+/// The properties of a glyph that a [`TextArea::glyph_modifier`] can change before it is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphInstance {
+    /// The offset of the glyph from its laid out position in physical pixels, rounded to whole
+    /// pixels. Starts at zero.
+    pub offset: [f32; 2],
+    /// The color of the glyph. Starts at the color of the glyph in the buffer, or the default
+    /// color of the text area.
+    pub color: Color,
+    /// The opacity of the glyph and its effects, multiplied with the opacity of the text area.
+    /// Starts at `1.0`. Glyphs with an opacity of zero aren't drawn.
+    pub opacity: f32,
+}
+
+/// How a pattern texture is mapped onto the glyphs of a [`TextArea`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PatternMapping {
+    /// The pattern is anchored to the screen and repeats every pattern texture size, in physical
+    /// pixels.
+    Screen,
+    /// The pattern is stretched over each glyph.
+    Glyph,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -133,3 +1004,292 @@ pub struct TextShadow {
     pub shadow_intensity: f32,
     pub shadow_radius: f32,
 }
+
+/// A shadow drawn behind the glyphs of a [`TextArea`], offset and blurred.
+///
+/// The shadow is rendered from the same atlas glyphs as the text, so it doesn't need to be
+/// prepared separately.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DropShadow {
+    /// The offset of the shadow from the glyphs in logical pixels.
+    pub offset: [f32; 2],
+    /// The color of the shadow.
+    pub color: Color,
+    /// The blur radius of the shadow in logical pixels, or `0.0` for a hard shadow.
+    ///
+    /// The blur can't extend beyond the [`AtlasConfig::padding`] around each glyph.
+    pub softness: f32,
+}
+
+/// An outline of the glyphs of a [`TextArea`], drawn behind their fill.
+///
+/// The outline dilates the same atlas glyphs as the text, so it doesn't need to be prepared
+/// separately.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextOutline {
+    /// The width of the outline around the glyphs in logical pixels.
+    ///
+    /// The outline can't extend beyond the [`AtlasConfig::padding`] around each glyph, and widths
+    /// beyond 8 physical pixels are clamped.
+    pub width: f32,
+    /// The color of the outline.
+    pub color: Color,
+}
+
+/// An additive halo around the glyphs of a [`TextArea`].
+///
+/// Unlike a [`DropShadow`], the halo is centered on the glyphs and brightens whatever lies beneath
+/// it. The glows of all text areas of a [`TextRenderer`] are drawn beneath all of its text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextGlow {
+    /// The color of the glow.
+    pub color: Color,
+    /// The blur radius of the glow in logical pixels.
+    ///
+    /// The glow can't extend beyond the [`AtlasConfig::padding`] around each glyph, and radii
+    /// beyond 8 physical pixels are clamped.
+    pub radius: f32,
+    /// The factor the glow is brightened by, where `1.0` adds the color at the full coverage of
+    /// the glyphs.
+    pub intensity: f32,
+}
+
+/// A shadow drawn beneath the glyphs of a [`TextArea`], rendered offscreen and blurred with a
+/// Gaussian of the given radius.
+///
+/// The shadows of all text areas of a [`TextRenderer`] are composited beneath all of its text,
+/// regardless of their `z_order`. The offset glyphs are clipped to the bounds of their text
+/// area before they are blurred, so the blur may extend beyond the bounds by up to its radius.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowStyle {
+    /// The blur radius of the shadow in logical pixels, or `0.0` for a hard shadow. Radii beyond
+    /// 64 physical pixels are clamped.
+    pub radius: f32,
+    /// The color of the shadow.
+    pub color: Color,
+    /// The offset of the shadow from the glyphs in logical pixels.
+    pub offset: [f32; 2],
+}
+
+/// A filled rectangle drawn behind the content of a [`TextArea`].
+///
+/// The rectangle spans all laid-out lines of the buffer, from the leftmost to the rightmost
+/// glyph, and is clipped along with the text of the area. It is drawn in the same pipeline as the
+/// glyphs, beneath all other instances of the area.
+#[derive(Clone, Copy, Debug)]
+pub struct TextBackground {
+    /// The color of the background.
+    pub color: Color,
+    /// Additional metadata about the background, passed to `metadata_to_depth`.
+    pub metadata: usize,
+    /// Whether the glyphs of the text area are cut out of the background instead of being drawn
+    /// over it, so that whatever lies behind the background shows through them, as in cutout
+    /// headers.
+    ///
+    /// The glyphs keep their effects, such as outlines. Knockout backgrounds are drawn into an
+    /// offscreen target by [`TextRenderer::render_knockouts`], which must be called before the
+    /// render pass that the text is rendered in, and are composited beneath all text of the
+    /// renderer by `render`.
+    pub knockout: bool,
+}
+
+/// A gradient filling the glyphs of a [`TextArea`], evaluated across the box spanning its
+/// laid-out content.
+///
+/// The gradient replaces the color of mask glyphs, while color glyphs such as emoji keep their
+/// colors. A [`TextRenderer`] draws the gradients of up to 63 text areas at once; the glyphs of
+/// further text areas keep their color.
+#[derive(Clone, Copy, Debug)]
+pub struct TextGradient<'a> {
+    /// The shape of the gradient.
+    pub kind: GradientKind,
+    /// The colors of the gradient, sorted by offset. At most 8 stops are used.
+    pub stops: &'a [GradientStop],
+}
+
+/// The shape of a [`TextGradient`]. Positions are given relative to the content box of the text
+/// area, where `[0.0, 0.0]` is its top-left and `[1.0, 1.0]` its bottom-right corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GradientKind {
+    /// Blends the stops along the line from `start` to `end`, at offsets `0.0` and `1.0`.
+    Linear {
+        /// The position of offset `0.0`.
+        start: [f32; 2],
+        /// The position of offset `1.0`.
+        end: [f32; 2],
+    },
+    /// Blends the stops outwards from `center`, reaching offset `1.0` at the ellipse with the
+    /// given horizontal and vertical `radius`.
+    Radial {
+        /// The position of offset `0.0`.
+        center: [f32; 2],
+        /// The horizontal and vertical radius of offset `1.0`.
+        radius: [f32; 2],
+    },
+}
+
+/// A color of a [`TextGradient`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GradientStop {
+    /// The offset of the stop along the gradient, usually in `0.0..=1.0`.
+    pub offset: f32,
+    /// The color at the offset.
+    pub color: Color,
+}
+
+/// The colors of the corners of each glyph of a [`TextArea`], which replace the color of mask
+/// glyphs and are interpolated across each glyph quad.
+///
+/// This is a cheap way to add vertical shine or fade effects to text without a pattern or a
+/// gradient over the whole text area.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphColors {
+    /// The color of the top-left corner.
+    pub top_left: Color,
+    /// The color of the top-right corner.
+    pub top_right: Color,
+    /// The color of the bottom-left corner.
+    pub bottom_left: Color,
+    /// The color of the bottom-right corner.
+    pub bottom_right: Color,
+}
+
+impl GlyphColors {
+    /// Colors the top and bottom of each glyph, e.g. for a vertical fade.
+    pub fn vertical(top: Color, bottom: Color) -> Self {
+        Self {
+            top_left: top,
+            top_right: top,
+            bottom_left: bottom,
+            bottom_right: bottom,
+        }
+    }
+}
+
+/// A range of text in a [`TextArea`] highlighted with a solid color beneath the glyphs.
+///
+/// A rectangle spanning the height of the line is drawn for each laid-out run the selection
+/// covers, so a selection across wrapped or multiple lines is highlighted line by line. The
+/// cursors can be given in either order.
+#[derive(Clone, Copy, Debug)]
+pub struct TextSelection {
+    /// The start of the selection.
+    pub start: Cursor,
+    /// The end of the selection.
+    pub end: Cursor,
+    /// The color of the highlight.
+    pub color: Color,
+    /// Additional metadata about the selection, passed to `metadata_to_depth`.
+    pub metadata: usize,
+}
+
+/// The decorations of a span of text in a [`TextArea`], see [`TextArea::decorations`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextDecoration {
+    /// Draws a line below the baseline.
+    pub underline: bool,
+    /// The style of the underline.
+    pub underline_style: UnderlineStyle,
+    /// Draws a line through the glyphs.
+    pub strikethrough: bool,
+    /// The color of the lines, like `text-decoration-color` in CSS, or `None` to use the color of
+    /// the glyphs they decorate.
+    pub color: Option<Color>,
+}
+
+/// The style of an underline, drawn procedurally in the fragment shader.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum UnderlineStyle {
+    /// A straight line.
+    #[default]
+    Solid = 0,
+    /// A wavy line, e.g. for spell-check squiggles.
+    Wavy = 1,
+    /// A dashed line.
+    Dashed = 2,
+    /// A dotted line.
+    Dotted = 3,
+}
+
+/// How the color glyphs of a [`TextArea`], such as emoji, are drawn, e.g. to make them match a
+/// disabled or ghosted UI state.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorGlyphStyle {
+    /// Multiplies the colors of the glyphs by the given color.
+    Tint(Color),
+    /// Draws the glyphs in shades of gray.
+    Grayscale,
+    /// Draws only the shape of the glyphs, filled with their text color like a mask glyph.
+    Silhouette,
+}
+
+/// Options for rasterizing the outlines of the glyphs of a [`TextArea`], see
+/// [`TextArea::rasterization`].
+///
+/// Glyphs rasterized with different options are cached separately.
+#[derive(Clone, Copy, Debug)]
+pub struct GlyphRasterization {
+    /// Whether the outlines are hinted, which aligns their stems and heights to the pixel grid.
+    pub hinting: bool,
+    /// The amount in physical pixels by which the outlines are emboldened at sizes up to 12
+    /// pixels, fading out towards 36 pixels. This keeps small text from looking faint on low-DPI
+    /// displays, like stem darkening in FreeType. `0.0` disables it.
+    pub stem_darkening: f32,
+}
+
+impl GlyphRasterization {
+    /// The amount by which the outlines of glyphs of the given size are emboldened.
+    pub(crate) fn darkening(&self, font_size: f32) -> f32 {
+        let fade = ((36.0 - font_size) / (36.0 - 12.0)).clamp(0.0, 1.0);
+        self.stem_darkening.max(0.0) * fade
+    }
+}
+
+impl Default for GlyphRasterization {
+    fn default() -> Self {
+        Self {
+            hinting: true,
+            stem_darkening: 0.0,
+        }
+    }
+}
+
+impl PartialEq for GlyphRasterization {
+    fn eq(&self, other: &Self) -> bool {
+        self.hinting == other.hinting
+            && self.stem_darkening.to_bits() == other.stem_darkening.to_bits()
+    }
+}
+
+impl Eq for GlyphRasterization {}
+
+impl Hash for GlyphRasterization {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hinting.hash(state);
+        self.stem_darkening.to_bits().hash(state);
+    }
+}
+
+/// A caret drawn at a cursor position in a [`TextArea`].
+///
+/// The caret spans the height of the line containing the cursor and is positioned, scaled and
+/// clipped along with the text of the area. To place a caret at a point instead, convert the
+/// point to a cursor with [`Buffer::hit`].
+#[derive(Clone, Copy, Debug)]
+pub struct TextCaret {
+    /// The cursor to draw the caret at.
+    pub cursor: Cursor,
+    /// The width of the caret in logical pixels.
+    pub width: f32,
+    /// The color of the caret.
+    pub color: Color,
+    /// The phase of the blink cycle of the caret, where `0.0..0.5` shows the caret and
+    /// `0.5..1.0` hides it, repeating every `1.0`. `None` always shows the caret.
+    ///
+    /// Advance the phase by the elapsed time divided by the blink period, and reset it to `0.0`
+    /// whenever the caret moves so that it is shown right away.
+    pub blink_phase: Option<f32>,
+    /// Additional metadata about the caret, passed to `metadata_to_depth`.
+    pub metadata: usize,
+}
@@ -0,0 +1,27 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+// Covers the target mip level with a single triangle
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_idx: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_idx << 1u) & 2u), f32(vertex_idx & 2u));
+
+    var vert_output: VertexOutput;
+    vert_output.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    vert_output.uv = uv;
+    return vert_output;
+}
+
+// Averages the 2x2 texels of the previous mip level that cover each texel of the target level
+@fragment
+fn fs_main(in_frag: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(source_texture, source_sampler, in_frag.uv, 0.0);
+}
@@ -0,0 +1,212 @@
+//! A simple binary format for saving the glyph cache of a [`TextAtlas`](crate::TextAtlas) and
+//! restoring it later, so previously seen glyphs don't have to be rasterized again.
+//!
+//! All values are stored in little-endian byte order. The data starts with the magic bytes
+//! `GLYC`, a `u32` version and a `u32` glyph count, followed by the glyphs. Each glyph is stored
+//! as:
+//!
+//! - the content type as a `u8` (0 for color, 1 for mask, 2 for glyphs without pixels, 3 for
+//!   subpixel masks)
+//! - the PostScript name of the font as a `u16` length followed by UTF-8 bytes
+//! - the face index of the font as a `u32`
+//! - the rest of the cache key: the glyph id (`u16`), font size bits (`u32`), x and y subpixel
+//!   bins (`u8` each) and flags (`u32`)
+//! - the placement: width and height (`u16` each), top and left (`i16` each)
+//! - the pixels, `width * height * channels` bytes
+//!
+//! Fonts are identified by name because font ids are only valid within the `FontSystem` that
+//! assigned them.
+
+use crate::{fontdb, CacheKey, SubpixelBin};
+use cosmic_text::CacheKeyFlags;
+
+const MAGIC: &[u8; 4] = b"GLYC";
+const VERSION: u32 = 3;
+
+const CONTENT_TYPE_COLOR: u8 = 0;
+const CONTENT_TYPE_MASK: u8 = 1;
+const CONTENT_TYPE_EMPTY: u8 = 2;
+const CONTENT_TYPE_SUBPIXEL_MASK: u8 = 3;
+
+/// The content of a persisted glyph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PersistedContent {
+    Color,
+    Mask,
+    /// A glyph without any pixels, e.g. a space.
+    Empty,
+    SubpixelMask,
+}
+
+impl PersistedContent {
+    fn num_channels(self) -> usize {
+        match self {
+            PersistedContent::Color | PersistedContent::SubpixelMask => 4,
+            PersistedContent::Mask => 1,
+            PersistedContent::Empty => 0,
+        }
+    }
+}
+
+/// A glyph of the glyph cache, with its font identified independently of the `FontSystem` it was
+/// loaded into.
+#[derive(Debug, Clone)]
+pub(crate) struct PersistedGlyph {
+    pub content: PersistedContent,
+    pub post_script_name: String,
+    pub face_index: u32,
+    pub cache_key: CacheKey,
+    pub width: u16,
+    pub height: u16,
+    pub top: i16,
+    pub left: i16,
+    pub data: Vec<u8>,
+}
+
+pub(crate) fn encode(glyphs: &[PersistedGlyph]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(glyphs.len() as u32).to_le_bytes());
+
+    for glyph in glyphs {
+        let key = &glyph.cache_key;
+        out.push(match glyph.content {
+            PersistedContent::Color => CONTENT_TYPE_COLOR,
+            PersistedContent::Mask => CONTENT_TYPE_MASK,
+            PersistedContent::Empty => CONTENT_TYPE_EMPTY,
+            PersistedContent::SubpixelMask => CONTENT_TYPE_SUBPIXEL_MASK,
+        });
+        out.extend_from_slice(&(glyph.post_script_name.len() as u16).to_le_bytes());
+        out.extend_from_slice(glyph.post_script_name.as_bytes());
+        out.extend_from_slice(&glyph.face_index.to_le_bytes());
+        out.extend_from_slice(&key.glyph_id.to_le_bytes());
+        out.extend_from_slice(&key.font_size_bits.to_le_bytes());
+        out.push(encode_bin(key.x_bin));
+        out.push(encode_bin(key.y_bin));
+        out.extend_from_slice(&key.flags.bits().to_le_bytes());
+        out.extend_from_slice(&glyph.width.to_le_bytes());
+        out.extend_from_slice(&glyph.height.to_le_bytes());
+        out.extend_from_slice(&glyph.top.to_le_bytes());
+        out.extend_from_slice(&glyph.left.to_le_bytes());
+        out.extend_from_slice(&glyph.data);
+    }
+
+    out
+}
+
+/// Decodes persisted glyphs, resolving their fonts with `resolve_font`. Glyphs of fonts that
+/// can't be resolved are skipped.
+///
+/// Returns `None` if the data is malformed or was written by an incompatible version.
+pub(crate) fn decode(
+    data: &[u8],
+    mut resolve_font: impl FnMut(&str, u32) -> Option<fontdb::ID>,
+) -> Option<Vec<PersistedGlyph>> {
+    let mut reader = Reader { data };
+
+    if reader.bytes(MAGIC.len())? != MAGIC || reader.u32()? != VERSION {
+        return None;
+    }
+
+    let count = reader.u32()?;
+    let mut glyphs = Vec::new();
+
+    for _ in 0..count {
+        let content = match reader.u8()? {
+            CONTENT_TYPE_COLOR => PersistedContent::Color,
+            CONTENT_TYPE_MASK => PersistedContent::Mask,
+            CONTENT_TYPE_EMPTY => PersistedContent::Empty,
+            CONTENT_TYPE_SUBPIXEL_MASK => PersistedContent::SubpixelMask,
+            _ => return None,
+        };
+        let name_len = reader.u16()? as usize;
+        let post_script_name = std::str::from_utf8(reader.bytes(name_len)?)
+            .ok()?
+            .to_owned();
+        let face_index = reader.u32()?;
+        let glyph_id = reader.u16()?;
+        let font_size_bits = reader.u32()?;
+        let x_bin = decode_bin(reader.u8()?)?;
+        let y_bin = decode_bin(reader.u8()?)?;
+        let flags = CacheKeyFlags::from_bits_truncate(reader.u32()?);
+        let width = reader.u16()?;
+        let height = reader.u16()?;
+        let top = reader.u16()? as i16;
+        let left = reader.u16()? as i16;
+        let len = width as usize * height as usize * content.num_channels();
+        let data = reader.bytes(len)?;
+
+        let Some(font_id) = resolve_font(&post_script_name, face_index) else {
+            continue;
+        };
+        let cache_key = CacheKey {
+            font_id,
+            glyph_id,
+            font_size_bits,
+            x_bin,
+            y_bin,
+            flags,
+        };
+
+        glyphs.push(PersistedGlyph {
+            content,
+            post_script_name,
+            face_index,
+            cache_key,
+            width,
+            height,
+            top,
+            left,
+            data: data.to_vec(),
+        });
+    }
+
+    Some(glyphs)
+}
+
+fn encode_bin(bin: SubpixelBin) -> u8 {
+    match bin {
+        SubpixelBin::Zero => 0,
+        SubpixelBin::One => 1,
+        SubpixelBin::Two => 2,
+        SubpixelBin::Three => 3,
+    }
+}
+
+fn decode_bin(bin: u8) -> Option<SubpixelBin> {
+    match bin {
+        0 => Some(SubpixelBin::Zero),
+        1 => Some(SubpixelBin::One),
+        2 => Some(SubpixelBin::Two),
+        3 => Some(SubpixelBin::Three),
+        _ => None,
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+}
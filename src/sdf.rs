@@ -0,0 +1,202 @@
+//! Signed distance fields of glyphs, so that one rasterization of a glyph can be rendered crisply
+//! at any size.
+//!
+//! Distances are computed with the exact euclidean distance transform of Felzenszwalb and
+//! Huttenlocher on the thresholded coverage of a glyph. With the `msdf` feature, multi-channel
+//! distance fields that keep corners sharp are generated from the outline of a glyph with `fdsm`.
+
+use crate::{CacheKey, FontSystem};
+use swash::zeno::Placement;
+
+/// The font size in physical pixels that distance fields are rasterized at.
+pub(crate) const SDF_FONT_SIZE: f32 = 48.0;
+
+/// The distance from the outline of a glyph, in texels, at which its distance field saturates.
+/// The field is stored with this many texels of margin around the glyph. Must match
+/// `SDF_SPREAD` in the shader.
+pub(crate) const SDF_SPREAD: usize = 6;
+
+/// Returns the placement of the distance field of a glyph whose mask has the given placement.
+pub(crate) fn padded_placement(placement: Placement) -> Placement {
+    let spread = SDF_SPREAD as u32;
+    Placement {
+        left: placement.left - spread as i32,
+        top: placement.top + spread as i32,
+        width: placement.width + 2 * spread,
+        height: placement.height + 2 * spread,
+    }
+}
+
+/// Generates the multi-channel signed distance field of the outline of a glyph with the given
+/// [`padded_placement`], encoded like [`signed_distance_field`]. The alpha channel holds the
+/// median of the color channels, so that effects can sample it like a single-channel field.
+#[cfg(feature = "msdf")]
+pub(crate) fn multi_channel_distance_field(
+    font_system: &mut FontSystem,
+    cache_key: CacheKey,
+    placement: Placement,
+) -> Option<Vec<u8>> {
+    use fdsm::{
+        bezier::scanline::FillRule, generate::generate_msdf, render::correct_sign_msdf,
+        shape::Shape, transform::Transform,
+    };
+    use image::RgbImage;
+    use nalgebra::{Affine2, Matrix3};
+
+    let index = font_system.db().face(cache_key.font_id)?.index;
+    let font = font_system.get_font(cache_key.font_id)?;
+    let face = ttf_parser::Face::parse(font.data(), index).ok()?;
+    let scale =
+        f64::from(f32::from_bits(cache_key.font_size_bits)) / f64::from(face.units_per_em());
+
+    // Font units point up from the baseline, while rows go down from the top of the placement
+    let mut shape = Shape::load_from_face(&face, ttf_parser::GlyphId(cache_key.glyph_id));
+    shape.transform(&Affine2::from_matrix_unchecked(Matrix3::new(
+        scale,
+        0.0,
+        -f64::from(placement.left),
+        0.0,
+        -scale,
+        f64::from(placement.top),
+        0.0,
+        0.0,
+        1.0,
+    )));
+    let shape = Shape::edge_coloring_simple(shape, 0.03, 0).prepare();
+
+    let mut image = RgbImage::new(placement.width, placement.height);
+    generate_msdf(&shape, 2.0 * SDF_SPREAD as f64, &mut image);
+    correct_sign_msdf(&mut image, &shape, FillRule::Nonzero);
+
+    Some(
+        image
+            .pixels()
+            .flat_map(|pixel| {
+                let [r, g, b] = pixel.0;
+                [r, g, b, r.min(g).max(r.max(g).min(b))]
+            })
+            .collect(),
+    )
+}
+
+/// Multi-channel distance fields can only be generated with the `msdf` feature.
+#[cfg(not(feature = "msdf"))]
+pub(crate) fn multi_channel_distance_field(
+    _font_system: &mut FontSystem,
+    _cache_key: CacheKey,
+    _placement: Placement,
+) -> Option<Vec<u8>> {
+    None
+}
+
+/// Converts the coverage of a mask glyph into a signed distance field that is larger by
+/// [`SDF_SPREAD`] on each side. Each texel stores `0.5` on the outline of the glyph, increasing
+/// towards its inside by `0.5` per [`SDF_SPREAD`] texels.
+pub(crate) fn signed_distance_field(coverage: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let padded_width = width + 2 * SDF_SPREAD;
+    let padded_height = height + 2 * SDF_SPREAD;
+
+    let mut inside = vec![false; padded_width * padded_height];
+    for (y, row) in coverage.chunks_exact(width).take(height).enumerate() {
+        for (x, &alpha) in row.iter().enumerate() {
+            inside[(y + SDF_SPREAD) * padded_width + x + SDF_SPREAD] = alpha >= 128;
+        }
+    }
+
+    // The squared distances to the closest texel outside and inside of the glyph
+    let to_outside = squared_distances(&inside, padded_width, padded_height, false);
+    let to_inside = squared_distances(&inside, padded_width, padded_height, true);
+
+    inside
+        .iter()
+        .zip(to_outside.iter().zip(&to_inside))
+        .map(|(&inside, (&to_outside, &to_inside))| {
+            // The outline lies halfway between the centers of texels inside and outside
+            let distance = if inside {
+                to_outside.sqrt() - 0.5
+            } else {
+                0.5 - to_inside.sqrt()
+            };
+            let value = 0.5 + distance / (2.0 * SDF_SPREAD as f32);
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+/// Returns the squared distance of each texel to the closest texel whose `inside` value equals
+/// `target`.
+fn squared_distances(inside: &[bool], width: usize, height: usize, target: bool) -> Vec<f32> {
+    let mut distances: Vec<f32> = inside
+        .iter()
+        .map(|&inside| if inside == target { 0.0 } else { f32::INFINITY })
+        .collect();
+
+    let len = width.max(height);
+    let mut line = vec![0.0; len];
+    let mut output = vec![0.0; len];
+    let mut vertices = vec![0; len];
+    let mut boundaries = vec![0.0; len + 1];
+
+    for x in 0..width {
+        for y in 0..height {
+            line[y] = distances[y * width + x];
+        }
+        transform_line(&line[..height], &mut output, &mut vertices, &mut boundaries);
+        for y in 0..height {
+            distances[y * width + x] = output[y];
+        }
+    }
+
+    for row in distances.chunks_exact_mut(width) {
+        line[..width].copy_from_slice(row);
+        transform_line(&line[..width], &mut output, &mut vertices, &mut boundaries);
+        row.copy_from_slice(&output[..width]);
+    }
+
+    distances
+}
+
+/// The one-dimensional squared distance transform of `f`, computed as the lower envelope of the
+/// parabolas rooted at each sample.
+fn transform_line(f: &[f32], output: &mut [f32], vertices: &mut [usize], boundaries: &mut [f32]) {
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2.0 * (q as f32 - p as f32))
+    };
+
+    // Samples at infinity have no parabola
+    let Some(first) = f.iter().position(|value| value.is_finite()) else {
+        output[..f.len()].fill(f32::INFINITY);
+        return;
+    };
+
+    let mut k = 0;
+    vertices[0] = first;
+    boundaries[0] = f32::NEG_INFINITY;
+    boundaries[1] = f32::INFINITY;
+
+    for (q, value) in f.iter().enumerate().skip(first + 1) {
+        if !value.is_finite() {
+            continue;
+        }
+
+        let mut s = intersection(q, vertices[k]);
+        while s <= boundaries[k] {
+            k -= 1;
+            s = intersection(q, vertices[k]);
+        }
+
+        k += 1;
+        vertices[k] = q;
+        boundaries[k] = s;
+        boundaries[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+    for (q, value) in output[..f.len()].iter_mut().enumerate() {
+        while boundaries[k + 1] < q as f32 {
+            k += 1;
+        }
+        let offset = q as f32 - vertices[k] as f32;
+        *value = offset * offset + f[vertices[k]];
+    }
+}
@@ -8,6 +8,14 @@ struct VertexInput {
     @location(5) depth: f32,
     @location(6) shadow_radius: f32,
     @location(7) shadow_intensity: f32,
+    @location(8) flags: u32,
+    @location(9) opacity: f32,
+    @location(10) transform: u32,
+    @location(11) embolden: f32,
+    @location(12) oblique: vec2<f32>,
+    @location(13) texel_scale: f32,
+    // The cosine and sine of the rotation angle, followed by the position rotated around
+    @location(14) rotation: vec4<f32>,
 }
 
 struct VertexOutput {
@@ -17,24 +25,194 @@ struct VertexOutput {
     @location(2) @interpolate(flat) content_type: u32,
     @location(3) shadow_radius: f32,
     @location(4) shadow_intensity: f32, 
+    @location(5) pattern_uv: vec2<f32>,
+    @location(6) @interpolate(flat) flags: u32,
+    @location(7) @interpolate(flat) color_conversion: u32,
+    @location(8) @interpolate(flat) opacity: f32,
+    // The position in physical pixels before the transform of the text area
+    @location(9) local_position: vec2<f32>,
+    @location(10) @interpolate(flat) embolden: f32,
+    // The index of the rounded clip of the text area plus one, or zero if it has none
+    @location(11) @interpolate(flat) clip: u32,
+    // The position within the quad of the instance, from zero at its top left to one at its
+    // bottom right corner
+    @location(12) quad_uv: vec2<f32>,
 };
 
 struct Params {
     screen_resolution: vec2<u32>,
+    edge_feather: f32,
+    gamma: f32,
+    contrast: f32,
+    zoom: f32,
+    offset: vec2<f32>,
+};
+
+// `linear = (a * encoded + b) ^ g` if `encoded >= d`, and `linear = c * encoded` otherwise
+struct TransferParams {
+    g: f32,
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+};
+
+// A linear (`kind == 0`) or radial (`kind == 1`) gradient across `rect`, in physical pixels.
+// `geometry` holds the start and end points of a linear gradient, or the center and radii of a
+// radial one, relative to `rect`. Glyph colors (`kind == 2`) store the colors of the top-left,
+// top-right, bottom-left and bottom-right corners of each glyph in `colors[0]`.
+struct Gradient {
+    rect: vec4<f32>,
+    geometry: vec4<f32>,
+    kind: u32,
+    stop_count: u32,
+    _pad0: u32,
+    _pad1: u32,
+    offsets: array<vec4<f32>, 2>,
+    colors: array<vec4<u32>, 2>,
+};
+
+// Maps the physical position and depth of a vertex to physical pixels, or to clip space if
+// `WORLD_TRANSFORM_BIT` is set
+struct Transform {
+    matrix: mat4x4<f32>,
+};
+
+struct Transforms {
+    transforms: array<Transform, 255>,
+};
+
+// The left, top, right and bottom edges of the bounds of a text area in physical pixels before its
+// transform, and the radii of its top-left, top-right, bottom-right and bottom-left corners
+struct Clip {
+    rect: vec4<f32>,
+    radii: vec4<f32>,
+};
+
+struct Clips {
+    clips: array<Clip, 255>,
+};
+
+// The projections applied to world-transformed vertices in each view of a multiview render pass,
+// the factor the brightness of the text is scaled by, whether depths are mapped for a reverse-Z
+// depth buffer and the debug visualizations to draw
+struct RendererParams {
+    projections: array<mat4x4<f32>, 4>,
+    brightness: f32,
+    reverse_z: u32,
+    debug: u32,
+};
+
+struct Gradients {
+    gradients: array<Gradient, 63>,
 };
 
 @group(0) @binding(0)
-var color_atlas_texture: texture_2d<f32>;
+var color_atlas_texture: texture_2d_array<f32>;
 
 @group(0) @binding(1)
-var mask_atlas_texture: texture_2d<f32>;
+var mask_atlas_texture: texture_2d_array<f32>;
 
 @group(0) @binding(2)
 var atlas_sampler: sampler;
 
+@group(0) @binding(3)
+var atlas_mipmap_sampler: sampler;
+
+@group(0) @binding(4)
+var<uniform> transfer_params: TransferParams;
+
+@group(0) @binding(5)
+var prebaked_color_texture: texture_2d_array<f32>;
+
+@group(0) @binding(6)
+var prebaked_mask_texture: texture_2d_array<f32>;
+
+@group(0) @binding(7)
+var sdf_atlas_texture: texture_2d_array<f32>;
+
 @group(1) @binding(0)
 var<uniform> params: Params;
 
+@group(2) @binding(0)
+var pattern_texture: texture_2d<f32>;
+
+@group(2) @binding(1)
+var pattern_sampler: sampler;
+
+@group(2) @binding(2)
+var<uniform> gradients: Gradients;
+
+@group(2) @binding(3)
+var<uniform> transforms: Transforms;
+
+@group(2) @binding(4)
+var<uniform> clips: Clips;
+
+@group(2) @binding(5)
+var<uniform> renderer: RendererParams;
+
+// An instance in the layout of the vertex buffer, read from a storage buffer by `vs_pulled`. The
+// slant is split into scalars, as a `vec2<f32>` would be aligned differently.
+struct Instance {
+    pos: vec2<i32>,
+    dim: u32,
+    uv: u32,
+    color: u32,
+    content_type_with_srgb: u32,
+    depth: f32,
+    shadow_radius: f32,
+    shadow_intensity: f32,
+    flags: u32,
+    opacity: f32,
+    transform: u32,
+    embolden: f32,
+    oblique_x: f32,
+    oblique_y: f32,
+    texel_scale: f32,
+    rotation: vec4<f32>,
+}
+
+@group(3) @binding(0)
+var<storage, read> instances: array<Instance>;
+
+// The transform applied after the transform of the text area, set by `vs_placed`
+var<private> placement: mat3x2<f32> = mat3x2<f32>(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+
+const PATTERN_SCREEN_FLAG: u32 = 1u;
+const PATTERN_GLYPH_FLAG: u32 = 2u;
+const PATTERN_FLAGS: u32 = 3u;
+const MIPMAP_FLAG: u32 = 4u;
+const MERGED_FLAG: u32 = 8u;
+const PREBAKED_FLAG: u32 = 16u;
+const DROP_SHADOW_FLAG: u32 = 32u;
+const OUTLINE_FLAG: u32 = 64u;
+const GLOW_FLAG: u32 = 128u;
+const MAX_BLUR_RADIUS: f32 = 8.0;
+const DECORATION_STYLE_SHIFT: u32 = 8u;
+const DECORATION_STYLE_MASK: u32 = 3u;
+const COLOR_GLYPH_STYLE_SHIFT: u32 = 8u;
+const COLOR_GLYPH_STYLE_MASK: u32 = 3u;
+const GRADIENT_SHIFT: u32 = 10u;
+const GRADIENT_MASK: u32 = 63u;
+const GLYPH_COLORS_KIND: u32 = 2u;
+const LAYER_SHIFT: u32 = 16u;
+const WORLD_TRANSFORM_BIT: u32 = 0x80000000u;
+const TRANSFORM_MASK: u32 = 0xffu;
+const CLIP_SHIFT: u32 = 8u;
+const CLIP_MASK: u32 = 0xffu;
+const SOLID_QUAD_CONTENT_TYPE: u32 = 2u;
+const SUBPIXEL_MASK_CONTENT_TYPE: u32 = 3u;
+const SDF_CONTENT_TYPE: u32 = 4u;
+const MSDF_CONTENT_TYPE: u32 = 5u;
+// The distance in texels at which distance fields saturate, must match `SDF_SPREAD` in sdf.rs
+const SDF_SPREAD: f32 = 6.0;
+const DEBUG_QUAD_OUTLINES: u32 = 1u;
+const DEBUG_OVERDRAW: u32 = 2u;
+
 fn srgb_to_linear(c: f32) -> f32 {
     if c <= 0.04045 {
         return c / 12.92;
@@ -43,13 +221,367 @@ fn srgb_to_linear(c: f32) -> f32 {
     }
 }
 
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        return c * 12.92;
+    } else {
+        return 1.055 * pow(c, 1.0 / 2.4) - 0.055;
+    }
+}
+
+fn decode_custom(c: f32) -> f32 {
+    if c >= transfer_params.d {
+        return pow(max(transfer_params.a * c + transfer_params.b, 0.0), transfer_params.g);
+    } else {
+        return transfer_params.c * c;
+    }
+}
+
+fn decode_custom_rgb(rgb: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(decode_custom(rgb.r), decode_custom(rgb.g), decode_custom(rgb.b));
+}
+
+// The textures the glyph of an instance is stored in depend on its flags
+fn color_dimensions(flags: u32) -> vec2<u32> {
+    if (flags & PREBAKED_FLAG) != 0u {
+        return textureDimensions(prebaked_color_texture);
+    }
+    return textureDimensions(color_atlas_texture);
+}
+
+fn mask_dimensions(flags: u32) -> vec2<u32> {
+    if (flags & PREBAKED_FLAG) != 0u {
+        return textureDimensions(prebaked_mask_texture);
+    }
+    if (flags & MERGED_FLAG) != 0u {
+        return textureDimensions(color_atlas_texture);
+    }
+    return textureDimensions(mask_atlas_texture);
+}
+
+// Returns the dimensions of the atlas texture the glyph of an instance is stored in
+fn atlas_dimensions(content_type: u32, flags: u32) -> vec2<u32> {
+    switch content_type {
+        case 1u: {
+            return mask_dimensions(flags);
+        }
+        case SDF_CONTENT_TYPE: {
+            return textureDimensions(sdf_atlas_texture);
+        }
+        default: {
+            return color_dimensions(flags);
+        }
+    }
+}
+
+fn sample_mask_level(uv: vec2<f32>, layer: u32, flags: u32) -> f32 {
+    if (flags & PREBAKED_FLAG) != 0u {
+        return textureSampleLevel(prebaked_mask_texture, atlas_sampler, uv, layer, 0.0).x;
+    }
+    if (flags & MERGED_FLAG) != 0u {
+        return textureSampleLevel(color_atlas_texture, atlas_sampler, uv, layer, 0.0).a;
+    }
+    return textureSampleLevel(mask_atlas_texture, atlas_sampler, uv, layer, 0.0).x;
+}
+
+// Antialiases the outline of a distance field over the distance `width` around it
+fn distance_alpha(distance: f32, width: f32) -> f32 {
+    return clamp((distance - 0.5) / max(width, 1e-4) + 0.5, 0.0, 1.0);
+}
+
+// Returns the distance encoded in a multi-channel distance field, the median of its channels
+fn msdf_distance(sample: vec3<f32>, conversion: u32) -> f32 {
+    let channels = undo_srgb_decoding(sample, conversion);
+    return max(min(channels.r, channels.g), min(max(channels.r, channels.g), channels.b));
+}
+
+// Returns the alpha of a color glyph, or the coverage of a mask glyph
+fn sample_alpha_level(uv: vec2<f32>, layer: u32, content_type: u32, flags: u32) -> f32 {
+    if content_type == 1u {
+        return sample_mask_level(uv, layer, flags);
+    }
+    if content_type == SDF_CONTENT_TYPE {
+        let distance = textureSampleLevel(sdf_atlas_texture, atlas_sampler, uv, layer, 0.0).x;
+        return distance_alpha(distance, 1.0 / (2.0 * SDF_SPREAD));
+    }
+    if content_type == MSDF_CONTENT_TYPE {
+        // The alpha channel holds the median distance
+        let distance = textureSampleLevel(color_atlas_texture, atlas_sampler, uv, layer, 0.0).a;
+        return distance_alpha(distance, 1.0 / (2.0 * SDF_SPREAD));
+    }
+    if (flags & PREBAKED_FLAG) != 0u {
+        return textureSampleLevel(prebaked_color_texture, atlas_sampler, uv, layer, 0.0).a;
+    }
+    return textureSampleLevel(color_atlas_texture, atlas_sampler, uv, layer, 0.0).a;
+}
+
+// Blurs the alpha of a glyph with a cone kernel of the given radius in pixels
+fn drop_shadow_alpha(uv: vec2<f32>, layer: u32, content_type: u32, flags: u32, radius: f32) -> f32 {
+    if radius <= 0.0 {
+        return sample_alpha_level(uv, layer, content_type, flags);
+    }
+
+    let pixel_size = 1.0 / vec2<f32>(atlas_dimensions(content_type, flags));
+    let r = i32(min(ceil(radius), MAX_BLUR_RADIUS));
+
+    var alpha = 0.0;
+    var total_weight = 0.0;
+    for (var dy: i32 = -r; dy <= r; dy = dy + 1) {
+        for (var dx: i32 = -r; dx <= r; dx = dx + 1) {
+            let offset = vec2<f32>(f32(dx), f32(dy));
+            let weight = max(1.0 - length(offset) / (radius + 1.0), 0.0);
+            if weight > 0.0 {
+                alpha += weight * sample_alpha_level(uv + offset * pixel_size, layer, content_type, flags);
+                total_weight += weight;
+            }
+        }
+    }
+
+    return alpha / total_weight;
+}
+
+// Dilates the alpha of a glyph by the given width in pixels, antialiasing the outer edge
+fn outline_alpha(uv: vec2<f32>, layer: u32, content_type: u32, flags: u32, width: f32) -> f32 {
+    let pixel_size = 1.0 / vec2<f32>(atlas_dimensions(content_type, flags));
+    let r = i32(min(ceil(width), MAX_BLUR_RADIUS));
+
+    var alpha = 0.0;
+    for (var dy: i32 = -r; dy <= r; dy = dy + 1) {
+        for (var dx: i32 = -r; dx <= r; dx = dx + 1) {
+            let offset = vec2<f32>(f32(dx), f32(dy));
+            let weight = clamp(width + 0.5 - length(offset), 0.0, 1.0);
+            if weight > 0.0 {
+                alpha = max(alpha, weight * sample_alpha_level(uv + offset * pixel_size, layer, content_type, flags));
+            }
+        }
+    }
+
+    return alpha;
+}
+
+// The coverage of a glyph eroded by `width` texels, the inverse of `outline_alpha`
+fn erosion_alpha(uv: vec2<f32>, layer: u32, content_type: u32, flags: u32, width: f32) -> f32 {
+    let pixel_size = 1.0 / vec2<f32>(atlas_dimensions(content_type, flags));
+    let r = i32(min(ceil(width), MAX_BLUR_RADIUS));
+
+    var alpha = 1.0;
+    for (var dy: i32 = -r; dy <= r; dy = dy + 1) {
+        for (var dx: i32 = -r; dx <= r; dx = dx + 1) {
+            let offset = vec2<f32>(f32(dx), f32(dy));
+            let weight = clamp(width + 0.5 - length(offset), 0.0, 1.0);
+            if weight > 0.0 {
+                let sample = sample_alpha_level(uv + offset * pixel_size, layer, content_type, flags);
+                alpha = min(alpha, 1.0 - weight * (1.0 - sample));
+            }
+        }
+    }
+
+    return alpha;
+}
+
+// Unpacks an ARGB color, converting it with the given color conversion
+fn unpack_color(color: u32, conversion: u32) -> vec4<f32> {
+    let encoded = vec4<f32>(
+        f32((color & 0x00ff0000u) >> 16u) / 255.0,
+        f32((color & 0x0000ff00u) >> 8u) / 255.0,
+        f32(color & 0x000000ffu) / 255.0,
+        f32((color & 0xff000000u) >> 24u) / 255.0,
+    );
+
+    switch conversion {
+        case 1u: {
+            return vec4<f32>(
+                srgb_to_linear(encoded.r),
+                srgb_to_linear(encoded.g),
+                srgb_to_linear(encoded.b),
+                encoded.a,
+            );
+        }
+        case 2u: {
+            return vec4<f32>(decode_custom_rgb(encoded.rgb), encoded.a);
+        }
+        default: {
+            return encoded;
+        }
+    }
+}
+
+// Evaluates a gradient at `pos` in physical pixels
+fn gradient_color(index: u32, pos: vec2<f32>, conversion: u32) -> vec4<f32> {
+    let rect = gradients.gradients[index].rect;
+    let geometry = gradients.gradients[index].geometry;
+    let p = (pos - rect.xy) / max(rect.zw, vec2<f32>(1.0));
+
+    var t = 0.0;
+    if gradients.gradients[index].kind == 0u {
+        let direction = geometry.zw - geometry.xy;
+        t = dot(p - geometry.xy, direction) / max(dot(direction, direction), 1e-6);
+    } else {
+        t = length((p - geometry.xy) / max(geometry.zw, vec2<f32>(1e-6)));
+    }
+
+    // Blend towards each stop that `t` has passed, in order
+    var color = unpack_color(gradients.gradients[index].colors[0].x, conversion);
+    for (var i = 1u; i < gradients.gradients[index].stop_count; i = i + 1u) {
+        let start = gradients.gradients[index].offsets[(i - 1u) / 4u][(i - 1u) % 4u];
+        let end = gradients.gradients[index].offsets[i / 4u][i % 4u];
+        let stop_color = unpack_color(gradients.gradients[index].colors[i / 4u][i % 4u], conversion);
+        color = mix(color, stop_color, clamp((t - start) / max(end - start, 1e-6), 0.0, 1.0));
+    }
+
+    return color;
+}
+
+// Returns the coverage of a decoration of the given style at `pos` pixels within its quad. Wavy
+// quads are three times as high as their stroke is `thickness`.
+fn decoration_alpha(pos: vec2<f32>, style: u32, thickness: f32) -> f32 {
+    switch style {
+        // Wavy
+        case 1u: {
+            let period = max(4.0 * thickness, 4.0);
+            let center = 1.5 * thickness + thickness * sin(6.2831853 * pos.x / period);
+            return clamp(0.5 * thickness + 0.5 - abs(pos.y - center), 0.0, 1.0);
+        }
+        // Dashed
+        case 2u: {
+            let period = max(6.0 * thickness, 4.0);
+            return select(0.0, 1.0, fract(pos.x / period) < 0.5);
+        }
+        // Dotted
+        case 3u: {
+            let period = max(2.0 * thickness, 2.0);
+            let dot = vec2<f32>((floor(pos.x / period) + 0.25) * period, 0.5 * thickness);
+            return clamp(0.5 * thickness + 0.5 - length(pos - dot), 0.0, 1.0);
+        }
+        default: {
+            return 1.0;
+        }
+    }
+}
+
+// Sharpens the alpha ramp of a magnified glyph edge into a smooth transition that is
+// `params.edge_feather` pixels wide. `alpha_width` is the screen-space derivative of `alpha`.
+fn feather_edge(alpha: f32, alpha_width: f32) -> f32 {
+    if params.edge_feather <= 0.0 {
+        return alpha;
+    }
+
+    let half_width = clamp(0.5 * alpha_width * params.edge_feather, 1e-4, 0.5);
+    return smoothstep(0.5 - half_width, 0.5 + half_width, alpha);
+}
+
+// Applies `params.contrast` and the luminance-dependent `params.gamma` to the coverage of a mask
+// glyph filled with `rgb`, so that light and dark text look equally heavy
+fn correct_coverage(coverage: vec3<f32>, rgb: vec3<f32>) -> vec3<f32> {
+    if params.gamma == 1.0 && params.contrast == 0.0 {
+        return coverage;
+    }
+
+    let boosted = clamp(coverage + params.contrast * coverage * (1.0 - coverage), vec3<f32>(0.0), vec3<f32>(1.0));
+    let luminance = dot(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(0.2126, 0.7152, 0.0722));
+    return pow(boosted, vec3<f32>(mix(1.0, 1.0 / params.gamma, luminance)));
+}
+
+// Replaced in multiview pipelines by the index of the view being rendered
+fn view_index(in_vert: VertexInput) -> u32 {
+    return 0u;
+}
+
+// The compact instance layout of renderers whose instances don't use shadows, emboldening,
+// slanting, rotation or distance fields
+struct PackedVertexInput {
+    @builtin(vertex_index) index: u32,
+    @location(0) pos: vec2<i32>,
+    @location(1) dim: u32,
+    @location(2) uv: u32,
+    @location(3) color: u32,
+    // The content type and the sRGB flag in the two low bytes, followed by the opacity as a
+    // 16-bit normalized integer
+    @location(4) content_type_srgb_opacity: u32,
+    @location(5) depth: f32,
+    @location(6) flags: u32,
+    @location(7) transform: u32,
+}
+
 @vertex
 fn vs_main(in_vert: VertexInput) -> VertexOutput {
+    return vertex_output(in_vert);
+}
+
+@vertex
+fn vs_packed(packed: PackedVertexInput) -> VertexOutput {
+    var in_vert: VertexInput;
+    in_vert.vertex_idx = packed.index;
+    in_vert.pos = packed.pos;
+    in_vert.dim = packed.dim;
+    in_vert.uv = packed.uv;
+    in_vert.color = packed.color;
+    in_vert.content_type_with_srgb = (packed.content_type_srgb_opacity & 0xffu)
+        | (((packed.content_type_srgb_opacity >> 8u) & 0xffu) << 16u);
+    in_vert.depth = packed.depth;
+    in_vert.flags = packed.flags;
+    in_vert.opacity = f32(packed.content_type_srgb_opacity >> 16u) / 65535.0;
+    in_vert.transform = packed.transform;
+    in_vert.texel_scale = 1.0;
+    in_vert.rotation = vec4<f32>(1.0, 0.0, 0.0, 0.0);
+    return vertex_output(in_vert);
+}
+
+// Draws six vertices per instance as a triangle list, so that all instances of a renderer can be
+// drawn without a vertex buffer
+@vertex
+fn vs_pulled(@builtin(vertex_index) index: u32) -> VertexOutput {
+    return vertex_output(pulled_input(index));
+}
+
+// The columns of a `TextTransform` that places the instances of a text area
+struct PlacementInput {
+    @builtin(vertex_index) index: u32,
+    @location(0) x_axis: vec2<f32>,
+    @location(1) y_axis: vec2<f32>,
+    @location(2) offset: vec2<f32>,
+}
+
+// Draws pulled instances once for each placement, which transforms their positions in physical
+// pixels after the transform of their text area. World-transformed instances aren't placed.
+@vertex
+fn vs_placed(placed: PlacementInput) -> VertexOutput {
+    placement = mat3x2<f32>(placed.x_axis, placed.y_axis, placed.offset);
+    return vertex_output(pulled_input(placed.index));
+}
+
+// Reads the vertex with the given index of the instances drawn as a triangle list
+fn pulled_input(index: u32) -> VertexInput {
+    let instance = instances[index / 6u];
+    // The corners of the two triangles of the quad, numbered like the vertices of `vs_main`
+    var corners = array<u32, 6>(0u, 1u, 2u, 2u, 1u, 3u);
+
+    var in_vert: VertexInput;
+    in_vert.vertex_idx = corners[index % 6u];
+    in_vert.pos = instance.pos;
+    in_vert.dim = instance.dim;
+    in_vert.uv = instance.uv;
+    in_vert.color = instance.color;
+    in_vert.content_type_with_srgb = instance.content_type_with_srgb;
+    in_vert.depth = instance.depth;
+    in_vert.shadow_radius = instance.shadow_radius;
+    in_vert.shadow_intensity = instance.shadow_intensity;
+    in_vert.flags = instance.flags;
+    in_vert.opacity = instance.opacity;
+    in_vert.transform = instance.transform;
+    in_vert.embolden = instance.embolden;
+    in_vert.oblique = vec2<f32>(instance.oblique_x, instance.oblique_y);
+    in_vert.texel_scale = instance.texel_scale;
+    in_vert.rotation = instance.rotation;
+    return in_vert;
+}
+
+fn vertex_output(in_vert: VertexInput) -> VertexOutput {
     var pos = in_vert.pos;
     let width = in_vert.dim & 0xffffu;
     let height = (in_vert.dim & 0xffff0000u) >> 16u;
     let color = in_vert.color;
-    var uv = vec2<u32>(in_vert.uv & 0xffffu, (in_vert.uv & 0xffff0000u) >> 16u);
+    let uv = vec2<u32>(in_vert.uv & 0xffffu, (in_vert.uv & 0xffff0000u) >> 16u);
     let v = in_vert.vertex_idx;
 
     let corner_position = vec2<u32>(
@@ -59,73 +591,259 @@ fn vs_main(in_vert: VertexInput) -> VertexOutput {
 
     let corner_offset = vec2<u32>(width, height) * corner_position;
 
-    uv = uv + corner_offset;
     pos = pos + vec2<i32>(corner_offset);
 
     var vert_output: VertexOutput;
 
-    vert_output.position = vec4<f32>(
-        2.0 * vec2<f32>(pos) / vec2<f32>(params.screen_resolution) - 1.0,
-        in_vert.depth,
-        1.0,
+    // Slant the quad around the baseline
+    var local_pos = vec2<f32>(pos);
+    local_pos.x += in_vert.oblique.x * (in_vert.oblique.y - local_pos.y);
+
+    // Rotate the quad onto a text path
+    let pivot_offset = local_pos - in_vert.rotation.zw;
+    local_pos = in_vert.rotation.zw + vec2<f32>(
+        in_vert.rotation.x * pivot_offset.x - in_vert.rotation.y * pivot_offset.y,
+        in_vert.rotation.y * pivot_offset.x + in_vert.rotation.x * pivot_offset.y,
     );
 
-    vert_output.position.y *= -1.0;
+    var screen_pos = local_pos;
+
+    var world_pos = vec4<f32>(0.0);
+    let transform = in_vert.transform & TRANSFORM_MASK;
+    if transform != 0u {
+        let matrix = transforms.transforms[transform - 1u].matrix;
+        world_pos = matrix * vec4<f32>(screen_pos, in_vert.depth, 1.0);
+        screen_pos = world_pos.xy;
+    }
+
+    if (in_vert.transform & WORLD_TRANSFORM_BIT) != 0u {
+        vert_output.position = renderer.projections[view_index(in_vert)] * world_pos;
+    } else {
+        screen_pos = placement * vec3<f32>(screen_pos, 1.0);
+        screen_pos = (screen_pos - params.offset) * params.zoom;
+        vert_output.position = vec4<f32>(
+            2.0 * screen_pos / vec2<f32>(params.screen_resolution) - 1.0,
+            select(in_vert.depth, 1.0 - in_vert.depth, renderer.reverse_z != 0u),
+            1.0,
+        );
+
+        vert_output.position.y *= -1.0;
+    }
 
     let content_type = in_vert.content_type_with_srgb & 0xffffu;
     let srgb = (in_vert.content_type_with_srgb & 0xffff0000u) >> 16u;
 
-    switch srgb {
-        case 0u: {
-            vert_output.color = vec4<f32>(
-                f32((color & 0x00ff0000u) >> 16u) / 255.0,
-                f32((color & 0x0000ff00u) >> 8u) / 255.0,
-                f32(color & 0x000000ffu) / 255.0,
-                f32((color & 0xff000000u) >> 24u) / 255.0,
-            );
-        }
-        case 1u: {
-            vert_output.color = vec4<f32>(
-                srgb_to_linear(f32((color & 0x00ff0000u) >> 16u) / 255.0),
-                srgb_to_linear(f32((color & 0x0000ff00u) >> 8u) / 255.0),
-                srgb_to_linear(f32(color & 0x000000ffu) / 255.0),
-                f32((color & 0xff000000u) >> 24u) / 255.0,
-            );
-        }
-        default: {}
+    vert_output.color = unpack_color(color, srgb);
+    vert_output.color_conversion = srgb;
+    vert_output.opacity = in_vert.opacity;
+    vert_output.local_position = local_pos;
+    vert_output.embolden = in_vert.embolden;
+    vert_output.clip = (in_vert.transform >> CLIP_SHIFT) & CLIP_MASK;
+    vert_output.quad_uv = vec2<f32>(corner_position);
+
+    let gradient = (in_vert.flags >> GRADIENT_SHIFT) & GRADIENT_MASK;
+    if gradient != 0u && gradients.gradients[gradient - 1u].kind == GLYPH_COLORS_KIND {
+        let corner = corner_position.x + 2u * corner_position.y;
+        vert_output.color = unpack_color(gradients.gradients[gradient - 1u].colors[0][corner], srgb);
     }
 
-    var dim: vec2<u32> = vec2(0u);
-    switch content_type {
-        case 0u: {
-            dim = textureDimensions(color_atlas_texture);
-            break;
-        }
-        case 1u: {
-            dim = textureDimensions(mask_atlas_texture);
-            break;
-        }
-        default: {}
+    var dim: vec2<u32> = vec2(1u);
+    // Solid quads don't sample an atlas
+    if content_type != SOLID_QUAD_CONTENT_TYPE {
+        dim = atlas_dimensions(content_type, in_vert.flags);
     }
 
     vert_output.content_type = content_type;
 
-    vert_output.uv = vec2<f32>(uv) / vec2<f32>(dim);
+    // Glyphs rendered from distance fields span more or fewer texels than pixels
+    let uv_offset = vec2<f32>(corner_offset) * in_vert.texel_scale;
+    vert_output.uv = (vec2<f32>(uv) + uv_offset) / vec2<f32>(dim);
 
     vert_output.shadow_radius = in_vert.shadow_radius;
     vert_output.shadow_intensity = in_vert.shadow_intensity;
 
+    vert_output.flags = in_vert.flags;
+    switch in_vert.flags & PATTERN_FLAGS {
+        case PATTERN_SCREEN_FLAG: {
+            vert_output.pattern_uv = vec2<f32>(pos) / vec2<f32>(textureDimensions(pattern_texture));
+        }
+        case PATTERN_GLYPH_FLAG: {
+            vert_output.pattern_uv = vec2<f32>(corner_position);
+        }
+        default: {
+            vert_output.pattern_uv = vec2<f32>(0.0);
+        }
+    }
+
     return vert_output;
 }
 
 @fragment
 fn fs_main(in_frag: VertexOutput) -> @location(0) vec4<f32> {
+    return output_color(in_frag);
+}
+
+@fragment
+fn fs_premultiplied(in_frag: VertexOutput) -> @location(0) vec4<f32> {
+    let color = output_color(in_frag);
+
+    // The color of glows is already premultiplied
+    if (in_frag.flags & GLOW_FLAG) != 0u && in_frag.content_type != SOLID_QUAD_CONTENT_TYPE {
+        return color;
+    }
+
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
+
+// Replaced by the fragment snippet of a `TextShader::FragmentHook`
+fn glyphon_fragment(color: vec4<f32>, in_frag: VertexOutput) -> vec4<f32> {
+    return color;
+}
+
+// The color of an instance with the opacity of its text area applied
+fn output_color(in_frag: VertexOutput) -> vec4<f32> {
+    let clip = clip_coverage(in_frag);
+    let fragment = glyphon_fragment(instance_color(in_frag), in_frag);
+    let color = vec4<f32>(fragment.rgb * renderer.brightness, fragment.a);
+
+    // Glows are blended additively, so their opacity scales their premultiplied color
+    if (in_frag.flags & GLOW_FLAG) != 0u && in_frag.content_type != SOLID_QUAD_CONTENT_TYPE {
+        return debug_color(vec4<f32>(color.rgb * in_frag.opacity * clip, color.a * clip), in_frag);
+    }
+
+    return debug_color(vec4<f32>(color.rgb, color.a * in_frag.opacity * clip), in_frag);
+}
+
+// Replaces `color` with the debug visualizations of `renderer.debug`. Must be called in uniform
+// control flow.
+fn debug_color(color: vec4<f32>, in_frag: VertexOutput) -> vec4<f32> {
+    // The distance in pixels to the nearest edge of the quad
+    let edge = min(in_frag.quad_uv, 1.0 - in_frag.quad_uv) / max(fwidth(in_frag.quad_uv), vec2<f32>(1e-4));
+
+    var output = color;
+    if (renderer.debug & DEBUG_OVERDRAW) != 0u {
+        // Every quad adds the same translucent color, so pixels covered by many quads heat up
+        output = vec4<f32>(1.0, 0.25, 0.0, 0.125);
+    }
+    if (renderer.debug & DEBUG_QUAD_OUTLINES) != 0u && min(edge.x, edge.y) < 1.0 {
+        output = vec4<f32>(0.0, 1.0, 0.0, 1.0);
+    }
+    return output;
+}
+
+// The antialiased coverage of a fragment by the rounded clip of its text area. Must be called in
+// uniform control flow.
+fn clip_coverage(in_frag: VertexOutput) -> f32 {
+    let pixel_width = fwidth(in_frag.local_position);
+    if in_frag.clip == 0u {
+        return 1.0;
+    }
+
+    let clip = clips.clips[in_frag.clip - 1u];
+    let half_size = 0.5 * (clip.rect.zw - clip.rect.xy);
+    let position = in_frag.local_position - 0.5 * (clip.rect.xy + clip.rect.zw);
+
+    // The radius of the corner in the quadrant of the fragment
+    let side_radii = select(clip.radii.xw, clip.radii.yz, position.x > 0.0);
+    let radius = select(side_radii.x, side_radii.y, position.y > 0.0);
+
+    // The signed distance to the rounded rectangle, negative inside of it
+    let q = abs(position) - half_size + radius;
+    let distance = min(max(q.x, q.y), 0.0) + length(max(q, vec2<f32>(0.0))) - radius;
+
+    return clamp(0.5 - distance / max(max(pixel_width.x, pixel_width.y), 1e-4), 0.0, 1.0);
+}
+
+// The color of an instance before the opacity of its text area is applied
+fn instance_color(in_frag: VertexOutput) -> vec4<f32> {
+    // Derivatives must be computed in uniform control flow, so sample all atlases up front
+    let layer = in_frag.flags >> LAYER_SHIFT;
+    let mipmapped = (in_frag.flags & MIPMAP_FLAG) != 0u;
+    let prebaked = (in_frag.flags & PREBAKED_FLAG) != 0u;
+    let atlas_color_sample = select(
+        textureSampleLevel(color_atlas_texture, atlas_sampler, in_frag.uv, layer, 0.0),
+        textureSample(color_atlas_texture, atlas_mipmap_sampler, in_frag.uv, layer),
+        mipmapped,
+    );
+    let color_sample = select(
+        atlas_color_sample,
+        textureSampleLevel(prebaked_color_texture, atlas_sampler, in_frag.uv, layer, 0.0),
+        prebaked,
+    );
+    let merged = (in_frag.flags & MERGED_FLAG) != 0u;
+    let mask_sample = select(
+        select(
+            select(
+                textureSampleLevel(mask_atlas_texture, atlas_sampler, in_frag.uv, layer, 0.0).x,
+                textureSample(mask_atlas_texture, atlas_mipmap_sampler, in_frag.uv, layer).x,
+                mipmapped,
+            ),
+            atlas_color_sample.a,
+            merged,
+        ),
+        textureSampleLevel(prebaked_mask_texture, atlas_sampler, in_frag.uv, layer, 0.0).x,
+        prebaked,
+    );
+    let color_alpha_width = fwidth(color_sample.a);
+    let mask_alpha_width = fwidth(mask_sample);
+    let sdf_sample =
+        textureSampleLevel(sdf_atlas_texture, atlas_sampler, in_frag.uv, layer, 0.0).x;
+    let sdf_width = fwidth(sdf_sample);
+    let msdf_sample = msdf_distance(color_sample.rgb, in_frag.color_conversion);
+    let msdf_width = fwidth(msdf_sample);
+
+    if (in_frag.flags & DROP_SHADOW_FLAG) != 0u && in_frag.content_type != SOLID_QUAD_CONTENT_TYPE {
+        let alpha = drop_shadow_alpha(in_frag.uv, layer, in_frag.content_type, in_frag.flags, in_frag.shadow_radius);
+        return vec4<f32>(in_frag.color.rgb, in_frag.color.a * alpha);
+    }
+
+    if (in_frag.flags & GLOW_FLAG) != 0u && in_frag.content_type != SOLID_QUAD_CONTENT_TYPE {
+        let alpha = drop_shadow_alpha(in_frag.uv, layer, in_frag.content_type, in_frag.flags, in_frag.shadow_radius);
+        // Glows are blended additively, so their color is premultiplied by their alpha
+        let glow_alpha = in_frag.color.a * alpha * in_frag.shadow_intensity;
+        return vec4<f32>(in_frag.color.rgb * glow_alpha, 0.0);
+    }
+
+    if (in_frag.flags & OUTLINE_FLAG) != 0u && in_frag.content_type != SOLID_QUAD_CONTENT_TYPE {
+        let alpha = outline_alpha(in_frag.uv, layer, in_frag.content_type, in_frag.flags, in_frag.shadow_radius);
+        return vec4<f32>(in_frag.color.rgb, in_frag.color.a * alpha);
+    }
+
     switch in_frag.content_type {
         case 0u: {
-            return textureSampleLevel(color_atlas_texture, atlas_sampler, in_frag.uv, 0.0);
+            var rgb = color_sample.rgb;
+            if in_frag.color_conversion == 2u {
+                rgb = decode_custom_rgb(rgb);
+            }
+            let alpha = feather_edge(color_sample.a, color_alpha_width);
+
+            switch (in_frag.flags >> COLOR_GLYPH_STYLE_SHIFT) & COLOR_GLYPH_STYLE_MASK {
+                case 1u: {
+                    return vec4<f32>(rgb * in_frag.color.rgb, alpha * in_frag.color.a);
+                }
+                case 2u: {
+                    return vec4<f32>(vec3<f32>(dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722))), alpha);
+                }
+                case 3u: {
+                    return vec4<f32>(in_frag.color.rgb, alpha * in_frag.color.a);
+                }
+                default: {
+                    return vec4<f32>(rgb, alpha);
+                }
+            }
         }
         case 1u: {
-            let glyph_alpha = textureSampleLevel(mask_atlas_texture, atlas_sampler, in_frag.uv, 0.0).x;
+            var glyph_alpha = feather_edge(mask_sample, mask_alpha_width);
+            if in_frag.embolden > 0.0 {
+                let dilated = outline_alpha(in_frag.uv, layer, in_frag.content_type, in_frag.flags, in_frag.embolden);
+                glyph_alpha = max(glyph_alpha, dilated);
+            } else if in_frag.embolden < 0.0 {
+                let eroded = erosion_alpha(in_frag.uv, layer, in_frag.content_type, in_frag.flags, -in_frag.embolden);
+                glyph_alpha = min(glyph_alpha, eroded);
+            }
+
+            let color = fill_color(in_frag);
+            glyph_alpha = correct_coverage(vec3<f32>(glyph_alpha), color.rgb).x;
 
             var max_shadow_value = 0.0;
 
@@ -134,7 +852,7 @@ fn fs_main(in_frag: VertexOutput) -> @location(0) vec4<f32> {
             let shadow_rgb = vec3<f32>(0.0, 0.0, 0.0);
 
             if (radius_pixels > 0.0) {
-                let tex_dims = vec2<f32>(textureDimensions(mask_atlas_texture, 0u));
+                let tex_dims = vec2<f32>(mask_dimensions(in_frag.flags));
                 let pixel_size = vec2<f32>(1.0 / tex_dims.x, 1.0 / tex_dims.y);
 
                 let R_int = i32(min(ceil(radius_pixels), MAX_KERNEL_RADIUS));
@@ -148,7 +866,7 @@ fn fs_main(in_frag: VertexOutput) -> @location(0) vec4<f32> {
                             let dist_pixels = sqrt(dist_sq);
                             let sample_uv = in_frag.uv - offset_pixels * pixel_size;
 
-                            let text_mask_at_P = textureSampleLevel(mask_atlas_texture, atlas_sampler, sample_uv, 0.0).x;
+                            let text_mask_at_P = sample_mask_level(sample_uv, layer, in_frag.flags);
 
                             if (text_mask_at_P > 0.01) {
                                 let falloff = smoothstep(radius_pixels, 0.0, dist_pixels);
@@ -162,14 +880,67 @@ fn fs_main(in_frag: VertexOutput) -> @location(0) vec4<f32> {
 
             let combined_shape_alpha = clamp(max(glyph_alpha, max_shadow_value), 0.0, 1.0);
             
-            let final_rgb = mix(shadow_rgb, in_frag.color.rgb, glyph_alpha);
+            let final_rgb = mix(shadow_rgb, color.rgb, glyph_alpha);
             
-            let final_a = in_frag.color.a * combined_shape_alpha;
+            let final_a = color.a * combined_shape_alpha;
             
             return vec4<f32>(final_rgb, final_a);
         }
+        case 2u: {
+            let style = (in_frag.flags >> DECORATION_STYLE_SHIFT) & DECORATION_STYLE_MASK;
+            let alpha = decoration_alpha(in_frag.uv, style, in_frag.shadow_radius);
+            return vec4<f32>(in_frag.color.rgb, in_frag.color.a * alpha);
+        }
+        case 3u: {
+            // Without dual-source blending, subpixel coverage is averaged into a grayscale mask
+            let fill = fill_color(in_frag);
+            let coverage = correct_coverage(subpixel_coverage(in_frag.uv, layer, in_frag.flags, in_frag.color_conversion), fill.rgb);
+            return vec4<f32>(fill.rgb, fill.a * (coverage.r + coverage.g + coverage.b) / 3.0);
+        }
+        case 4u, 5u: {
+            let is_msdf = in_frag.content_type == MSDF_CONTENT_TYPE;
+            // Faux bold and thickness move the outline outwards by the embolden distance in texels
+            let distance = select(sdf_sample, msdf_sample, is_msdf)
+                + in_frag.embolden / (2.0 * SDF_SPREAD);
+            // Antialiased over one pixel at any scale
+            let alpha = distance_alpha(distance, select(sdf_width, msdf_width, is_msdf));
+            let fill = fill_color(in_frag);
+            return vec4<f32>(fill.rgb, fill.a * correct_coverage(vec3<f32>(alpha), fill.rgb).x);
+        }
         default: {
             return vec4<f32>(0.0);
         }
     }
 }
+
+// The color a mask glyph is filled with, from its gradient and pattern
+fn fill_color(in_frag: VertexOutput) -> vec4<f32> {
+    var color = in_frag.color;
+    let gradient = (in_frag.flags >> GRADIENT_SHIFT) & GRADIENT_MASK;
+    if gradient != 0u && gradients.gradients[gradient - 1u].kind != GLYPH_COLORS_KIND {
+        color = gradient_color(gradient - 1u, in_frag.local_position, in_frag.color_conversion);
+    }
+    if (in_frag.flags & PATTERN_FLAGS) != 0u {
+        color *= textureSampleLevel(pattern_texture, pattern_sampler, in_frag.pattern_uv, 0.0);
+    }
+    return color;
+}
+
+// Returns the per-channel coverage of a subpixel mask glyph
+fn subpixel_coverage(uv: vec2<f32>, layer: u32, flags: u32, conversion: u32) -> vec3<f32> {
+    var coverage: vec3<f32>;
+    if (flags & PREBAKED_FLAG) != 0u {
+        coverage = textureSampleLevel(prebaked_color_texture, atlas_sampler, uv, layer, 0.0).rgb;
+    } else {
+        coverage = textureSampleLevel(color_atlas_texture, atlas_sampler, uv, layer, 0.0).rgb;
+    }
+    return undo_srgb_decoding(coverage, conversion);
+}
+
+// Data other than colors is stored in the color atlas as-is, so undo the decoding of sRGB atlases
+fn undo_srgb_decoding(rgb: vec3<f32>, conversion: u32) -> vec3<f32> {
+    if conversion == 1u {
+        return vec3<f32>(linear_to_srgb(rgb.r), linear_to_srgb(rgb.g), linear_to_srgb(rgb.b));
+    }
+    return rgb;
+}
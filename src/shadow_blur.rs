@@ -0,0 +1,556 @@
+use crate::{
+    cache::InstanceLayout, text_render::create_oversized_buffer, GlyphToRender, Resolution,
+    TextAtlas, Viewport,
+};
+use std::{borrow::Cow, mem, num::NonZeroU64, ops::Range, slice};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry,
+    BindingResource, BindingType, BlendState, Buffer, BufferBinding, BufferBindingType,
+    BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, CompareFunction,
+    DepthStencilState, Device, Extent3d, FilterMode, FragmentState, LoadOp, MultisampleState,
+    Operations, PipelineCache, PipelineCompilationOptions, PipelineLayout,
+    PipelineLayoutDescriptor, PrimitiveState, Queue, RenderBundleEncoder, RenderPass,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    Sampler, SamplerBindingType, SamplerDescriptor, ShaderModule, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages, StoreOp, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+    VertexState,
+};
+
+/// The largest blur radius of a [`ShadowStyle`] in physical pixels. Larger radii are clamped to
+/// bound the number of samples per pixel.
+///
+/// [`ShadowStyle`]: crate::ShadowStyle
+pub(crate) const MAX_SHADOW_RADIUS: f32 = 64.0;
+
+/// The format of the offscreen targets. Coverage is stored premultiplied, so that it can be
+/// blurred and composited without fringes.
+const TARGET_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct BlurParams {
+    direction: [f32; 2],
+    radius: f32,
+    _pad: u32,
+}
+
+/// Renders the glyph coverage of text areas with a [`ShadowStyle`] into an offscreen target,
+/// blurs it with a separable Gaussian and composites the result beneath the text.
+///
+/// Shadows with the same blur radius are blurred together, and the blurred groups are
+/// accumulated into a single target that is composited with one draw.
+///
+/// [`ShadowStyle`]: crate::ShadowStyle
+pub(crate) struct ShadowBlur {
+    texture_layout: BindGroupLayout,
+    params_layout: BindGroupLayout,
+    sampler: Sampler,
+    coverage_pipeline: RenderPipeline,
+    blur_pipeline: RenderPipeline,
+    accumulate_pipeline: RenderPipeline,
+    composite_pipeline: RenderPipeline,
+    vertex_buffer: Buffer,
+    vertex_buffer_size: u64,
+    /// The stride of the blur parameters in `params_buffer`, which are bound with dynamic offsets.
+    params_stride: u64,
+    params_buffer: Buffer,
+    params_bind_group: BindGroup,
+    targets: Option<BlurTargets>,
+    groups: Vec<BlurGroup>,
+}
+
+/// The shadow instances that are blurred with the same radius.
+struct BlurGroup {
+    radius: f32,
+    instances: Range<u32>,
+}
+
+/// The viewport-sized targets of the blur. Coverage is rendered into the first target, blurred
+/// horizontally into the second and vertically back into the first, then accumulated into the
+/// third.
+struct BlurTargets {
+    resolution: Resolution,
+    views: [TextureView; 3],
+    bind_groups: [BindGroup; 3],
+}
+
+impl ShadowBlur {
+    /// Creates the blur resources for a renderer whose text is drawn with the given
+    /// `multisample` and `depth_stencil` states.
+    pub(crate) fn new(
+        device: &Device,
+        atlas: &TextAtlas,
+        multisample: MultisampleState,
+        depth_stencil: Option<DepthStencilState>,
+    ) -> Self {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("glyphon blur shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("blur.wgsl"))),
+        });
+
+        let texture_layout = create_texture_layout(device);
+
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(mem::size_of::<BlurParams>() as u64),
+                },
+                count: None,
+            }],
+            label: Some("glyphon blur params bind group layout"),
+        });
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("glyphon blur sampler"),
+            min_filter: FilterMode::Linear,
+            mag_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let blur_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("glyphon blur pipeline layout"),
+            bind_group_layouts: &[&texture_layout, &params_layout],
+            push_constant_ranges: &[],
+        });
+        let composite_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("glyphon shadow composite pipeline layout"),
+            bind_group_layouts: &[&texture_layout],
+            push_constant_ranges: &[],
+        });
+
+        let coverage_pipeline = atlas.cache.get_or_create_pipeline(
+            device,
+            ColorTargetState {
+                format: TARGET_FORMAT,
+                blend: Some(BlendState::ALPHA_BLENDING),
+                write_mask: ColorWrites::default(),
+            },
+            MultisampleState::default(),
+            None,
+            None,
+            InstanceLayout::Full,
+        );
+        let blur_pipeline = create_fullscreen_pipeline(
+            device,
+            "glyphon blur pipeline",
+            &shader,
+            &blur_layout,
+            "fs_blur",
+            ColorTargetState {
+                format: TARGET_FORMAT,
+                blend: None,
+                write_mask: ColorWrites::default(),
+            },
+            MultisampleState::default(),
+            None,
+            atlas.cache.pipeline_cache(),
+        );
+        let accumulate_pipeline = create_fullscreen_pipeline(
+            device,
+            "glyphon shadow accumulate pipeline",
+            &shader,
+            &composite_layout,
+            "fs_composite",
+            ColorTargetState {
+                format: TARGET_FORMAT,
+                blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                write_mask: ColorWrites::default(),
+            },
+            MultisampleState::default(),
+            None,
+            atlas.cache.pipeline_cache(),
+        );
+
+        // The shadows lie beneath all text of the renderer, so they neither test nor write depth
+        let depth_stencil = depth_stencil.map(|depth_stencil| DepthStencilState {
+            depth_write_enabled: false,
+            depth_compare: CompareFunction::Always,
+            ..depth_stencil
+        });
+        let composite_pipeline = create_fullscreen_pipeline(
+            device,
+            "glyphon shadow composite pipeline",
+            &shader,
+            &composite_layout,
+            "fs_composite",
+            ColorTargetState {
+                format: atlas.format,
+                blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                write_mask: ColorWrites::default(),
+            },
+            multisample,
+            depth_stencil,
+            atlas.cache.pipeline_cache(),
+        );
+
+        let vertex_buffer_size = 4096;
+        let vertex_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("glyphon shadow vertices"),
+            size: vertex_buffer_size,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let params_stride = (mem::size_of::<BlurParams>() as u64).next_multiple_of(alignment);
+        let (params_buffer, params_bind_group) =
+            create_params_buffer(device, &params_layout, params_stride * 2);
+
+        Self {
+            texture_layout,
+            params_layout,
+            sampler,
+            coverage_pipeline,
+            blur_pipeline,
+            accumulate_pipeline,
+            composite_pipeline,
+            vertex_buffer,
+            vertex_buffer_size,
+            params_stride,
+            params_buffer,
+            params_bind_group,
+            targets: None,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Uploads the shadow instances to blur, along with the blur radius of each in physical
+    /// pixels. Instances with the same radius are blurred together, in the order they were
+    /// provided.
+    pub(crate) fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        resolution: Resolution,
+        shadows: &mut [(f32, GlyphToRender)],
+    ) {
+        self.groups.clear();
+        if shadows.is_empty() || resolution.width == 0 || resolution.height == 0 {
+            return;
+        }
+
+        shadows.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut vertices = Vec::with_capacity(shadows.len());
+        for (index, &(radius, vertex)) in shadows.iter().enumerate() {
+            let index = index as u32;
+            match self.groups.last_mut() {
+                Some(group) if group.radius == radius => group.instances.end = index + 1,
+                _ => self.groups.push(BlurGroup {
+                    radius,
+                    instances: index..index + 1,
+                }),
+            }
+            vertices.push(vertex);
+        }
+
+        let vertices = vertices.as_slice();
+        let vertices_raw = unsafe {
+            slice::from_raw_parts(
+                vertices as *const _ as *const u8,
+                mem::size_of_val(vertices),
+            )
+        };
+
+        if self.vertex_buffer_size >= vertices_raw.len() as u64 {
+            queue.write_buffer(&self.vertex_buffer, 0, vertices_raw);
+        } else {
+            self.vertex_buffer.destroy();
+
+            let (buffer, buffer_size) = create_oversized_buffer(
+                device,
+                Some("glyphon shadow vertices"),
+                vertices_raw,
+                BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            );
+
+            self.vertex_buffer = buffer;
+            self.vertex_buffer_size = buffer_size;
+        }
+
+        // Each group is blurred horizontally, then vertically
+        let params_size = self.params_stride * 2 * self.groups.len() as u64;
+        if self.params_buffer.size() < params_size {
+            self.params_buffer.destroy();
+
+            let (buffer, bind_group) =
+                create_params_buffer(device, &self.params_layout, params_size.next_power_of_two());
+            self.params_buffer = buffer;
+            self.params_bind_group = bind_group;
+        }
+
+        let mut params = vec![0u8; params_size as usize];
+        for (index, group) in self.groups.iter().enumerate() {
+            for (pass, direction) in [[1.0, 0.0], [0.0, 1.0]].into_iter().enumerate() {
+                let blur_params = BlurParams {
+                    direction,
+                    radius: group.radius,
+                    _pad: 0,
+                };
+                let offset = (index * 2 + pass) * self.params_stride as usize;
+                let raw = unsafe {
+                    slice::from_raw_parts(
+                        &blur_params as *const _ as *const u8,
+                        mem::size_of::<BlurParams>(),
+                    )
+                };
+                params[offset..offset + raw.len()].copy_from_slice(raw);
+            }
+        }
+        queue.write_buffer(&self.params_buffer, 0, &params);
+
+        if self.targets.as_ref().map(|t| t.resolution) != Some(resolution) {
+            self.targets = Some(self.create_targets(device, resolution));
+        }
+    }
+
+    /// Renders and blurs the shadows that were previously provided to `prepare` into the
+    /// accumulation target.
+    pub(crate) fn render(
+        &self,
+        encoder: &mut CommandEncoder,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pattern_bind_group: &BindGroup,
+    ) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+
+        for (index, group) in self.groups.iter().enumerate() {
+            {
+                let mut pass = begin_pass(
+                    encoder,
+                    "glyphon shadow coverage pass",
+                    &targets.views[0],
+                    LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                );
+                pass.set_pipeline(&self.coverage_pipeline);
+                pass.set_bind_group(0, &atlas.bind_group, &[]);
+                viewport.bind(&mut pass);
+                pass.set_bind_group(atlas.cache.pattern_group(), pattern_bind_group, &[]);
+                pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                pass.draw(0..4, group.instances.clone());
+            }
+
+            for (pass_index, (source, target)) in [(0, 1), (1, 0)].into_iter().enumerate() {
+                let offset = (index * 2 + pass_index) as u64 * self.params_stride;
+                let mut pass = begin_pass(
+                    encoder,
+                    "glyphon shadow blur pass",
+                    &targets.views[target],
+                    LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                );
+                pass.set_pipeline(&self.blur_pipeline);
+                pass.set_bind_group(0, &targets.bind_groups[source], &[]);
+                pass.set_bind_group(1, &self.params_bind_group, &[offset as u32]);
+                pass.draw(0..3, 0..1);
+            }
+
+            {
+                let load = if index == 0 {
+                    LoadOp::Clear(wgpu::Color::TRANSPARENT)
+                } else {
+                    LoadOp::Load
+                };
+                let mut pass = begin_pass(
+                    encoder,
+                    "glyphon shadow accumulate pass",
+                    &targets.views[2],
+                    load,
+                );
+                pass.set_pipeline(&self.accumulate_pipeline);
+                pass.set_bind_group(0, &targets.bind_groups[0], &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+    }
+
+    /// Composites the blurred shadows onto the render target of `pass`.
+    pub(crate) fn composite(&self, pass: &mut RenderPass<'_>) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        if self.groups.is_empty() {
+            return;
+        }
+
+        pass.set_pipeline(&self.composite_pipeline);
+        pass.set_bind_group(0, &targets.bind_groups[2], &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Records the compositing of the blurred shadows into a render bundle, like `composite`.
+    pub(crate) fn composite_bundle<'a>(&'a self, encoder: &mut RenderBundleEncoder<'a>) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        if self.groups.is_empty() {
+            return;
+        }
+
+        encoder.set_pipeline(&self.composite_pipeline);
+        encoder.set_bind_group(0, &targets.bind_groups[2], &[]);
+        encoder.draw(0..3, 0..1);
+    }
+
+    fn create_targets(&self, device: &Device, resolution: Resolution) -> BlurTargets {
+        let create_target = || {
+            let texture = device.create_texture(&TextureDescriptor {
+                label: Some("glyphon shadow target"),
+                size: Extent3d {
+                    width: resolution.width,
+                    height: resolution.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TARGET_FORMAT,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&TextureViewDescriptor::default());
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                layout: &self.texture_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&view),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+                label: Some("glyphon shadow target bind group"),
+            });
+            (view, bind_group)
+        };
+
+        let [(view_a, bind_group_a), (view_b, bind_group_b), (view_c, bind_group_c)] =
+            [create_target(), create_target(), create_target()];
+
+        BlurTargets {
+            resolution,
+            views: [view_a, view_b, view_c],
+            bind_groups: [bind_group_a, bind_group_b, bind_group_c],
+        }
+    }
+}
+
+/// Creates the layout of a bind group with a filterable texture and a sampler, as sampled by
+/// `fs_blur` and `fs_composite`.
+pub(crate) fn create_texture_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: TextureViewDimension::D2,
+                    sample_type: TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+        label: Some("glyphon blur texture bind group layout"),
+    })
+}
+
+fn create_params_buffer(
+    device: &Device,
+    layout: &BindGroupLayout,
+    size: u64,
+) -> (Buffer, BindGroup) {
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("glyphon blur params"),
+        size,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        layout,
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: BindingResource::Buffer(BufferBinding {
+                buffer: &buffer,
+                offset: 0,
+                size: NonZeroU64::new(mem::size_of::<BlurParams>() as u64),
+            }),
+        }],
+        label: Some("glyphon blur params bind group"),
+    });
+
+    (buffer, bind_group)
+}
+
+pub(crate) fn create_fullscreen_pipeline(
+    device: &Device,
+    label: &str,
+    shader: &ShaderModule,
+    layout: &PipelineLayout,
+    entry_point: &str,
+    target: ColorTargetState,
+    multisample: MultisampleState,
+    depth_stencil: Option<DepthStencilState>,
+    pipeline_cache: Option<&PipelineCache>,
+) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: PipelineCompilationOptions::default(),
+        },
+        fragment: Some(FragmentState {
+            module: shader,
+            entry_point: Some(entry_point),
+            targets: &[Some(target)],
+            compilation_options: PipelineCompilationOptions::default(),
+        }),
+        primitive: PrimitiveState::default(),
+        depth_stencil,
+        multisample,
+        multiview: None,
+        cache: pipeline_cache,
+    })
+}
+
+pub(crate) fn begin_pass<'a>(
+    encoder: &'a mut CommandEncoder,
+    label: &str,
+    target: &TextureView,
+    load: LoadOp<wgpu::Color>,
+) -> RenderPass<'a> {
+    encoder.begin_render_pass(&RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: Operations {
+                load,
+                store: StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
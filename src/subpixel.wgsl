@@ -0,0 +1,32 @@
+// Appended to shader.wgsl for renderers whose atlas rasterizes subpixel masks. The second blend
+// source carries a blend factor per color channel, so each subpixel is covered independently.
+struct SubpixelOutput {
+    @location(0) @blend_src(0) color: vec4<f32>,
+    @location(0) @blend_src(1) blend: vec4<f32>,
+}
+
+@fragment
+fn fs_subpixel(in_frag: VertexOutput) -> SubpixelOutput {
+    // Computed first so that derivatives are taken in uniform control flow
+    let color = output_color(in_frag);
+    let clip = clip_coverage(in_frag);
+
+    var out: SubpixelOutput;
+    out.color = color;
+    out.blend = vec4<f32>(color.a);
+
+    // Debug visualizations aren't blended per subpixel
+    let effects = DROP_SHADOW_FLAG | OUTLINE_FLAG | GLOW_FLAG;
+    if renderer.debug == 0u && in_frag.content_type == SUBPIXEL_MASK_CONTENT_TYPE && (in_frag.flags & effects) == 0u {
+        let layer = in_frag.flags >> LAYER_SHIFT;
+        let fragment = glyphon_fragment(fill_color(in_frag), in_frag);
+        let fill = vec4<f32>(fragment.rgb * renderer.brightness, fragment.a);
+        let coverage = correct_coverage(subpixel_coverage(in_frag.uv, layer, in_frag.flags, in_frag.color_conversion), fill.rgb);
+        let alpha = fill.a * in_frag.opacity * clip;
+        let max_coverage = max(coverage.r, max(coverage.g, coverage.b));
+        out.color = vec4<f32>(fill.rgb, alpha * max_coverage);
+        out.blend = vec4<f32>(coverage * alpha, alpha * max_coverage);
+    }
+
+    return out;
+}
@@ -1,60 +1,357 @@
 use crate::{
-    text_render::GlyphonCacheKey, Cache, ContentType, FontSystem, GlyphDetails, GpuCacheStatus,
-    RasterizeCustomGlyphRequest, RasterizedCustomGlyph, SwashCache, SHADOW_MARGIN_PX,
+    cache::{AtlasViews, InstanceLayout},
+    fontdb,
+    persist::{self, PersistedContent, PersistedGlyph},
+    sdf,
+    text_render::GlyphonCacheKey,
+    AtlasError, AtlasFullError, Cache, CacheKey, ContentType, DownloadError, FontSystem,
+    GlyphDetails, GlyphRasterization, GpuCacheStatus, LoadGlyphCacheError,
+    RasterizeCustomGlyphRequest, RasterizedCustomGlyph, SwashCache, SwashContent, SwashImage,
+    TransferParams, SHADOW_MARGIN_PX,
 };
+use cosmic_text::{CacheKeyFlags, Font};
 use etagere::{size2, Allocation, BucketedAtlasAllocator};
 use lru::LruCache;
 use rustc_hash::FxHasher;
-use std::{collections::HashSet, hash::BuildHasherDefault};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    hash::BuildHasherDefault,
+    mem,
+    num::NonZeroU32,
+    slice,
+    sync::{Arc, Mutex},
+};
+use swash::{
+    scale::{Render, ScaleContext, Source, StrikeWith},
+    zeno::{Angle, Format, Transform, Vector},
+};
 use wgpu::{
-    BindGroup, DepthStencilState, Device, Extent3d, MultisampleState, Origin3d, Queue,
-    RenderPipeline, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect,
-    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
-    TextureViewDescriptor,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BlendState, Buffer, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites,
+    CommandEncoderDescriptor, DepthStencilState, Device, Extent3d, Features, MapMode,
+    MultisampleState, Origin3d, PollType, Queue, RenderPipeline, TexelCopyBufferInfo,
+    TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+    TextureViewDimension, COPY_BYTES_PER_ROW_ALIGNMENT,
 };
 
 type Hasher = BuildHasherDefault<FxHasher>;
 
-const M: i32 = SHADOW_MARGIN_PX as i32;
+/// A function called with the key of every glyph that is removed from the atlas, shared by the
+/// color and mask atlases.
+type EvictionListener = Arc<Mutex<dyn FnMut(GlyphonCacheKey) + Send>>;
+
+/// Rasterizes a text glyph, going through the image cache of the [`SwashCache`] if `shared` is
+/// set so that other atlases can reuse the image. Mask glyphs are rasterized as subpixel masks if
+/// `subpixel` is set, which bypasses the image cache like non-default `rasterization` options.
+pub(crate) fn rasterize_text_glyph(
+    cache: &mut SwashCache,
+    font_system: &mut FontSystem,
+    cache_key: CacheKey,
+    rasterization: GlyphRasterization,
+    shared: bool,
+    subpixel: bool,
+) -> Option<SwashImage> {
+    if subpixel || rasterization != GlyphRasterization::default() {
+        rasterize_swash_glyph(font_system, cache_key, rasterization, subpixel)
+    } else if shared {
+        cache.get_image(font_system, cache_key).clone()
+    } else {
+        cache.get_image_uncached(font_system, cache_key)
+    }
+}
+
+/// Rasterizes the signed distance field of a text glyph whose cache key was normalized to
+/// [`SDF_FONT_SIZE`](sdf::SDF_FONT_SIZE). Color glyphs are rasterized as usual.
+pub(crate) fn rasterize_sdf_glyph(
+    cache: &mut SwashCache,
+    font_system: &mut FontSystem,
+    cache_key: CacheKey,
+    shared: bool,
+) -> Option<SwashImage> {
+    let mut image = rasterize_text_glyph(
+        cache,
+        font_system,
+        cache_key,
+        GlyphRasterization::default(),
+        shared,
+        false,
+    )?;
+
+    let (width, height) = (image.placement.width, image.placement.height);
+    if image.content != SwashContent::Mask || width == 0 || height == 0 {
+        return Some(image);
+    }
+
+    image.data = sdf::signed_distance_field(&image.data, width as usize, height as usize);
+    image.placement = sdf::padded_placement(image.placement);
+
+    Some(image)
+}
+
+/// Rasterizes the multi-channel signed distance field of a text glyph like
+/// [`rasterize_sdf_glyph`], which requires the `msdf` feature. The image keeps the mask content
+/// type of the glyph, with four channels per pixel. Color glyphs are rasterized as usual.
+pub(crate) fn rasterize_msdf_glyph(
+    cache: &mut SwashCache,
+    font_system: &mut FontSystem,
+    cache_key: CacheKey,
+    shared: bool,
+) -> Option<SwashImage> {
+    let mut image = rasterize_text_glyph(
+        cache,
+        font_system,
+        cache_key,
+        GlyphRasterization::default(),
+        shared,
+        false,
+    )?;
+
+    let (width, height) = (image.placement.width, image.placement.height);
+    if image.content != SwashContent::Mask || width == 0 || height == 0 {
+        return Some(image);
+    }
+
+    image.placement = sdf::padded_placement(image.placement);
+    image.data = sdf::multi_channel_distance_field(font_system, cache_key, image.placement)?;
+
+    Some(image)
+}
+
+/// Rasterizes a text glyph like [`SwashCache`] does, but with the given `rasterization` options
+/// and into a subpixel mask for a horizontal RGB display if `subpixel` is set. Color glyphs are
+/// rasterized as usual.
+fn rasterize_swash_glyph(
+    font_system: &mut FontSystem,
+    cache_key: CacheKey,
+    rasterization: GlyphRasterization,
+    subpixel: bool,
+) -> Option<SwashImage> {
+    let font = font_system.get_font(cache_key.font_id)?;
+    rasterize_font_glyph(
+        &font,
+        &mut ScaleContext::new(),
+        cache_key,
+        rasterization,
+        subpixel,
+    )
+}
+
+/// Rasterizes a text glyph of a font that was already loaded like [`rasterize_swash_glyph`], which
+/// doesn't need the [`FontSystem`] and so can run on any thread.
+pub(crate) fn rasterize_font_glyph(
+    font: &Font,
+    context: &mut ScaleContext,
+    cache_key: CacheKey,
+    rasterization: GlyphRasterization,
+    subpixel: bool,
+) -> Option<SwashImage> {
+    let font_size = f32::from_bits(cache_key.font_size_bits);
+
+    let mut scaler = context
+        .builder(font.as_swash())
+        .size(font_size)
+        .hint(rasterization.hinting)
+        .build();
+    let offset = Vector::new(cache_key.x_bin.as_float(), cache_key.y_bin.as_float());
+    let fake_italic = cache_key.flags.contains(CacheKeyFlags::FAKE_ITALIC);
+
+    let mut image = Render::new(&[
+        Source::ColorOutline(0),
+        Source::ColorBitmap(StrikeWith::BestFit),
+        Source::Outline,
+    ])
+    .format(if subpixel {
+        Format::Subpixel
+    } else {
+        Format::Alpha
+    })
+    .embolden(rasterization.darkening(font_size))
+    .offset(offset)
+    .transform(
+        fake_italic.then(|| Transform::skew(Angle::from_degrees(14.0), Angle::from_degrees(0.0))),
+    )
+    .render(&mut scaler, cache_key.glyph_id)?;
+
+    if image.content == SwashContent::SubpixelMask {
+        // Effects like outlines use the coverage of the whole pixel
+        for pixel in image.data.chunks_exact_mut(4) {
+            pixel[3] = pixel[0].max(pixel[1]).max(pixel[2]);
+        }
+    }
+
+    Some(image)
+}
+
+/// Allocates space for a glyph along with the padding around it. The returned rectangle starts
+/// at the top-left corner of the glyph inside the padding.
+fn allocate_padded(
+    packer: &mut BucketedAtlasAllocator,
+    width: usize,
+    height: usize,
+    padding: u16,
+) -> Option<Allocation> {
+    let padding = padding as i32;
+    let padded = size2(width as i32 + 2 * padding, height as i32 + 2 * padding);
+    let mut allocation = packer.allocate(padded)?;
+
+    allocation.rectangle.min.x += padding;
+    allocation.rectangle.min.y += padding;
+    Some(allocation)
+}
+
+/// Copies the pixels of a glyph into the center of a zeroed image that is larger by `padding` on
+/// each side.
+fn pad_image_data(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    padding: usize,
+) -> Vec<u8> {
+    let row = width * channels;
+    let padded_row = (width + 2 * padding) * channels;
+    let mut padded = vec![0; padded_row * (height + 2 * padding)];
+
+    for (y, src) in data.chunks_exact(row).take(height).enumerate() {
+        let start = (y + padding) * padded_row + padding * channels;
+        padded[start..start + row].copy_from_slice(src);
+    }
+
+    padded
+}
 
 #[allow(dead_code)]
 pub(crate) struct InnerAtlas {
     pub kind: Kind,
     pub texture: Texture,
     pub texture_view: TextureView,
-    pub packer: BucketedAtlasAllocator,
-    pub size: u32,
+    /// One packer per layer of the texture array.
+    pub packers: Vec<BucketedAtlasAllocator>,
+    /// The fragmentation of the free space, computed again only after glyphs are allocated or
+    /// freed.
+    pub fragmentation: Option<f32>,
+    pub width: u32,
+    pub height: u32,
     pub glyph_cache: LruCache<GlyphonCacheKey, GlyphDetails, Hasher>,
     pub glyphs_in_use: HashSet<GlyphonCacheKey, Hasher>,
-    pub max_texture_dimension_2d: u32,
+    pub initial_size: u32,
+    pub max_size: u32,
+    pub growth_factor: u32,
+    pub budget: AtlasBudget,
+    pub trim_policy: TrimPolicy,
+    /// The number of trims since the atlas was created.
+    pub trims: u64,
+    /// The atlas space used by the cached glyphs, in bytes.
+    pub used_bytes: u64,
+    pub evictions: u64,
+    /// Incremented whenever glyphs are removed or move within the texture, which invalidates the
+    /// instances of retained text areas.
+    pub generation: u64,
+    pub max_texture_array_layers: u32,
+    pub share_rasterized_glyphs: bool,
+    pub mipmaps: bool,
+    /// Whether the mip levels need to be regenerated from the first mip level.
+    pub mipmaps_dirty: bool,
+    /// The empty space around each glyph, in pixels.
+    pub padding: u16,
+    /// The top-left corner of the region of the texture the atlas uses.
+    pub origin: (u32, u32),
+    /// Whether the texture is owned by the application, in which case it is never replaced.
+    pub external: bool,
+    /// The least number of layers of the texture, which is two on downlevel adapters that can't
+    /// view textures with a single layer as arrays.
+    pub min_layers: u32,
+    pub eviction_listener: Option<EvictionListener>,
+    /// The glyph images staged by [`InnerAtlas::stage_upload`], with the rows of each image
+    /// aligned for copying.
+    pub staged_uploads: Vec<u8>,
+    pub staged_copies: Vec<StagedCopy>,
+}
+
+/// A glyph image in `InnerAtlas::staged_uploads` and where it is copied to.
+pub(crate) struct StagedCopy {
+    offset: u64,
+    bytes_per_row: u32,
+    origin: Origin3d,
+    size: Extent3d,
 }
 
 impl InnerAtlas {
-    const INITIAL_SIZE: u32 = 4096;
+    fn new(device: &Device, kind: Kind, config: &AtlasConfig) -> Self {
+        let limits = device.limits();
+        let max_size = config.max_size.clamp(1, limits.max_texture_dimension_2d);
+        let initial_size = config.initial_size.clamp(1, max_size);
 
-    fn new(device: &Device, _queue: &Queue, kind: Kind) -> Self {
-        let max_texture_dimension_2d = device.limits().max_texture_dimension_2d;
-        let size = Self::INITIAL_SIZE.min(max_texture_dimension_2d);
+        // Create a texture to use for our atlas
+        let texture = Self::create_texture(
+            device,
+            kind,
+            initial_size,
+            initial_size,
+            config.min_layers(),
+            config.mipmaps,
+        );
 
-        let packer = BucketedAtlasAllocator::new(size2(size as i32, size as i32));
+        Self::with_texture(
+            kind,
+            config,
+            texture,
+            (initial_size, initial_size),
+            max_size,
+            limits.max_texture_array_layers,
+        )
+    }
 
-        // Create a texture to use for our atlas
-        let texture = device.create_texture(&TextureDescriptor {
-            label: Some("glyphon atlas"),
-            size: Extent3d {
-                width: size,
-                height: size,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: kind.texture_format(),
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
+    /// Creates an atlas that stores its glyphs in a region of a texture owned by the application.
+    /// The atlas never grows or replaces the texture.
+    fn new_external(kind: Kind, config: &AtlasConfig, external: ExternalAtlasTexture) -> Self {
+        let ExternalAtlasTexture {
+            texture,
+            x,
+            y,
+            width,
+            height,
+        } = external;
 
-        let texture_view = texture.create_view(&TextureViewDescriptor::default());
+        assert_eq!(
+            texture.format(),
+            kind.texture_format(),
+            "External atlas texture must have the format {:?}",
+            kind.texture_format(),
+        );
+        assert!(
+            x + width <= texture.width() && y + height <= texture.height(),
+            "External atlas region {x},{y} of size {width}x{height} is out of the bounds of the texture",
+        );
+
+        let max_size = width.max(height);
+        let mut atlas = Self::with_texture(kind, config, texture, (width, height), max_size, 1);
+        atlas.origin = (x, y);
+        atlas.external = true;
+        // The other mip levels belong to the application
+        atlas.mipmaps = false;
+        atlas
+    }
+
+    fn with_texture(
+        kind: Kind,
+        config: &AtlasConfig,
+        texture: Texture,
+        (width, height): (u32, u32),
+        max_size: u32,
+        max_texture_array_layers: u32,
+    ) -> Self {
+        let initial_size = width.min(height);
+        let growth_factor = config.growth_factor.max(2);
+
+        let packers = vec![BucketedAtlasAllocator::new(size2(
+            width as i32,
+            height as i32,
+        ))];
+
+        let texture_view = Self::create_texture_view(&texture);
 
         let glyph_cache = LruCache::unbounded_with_hasher(Hasher::default());
         let glyphs_in_use = HashSet::with_hasher(Hasher::default());
@@ -63,27 +360,904 @@ impl InnerAtlas {
             kind,
             texture,
             texture_view,
-            packer,
-            size,
+            packers,
+            fragmentation: None,
+            width,
+            height,
             glyph_cache,
             glyphs_in_use,
-            max_texture_dimension_2d,
+            initial_size,
+            max_size,
+            growth_factor,
+            budget: config.budget,
+            trim_policy: config.trim_policy,
+            trims: 0,
+            used_bytes: 0,
+            evictions: 0,
+            generation: 0,
+            max_texture_array_layers,
+            share_rasterized_glyphs: false,
+            mipmaps: config.mipmaps,
+            mipmaps_dirty: false,
+            padding: config.padding,
+            origin: (0, 0),
+            external: false,
+            min_layers: config.min_layers(),
+            eviction_listener: None,
+            staged_uploads: Vec::new(),
+            staged_copies: Vec::new(),
         }
     }
 
-    pub(crate) fn try_allocate(&mut self, width: usize, height: usize) -> Option<Allocation> {
-        let padded = size2(width as i32 + 2 * M, height as i32 + 2 * M);
-        let mut allocation = self.packer.allocate(padded)?;
+    fn create_texture(
+        device: &Device,
+        kind: Kind,
+        width: u32,
+        height: u32,
+        layers: u32,
+        mipmaps: bool,
+    ) -> Texture {
+        let (mip_level_count, usage) = if mipmaps {
+            // The mip levels are rendered from the previous level
+            (
+                width.max(height).ilog2() + 1,
+                TextureUsages::RENDER_ATTACHMENT,
+            )
+        } else {
+            (1, TextureUsages::empty())
+        };
+
+        device.create_texture(&TextureDescriptor {
+            label: Some(kind.label()),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: kind.texture_format(),
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC
+                | usage,
+            view_formats: &[],
+        })
+    }
+
+    fn create_texture_view(texture: &Texture) -> TextureView {
+        // Always view the texture as an array, even if it only has a single layer
+        texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        })
+    }
 
-        allocation.rectangle.min.x += M;
-        allocation.rectangle.min.y += M;
-        Some(allocation)
+    fn layers(&self) -> u32 {
+        self.packers.len() as u32
+    }
+
+    /// Returns the error of a glyph of the given content type and size that couldn't be
+    /// allocated in the atlas.
+    pub(crate) fn full_error(
+        &self,
+        content_type: ContentType,
+        glyph: GlyphonCacheKey,
+        requested: (u32, u32),
+        error: AtlasError,
+    ) -> AtlasFullError {
+        AtlasFullError {
+            content_type,
+            glyph,
+            requested,
+            atlas_size: (self.width, self.height),
+            layers: self.layers(),
+            max_size: self.max_size,
+            max_layers: self.max_texture_array_layers,
+            error,
+        }
+    }
+
+    /// Returns the layer and position of the allocated glyph.
+    pub(crate) fn try_allocate(
+        &mut self,
+        width: usize,
+        height: usize,
+    ) -> Option<(u16, Allocation)> {
+        trace_span!("glyphon::allocate", kind = ?self.kind, width, height);
+
+        let padding = self.padding;
+        let (x, y) = (self.origin.0 as i32, self.origin.1 as i32);
+        self.fragmentation = None;
+        self.packers
+            .iter_mut()
+            .enumerate()
+            .find_map(|(layer, packer)| {
+                let mut allocation = allocate_padded(packer, width, height, padding)?;
+
+                // Move the allocation into the region of the texture the atlas uses
+                allocation.rectangle.min.x += x;
+                allocation.rectangle.min.y += y;
+                allocation.rectangle.max.x += x;
+                allocation.rectangle.max.y += y;
+                Some((layer as u16, allocation))
+            })
+    }
+
+    /// Uploads the pixels of a glyph whose top-left corner is at `origin`, clearing the padding
+    /// around it.
+    ///
+    /// Mask glyphs uploaded to a color atlas are stored as white pixels with their coverage in
+    /// the alpha channel.
+    pub(crate) fn upload(
+        &self,
+        queue: &Queue,
+        origin: Origin3d,
+        width: u16,
+        height: u16,
+        content_type: ContentType,
+        data: &[u8],
+    ) {
+        let (data, origin, size) = self.padded_image(origin, width, height, content_type, data);
+
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin,
+                aspect: TextureAspect::All,
+            },
+            &data,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(size.width * self.num_channels() as u32),
+                rows_per_image: None,
+            },
+            size,
+        );
+    }
+
+    /// Stages a glyph image like [`InnerAtlas::upload`] uploads it, to be copied into the texture
+    /// along with the other staged images by [`InnerAtlas::flush_uploads`].
+    pub(crate) fn stage_upload(
+        &mut self,
+        origin: Origin3d,
+        width: u16,
+        height: u16,
+        content_type: ContentType,
+        data: &[u8],
+    ) {
+        let (data, origin, size) = self.padded_image(origin, width, height, content_type, data);
+        let row = size.width as usize * self.num_channels();
+        let bytes_per_row = (row as u32).next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let offset = self.staged_uploads.len();
+        self.staged_uploads
+            .resize(offset + bytes_per_row as usize * size.height as usize, 0);
+        for (y, src) in data.chunks_exact(row).enumerate() {
+            let start = offset + y * bytes_per_row as usize;
+            self.staged_uploads[start..start + row].copy_from_slice(src);
+        }
+
+        self.staged_copies.push(StagedCopy {
+            offset: offset as u64,
+            bytes_per_row,
+            origin,
+            size,
+        });
+    }
+
+    /// Copies the images staged by [`InnerAtlas::stage_upload`] into the texture from a single
+    /// staging buffer.
+    pub(crate) fn flush_uploads(&mut self, device: &Device, queue: &Queue) {
+        if self.staged_copies.is_empty() {
+            return;
+        }
+        trace_span!(
+            "glyphon::upload_glyphs",
+            kind = ?self.kind,
+            glyphs = self.staged_copies.len(),
+            bytes = self.staged_uploads.len(),
+        );
+
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("glyphon atlas uploads"),
+            contents: &self.staged_uploads,
+            usage: BufferUsages::COPY_SRC,
+        });
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("glyphon atlas uploads"),
+        });
+        for copy in self.staged_copies.drain(..) {
+            encoder.copy_buffer_to_texture(
+                TexelCopyBufferInfo {
+                    buffer: &buffer,
+                    layout: TexelCopyBufferLayout {
+                        offset: copy.offset,
+                        bytes_per_row: Some(copy.bytes_per_row),
+                        rows_per_image: None,
+                    },
+                },
+                TexelCopyTextureInfo {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: copy.origin,
+                    aspect: TextureAspect::All,
+                },
+                copy.size,
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+        self.staged_uploads.clear();
+    }
+
+    /// Expands a glyph image to the channels of the texture and pads it, returning it along with
+    /// the origin and size of the padded image.
+    fn padded_image<'a>(
+        &self,
+        origin: Origin3d,
+        width: u16,
+        height: u16,
+        content_type: ContentType,
+        data: &'a [u8],
+    ) -> (Cow<'a, [u8]>, Origin3d, Extent3d) {
+        let mut data = Cow::Borrowed(data);
+        if content_type == ContentType::Mask && self.num_channels() == 4 {
+            data = Cow::Owned(data.iter().flat_map(|&a| [255, 255, 255, a]).collect());
+        }
+
+        let padding = self.padding as u32;
+        if padding != 0 {
+            data = Cow::Owned(pad_image_data(
+                &data,
+                width as usize,
+                height as usize,
+                self.num_channels(),
+                padding as usize,
+            ));
+        }
+
+        let origin = Origin3d {
+            x: origin.x - padding,
+            y: origin.y - padding,
+            z: origin.z,
+        };
+        let size = Extent3d {
+            width: width as u32 + 2 * padding,
+            height: height as u32 + 2 * padding,
+            depth_or_array_layers: 1,
+        };
+        (data, origin, size)
+    }
+
+    /// Returns the atlas space used by a glyph of the given size, including the padding around
+    /// it.
+    pub(crate) fn glyph_bytes(&self, width: u16, height: u16) -> u64 {
+        let padding = 2 * self.padding as u64;
+        (width as u64 + padding) * (height as u64 + padding) * self.num_channels() as u64
+    }
+
+    /// Evicts the least recently used glyph that is not in use. Returns `false` if there is no
+    /// such glyph.
+    pub(crate) fn evict_lru(&mut self) -> bool {
+        let Some(cache_key) = self
+            .glyph_cache
+            .iter()
+            .rev()
+            .map(|(&cache_key, _)| cache_key)
+            .find(|cache_key| !self.glyphs_in_use.contains(cache_key))
+        else {
+            return false;
+        };
+
+        self.evictions += 1;
+        trace_event!(kind = ?self.kind, ?cache_key, "evicted least recently used glyph");
+        self.remove(&cache_key)
+    }
+
+    /// Evicts the least recently used glyphs that are not in use until a new glyph of the given
+    /// size fits into the budget.
+    pub(crate) fn make_room_in_budget(&mut self, width: u16, height: u16) {
+        match self.budget {
+            AtlasBudget::Unbounded => {}
+            AtlasBudget::Glyphs(max_glyphs) => {
+                while self.glyph_cache.len() >= max_glyphs && self.evict_lru() {}
+            }
+            AtlasBudget::Bytes(max_bytes) => {
+                let bytes = self.glyph_bytes(width, height);
+                while self.used_bytes + bytes > max_bytes && self.evict_lru() {}
+            }
+        }
+    }
+
+    /// Returns whether the atlas can still make room, by growing the texture or by adding a layer.
+    /// External atlases never grow.
+    fn can_grow(&self) -> bool {
+        !self.external
+            && (self.width < self.max_size
+                || self.height < self.max_size
+                || self.layers() < self.max_texture_array_layers)
+    }
+
+    /// Returns whether a glyph of the given size fits into an atlas texture of the maximum size,
+    /// or into the region of an external atlas.
+    pub(crate) fn fits_max_size(&self, width: u16, height: u16) -> bool {
+        let padding = 2 * self.padding as u32;
+        let (max_width, max_height) = if self.external {
+            (self.width, self.height)
+        } else {
+            (self.max_size, self.max_size)
+        };
+        width as u32 <= max_width.saturating_sub(padding)
+            && height as u32 <= max_height.saturating_sub(padding)
+    }
+
+    pub(crate) fn max_allocatable(&self) -> (u32, u32) {
+        let padding = 2 * self.padding as u32;
+
+        // The atlas can grow to the maximum texture size, so anything that fits there can be
+        // allocated eventually
+        if self.can_grow() {
+            let max = self.max_size.saturating_sub(padding);
+            return (max, max);
+        }
+
+        // Otherwise, probe the free space for the largest square allocation
+        let max = self.largest_free_square();
+
+        (max, max)
+    }
+
+    /// Probes copies of the packers for the largest square glyph that can be allocated, not
+    /// counting the padding around it. The packers themselves are left untouched, since
+    /// allocating and freeing the probes would change where later glyphs are placed.
+    fn largest_free_square(&self) -> u32 {
+        let mut largest = 0;
+        let padding = 2 * self.padding as u32;
+
+        for packer in &self.packers {
+            let mut packer = packer.clone();
+            let mut low = largest;
+            let mut high = self.width.min(self.height).saturating_sub(padding);
+
+            while low < high {
+                let mid = (low + high).div_ceil(2);
+                let probe = size2((mid + padding) as i32, (mid + padding) as i32);
+
+                match packer.allocate(probe) {
+                    Some(allocation) => {
+                        packer.deallocate(allocation.id);
+                        low = mid;
+                    }
+                    None => high = mid - 1,
+                }
+            }
+
+            largest = low;
+        }
+
+        largest
+    }
+
+    /// Returns how fragmented the free space of the atlas is, from `0.0` (the free space is one
+    /// contiguous area) to `1.0` (nothing fits anymore).
+    ///
+    /// The result is cached until glyphs are allocated or freed.
+    pub(crate) fn fragmentation(&mut self) -> f32 {
+        if let Some(fragmentation) = self.fragmentation {
+            return fragmentation;
+        }
+
+        let fragmentation = self.compute_fragmentation();
+        self.fragmentation = Some(fragmentation);
+        fragmentation
+    }
+
+    fn compute_fragmentation(&self) -> f32 {
+        let free_space: i64 = self
+            .packers
+            .iter()
+            .map(|packer| packer.free_space() as i64)
+            .sum();
+        if free_space <= 0 || self.glyph_cache.is_empty() {
+            return 0.0;
+        }
+
+        let largest_free_square = self.largest_free_square() as f32 + 2.0 * self.padding as f32;
+
+        1.0 - (largest_free_square * largest_free_square / free_space as f32).min(1.0)
+    }
+
+    /// Repacks all glyphs into a new texture of the same size to defragment the free space.
+    /// Returns whether the glyphs were repacked, which they never are for external atlases.
+    ///
+    /// This must not be called between preparing and rendering text, as the glyphs move.
+    pub(crate) fn compact(&mut self, device: &Device, queue: &Queue) -> bool {
+        self.repack(device, queue, (self.width, self.height))
+    }
+
+    /// Removes the glyphs that were not used since the last trim, then repacks the remaining
+    /// glyphs into the smallest texture they fit in, down to the initial size and a single layer.
+    /// The texture shrinks in the reverse order of [`InnerAtlas::grow`], one dimension at a time.
+    /// Returns whether the texture shrank.
+    ///
+    /// This must not be called between preparing and rendering text, as the glyphs move.
+    pub(crate) fn shrink(&mut self, device: &Device, queue: &Queue) -> bool {
+        let unused: Vec<GlyphonCacheKey> = self
+            .glyph_cache
+            .iter()
+            .map(|(&cache_key, _)| cache_key)
+            .filter(|cache_key| !self.glyphs_in_use.contains(cache_key))
+            .collect();
+
+        for cache_key in &unused {
+            self.remove(cache_key);
+        }
+        self.evictions += unused.len() as u64;
+
+        let min_size = self.initial_size;
+        let allocated_space: u64 = self
+            .packers
+            .iter()
+            .map(|packer| packer.allocated_space().max(0) as u64)
+            .sum();
+
+        // The height grows last, so it shrinks first
+        let mut sizes = Vec::new();
+        let (mut width, mut height) = (self.width, self.height);
+        loop {
+            if height >= width {
+                height /= self.growth_factor;
+            } else {
+                width /= self.growth_factor;
+            }
+            if width < min_size || height < min_size {
+                break;
+            }
+            sizes.push((width, height));
+        }
+
+        // Try the smallest sizes first
+        for &(width, height) in sizes.iter().rev() {
+            if (width as u64 * height as u64) < allocated_space {
+                continue;
+            }
+
+            if self.repack(device, queue, (width, height)) {
+                return true;
+            }
+        }
+
+        // Repacking at the same size drops the layers that are no longer needed
+        let layers = self.layers();
+        layers > 1
+            && self.repack(device, queue, (self.width, self.height))
+            && self.layers() < layers
+    }
+
+    /// Repacks all glyphs into a new texture of the given size, copying them on the GPU so that
+    /// nothing needs to be rasterized again. The glyphs are packed into as few layers as
+    /// possible.
+    ///
+    /// If the glyphs don't fit into a single layer of a smaller texture, the atlas is left
+    /// unchanged and `false` is returned. Glyphs that no longer fit into a texture of the current
+    /// size are removed.
+    fn repack(&mut self, device: &Device, queue: &Queue, (width, height): (u32, u32)) -> bool {
+        if self.external {
+            return false;
+        }
+        trace_span!("glyphon::repack", kind = ?self.kind, width, height);
+        // The staged glyphs are copied along with the others
+        self.flush_uploads(device, queue);
+
+        let same_size = width >= self.width && height >= self.height;
+        let max_layers = if same_size { self.layers() } else { 1 };
+        let mut packers = vec![BucketedAtlasAllocator::new(size2(
+            width as i32,
+            height as i32,
+        ))];
+
+        // Place the tallest glyphs first, which packs them into fewer shelves
+        let mut glyphs: Vec<(GlyphonCacheKey, u16, u16)> = self
+            .glyph_cache
+            .iter()
+            .filter_map(|(&cache_key, glyph)| match glyph.gpu_cache {
+                GpuCacheStatus::InAtlas { .. } => Some((cache_key, glyph.width, glyph.height)),
+                GpuCacheStatus::SkipRasterization => None,
+            })
+            .collect();
+        glyphs.sort_by_key(|&(_, _, height)| std::cmp::Reverse(height));
+
+        let mut moves = Vec::with_capacity(glyphs.len());
+        let mut lost_glyphs = Vec::new();
+
+        for (cache_key, glyph_width, glyph_height) in glyphs {
+            let (glyph_width, glyph_height) = (glyph_width as usize, glyph_height as usize);
+            let mut allocation = None;
+            for (layer, packer) in packers.iter_mut().enumerate() {
+                if let Some(a) = allocate_padded(packer, glyph_width, glyph_height, self.padding) {
+                    allocation = Some((layer as u16, a));
+                    break;
+                }
+            }
+
+            if allocation.is_none() && (packers.len() as u32) < max_layers {
+                let mut packer = BucketedAtlasAllocator::new(size2(width as i32, height as i32));
+                allocation = allocate_padded(&mut packer, glyph_width, glyph_height, self.padding)
+                    .map(|a| (packers.len() as u16, a));
+                packers.push(packer);
+            }
+
+            match allocation {
+                Some(allocation) => moves.push((cache_key, allocation)),
+                None if same_size => lost_glyphs.push(cache_key),
+                None => return false,
+            }
+        }
+
+        let texture = Self::create_texture(
+            device,
+            self.kind,
+            width,
+            height,
+            (packers.len() as u32).max(self.min_layers),
+            self.mipmaps,
+        );
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("glyphon atlas repack"),
+        });
+        let padding = self.padding as u32;
+
+        for (cache_key, (new_layer, allocation)) in moves {
+            let Some(glyph) = self.glyph_cache.peek_mut(&cache_key) else {
+                continue;
+            };
+            let GpuCacheStatus::InAtlas {
+                x,
+                y,
+                layer,
+                content_type,
+            } = glyph.gpu_cache
+            else {
+                continue;
+            };
+            let new_min = allocation.rectangle.min;
+
+            encoder.copy_texture_to_texture(
+                TexelCopyTextureInfo {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: x as u32 - padding,
+                        y: y as u32 - padding,
+                        z: layer as u32,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: new_min.x as u32 - padding,
+                        y: new_min.y as u32 - padding,
+                        z: new_layer as u32,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                Extent3d {
+                    width: glyph.width as u32 + 2 * padding,
+                    height: glyph.height as u32 + 2 * padding,
+                    depth_or_array_layers: 1,
+                },
+            );
+
+            glyph.gpu_cache = GpuCacheStatus::InAtlas {
+                x: new_min.x as u16,
+                y: new_min.y as u16,
+                layer: new_layer,
+                content_type,
+            };
+            glyph.atlas_id = Some(allocation.id);
+        }
+
+        queue.submit(Some(encoder.finish()));
+
+        // The old allocations are gone along with the old packers
+        for cache_key in &lost_glyphs {
+            if let Some(glyph) = self.glyph_cache.peek_mut(cache_key) {
+                glyph.atlas_id = None;
+            }
+            self.remove(cache_key);
+        }
+
+        self.packers = packers;
+        self.fragmentation = None;
+        self.texture = texture;
+        self.texture_view = Self::create_texture_view(&self.texture);
+        self.mipmaps_dirty = true;
+        self.width = width;
+        self.height = height;
+        self.generation += 1;
+
+        true
     }
 
     pub fn num_channels(&self) -> usize {
         self.kind.num_channels()
     }
 
+    /// Copies the texture into a CPU buffer, blocking until the GPU is done.
+    pub(crate) fn download(
+        &self,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<AtlasImage, DownloadError> {
+        let layers = self.layers();
+        let unpadded_bytes_per_row = self.width * self.num_channels() as u32;
+        let bytes_per_row = unpadded_bytes_per_row.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("glyphon atlas download"),
+            size: bytes_per_row as u64 * self.height as u64 * layers as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("glyphon atlas download"),
+        });
+        encoder.copy_texture_to_buffer(
+            TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: self.origin.0,
+                    y: self.origin.1,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: layers,
+            },
+        );
+        let submission = queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device
+            .poll(PollType::WaitForSubmissionIndex(submission))
+            .map_err(|_| DownloadError::MapFailed)?;
+        receiver
+            .recv()
+            .map_err(|_| DownloadError::MapFailed)?
+            .map_err(|_| DownloadError::MapFailed)?;
+
+        // Strip the row padding required by the copy
+        let mut data = Vec::with_capacity(unpadded_bytes_per_row as usize * self.height as usize);
+        {
+            let mapped = slice.get_mapped_range();
+            for row in mapped.chunks(bytes_per_row as usize) {
+                data.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        buffer.unmap();
+
+        let rectangles = self
+            .glyph_cache
+            .iter()
+            .filter_map(|(_, glyph)| match glyph.gpu_cache {
+                GpuCacheStatus::InAtlas { x, y, layer, .. } => Some(AtlasRect {
+                    layer: layer as u32,
+                    x: x as u32 - self.origin.0,
+                    y: y as u32 - self.origin.1,
+                    width: glyph.width as u32,
+                    height: glyph.height as u32,
+                }),
+                GpuCacheStatus::SkipRasterization => None,
+            })
+            .collect();
+
+        Ok(AtlasImage {
+            width: self.width,
+            height: self.height,
+            layers,
+            format: self.kind.texture_format(),
+            data,
+            rectangles,
+        })
+    }
+
+    /// Collects the cached text glyphs along with their pixels, least recently used first.
+    fn persisted_glyphs(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        font_system: &FontSystem,
+        glyphs: &mut Vec<PersistedGlyph>,
+    ) -> Result<(), DownloadError> {
+        let image = self.download(device, queue)?;
+        let channels = self.num_channels();
+        let row_bytes = image.width as usize * channels;
+        let layer_bytes = row_bytes * image.height as usize;
+
+        for (cache_key, glyph) in self.glyph_cache.iter().rev() {
+            // Custom glyphs are rasterized by the application, which may render them differently
+            // in the next session
+            let GlyphonCacheKey::Text(cache_key) = *cache_key else {
+                continue;
+            };
+            let Some(face) = font_system.db().face(cache_key.font_id) else {
+                continue;
+            };
+
+            let (content, data) = match glyph.gpu_cache {
+                GpuCacheStatus::InAtlas {
+                    x,
+                    y,
+                    layer,
+                    content_type,
+                } => {
+                    let (x, y) = (x as u32 - self.origin.0, y as u32 - self.origin.1);
+                    let mut data =
+                        Vec::with_capacity(glyph.width as usize * glyph.height as usize * channels);
+                    for row in y as usize..y as usize + glyph.height as usize {
+                        let start =
+                            layer as usize * layer_bytes + row * row_bytes + x as usize * channels;
+                        data.extend_from_slice(
+                            &image.data[start..start + glyph.width as usize * channels],
+                        );
+                    }
+
+                    let content = match content_type {
+                        ContentType::Mask => PersistedContent::Mask,
+                        ContentType::Color => PersistedContent::Color,
+                        ContentType::SubpixelMask => PersistedContent::SubpixelMask,
+                        // Distance fields are only cached under SDF keys
+                        ContentType::Sdf | ContentType::Msdf => continue,
+                    };
+                    // Mask glyphs of a merged atlas only keep their alpha channel
+                    if content == PersistedContent::Mask && channels == 4 {
+                        data = data.chunks_exact(4).map(|pixel| pixel[3]).collect();
+                    }
+                    (content, data)
+                }
+                GpuCacheStatus::SkipRasterization => (PersistedContent::Empty, Vec::new()),
+            };
+
+            glyphs.push(PersistedGlyph {
+                content,
+                post_script_name: face.post_script_name.clone(),
+                face_index: face.index,
+                cache_key,
+                width: glyph.width,
+                height: glyph.height,
+                top: glyph.top,
+                left: glyph.left,
+                data,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Uploads a persisted glyph and inserts it into the cache without marking it as in use.
+    /// Returns `false` if the glyph doesn't fit into the atlas without growing it.
+    fn restore(&mut self, queue: &Queue, glyph: PersistedGlyph) -> bool {
+        let (gpu_cache, atlas_id) = if glyph.content == PersistedContent::Empty {
+            (GpuCacheStatus::SkipRasterization, None)
+        } else {
+            let content_type = match glyph.content {
+                PersistedContent::Mask => ContentType::Mask,
+                PersistedContent::SubpixelMask => ContentType::SubpixelMask,
+                PersistedContent::Color | PersistedContent::Empty => ContentType::Color,
+            };
+            self.make_room_in_budget(glyph.width, glyph.height);
+            let Some((layer, allocation)) =
+                self.try_allocate(glyph.width as usize, glyph.height as usize)
+            else {
+                return false;
+            };
+            let atlas_min = allocation.rectangle.min;
+
+            self.upload(
+                queue,
+                Origin3d {
+                    x: atlas_min.x as u32,
+                    y: atlas_min.y as u32,
+                    z: layer as u32,
+                },
+                glyph.width,
+                glyph.height,
+                content_type,
+                &glyph.data,
+            );
+            self.used_bytes += self.glyph_bytes(glyph.width, glyph.height);
+            self.mipmaps_dirty = true;
+
+            (
+                GpuCacheStatus::InAtlas {
+                    x: atlas_min.x as u16,
+                    y: atlas_min.y as u16,
+                    layer,
+                    content_type,
+                },
+                Some(allocation.id),
+            )
+        };
+
+        self.glyph_cache.put(
+            GlyphonCacheKey::Text(glyph.cache_key),
+            GlyphDetails {
+                width: glyph.width,
+                height: glyph.height,
+                gpu_cache,
+                atlas_id,
+                top: glyph.top,
+                left: glyph.left,
+                last_used_trim: self.trims,
+            },
+        );
+
+        true
+    }
+
+    pub(crate) fn stats(&self) -> AtlasTextureStats {
+        let total_space = self.width as u64 * self.height as u64 * self.layers() as u64;
+        let allocated_space: u64 = self
+            .packers
+            .iter()
+            .map(|packer| packer.allocated_space().max(0) as u64)
+            .sum();
+
+        AtlasTextureStats {
+            width: self.width,
+            height: self.height,
+            layers: self.layers(),
+            glyphs: self.glyph_cache.len(),
+            glyphs_in_use: self.glyphs_in_use.len(),
+            allocated_rectangles: self
+                .glyph_cache
+                .iter()
+                .filter(|(_, glyph)| glyph.atlas_id.is_some())
+                .count(),
+            occupancy: allocated_space as f32 / total_space as f32,
+            used_bytes: self.used_bytes,
+            evictions: self.evictions,
+        }
+    }
+
+    pub(crate) fn grow_cost_estimate(&self) -> GrowCost {
+        // Adding a layer copies the existing layers on the GPU instead of uploading them again
+        if self.external || (self.width >= self.max_size && self.height >= self.max_size) {
+            return GrowCost::default();
+        }
+
+        let mut cost = GrowCost::default();
+        for (_, glyph) in &self.glyph_cache {
+            if let GpuCacheStatus::InAtlas { .. } = glyph.gpu_cache {
+                cost.glyphs += 1;
+                cost.bytes += glyph.width as u64 * glyph.height as u64 * self.num_channels() as u64;
+            }
+        }
+
+        cost
+    }
+
     pub(crate) fn grow(
         &mut self,
         device: &wgpu::Device,
@@ -94,44 +1268,110 @@ impl InnerAtlas {
         mut rasterize_custom_glyph: impl FnMut(
             RasterizeCustomGlyphRequest,
         ) -> Option<RasterizedCustomGlyph>,
-    ) -> bool {
-        if self.size >= self.max_texture_dimension_2d {
-            return false;
+    ) -> Result<(), AtlasError> {
+        trace_span!("glyphon::grow", kind = ?self.kind);
+
+        // The texture of an external atlas belongs to the application
+        if self.external {
+            return Err(AtlasError::MaxSizeReached);
+        }
+
+        if self.width >= self.max_size && self.height >= self.max_size {
+            // The texture can't get any larger, so add a layer instead
+            return self.add_layer(device, queue);
         }
 
-        // Grow each dimension by a factor of 2. The growth factor was chosen to match the growth
-        // factor of `Vec`.`
-        const GROWTH_FACTOR: u32 = 2;
-        let new_size = (self.size * GROWTH_FACTOR).min(self.max_texture_dimension_2d);
+        // Grow one dimension at a time, the width first, so that the texture never takes more
+        // than `growth_factor` times the memory it took before
+        let grow = |size: u32| size.saturating_mul(self.growth_factor).min(self.max_size);
+        let grow_width = self.width < self.max_size
+            && (self.width <= self.height || self.height >= self.max_size);
+        let (new_width, new_height) = if grow_width {
+            (grow(self.width), self.height)
+        } else {
+            (self.width, grow(self.height))
+        };
+        trace_event!(
+            kind = ?self.kind,
+            width = new_width,
+            height = new_height,
+            "growing atlas texture"
+        );
+
+        for packer in &mut self.packers {
+            packer.grow(size2(new_width as i32, new_height as i32));
+        }
+        self.fragmentation = None;
 
-        self.packer.grow(size2(new_size as i32, new_size as i32));
+        // All glyphs are uploaded to the new texture below, including the staged ones
+        self.staged_uploads.clear();
+        self.staged_copies.clear();
 
         // Create a texture to use for our atlas
-        self.texture = device.create_texture(&TextureDescriptor {
-            label: Some("glyphon atlas"),
-            size: Extent3d {
-                width: new_size,
-                height: new_size,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: self.kind.texture_format(),
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
+        self.texture = Self::create_texture(
+            device,
+            self.kind,
+            new_width,
+            new_height,
+            self.layers().max(self.min_layers),
+            self.mipmaps,
+        );
+
+        // Glyphs that can no longer be rasterized (e.g. because their font was removed from the
+        // `FontSystem`) are dropped from the cache instead of being re-uploaded.
+        let mut lost_glyphs = Vec::new();
+        let mut rasterizer_mismatch = false;
+        let mut uploads = Vec::new();
 
         // Re-upload glyphs
         for (&cache_key, glyph) in &self.glyph_cache {
-            let (x, y) = match glyph.gpu_cache {
-                GpuCacheStatus::InAtlas { x, y, .. } => (x, y),
+            let (x, y, layer, content_type) = match glyph.gpu_cache {
+                GpuCacheStatus::InAtlas {
+                    x,
+                    y,
+                    layer,
+                    content_type,
+                } => (x, y, layer, content_type),
                 GpuCacheStatus::SkipRasterization => continue,
             };
 
             let (image_data, width, height) = match cache_key {
-                GlyphonCacheKey::Text(cache_key) => {
-                    let image = cache.get_image_uncached(font_system, cache_key).unwrap();
+                GlyphonCacheKey::Text(text_cache_key)
+                | GlyphonCacheKey::Rasterized(text_cache_key, _) => {
+                    let rasterization = match cache_key {
+                        GlyphonCacheKey::Rasterized(_, rasterization) => rasterization,
+                        _ => GlyphRasterization::default(),
+                    };
+                    let Some(image) = rasterize_text_glyph(
+                        cache,
+                        font_system,
+                        text_cache_key,
+                        rasterization,
+                        self.share_rasterized_glyphs,
+                        content_type == ContentType::SubpixelMask,
+                    ) else {
+                        lost_glyphs.push(cache_key);
+                        continue;
+                    };
+                    let width = image.placement.width as usize;
+                    let height = image.placement.height as usize;
+
+                    (image.data, width, height)
+                }
+                GlyphonCacheKey::Sdf(text_cache_key) | GlyphonCacheKey::Msdf(text_cache_key) => {
+                    let rasterize = match cache_key {
+                        GlyphonCacheKey::Msdf(_) => rasterize_msdf_glyph,
+                        _ => rasterize_sdf_glyph,
+                    };
+                    let Some(image) = rasterize(
+                        cache,
+                        font_system,
+                        text_cache_key,
+                        self.share_rasterized_glyphs,
+                    ) else {
+                        lost_glyphs.push(cache_key);
+                        continue;
+                    };
                     let width = image.placement.width as usize;
                     let height = image.placement.height as usize;
 
@@ -145,14 +1385,26 @@ impl InnerAtlas {
                         x_bin: cache_key.x_bin,
                         y_bin: cache_key.y_bin,
                         scale: scale_factor,
+                        content_type_hint: cache_key.content_type_hint,
                     };
 
+                    // The rest of the atlas is still re-uploaded when the rasterizer doesn't
+                    // reproduce a glyph, so that the atlas stays usable
                     let Some(rasterized_glyph) = (rasterize_custom_glyph)(input) else {
-                        panic!("Custom glyph rasterizer returned `None` when it previously returned `Some` for the same input {:?}", &input);
+                        rasterizer_mismatch = true;
+                        lost_glyphs.push(GlyphonCacheKey::Custom(cache_key));
+                        continue;
                     };
 
                     // Sanity checks on the rasterizer output
-                    rasterized_glyph.validate(&input, Some(self.kind.as_content_type()));
+                    rasterized_glyph.validate(&input);
+                    let rasterized_glyph =
+                        rasterized_glyph.with_content_type_hint(cache_key.content_type_hint);
+                    if rasterized_glyph.content_type != content_type {
+                        rasterizer_mismatch = true;
+                        lost_glyphs.push(GlyphonCacheKey::Custom(cache_key));
+                        continue;
+                    }
 
                     (
                         rasterized_glyph.data,
@@ -162,39 +1414,174 @@ impl InnerAtlas {
                 }
             };
 
-            queue.write_texture(
-                TexelCopyTextureInfo {
-                    texture: &self.texture,
-                    mip_level: 0,
-                    origin: Origin3d {
-                        x: x as u32,
-                        y: y as u32,
-                        z: 0,
-                    },
-                    aspect: TextureAspect::All,
-                },
-                &image_data,
-                TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(width as u32 * self.kind.num_channels() as u32),
-                    rows_per_image: None,
-                },
-                Extent3d {
-                    width: width as u32,
-                    height: height as u32,
-                    depth_or_array_layers: 1,
-                },
-            );
+            let origin = Origin3d {
+                x: x as u32,
+                y: y as u32,
+                z: layer as u32,
+            };
+            uploads.push((
+                origin,
+                width as u16,
+                height as u16,
+                content_type,
+                image_data,
+            ));
+        }
+
+        for (origin, width, height, content_type, image_data) in uploads {
+            self.stage_upload(origin, width, height, content_type, &image_data);
         }
+        self.flush_uploads(device, queue);
 
-        self.texture_view = self.texture.create_view(&TextureViewDescriptor::default());
-        self.size = new_size;
+        for cache_key in &lost_glyphs {
+            self.remove(cache_key);
+        }
 
-        true
+        self.texture_view = Self::create_texture_view(&self.texture);
+        self.mipmaps_dirty = true;
+        self.width = new_width;
+        self.height = new_height;
+
+        if rasterizer_mismatch {
+            return Err(AtlasError::RasterizerMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Adds a layer to the texture array, copying the existing layers on the GPU.
+    fn add_layer(&mut self, device: &Device, queue: &Queue) -> Result<(), AtlasError> {
+        // The staged glyphs are copied along with the existing layers
+        self.flush_uploads(device, queue);
+
+        let layers = self.layers();
+        if layers >= self.max_texture_array_layers {
+            return Err(AtlasError::MaxSizeReached);
+        }
+        trace_event!(kind = ?self.kind, layers = layers + 1, "adding atlas layer");
+
+        let packer = BucketedAtlasAllocator::new(size2(self.width as i32, self.height as i32));
+        // The texture of a downlevel atlas may already have an unused layer
+        if self.texture.depth_or_array_layers() > layers {
+            self.packers.push(packer);
+            self.fragmentation = None;
+            return Ok(());
+        }
+
+        let texture = Self::create_texture(
+            device,
+            self.kind,
+            self.width,
+            self.height,
+            (layers + 1).max(self.min_layers),
+            self.mipmaps,
+        );
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("glyphon atlas add layer"),
+        });
+        encoder.copy_texture_to_texture(
+            self.texture.as_image_copy(),
+            texture.as_image_copy(),
+            Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: layers,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        self.packers.push(packer);
+        self.fragmentation = None;
+        self.texture = texture;
+        self.texture_view = Self::create_texture_view(&self.texture);
+        self.mipmaps_dirty = true;
+
+        Ok(())
     }
 
     fn trim(&mut self) {
+        self.trims += 1;
+        for cache_key in &self.glyphs_in_use {
+            if let Some(glyph) = self.glyph_cache.peek_mut(cache_key) {
+                glyph.last_used_trim = self.trims;
+            }
+        }
         self.glyphs_in_use.clear();
+
+        match self.trim_policy {
+            TrimPolicy::KeepAll => {}
+            TrimPolicy::MaxAge(max_age) => {
+                let expired: Vec<GlyphonCacheKey> = self
+                    .glyph_cache
+                    .iter()
+                    .filter(|(_, glyph)| self.trims - glyph.last_used_trim >= max_age as u64)
+                    .map(|(&cache_key, _)| cache_key)
+                    .collect();
+
+                for cache_key in &expired {
+                    self.remove(cache_key);
+                }
+                self.evictions += expired.len() as u64;
+                if !expired.is_empty() {
+                    trace_event!(
+                        kind = ?self.kind,
+                        glyphs = expired.len(),
+                        "evicted expired glyphs"
+                    );
+                }
+            }
+            TrimPolicy::MaxGlyphs(max_glyphs) => {
+                while self.glyph_cache.len() > max_glyphs && self.evict_lru() {}
+            }
+        }
+    }
+
+    /// Removes a glyph from the cache and frees its space in the packer.
+    pub(crate) fn remove(&mut self, cache_key: &GlyphonCacheKey) -> bool {
+        let Some(details) = self.glyph_cache.pop(cache_key) else {
+            return false;
+        };
+        self.generation += 1;
+
+        if let GpuCacheStatus::InAtlas { layer, .. } = details.gpu_cache {
+            if let Some(atlas_id) = details.atlas_id {
+                self.packers[layer as usize].deallocate(atlas_id);
+                self.fragmentation = None;
+            }
+            self.used_bytes = self
+                .used_bytes
+                .saturating_sub(self.glyph_bytes(details.width, details.height));
+        }
+        self.glyphs_in_use.remove(cache_key);
+
+        if let Some(listener) = &self.eviction_listener {
+            (listener.lock().expect("Lock eviction listener"))(*cache_key);
+        }
+
+        true
+    }
+
+    fn evict_font(&mut self, font_id: fontdb::ID) {
+        let cache_keys: Vec<GlyphonCacheKey> = self
+            .glyph_cache
+            .iter()
+            .filter_map(|(cache_key, _)| match cache_key {
+                GlyphonCacheKey::Text(key)
+                | GlyphonCacheKey::Rasterized(key, _)
+                | GlyphonCacheKey::Sdf(key)
+                | GlyphonCacheKey::Msdf(key)
+                    if key.font_id == font_id =>
+                {
+                    Some(*cache_key)
+                }
+                _ => None,
+            })
+            .collect();
+
+        for cache_key in &cache_keys {
+            self.remove(cache_key);
+        }
     }
 }
 
@@ -202,19 +1589,37 @@ impl InnerAtlas {
 pub(crate) enum Kind {
     Mask,
     Color { srgb: bool },
+    Sdf,
 }
 
-impl Kind {
+impl Kind {
+    fn for_color_mode(color_mode: ColorMode) -> Self {
+        Kind::Color {
+            srgb: match color_mode {
+                ColorMode::Accurate | ColorMode::Hdr => true,
+                ColorMode::Web | ColorMode::Custom(_) => false,
+            },
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Mask => "glyphon mask atlas",
+            Kind::Color { .. } => "glyphon color atlas",
+            Kind::Sdf => "glyphon sdf atlas",
+        }
+    }
+
     fn num_channels(self) -> usize {
         match self {
-            Kind::Mask => 1,
+            Kind::Mask | Kind::Sdf => 1,
             Kind::Color { .. } => 4,
         }
     }
 
     fn texture_format(self) -> wgpu::TextureFormat {
         match self {
-            Kind::Mask => TextureFormat::R8Unorm,
+            Kind::Mask | Kind::Sdf => TextureFormat::R8Unorm,
             Kind::Color { srgb } => {
                 if srgb {
                     TextureFormat::Rgba8UnormSrgb
@@ -224,17 +1629,10 @@ impl Kind {
             }
         }
     }
-
-    fn as_content_type(&self) -> ContentType {
-        match self {
-            Self::Mask => ContentType::Mask,
-            Self::Color { .. } => ContentType::Color,
-        }
-    }
 }
 
 /// The color mode of a [`TextAtlas`].
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ColorMode {
     /// Accurate color management.
     ///
@@ -254,16 +1652,391 @@ pub enum ColorMode {
     /// This mode should be used to render to a linear RGB texture containing
     /// sRGB colors.
     Web,
+
+    /// Custom color management.
+    ///
+    /// Colored glyphs are stored in a linear RGB texture, and the shader decodes
+    /// the colors of the text and of colored glyphs with the given transfer
+    /// function. Blending happens on the decoded values.
+    ///
+    /// This mode can be used to match the color pipeline of an engine that
+    /// doesn't use sRGB, e.g. one that encodes colors with a gamma of 1.8.
+    Custom(TransferFunction),
+
+    /// HDR color management.
+    ///
+    /// Colored glyphs are stored in a proper sRGB texture and the colors of the text are
+    /// linearized like with [`ColorMode::Accurate`], producing linear values that can be
+    /// composited into a floating-point render target such as `Rgba16Float`.
+    ///
+    /// Use [`TextRenderer::set_brightness`] to scale the text to the brightness of the rest of
+    /// the HDR scene.
+    ///
+    /// [`TextRenderer::set_brightness`]: crate::TextRenderer::set_brightness
+    Hdr,
+}
+
+/// A transfer function that decodes encoded color values to linear values, see
+/// [`ColorMode::Custom`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferFunction {
+    /// The sRGB transfer function.
+    Srgb,
+    /// A power function, `linear = encoded ^ gamma`.
+    Gamma(f32),
+    /// A parametric curve: `linear = (a * encoded + b) ^ g` if `encoded >= d`, and
+    /// `linear = c * encoded` otherwise.
+    ///
+    /// The sRGB transfer function is `g = 2.4`, `a = 1 / 1.055`, `b = 0.055 / 1.055`,
+    /// `c = 1 / 12.92` and `d = 0.04045`.
+    Parametric {
+        g: f32,
+        a: f32,
+        b: f32,
+        c: f32,
+        d: f32,
+    },
+}
+
+impl TransferFunction {
+    fn params(self) -> TransferParams {
+        let (g, a, b, c, d) = match self {
+            TransferFunction::Srgb => (2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045),
+            TransferFunction::Gamma(gamma) => (gamma, 1.0, 0.0, 0.0, 0.0),
+            TransferFunction::Parametric { g, a, b, c, d } => (g, a, b, c, d),
+        };
+
+        TransferParams {
+            g,
+            a,
+            b,
+            c,
+            d,
+            _pad: [0; 3],
+        }
+    }
+}
+
+/// Statistics about a [`TextAtlas`], as returned by [`TextAtlas::stats`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasStats {
+    /// Statistics about the texture containing mask glyphs.
+    pub mask: AtlasTextureStats,
+    /// Statistics about the texture containing color glyphs.
+    pub color: AtlasTextureStats,
+    /// Statistics about the texture containing the signed distance fields of glyphs.
+    pub sdf: AtlasTextureStats,
+}
+
+/// Statistics about one of the textures of a [`TextAtlas`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasTextureStats {
+    /// The width of the texture, in pixels.
+    pub width: u32,
+    /// The height of the texture, in pixels.
+    pub height: u32,
+    /// The number of layers of the texture array.
+    pub layers: u32,
+    /// The number of cached glyphs.
+    pub glyphs: usize,
+    /// The number of glyphs used since the last [`TextAtlas::trim`].
+    pub glyphs_in_use: usize,
+    /// The number of rectangles allocated in the texture. Empty glyphs, such as spaces, are
+    /// cached without allocating a rectangle.
+    pub allocated_rectangles: usize,
+    /// The fraction of the texture covered by allocated rectangles, from `0.0` to `1.0`.
+    pub occupancy: f32,
+    /// The space used by the cached glyphs, in bytes.
+    pub used_bytes: u64,
+    /// The number of glyphs evicted since the atlas was created, either to stay within the
+    /// [`AtlasBudget`] or by [`TextAtlas::trim_and_shrink`].
+    pub evictions: u64,
+}
+
+/// A copy of the textures of a [`TextAtlas`], as returned by [`TextAtlas::download`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtlasSnapshot {
+    /// The texture containing mask glyphs.
+    pub mask: AtlasImage,
+    /// The texture containing color glyphs.
+    pub color: AtlasImage,
+    /// The texture containing the signed distance fields of glyphs.
+    pub sdf: AtlasImage,
+}
+
+/// A copy of one of the textures of a [`TextAtlas`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtlasImage {
+    /// The width of each layer, in pixels.
+    pub width: u32,
+    /// The height of each layer, in pixels.
+    pub height: u32,
+    /// The number of layers of the texture array.
+    pub layers: u32,
+    /// The format of the texture.
+    pub format: TextureFormat,
+    /// The tightly packed pixels of each layer, one after another, in rows from top to bottom.
+    pub data: Vec<u8>,
+    /// The rectangles of the cached glyphs.
+    pub rectangles: Vec<AtlasRect>,
+}
+
+/// The rectangle of a glyph in an [`AtlasImage`], not including the padding around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasRect {
+    /// The layer of the texture array.
+    pub layer: u32,
+    /// The left edge of the rectangle, in pixels.
+    pub x: u32,
+    /// The top edge of the rectangle, in pixels.
+    pub y: u32,
+    /// The width of the rectangle, in pixels.
+    pub width: u32,
+    /// The height of the rectangle, in pixels.
+    pub height: u32,
+}
+
+/// The work that growing an atlas would cause, as returned by
+/// [`TextAtlas::grow_cost_estimate`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct GrowCost {
+    /// The number of glyphs that would be rasterized and uploaded again.
+    pub glyphs: usize,
+    /// The total size in bytes of the glyph images that would be uploaded again.
+    pub bytes: u64,
+}
+
+/// Controls the size of the textures of a [`TextAtlas`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasConfig {
+    /// The initial width and height of the atlas textures, in pixels.
+    ///
+    /// Defaults to `4096`.
+    pub initial_size: u32,
+    /// The maximum width and height the atlas textures can grow to, in pixels. This is limited by
+    /// the `max_texture_dimension_2d` limit of the device.
+    ///
+    /// Defaults to `u32::MAX`, i.e. the device limit.
+    pub max_size: u32,
+    /// The factor to multiply the width or height of an atlas texture by when it grows. The
+    /// dimensions grow one at a time, the width first, e.g. from 4096x4096 to 8192x4096 to
+    /// 8192x8192. Values smaller than `2` are treated as `2`.
+    ///
+    /// Defaults to `2`, which matches the growth factor of `Vec`.
+    pub growth_factor: u32,
+    /// The amount of glyphs each atlas texture keeps cached.
+    ///
+    /// Defaults to [`AtlasBudget::Unbounded`].
+    pub budget: AtlasBudget,
+    /// The glyphs each atlas texture evicts when the atlas is trimmed.
+    ///
+    /// Defaults to [`TrimPolicy::KeepAll`].
+    pub trim_policy: TrimPolicy,
+    /// Whether to generate mip levels for the atlas textures, so that text rendered minified
+    /// (e.g. on world-space quads that shrink with distance) is sampled trilinearly instead of
+    /// aliasing.
+    ///
+    /// The mip levels are regenerated during [`TextRenderer::prepare`] whenever glyphs were
+    /// uploaded. Glyphs are separated by their [`padding`](Self::padding), so they only start to
+    /// bleed into each other at the mip levels where the padding shrinks below a pixel.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// [`TextRenderer::prepare`]: crate::TextRenderer::prepare
+    pub mipmaps: bool,
+    /// The empty space around each glyph in the atlas, in pixels.
+    ///
+    /// Text shadows can only extend as far as the padding, so use `0` to pack the glyphs as
+    /// densely as possible when no shadows are drawn, or a larger value for large shadow radii.
+    ///
+    /// Defaults to [`SHADOW_MARGIN_PX`].
+    pub padding: u16,
+    /// Whether to store mask glyphs in the alpha channel of the color atlas texture instead of a
+    /// separate mask texture.
+    ///
+    /// A single texture only reserves memory for one set of growth steps and one
+    /// [`AtlasBudget`], at the cost of storing mask glyphs with four bytes per pixel instead of
+    /// one. The mask statistics and snapshots of a merged atlas are empty.
+    ///
+    /// Defaults to `false`.
+    pub merged: bool,
+    /// Whether to rasterize mask glyphs with subpixel antialiasing for horizontal RGB displays,
+    /// which makes small text look sharper on desktop displays.
+    ///
+    /// Subpixel masks are stored in the color atlas and need dual-source blending, so this only
+    /// takes effect if the device was created with [`Features::DUAL_SOURCE_BLENDING`], and
+    /// mask glyphs are rasterized in grayscale otherwise. Subpixel antialiasing needs to blend
+    /// onto an opaque render target to look right.
+    ///
+    /// Defaults to `false`.
+    pub subpixel: bool,
+    /// Whether the atlas is used with a downlevel adapter, such as WebGL2 through the GL backend
+    /// of wgpu.
+    ///
+    /// The GL backend can only view textures with more than one layer as texture arrays, so the
+    /// atlas textures are then created with at least two layers, and the textures given to
+    /// [`TextAtlas::with_external_textures`] need two layers as well. The atlas textures are
+    /// always limited to the `max_texture_dimension_2d` and `max_texture_array_layers` limits of
+    /// the device, and features the device lacks, such as dual-source blending or push constants,
+    /// are never used.
+    ///
+    /// Defaults to `false`.
+    pub downlevel: bool,
+}
+
+/// A region of a texture owned by the application that a [`TextAtlas`] stores glyphs in.
+/// See [`TextAtlas::with_external_textures`].
+#[derive(Debug, Clone)]
+pub struct ExternalAtlasTexture {
+    /// The texture to store the glyphs in. Only its first layer is used.
+    pub texture: Texture,
+    /// The left edge of the region, in pixels.
+    pub x: u32,
+    /// The top edge of the region, in pixels.
+    pub y: u32,
+    /// The width of the region, in pixels.
+    pub width: u32,
+    /// The height of the region, in pixels.
+    pub height: u32,
+}
+
+/// The textures of a [`TextAtlas`] created with [`TextAtlas::with_external_textures`].
+#[derive(Debug, Clone)]
+pub struct ExternalAtlasTextures {
+    /// The region to store color glyphs in, such as emoji.
+    pub color: ExternalAtlasTexture,
+    /// The region to store mask glyphs in, such as regular text.
+    pub mask: ExternalAtlasTexture,
+}
+
+/// A read-only page of glyphs rasterized ahead of time, e.g. the glyphs of a fixed UI font
+/// compressed offline. See [`TextAtlas::set_prebaked_page`].
+#[derive(Debug, Clone)]
+pub struct PrebakedAtlasPage {
+    /// The texture containing the glyphs. Only its first layer is used, and it must have the
+    /// `TEXTURE_BINDING` usage.
+    pub texture: Texture,
+    /// The empty space around each glyph in the texture, in pixels. Text shadows can only extend
+    /// as far as the padding.
+    pub padding: u16,
+    /// The glyphs of the page.
+    pub glyphs: Vec<PrebakedGlyph>,
+}
+
+/// A glyph of a [`PrebakedAtlasPage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrebakedGlyph {
+    /// The key the glyph is prepared with.
+    pub cache_key: GlyphonCacheKey,
+    /// The left edge of the glyph in the texture, not including the padding, in pixels.
+    pub x: u16,
+    /// The top edge of the glyph in the texture, not including the padding, in pixels.
+    pub y: u16,
+    /// The width of the glyph, in pixels.
+    pub width: u16,
+    /// The height of the glyph, in pixels.
+    pub height: u16,
+    /// The offset from the pen position to the left edge of the glyph, in pixels, as in
+    /// [`SwashImage::placement`].
+    pub left: i16,
+    /// The offset from the baseline to the top edge of the glyph, in pixels, as in
+    /// [`SwashImage::placement`].
+    pub top: i16,
+}
+
+/// Limits the amount of glyphs cached in an atlas texture.
+///
+/// When a new glyph would exceed the budget, or doesn't fit into the atlas texture, the least
+/// recently used glyphs that were not used since the last [`TextAtlas::trim`] are evicted before
+/// the texture grows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AtlasBudget {
+    /// Glyphs are never evicted, the atlas grows instead.
+    #[default]
+    Unbounded,
+    /// The maximum number of cached glyphs.
+    Glyphs(usize),
+    /// The maximum atlas space used by the cached glyphs, in bytes.
+    Bytes(u64),
+}
+
+/// Controls which glyphs are evicted from an atlas texture by [`TextAtlas::trim`].
+///
+/// Unlike an [`AtlasBudget`], which evicts glyphs when new glyphs need the space during
+/// [`TextRenderer::prepare`], a trim policy evicts glyphs at a predictable point in the frame.
+///
+/// [`TextRenderer::prepare`]: crate::TextRenderer::prepare
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrimPolicy {
+    /// Glyphs are kept until the atlas needs their space.
+    #[default]
+    KeepAll,
+    /// Glyphs that were not used during the given number of trims are evicted. `MaxAge(1)`
+    /// evicts all glyphs that were not used since the last trim.
+    MaxAge(u32),
+    /// The least recently used glyphs are evicted until at most the given number of glyphs is
+    /// cached.
+    MaxGlyphs(usize),
+}
+
+impl AtlasConfig {
+    /// The least number of layers of the atlas textures.
+    fn min_layers(&self) -> u32 {
+        if self.downlevel {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+impl Default for AtlasConfig {
+    fn default() -> Self {
+        Self {
+            initial_size: 4096,
+            max_size: u32::MAX,
+            growth_factor: 2,
+            budget: AtlasBudget::Unbounded,
+            trim_policy: TrimPolicy::KeepAll,
+            mipmaps: false,
+            padding: SHADOW_MARGIN_PX,
+            merged: false,
+            subpixel: false,
+            downlevel: false,
+        }
+    }
 }
 
 /// An atlas containing a cache of rasterized glyphs that can be rendered.
 pub struct TextAtlas {
-    cache: Cache,
+    pub(crate) cache: Cache,
     pub(crate) bind_group: BindGroup,
     pub(crate) color_atlas: InnerAtlas,
     pub(crate) mask_atlas: InnerAtlas,
+    /// The signed distance fields of the glyphs of text areas with [`TextArea::sdf`] set.
+    ///
+    /// [`TextArea::sdf`]: crate::TextArea::sdf
+    pub(crate) sdf_atlas: InnerAtlas,
     pub(crate) format: TextureFormat,
     pub(crate) color_mode: ColorMode,
+    /// Whether mask glyphs are stored in the color atlas, see [`AtlasConfig::merged`].
+    pub(crate) merged: bool,
+    /// Whether text glyphs are rasterized as subpixel masks, see [`AtlasConfig::subpixel`].
+    pub(crate) subpixel: bool,
+    /// The transfer function of [`ColorMode::Custom`], unused by the other color modes.
+    transfer_params: Buffer,
+    prebaked_color: Option<PrebakedPage>,
+    prebaked_mask: Option<PrebakedPage>,
+    /// Incremented whenever the prebaked pages change.
+    prebaked_generation: u64,
+    compaction_threshold: f32,
+}
+
+/// A [`PrebakedAtlasPage`] with its glyphs ready to be looked up.
+struct PrebakedPage {
+    texture_view: TextureView,
+    padding: u16,
+    glyphs: HashMap<GlyphonCacheKey, GlyphDetails, Hasher>,
 }
 
 impl TextAtlas {
@@ -280,22 +2053,131 @@ impl TextAtlas {
         format: TextureFormat,
         color_mode: ColorMode,
     ) -> Self {
-        let color_atlas = InnerAtlas::new(
+        Self::with_config(
             device,
             queue,
-            Kind::Color {
-                srgb: match color_mode {
-                    ColorMode::Accurate => true,
-                    ColorMode::Web => false,
-                },
+            cache,
+            format,
+            color_mode,
+            AtlasConfig::default(),
+        )
+    }
+
+    /// Creates a new [`TextAtlas`] with the given [`ColorMode`] and [`AtlasConfig`].
+    pub fn with_config(
+        device: &Device,
+        _queue: &Queue,
+        cache: &Cache,
+        format: TextureFormat,
+        color_mode: ColorMode,
+        config: AtlasConfig,
+    ) -> Self {
+        let color_atlas = InnerAtlas::new(device, Kind::for_color_mode(color_mode), &config);
+        let mask_atlas = if config.merged {
+            // The mask texture stays bound but is never sampled
+            let placeholder = AtlasConfig {
+                initial_size: 1,
+                max_size: 1,
+                mipmaps: false,
+                ..config
+            };
+            InnerAtlas::new(device, Kind::Mask, &placeholder)
+        } else {
+            InnerAtlas::new(device, Kind::Mask, &config)
+        };
+
+        let mut atlas =
+            Self::with_atlases(device, cache, format, color_mode, color_atlas, mask_atlas);
+        atlas.merged = config.merged;
+        atlas.subpixel =
+            config.subpixel && device.features().contains(Features::DUAL_SOURCE_BLENDING);
+        atlas
+    }
+
+    /// Creates a new [`TextAtlas`] that stores its glyphs in regions of textures owned by the
+    /// application, e.g. to share the textures with a sprite packer.
+    ///
+    /// The atlas is confined to the given regions: it never grows, adds layers or compacts, and
+    /// [`AtlasConfig::initial_size`], [`AtlasConfig::max_size`], [`AtlasConfig::mipmaps`],
+    /// [`AtlasConfig::merged`] and [`AtlasConfig::subpixel`] are ignored. Glyphs that don't fit are evicted according to the
+    /// [`AtlasConfig::budget`], or fail to prepare with [`AtlasError::MaxSizeReached`]. The signed
+    /// distance fields of [`TextArea::sdf`](crate::TextArea::sdf) are still stored in a texture
+    /// owned by the atlas.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a texture doesn't have the format the atlas needs (`R8Unorm` for the mask
+    /// texture, and `Rgba8UnormSrgb` or `Rgba8Unorm` for the color texture depending on the
+    /// [`ColorMode`]), or if a region is out of the bounds of its texture. The textures must have
+    /// the `TEXTURE_BINDING` and `COPY_DST` usages.
+    pub fn with_external_textures(
+        device: &Device,
+        cache: &Cache,
+        format: TextureFormat,
+        color_mode: ColorMode,
+        config: AtlasConfig,
+        textures: ExternalAtlasTextures,
+    ) -> Self {
+        let color_atlas =
+            InnerAtlas::new_external(Kind::for_color_mode(color_mode), &config, textures.color);
+        let mask_atlas = InnerAtlas::new_external(Kind::Mask, &config, textures.mask);
+
+        Self::with_atlases(device, cache, format, color_mode, color_atlas, mask_atlas)
+    }
+
+    fn with_atlases(
+        device: &Device,
+        cache: &Cache,
+        format: TextureFormat,
+        color_mode: ColorMode,
+        color_atlas: InnerAtlas,
+        mask_atlas: InnerAtlas,
+    ) -> Self {
+        // Distance fields are rasterized at a single size, so they don't need mip levels
+        let sdf_atlas = InnerAtlas::new(
+            device,
+            Kind::Sdf,
+            &AtlasConfig {
+                initial_size: mask_atlas.initial_size,
+                max_size: mask_atlas.max_size,
+                growth_factor: mask_atlas.growth_factor,
+                budget: mask_atlas.budget,
+                trim_policy: mask_atlas.trim_policy,
+                mipmaps: false,
+                padding: mask_atlas.padding,
+                downlevel: mask_atlas.min_layers > 1,
+                ..AtlasConfig::default()
             },
         );
-        let mask_atlas = InnerAtlas::new(device, queue, Kind::Mask);
+
+        let params = match color_mode {
+            ColorMode::Custom(transfer_function) => transfer_function.params(),
+            ColorMode::Accurate | ColorMode::Web | ColorMode::Hdr => {
+                TransferFunction::Gamma(1.0).params()
+            }
+        };
+        let transfer_params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("glyphon transfer params"),
+            contents: unsafe {
+                slice::from_raw_parts(
+                    &params as *const TransferParams as *const u8,
+                    mem::size_of::<TransferParams>(),
+                )
+            },
+            usage: BufferUsages::UNIFORM,
+        });
 
         let bind_group = cache.create_atlas_bind_group(
             device,
-            &color_atlas.texture_view,
-            &mask_atlas.texture_view,
+            AtlasViews {
+                color: &color_atlas.texture_view,
+                mask: &mask_atlas.texture_view,
+                sdf: &sdf_atlas.texture_view,
+                // Stand in for the prebaked pages until they are set
+                prebaked_color: &color_atlas.texture_view,
+                prebaked_mask: &mask_atlas.texture_view,
+            },
+            &transfer_params,
         );
 
         Self {
@@ -303,14 +2185,379 @@ impl TextAtlas {
             bind_group,
             color_atlas,
             mask_atlas,
+            sdf_atlas,
             format,
             color_mode,
+            merged: false,
+            subpixel: false,
+            transfer_params,
+            prebaked_color: None,
+            prebaked_mask: None,
+            prebaked_generation: 0,
+            compaction_threshold: 0.5,
         }
     }
 
+    /// Marks all glyphs as unused and evicts glyphs according to the [`TrimPolicy`]. Call this
+    /// after rendering a frame.
     pub fn trim(&mut self) {
         self.mask_atlas.trim();
         self.color_atlas.trim();
+        self.sdf_atlas.trim();
+    }
+
+    /// Sets the [`TrimPolicy`] of the atlas, see [`AtlasConfig::trim_policy`].
+    pub fn set_trim_policy(&mut self, trim_policy: TrimPolicy) {
+        self.mask_atlas.trim_policy = trim_policy;
+        self.color_atlas.trim_policy = trim_policy;
+        self.sdf_atlas.trim_policy = trim_policy;
+    }
+
+    /// Sets a function that is called with the key of every glyph that is removed from the
+    /// atlas, e.g. to free auxiliary data kept per glyph.
+    ///
+    /// Glyphs are removed when they are evicted to stay within the [`AtlasBudget`] or to make
+    /// room for new glyphs, when unused glyphs are dropped by [`TextAtlas::trim_and_shrink`],
+    /// when they are evicted with [`TextAtlas::evict`] or [`TextAtlas::evict_font`], and when
+    /// they can't be rasterized again as the atlas grows.
+    pub fn set_eviction_listener(
+        &mut self,
+        listener: impl FnMut(GlyphonCacheKey) + Send + 'static,
+    ) {
+        let listener: EvictionListener = Arc::new(Mutex::new(listener));
+        self.mask_atlas.eviction_listener = Some(listener.clone());
+        self.sdf_atlas.eviction_listener = Some(listener.clone());
+        self.color_atlas.eviction_listener = Some(listener);
+    }
+
+    /// Removes the function set with [`TextAtlas::set_eviction_listener`].
+    pub fn clear_eviction_listener(&mut self) {
+        self.mask_atlas.eviction_listener = None;
+        self.sdf_atlas.eviction_listener = None;
+        self.color_atlas.eviction_listener = None;
+    }
+
+    /// Sets a read-only page of glyphs rasterized ahead of time, replacing the previous page of
+    /// the same content type.
+    ///
+    /// The glyphs of the page are rendered from its texture instead of being rasterized, so they
+    /// never take up space in the atlas. The page is a mask page if its texture has the
+    /// `Bc4RUnorm` format, and a color page if it has the `Bc7RgbaUnormSrgb` format with
+    /// [`ColorMode::Accurate`] and [`ColorMode::Hdr`] or the `Bc7RgbaUnorm` format with the other
+    /// color modes.
+    ///
+    /// The cache keys of text glyphs contain font ids, which are only valid within the
+    /// [`FontSystem`] that assigned them, so the glyphs of a page have to be mapped to the fonts
+    /// after they were loaded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the texture of the page doesn't have one of the formats above.
+    pub fn set_prebaked_page(&mut self, device: &Device, page: PrebakedAtlasPage) {
+        let color_format = match Kind::for_color_mode(self.color_mode) {
+            Kind::Color { srgb: true } => TextureFormat::Bc7RgbaUnormSrgb,
+            _ => TextureFormat::Bc7RgbaUnorm,
+        };
+        let content_type = match page.texture.format() {
+            TextureFormat::Bc4RUnorm => ContentType::Mask,
+            format if format == color_format => ContentType::Color,
+            format => panic!(
+                "Prebaked atlas page must have the format Bc4RUnorm or {color_format:?}, got {format:?}"
+            ),
+        };
+
+        let glyphs = page
+            .glyphs
+            .iter()
+            .map(|glyph| {
+                let details = GlyphDetails {
+                    width: glyph.width,
+                    height: glyph.height,
+                    gpu_cache: GpuCacheStatus::InAtlas {
+                        x: glyph.x,
+                        y: glyph.y,
+                        layer: 0,
+                        content_type,
+                    },
+                    atlas_id: None,
+                    top: glyph.top,
+                    left: glyph.left,
+                    last_used_trim: 0,
+                };
+                (glyph.cache_key, details)
+            })
+            .collect();
+        let page = Some(PrebakedPage {
+            texture_view: InnerAtlas::create_texture_view(&page.texture),
+            padding: page.padding,
+            glyphs,
+        });
+
+        match content_type {
+            ContentType::Color | ContentType::SubpixelMask | ContentType::Msdf => {
+                self.prebaked_color = page
+            }
+            ContentType::Mask | ContentType::Sdf => self.prebaked_mask = page,
+        }
+        self.prebaked_generation += 1;
+        self.rebind(device);
+    }
+
+    /// Removes the prebaked pages set with [`TextAtlas::set_prebaked_page`].
+    pub fn clear_prebaked_pages(&mut self, device: &Device) {
+        self.prebaked_color = None;
+        self.prebaked_mask = None;
+        self.prebaked_generation += 1;
+        self.rebind(device);
+    }
+
+    /// Sets whether rasterized text glyphs are kept in the image cache of the [`SwashCache`]
+    /// (disabled by default).
+    ///
+    /// When several atlases are prepared with the same [`SwashCache`], enabling this on each of
+    /// them rasterizes every glyph only once, including when an atlas grows. The images stay in
+    /// [`SwashCache::image_cache`] until it is cleared.
+    pub fn set_share_rasterized_glyphs(&mut self, share: bool) {
+        self.mask_atlas.share_rasterized_glyphs = share;
+        self.color_atlas.share_rasterized_glyphs = share;
+        self.sdf_atlas.share_rasterized_glyphs = share;
+    }
+
+    /// Evicts the glyphs that were not used since the last [`TextAtlas::trim`], trims the atlas,
+    /// and moves the remaining glyphs into smaller textures where they fit, down to the initial
+    /// atlas size. Returns whether any texture shrank.
+    ///
+    /// This reclaims GPU memory after a burst of text made the atlas grow. Like
+    /// [`TextAtlas::trim`], call it after rendering and before the next `prepare`, since it moves
+    /// glyphs that were already prepared.
+    pub fn trim_and_shrink(&mut self, device: &Device, queue: &Queue) -> bool {
+        let mask_shrank = self.mask_atlas.shrink(device, queue);
+        let color_shrank = self.color_atlas.shrink(device, queue);
+        let sdf_shrank = self.sdf_atlas.shrink(device, queue);
+        self.trim();
+
+        let shrank = mask_shrank || color_shrank || sdf_shrank;
+        if shrank {
+            self.rebind(device);
+        }
+
+        shrank
+    }
+
+    /// Repacks the glyphs of both atlases to defragment their free space, copying them on the GPU
+    /// so that nothing needs to be rasterized again.
+    ///
+    /// This avoids growing the atlas or [`AtlasError::MaxSizeReached`] errors when the free space is fragmented after a long session. It must be called after
+    /// rendering (e.g. along with [`TextAtlas::trim`]) and before the next `prepare`, since it
+    /// moves glyphs that were already prepared. See [`TextAtlas::maybe_compact`] to only compact
+    /// when needed.
+    pub fn compact(&mut self, device: &Device, queue: &Queue) {
+        self.mask_atlas.compact(device, queue);
+        self.color_atlas.compact(device, queue);
+        self.sdf_atlas.compact(device, queue);
+        self.rebind(device);
+    }
+
+    /// Sets how fragmented the free space of an atlas has to be before [`TextAtlas::maybe_compact`]
+    /// compacts it, from `0.0` to `1.0`. Defaults to `0.5`.
+    ///
+    /// The fragmentation compares the largest glyph that can still be allocated to the total free
+    /// space of the atlas.
+    pub fn set_compaction_threshold(&mut self, threshold: f32) {
+        self.compaction_threshold = threshold.clamp(0.0, 1.0);
+    }
+
+    /// Compacts the glyphs of each atlas whose free space is more fragmented than the compaction
+    /// threshold, and returns whether any atlas was compacted.
+    ///
+    /// Over a long session, glyphs being evicted and allocated again can fragment the atlas,
+    /// making it grow even though there is enough free space. Compacting moves the cached glyphs
+    /// on the GPU without rasterizing them again. This is cheap when nothing needs compacting, so
+    /// it can be called every frame, but it must be called after rendering (e.g. along with
+    /// [`TextAtlas::trim`]) and before the next `prepare`, since it moves glyphs that were
+    /// already prepared.
+    pub fn maybe_compact(&mut self, device: &Device, queue: &Queue) -> bool {
+        let mut did_compact = false;
+
+        for inner in [
+            &mut self.mask_atlas,
+            &mut self.color_atlas,
+            &mut self.sdf_atlas,
+        ] {
+            if inner.fragmentation() > self.compaction_threshold && inner.compact(device, queue) {
+                did_compact = true;
+            }
+        }
+
+        if did_compact {
+            self.rebind(device);
+        }
+
+        did_compact
+    }
+
+    /// Returns statistics about the atlas, e.g. for performance overlays or to tune how often the
+    /// atlas is trimmed.
+    pub fn stats(&self) -> AtlasStats {
+        AtlasStats {
+            mask: self.mask_atlas.stats(),
+            color: self.color_atlas.stats(),
+            sdf: self.sdf_atlas.stats(),
+        }
+    }
+
+    /// Copies both atlas textures into CPU memory, along with the rectangles of the cached
+    /// glyphs. This blocks until the GPU has finished the copy.
+    ///
+    /// This is meant for debugging the atlas, e.g. to visualize how glyphs are packed or to test
+    /// the atlas contents.
+    pub fn download(&self, device: &Device, queue: &Queue) -> Result<AtlasSnapshot, DownloadError> {
+        Ok(AtlasSnapshot {
+            mask: self.mask_atlas.download(device, queue)?,
+            color: self.color_atlas.download(device, queue)?,
+            sdf: self.sdf_atlas.download(device, queue)?,
+        })
+    }
+
+    /// Serializes the cached text glyphs, including their pixels, so they can be restored with
+    /// [`load_glyph_cache`](Self::load_glyph_cache) in a later session without rasterizing them
+    /// again. This blocks until the GPU has finished copying the atlas textures.
+    ///
+    /// Fonts are identified by their PostScript name and face index in the given
+    /// [`FontSystem`]. Custom glyphs and signed distance fields are not saved.
+    pub fn save_glyph_cache(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        font_system: &FontSystem,
+    ) -> Result<Vec<u8>, DownloadError> {
+        let mut glyphs = Vec::new();
+        self.mask_atlas
+            .persisted_glyphs(device, queue, font_system, &mut glyphs)?;
+        self.color_atlas
+            .persisted_glyphs(device, queue, font_system, &mut glyphs)?;
+
+        Ok(persist::encode(&glyphs))
+    }
+
+    /// Restores glyphs saved with [`save_glyph_cache`](Self::save_glyph_cache), so that they
+    /// don't have to be rasterized when they are first prepared. Returns the number of restored
+    /// glyphs.
+    ///
+    /// Glyphs whose font is not loaded in the given [`FontSystem`], glyphs that are already
+    /// cached, glyphs that don't fit into the atlas without growing it and mask glyphs saved with
+    /// a different [`AtlasConfig::subpixel`] setting are skipped. The restored glyphs are not in use, so they are evicted as usual if they aren't prepared
+    /// before the atlas needs the space.
+    pub fn load_glyph_cache(
+        &mut self,
+        queue: &Queue,
+        font_system: &FontSystem,
+        data: &[u8],
+    ) -> Result<usize, LoadGlyphCacheError> {
+        let fonts: HashMap<(&str, u32), fontdb::ID> = font_system
+            .db()
+            .faces()
+            .map(|face| ((face.post_script_name.as_str(), face.index), face.id))
+            .collect();
+        let glyphs = persist::decode(data, |name, index| fonts.get(&(name, index)).copied())
+            .ok_or(LoadGlyphCacheError::InvalidData)?;
+
+        let mut restored = 0;
+        for glyph in glyphs {
+            let cache_key = GlyphonCacheKey::Text(glyph.cache_key);
+            if self.mask_atlas.glyph_cache.contains(&cache_key)
+                || self.color_atlas.glyph_cache.contains(&cache_key)
+            {
+                continue;
+            }
+
+            // Subpixel and grayscale masks share their cache keys, so only the masks rasterized
+            // like the atlas rasterizes them now are restored
+            let subpixel_mismatch = match glyph.content {
+                PersistedContent::Mask => self.subpixel,
+                PersistedContent::SubpixelMask => !self.subpixel,
+                PersistedContent::Color | PersistedContent::Empty => false,
+            };
+            if subpixel_mismatch {
+                continue;
+            }
+
+            let inner = match glyph.content {
+                PersistedContent::Mask => self.inner_for_content_mut(ContentType::Mask),
+                PersistedContent::Color
+                | PersistedContent::SubpixelMask
+                | PersistedContent::Empty => &mut self.color_atlas,
+            };
+            if inner.restore(queue, glyph) {
+                restored += 1;
+            }
+        }
+
+        Ok(restored)
+    }
+
+    /// Estimates the work that growing the atlas for the given content type would cause right
+    /// now.
+    ///
+    /// Growing re-rasterizes and re-uploads every glyph in the atlas, so this can be used to
+    /// decide whether to [`trim`](Self::trim) the atlas first. Returns zero if the atlas is
+    /// already at its maximum size and can't grow.
+    pub fn grow_cost_estimate(&self, content_type: ContentType) -> GrowCost {
+        self.inner_for_content(content_type).grow_cost_estimate()
+    }
+
+    /// Returns the largest width and height of a glyph that can currently be allocated in the
+    /// atlas for the given content type, accounting for the padding around each glyph.
+    ///
+    /// If the atlas is still able to grow, this is the size limit of the atlas texture.
+    /// Otherwise, the remaining free space is probed for the largest square that fits.
+    pub fn max_allocatable(&mut self, content_type: ContentType) -> (u32, u32) {
+        self.inner_for_content_mut(content_type).max_allocatable()
+    }
+
+    /// Removes all cached glyphs that were rasterized from the font with the given `font_id`.
+    ///
+    /// Call this before removing a font from the [`FontSystem`], so that the atlas doesn't try
+    /// to rasterize glyphs of the missing font again when it grows.
+    pub fn evict_font(&mut self, font_id: fontdb::ID) {
+        self.mask_atlas.evict_font(font_id);
+        self.color_atlas.evict_font(font_id);
+        self.sdf_atlas.evict_font(font_id);
+    }
+
+    /// Returns whether the glyph with the given key is cached in the atlas or part of a prebaked
+    /// page.
+    pub fn contains(&self, cache_key: &GlyphonCacheKey) -> bool {
+        self.mask_atlas.glyph_cache.contains(cache_key)
+            || self.color_atlas.glyph_cache.contains(cache_key)
+            || self.sdf_atlas.glyph_cache.contains(cache_key)
+            || self.prebaked_glyph(cache_key).is_some()
+    }
+
+    /// Returns the view of the texture array that glyphs of the given content type are stored in,
+    /// which the quads of [`TextRenderer::quads`](crate::TextRenderer::quads) sample.
+    ///
+    /// Mask glyphs of a [merged](AtlasConfig::merged) atlas are stored in the alpha channel of
+    /// the color texture. The view is replaced when the atlas grows, so it must be fetched again
+    /// after each call to `prepare`.
+    pub fn texture_view(&self, content_type: ContentType) -> &TextureView {
+        &self.inner_for_content(content_type).texture_view
+    }
+
+    /// Removes the glyph with the given key from the atlas, so that it is rasterized again the
+    /// next time it is prepared. Returns whether the glyph was cached. Glyphs of prebaked pages
+    /// are never removed.
+    ///
+    /// This is useful to drop custom glyphs whose images changed, e.g. after switching the icon
+    /// theme. Text that was already prepared keeps rendering correctly until the freed space is
+    /// reused by the next [`TextRenderer::prepare`].
+    ///
+    /// [`TextRenderer::prepare`]: crate::TextRenderer::prepare
+    pub fn evict(&mut self, cache_key: &GlyphonCacheKey) -> bool {
+        let mask = self.mask_atlas.remove(cache_key);
+        let color = self.color_atlas.remove(cache_key);
+        let sdf = self.sdf_atlas.remove(cache_key);
+        mask || color || sdf
     }
 
     pub(crate) fn grow(
@@ -322,55 +2569,158 @@ impl TextAtlas {
         content_type: ContentType,
         scale_factor: f32,
         rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
-    ) -> bool {
-        let did_grow = match content_type {
-            ContentType::Mask => self.mask_atlas.grow(
-                device,
-                queue,
-                font_system,
-                cache,
-                scale_factor,
-                rasterize_custom_glyph,
-            ),
-            ContentType::Color => self.color_atlas.grow(
-                device,
-                queue,
-                font_system,
-                cache,
-                scale_factor,
-                rasterize_custom_glyph,
-            ),
-        };
+    ) -> Result<(), AtlasError> {
+        let result = self.inner_for_content_mut(content_type).grow(
+            device,
+            queue,
+            font_system,
+            cache,
+            scale_factor,
+            rasterize_custom_glyph,
+        );
 
-        if did_grow {
+        // The texture is replaced even if some custom glyphs couldn't be uploaded again
+        if result != Err(AtlasError::MaxSizeReached) {
             self.rebind(device);
         }
 
-        did_grow
+        result
+    }
+
+    /// Copies the glyphs staged while preparing text into the atlas textures.
+    pub(crate) fn flush_uploads(&mut self, device: &Device, queue: &Queue) {
+        for inner in [
+            &mut self.mask_atlas,
+            &mut self.color_atlas,
+            &mut self.sdf_atlas,
+        ] {
+            inner.flush_uploads(device, queue);
+        }
+    }
+
+    /// Regenerates the mip levels of the atlas textures that changed since the last call.
+    pub(crate) fn update_mipmaps(&mut self, device: &Device, queue: &Queue) {
+        for inner in [&mut self.mask_atlas, &mut self.color_atlas] {
+            if inner.mipmaps && inner.mipmaps_dirty {
+                self.cache.generate_mipmaps(device, queue, &inner.texture);
+                inner.mipmaps_dirty = false;
+            }
+        }
+    }
+
+    pub(crate) fn inner_for_content(&self, content_type: ContentType) -> &InnerAtlas {
+        match content_type {
+            ContentType::Mask if !self.merged => &self.mask_atlas,
+            ContentType::Sdf => &self.sdf_atlas,
+            _ => &self.color_atlas,
+        }
     }
 
     pub(crate) fn inner_for_content_mut(&mut self, content_type: ContentType) -> &mut InnerAtlas {
         match content_type {
-            ContentType::Color => &mut self.color_atlas,
-            ContentType::Mask => &mut self.mask_atlas,
+            ContentType::Mask if !self.merged => &mut self.mask_atlas,
+            ContentType::Sdf => &mut self.sdf_atlas,
+            _ => &mut self.color_atlas,
         }
     }
 
     pub(crate) fn get_or_create_pipeline(
         &self,
         device: &Device,
+        blend: BlendState,
+        write_mask: ColorWrites,
         multisample: MultisampleState,
         depth_stencil: Option<DepthStencilState>,
+        multiview: Option<NonZeroU32>,
+        instances: InstanceLayout,
     ) -> RenderPipeline {
-        self.cache
-            .get_or_create_pipeline(device, self.format, multisample, depth_stencil)
+        self.cache.get_or_create_pipeline(
+            device,
+            ColorTargetState {
+                format: self.format,
+                blend: Some(blend),
+                write_mask,
+            },
+            multisample,
+            depth_stencil,
+            multiview,
+            instances,
+        )
+    }
+
+    /// Returns a number that changes whenever prepared glyphs may have been removed from the atlas
+    /// or moved within it.
+    pub(crate) fn generation(&self) -> u64 {
+        self.mask_atlas.generation
+            + self.color_atlas.generation
+            + self.sdf_atlas.generation
+            + self.prebaked_generation
+    }
+
+    /// Marks a glyph that was prepared before as in use, so that it isn't evicted before the
+    /// next trim.
+    pub(crate) fn mark_glyph_in_use(&mut self, cache_key: &GlyphonCacheKey) {
+        for inner in [
+            &mut self.mask_atlas,
+            &mut self.color_atlas,
+            &mut self.sdf_atlas,
+        ] {
+            // Looking the glyph up also marks it as recently used
+            if inner.glyph_cache.get(cache_key).is_some() {
+                inner.glyphs_in_use.insert(*cache_key);
+                return;
+            }
+        }
+    }
+
+    /// Returns whether the glyph is cached in the atlas or a prebaked page.
+    pub(crate) fn contains_glyph(&self, cache_key: &GlyphonCacheKey) -> bool {
+        self.prebaked_glyph(cache_key).is_some()
+            || [&self.mask_atlas, &self.color_atlas, &self.sdf_atlas]
+                .into_iter()
+                .any(|inner| inner.glyph_cache.contains(cache_key))
+    }
+
+    /// Returns the details of a glyph of a prebaked page.
+    pub(crate) fn prebaked_glyph(&self, cache_key: &GlyphonCacheKey) -> Option<&GlyphDetails> {
+        [&self.prebaked_mask, &self.prebaked_color]
+            .into_iter()
+            .flatten()
+            .find_map(|page| page.glyphs.get(cache_key))
+    }
+
+    /// Returns the padding around the glyphs of the prebaked page of the given content type, if
+    /// it contains the glyph.
+    pub(crate) fn prebaked_padding(
+        &self,
+        content_type: ContentType,
+        cache_key: &GlyphonCacheKey,
+    ) -> Option<u16> {
+        let page = match content_type {
+            ContentType::Color | ContentType::SubpixelMask => self.prebaked_color.as_ref()?,
+            ContentType::Mask => self.prebaked_mask.as_ref()?,
+            ContentType::Sdf | ContentType::Msdf => return None,
+        };
+        page.glyphs.contains_key(cache_key).then_some(page.padding)
     }
 
     fn rebind(&mut self, device: &wgpu::Device) {
         self.bind_group = self.cache.create_atlas_bind_group(
             device,
-            &self.color_atlas.texture_view,
-            &self.mask_atlas.texture_view,
+            AtlasViews {
+                color: &self.color_atlas.texture_view,
+                mask: &self.mask_atlas.texture_view,
+                sdf: &self.sdf_atlas.texture_view,
+                prebaked_color: self
+                    .prebaked_color
+                    .as_ref()
+                    .map_or(&self.color_atlas.texture_view, |page| &page.texture_view),
+                prebaked_mask: self
+                    .prebaked_mask
+                    .as_ref()
+                    .map_or(&self.mask_atlas.texture_view, |page| &page.texture_view),
+            },
+            &self.transfer_params,
         );
     }
 }
@@ -1,49 +1,648 @@
+#[cfg(feature = "rayon")]
+use crate::text_atlas::rasterize_font_glyph;
 use crate::{
-    custom_glyph::CustomGlyphCacheKey, ColorMode, ContentType, FontSystem, GlyphDetails, GlyphToRender, GpuCacheStatus, PrepareError, RasterizeCustomGlyphRequest, RasterizedCustomGlyph, RenderError, SwashCache, SwashContent, TextArea, TextAtlas, TextShadow, Viewport
+    cache::InstanceLayout,
+    cell_grid::{CellGrid, INSTANCES_PER_CELL, ITALIC_OBLIQUE},
+    custom_glyph::CustomGlyphCacheKey,
+    knockout::{Knockout, KnockoutArea},
+    sdf::SDF_FONT_SIZE,
+    shadow_blur::{ShadowBlur, MAX_SHADOW_RADIUS},
+    text_atlas::{rasterize_msdf_glyph, rasterize_sdf_glyph, rasterize_text_glyph},
+    AtlasBudget, AtlasError, Cache, ClipParams, ColorGlyphStyle, ColorMode, ContentType,
+    CornerRadii, CustomGlyph, DropShadow, FontSystem, GlyphColors, GlyphDetails, GlyphIndex,
+    GlyphInstance, GlyphRasterization, GlyphToRender, GpuCacheStatus, GpuTimer, GradientKind,
+    GradientParams, GradientStop, PackedGlyph, PatternMapping, PrepareError,
+    RasterizeCustomGlyphRequest, RasterizedCustomGlyph, RenderError, RenderToTextureError,
+    RendererParams, Resolution, ShadowStyle, SwashCache, SwashContent, SwashImage, TextArea,
+    TextAtlas, TextBackground, TextBounds, TextCaret, TextDecoration, TextGlow, TextGradient,
+    TextOutline, TextPath, TextSelection, TextShadow, TextTransform, TransformParams,
+    UnderlineStyle, Viewport, CLIP_SHIFT, MAX_CLIPS, MAX_GRADIENTS, MAX_TRANSFORMS, MAX_VIEWS,
+    WORLD_TRANSFORM_BIT,
 };
-use cosmic_text::{Color, SubpixelBin};
-use std::slice;
+use cosmic_text::{
+    CacheKey, CacheKeyFlags, Color, Cursor, LayoutGlyph, LayoutRun, PhysicalGlyph, SubpixelBin,
+    Weight,
+};
+use rustc_hash::FxHasher;
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    mem,
+    num::NonZeroU32,
+    ops::Range,
+    ptr, slice,
+    sync::OnceLock,
+};
+#[cfg(feature = "rayon")]
+use swash::scale::ScaleContext;
 use wgpu::{
-    Buffer, BufferDescriptor, BufferUsages, DepthStencilState, Device, Extent3d, MultisampleState,
-    Origin3d, Queue, RenderPass, RenderPipeline, TexelCopyBufferLayout, TexelCopyTextureInfo,
-    TextureAspect, COPY_BUFFER_ALIGNMENT,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BlendComponent, BlendFactor, BlendOperation, BlendState, Buffer, BufferDescriptor,
+    BufferUsages, ColorWrites, CommandEncoder, CommandEncoderDescriptor, DepthStencilState, Device,
+    Extent3d, LoadOp, MultisampleState, Operations, Origin3d, Queue, RenderBundleEncoder,
+    RenderPass, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, StoreOp, Texture,
+    TextureDescriptor, TextureDimension, TextureUsages, TextureView, TextureViewDescriptor,
+    COPY_BUFFER_ALIGNMENT,
 };
-use crate::SHADOW_MARGIN_PX;
 
-const M: u16 = SHADOW_MARGIN_PX;
+/// A text renderer that uses cached glyphs to render text into an existing render pass.
+pub struct TextRenderer {
+    vertex_buffer: Buffer,
+    vertex_buffer_size: u64,
+    /// The offset of the instances in `vertex_buffer`, which is the shared vertex buffer of a
+    /// [`Cache`] with [`TextRenderer::set_shared_vertices`].
+    vertex_offset: u64,
+    /// The bytes last written to `vertex_buffer`, so that only the ranges that changed are
+    /// written again.
+    uploaded_vertices: Vec<u8>,
+    share_vertices: bool,
+    /// The cache whose shared vertex buffer holds the instances, and their range of it.
+    shared_vertices: Option<(Cache, Range<u64>)>,
+    pipeline: RenderPipeline,
+    /// Draws glows with additive blending.
+    glow_pipeline: RenderPipeline,
+    /// The text and glow pipelines that read instances in the compact layout of [`PackedGlyph`],
+    /// created when the instances of the renderer are first packed.
+    packed_pipelines: Option<(RenderPipeline, RenderPipeline)>,
+    /// The text and glow pipelines that pull instances from a storage buffer, created when the
+    /// renderer first prepares with [`TextRenderer::set_vertex_pulling`].
+    pulled_pipelines: Option<(RenderPipeline, RenderPipeline)>,
+    /// The text and glow pipelines that draw pulled instances at each placement passed to
+    /// [`TextRenderer::render_placed`], created along with `pulled_pipelines`.
+    placed_pipelines: Option<(RenderPipeline, RenderPipeline)>,
+    /// The layout of the instances in `vertex_buffer`.
+    instance_layout: InstanceLayout,
+    vertex_pulling: bool,
+    /// The bind group of `vertex_buffer` as the storage buffer of pulled instances, along with the
+    /// buffer it was created for.
+    instances_bind_group: Option<(Buffer, BindGroup)>,
+    /// The packed instances of `glyph_vertices`, if all of them fit into the compact layout.
+    packed_vertices: Vec<PackedGlyph>,
+    blend_mode: BlendMode,
+    write_mask: ColorWrites,
+    /// The instances of all areas, followed by the instances of their glows.
+    glyph_vertices: Vec<GlyphToRender>,
+    /// The glows of the text areas being prepared, which are drawn beneath all text.
+    glow_vertices: Vec<GlyphToRender>,
+    /// The range of `glyph_vertices` that holds the glows.
+    glow_instances: Range<u32>,
+    /// The selection of the text area being prepared, which is drawn behind its glyphs.
+    selection_vertices: Vec<GlyphToRender>,
+    /// The drop shadows of the text area being prepared, which are drawn behind its glyphs.
+    drop_shadow_vertices: Vec<GlyphToRender>,
+    /// The outlines of the text area being prepared, which are drawn between its drop shadows and
+    /// its glyphs.
+    outline_vertices: Vec<GlyphToRender>,
+    areas: Vec<PreparedArea>,
+    /// The debug markers of the glyphs and glows of the text areas, indexed by the position of
+    /// the area. They are created while preparing and kept, so that rendering doesn't allocate.
+    area_debug_markers: Vec<[String; 2]>,
+    pattern_bind_group: BindGroup,
+    /// The gradients of the text areas being prepared, indexed by the gradient bits of
+    /// `GlyphToRender::flags`.
+    gradients: Vec<GradientParams>,
+    gradient_buffer: Buffer,
+    /// The transforms of the text areas being prepared, indexed by `GlyphToRender::transform`.
+    transforms: Vec<TransformParams>,
+    transform_buffer: Buffer,
+    /// The rounded clips of the text areas being prepared, indexed by the clip bits of
+    /// `GlyphToRender::transform`.
+    clips: Vec<ClipParams>,
+    clip_buffer: Buffer,
+    params: RendererParams,
+    params_buffer: Buffer,
+    multisample: MultisampleState,
+    depth_stencil: Option<DepthStencilState>,
+    multiview: Option<NonZeroU32>,
+    /// Whether untransformed text areas are clipped with the scissor rect instead of clipping
+    /// their instances, see [`TextRenderer::set_scissor_clipping`].
+    scissor_clipping: bool,
+    debug_mode: DebugMode,
+    /// The instances of text areas with a [`ShadowStyle`], along with their blur radius in
+    /// physical pixels.
+    shadows: Vec<(f32, GlyphToRender)>,
+    /// Created when a text area with a [`ShadowStyle`] is first prepared.
+    shadow_blur: Option<ShadowBlur>,
+    /// The backgrounds of text areas with a knockout [`TextBackground`] and the glyphs cut out
+    /// of them, indexed by `knockout_areas`.
+    knockout_vertices: Vec<GlyphToRender>,
+    knockout_areas: Vec<KnockoutArea>,
+    /// Created when a text area with a knockout background is first prepared.
+    knockout: Option<Knockout>,
+    /// The instances of the text areas prepared with a handle, see
+    /// [`TextRenderer::prepare_retained`].
+    retained_areas: HashMap<TextAreaHandle, RetainedArea>,
+    next_area_handle: u64,
+    /// The text areas kept by the renderer in the order they are drawn, see
+    /// [`TextRenderer::insert`].
+    stored_areas: Vec<StoredArea>,
+    /// The cache keys of the glyphs of the retained text area being prepared.
+    area_glyph_keys: Vec<GlyphonCacheKey>,
+    /// The boxes of the shaped glyphs of the prepared text areas on screen, see
+    /// [`TextRenderer::hit_test`].
+    glyph_boxes: Vec<GlyphBox>,
+    /// The quads of the glyphs of the prepared text areas on screen, see
+    /// [`TextRenderer::glyph_rects`].
+    glyph_rects: Vec<GlyphRect>,
+    /// See [`TextRenderer::prepare_stats`].
+    prepare_stats: PrepareStats,
+    skip_unchanged_areas: bool,
+    /// The handle and fingerprint of the text area at each index of the last call to
+    /// [`TextRenderer::prepare`], whose instances are retained under the handle.
+    area_fingerprints: Vec<(TextAreaHandle, Option<u64>)>,
+}
+
+/// Statistics about the last call to prepare a [`TextRenderer`], as returned by
+/// [`TextRenderer::prepare_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrepareStats {
+    /// The number of text areas that were prepared, including reused retained areas.
+    pub areas: usize,
+    /// The number of retained text areas whose instances were reused without preparing them
+    /// again.
+    pub reused_areas: usize,
+    /// The number of shaped and custom glyphs in the visible lines of the prepared text areas.
+    pub glyphs: usize,
+    /// The number of glyphs skipped because they lie outside of the bounds of their text area.
+    pub culled_glyphs: usize,
+    /// The number of glyphs that were already cached in the atlas.
+    pub atlas_hits: usize,
+    /// The number of glyphs that weren't cached in the atlas yet.
+    pub atlas_misses: usize,
+    /// The number of glyphs that were rasterized and added to the atlas. Empty glyphs, such as
+    /// spaces, aren't counted.
+    pub rasterized_glyphs: usize,
+    /// The size of the rasterized glyphs, in bytes.
+    pub rasterized_bytes: u64,
+    /// The number of instances emitted, including backgrounds, decorations, shadows and glows.
+    pub instances: usize,
+    /// The number of instance bytes written to the vertex buffer.
+    pub uploaded_vertex_bytes: u64,
+}
 
-fn pad_image_data(src: &[u8], w: u16, h: u16, ty: ContentType) -> Vec<u8> {
-    if w == 0 || h == 0 {
-        return Vec::new();
+impl PrepareStats {
+    /// Counts a glyph that wasn't cached in the atlas and was rasterized instead.
+    fn count_miss(&mut self, image: Option<GetGlyphImageResult>) -> Option<GetGlyphImageResult> {
+        self.atlas_misses += 1;
+        if let Some(image) = &image {
+            if !image.data.is_empty() {
+                self.rasterized_glyphs += 1;
+                self.rasterized_bytes += image.data.len() as u64;
+            }
+        }
+        image
     }
-    let c = ty.bytes_per_pixel();
-    let pad = M as usize;
-    let orig_w = w as usize;
-    let orig_h = h as usize;
-    let padded_w = orig_w + 2 * pad;
-    let padded_h = orig_h + 2 * pad;
+}
+
+/// Identifies a text area whose instances are kept between calls to
+/// [`TextRenderer::prepare_retained`], see [`TextRenderer::create_area_handle`].
+///
+/// A handle is created once for each text area, marked dirty with
+/// [`TextRenderer::mark_area_dirty`] whenever the text area changes, and dropped with
+/// [`TextRenderer::remove_area`]. As text areas borrow their buffers, they are still passed to
+/// every call to `prepare_retained`, but only the ones that were marked dirty are walked again.
+/// To have the renderer keep the text areas instead, see [`TextRenderer::insert`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextAreaHandle(u64);
 
-    let mut dst = vec![0; padded_w * padded_h * c];
+/// Identifies a text area kept by a [`TextRenderer`], see [`TextRenderer::insert`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextId(TextAreaHandle);
+
+/// The glyph of a prepared text area at a position on screen, as returned by
+/// [`TextRenderer::hit_test`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hit {
+    /// The position of the text area in the text areas passed to `prepare`.
+    pub area_index: usize,
+    /// The index of the line of the buffer the glyph belongs to.
+    pub line: usize,
+    /// The byte index of the start of the cluster of the glyph within its line.
+    pub byte_index: usize,
+    /// The box of the glyph on screen in physical pixels, spanning its advance and the height of
+    /// its layout line.
+    pub glyph_bounds: TextBounds,
+}
+
+/// The quad of a glyph of a prepared text area on screen, as returned by
+/// [`TextRenderer::glyph_rects`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlyphRect {
+    /// The quad the glyph is drawn in, in physical pixels and clipped to the bounds of its text
+    /// area. This doesn't include the extent of its effects, and for text areas with a
+    /// [`TextArea::transform`] it's the bounding box of the transformed quad.
+    pub bounds: TextBounds,
+    /// The key of the glyph in the atlas.
+    pub cache_key: GlyphonCacheKey,
+}
+
+/// An instance prepared by [`TextRenderer::prepare`], as returned by [`TextRenderer::quads`], for
+/// drawing prepared text with a renderer of your own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphQuad {
+    /// The position of the text area of the quad in the text areas passed to `prepare`.
+    pub area_index: usize,
+    /// The top left corner of the quad in physical pixels, before the transform of its text area.
+    pub position: [i32; 2],
+    /// The size of the quad in physical pixels.
+    pub size: [u32; 2],
+    /// The top left corner of the region of the atlas texture the quad samples, in texels.
+    pub uv: [u32; 2],
+    /// The size of the region of the atlas texture the quad samples, in texels. It differs from
+    /// `size` for glyphs rendered from distance fields.
+    pub uv_size: [u32; 2],
+    /// The layer of the atlas texture the quad samples.
+    pub layer: u32,
+    /// The kind of atlas texture the quad samples, see [`TextAtlas::texture_view`], or `None`
+    /// for quads that are filled with their color without sampling the atlas, such as
+    /// backgrounds, selections, carets and decorations.
+    pub content_type: Option<ContentType>,
+    /// Whether the quad samples a page set with [`TextAtlas::set_prebaked_page`] instead of the
+    /// atlas texture.
+    pub prebaked: bool,
+    /// The color the quad is tinted with, packed as ARGB with 8 bits per channel. Like the colors
+    /// of text areas, it's encoded in sRGB, and [`ColorMode::Accurate`] converts it to linear
+    /// before blending.
+    pub color: Color,
+    /// The opacity the quad is drawn with, which includes the opacity of its text area.
+    pub opacity: f32,
+    /// The depth of the quad, from `0.0` (nearest) to `1.0` (farthest) as it was prepared, which
+    /// is [`TextArea::depth`] plus the depth returned by `metadata_to_depth`. It's written as
+    /// `1.0 - depth` with [`DepthConvention::Reversed`], while for text areas with a
+    /// [`TextArea::world_transform`] it's the Z coordinate transformed by the projection.
+    pub depth: f32,
+}
+
+/// The box of a shaped glyph on screen, which hit tests are resolved against.
+#[derive(Clone, Copy)]
+struct GlyphBox {
+    line: usize,
+    start: usize,
+    bounds: TextBounds,
+}
 
-    let row_src = orig_w * c;
-    let row_dst = padded_w * c;
+/// Glyphs rasterized ahead of time by [`TextRenderer::rasterize_pending`], which
+/// [`TextRenderer::commit`] uploads to the atlas.
+pub struct RasterizedText {
+    glyphs: HashMap<GlyphonCacheKey, GetGlyphImageResult>,
+}
 
-    for y in 0..orig_h {
-        let off_s = y * row_src;
-        let off_d = (y + pad) * row_dst + pad * c;
-        dst[off_d..off_d + row_src]
-            .copy_from_slice(&src[off_s..off_s + row_src]);
+impl RasterizedText {
+    /// Returns the number of glyphs that were rasterized.
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
     }
 
-    dst
+    /// Returns whether no glyphs were rasterized, e.g. because the atlas already contained all
+    /// of them.
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
 }
 
-/// A text renderer that uses cached glyphs to render text into an existing render pass.
-pub struct TextRenderer {
-    vertex_buffer: Buffer,
-    vertex_buffer_size: u64,
-    pipeline: RenderPipeline,
-    glyph_vertices: Vec<GlyphToRender>,
+impl Drop for TextRenderer {
+    fn drop(&mut self) {
+        if let Some((cache, range)) = self.shared_vertices.take() {
+            cache.free_vertices(range);
+        }
+    }
+}
+
+/// How the instances of a text area are transformed.
+#[derive(Clone, Copy)]
+enum AreaTransform {
+    /// A [`TextTransform`] in physical pixels.
+    Screen(TextTransform),
+    /// A [`TextArea::world_transform`] to clip space.
+    World([[f32; 4]; 4]),
+}
+
+/// The instances emitted for a single text area during `prepare`.
+struct PreparedArea {
+    /// The position of the text area in the text areas passed to `prepare`, which is kept when
+    /// the areas are sorted by their z order.
+    index: usize,
+    bounds: TextBounds,
+    z_order: i32,
+    stencil_reference: u32,
+    instances: Range<u32>,
+    glows: Range<u32>,
+    /// Indexes `glyph_boxes`.
+    glyph_boxes: Range<u32>,
+    /// Indexes `glyph_rects`.
+    glyph_rects: Range<u32>,
+}
+
+/// The instances a retained text area was last prepared with, which are reused until it is
+/// marked dirty.
+struct RetainedArea {
+    dirty: bool,
+    /// The state the instances depend on besides the text area, which prepares the area again when
+    /// it changes.
+    resolution: Resolution,
+    atlas_generation: u64,
+    scissor_clipping: bool,
+    debug_bounds: bool,
+    /// Whether the viewport had a camera, which leaves the instances unclipped by the screen.
+    camera: bool,
+    instances: Vec<GlyphToRender>,
+    glows: Vec<GlyphToRender>,
+    shadows: Vec<(f32, GlyphToRender)>,
+    knockout_vertices: Vec<GlyphToRender>,
+    /// Indexes `knockout_vertices`.
+    knockout_area: Option<KnockoutArea>,
+    gradient: Option<GradientParams>,
+    transform: Option<TransformParams>,
+    clip: Option<ClipParams>,
+    bounds: TextBounds,
+    z_order: i32,
+    stencil_reference: u32,
+    /// The glyphs the instances sample, which are kept in the atlas while the area is drawn.
+    glyph_keys: Vec<GlyphonCacheKey>,
+    glyph_boxes: Vec<GlyphBox>,
+    glyph_rects: Vec<GlyphRect>,
+}
+
+/// A text area kept by the renderer, see [`TextRenderer::insert`].
+struct StoredArea {
+    id: TextId,
+    /// The text area without its borrowed fields, which point to the copies below instead.
+    text_area: TextArea<'static>,
+    buffer: cosmic_text::Buffer,
+    custom_glyphs: Vec<CustomGlyph>,
+    gradient_stops: Vec<GradientStop>,
+    path: Option<TextPath>,
+}
+
+impl StoredArea {
+    fn new(id: TextId, text_area: &TextArea) -> Self {
+        Self {
+            id,
+            text_area: TextArea {
+                buffer: detached_buffer(),
+                left: text_area.left,
+                top: text_area.top,
+                scale: text_area.scale,
+                bounds: text_area.bounds,
+                default_color: text_area.default_color,
+                opacity: text_area.opacity,
+                custom_glyphs: &[],
+                shadow: text_area.shadow,
+                z_order: text_area.z_order,
+                caret: text_area.caret,
+                pattern: text_area.pattern,
+                drop_shadow: text_area.drop_shadow,
+                outline: text_area.outline,
+                glow: text_area.glow,
+                background: text_area.background,
+                gradient: text_area.gradient.map(|gradient| TextGradient {
+                    kind: gradient.kind,
+                    stops: &[],
+                }),
+                glyph_colors: text_area.glyph_colors,
+                selection: text_area.selection,
+                decorations: None,
+                shadow_style: text_area.shadow_style,
+                transform: text_area.transform,
+                world_transform: text_area.world_transform,
+                faux_bold: text_area.faux_bold,
+                thickness: text_area.thickness,
+                oblique: text_area.oblique,
+                sdf: text_area.sdf,
+                msdf: text_area.msdf,
+                corner_radii: text_area.corner_radii,
+                stencil_reference: text_area.stencil_reference,
+                path: None,
+                glyph_modifier: None,
+                color_glyphs: text_area.color_glyphs,
+                rasterization: text_area.rasterization,
+                depth: text_area.depth,
+            },
+            buffer: text_area.buffer.clone(),
+            custom_glyphs: text_area.custom_glyphs.to_vec(),
+            gradient_stops: text_area
+                .gradient
+                .map_or_else(Vec::new, |gradient| gradient.stops.to_vec()),
+            path: text_area.path.cloned(),
+        }
+    }
+
+    fn text_area(&self) -> TextArea<'_> {
+        TextArea {
+            buffer: &self.buffer,
+            custom_glyphs: &self.custom_glyphs,
+            gradient: self.text_area.gradient.map(|gradient| TextGradient {
+                kind: gradient.kind,
+                stops: &self.gradient_stops,
+            }),
+            path: self.path.as_ref(),
+            ..self.text_area.clone()
+        }
+    }
+}
+
+/// An empty buffer that the kept text areas point to, as they keep their buffers themselves.
+fn detached_buffer() -> &'static cosmic_text::Buffer {
+    static BUFFER: OnceLock<cosmic_text::Buffer> = OnceLock::new();
+    BUFFER.get_or_init(|| cosmic_text::Buffer::new_empty(cosmic_text::Metrics::new(1.0, 1.0)))
+}
+
+/// How a [`TextRenderer`] blends text with the render target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Standard alpha blending of text over the render target. This is the default.
+    ///
+    /// With an atlas that uses subpixel antialiasing, each subpixel is blended independently.
+    #[default]
+    Alpha,
+
+    /// Blending of premultiplied colors, for compositing text into intermediate render targets
+    /// that store premultiplied alpha.
+    PremultipliedAlpha,
+
+    /// Adds the color of text to the render target, leaving its alpha untouched, which suits
+    /// glowing text in game UIs.
+    Additive,
+
+    /// Multiplies the render target by the color of text, darkening it like ink.
+    Multiply,
+
+    /// Blends text with the given blend state, e.g. to match the blend factors of a compositor.
+    ///
+    /// The shader outputs premultiplied colors unless the source factor of the color component
+    /// is [`BlendFactor::SrcAlpha`].
+    Custom(BlendState),
+}
+
+impl BlendMode {
+    fn blend_state(self, subpixel: bool) -> BlendState {
+        match self {
+            BlendMode::Alpha if subpixel => SUBPIXEL_BLENDING,
+            BlendMode::Alpha => BlendState::ALPHA_BLENDING,
+            BlendMode::PremultipliedAlpha => BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                    src_factor: BlendFactor::Zero,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+            },
+            // The shader premultiplies the color, so that uncovered pixels keep the target color
+            BlendMode::Multiply => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::Dst,
+                    dst_factor: BlendFactor::OneMinusSrcAlpha,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            },
+            BlendMode::Custom(blend) => blend,
+        }
+    }
+}
+
+/// How the depths of text areas map to the depth buffer of the render target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DepthConvention {
+    /// Depths are written as they are, for depth buffers cleared to `1.0` and compared with
+    /// `Less` or `LessEqual`. This is the default.
+    #[default]
+    Standard,
+
+    /// Depths are written as `1.0 - depth`, for reverse-Z depth buffers cleared to `0.0` and
+    /// compared with `Greater` or `GreaterEqual`, so the same depths order text alike with both
+    /// conventions.
+    ///
+    /// The depths of text areas with a [`TextArea::world_transform`] come from their projection
+    /// and are left as they are.
+    Reversed,
+}
+
+/// Debug visualizations drawn by a [`TextRenderer`], see [`TextRenderer::set_debug_mode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DebugMode {
+    /// Outlines the quad of every instance, such as glyphs, backgrounds and decorations.
+    pub quad_outlines: bool,
+    /// Outlines the bounds of every text area that text is clipped to.
+    pub bounds: bool,
+    /// Replaces the text with a heatmap of overdraw, where every quad adds the same translucent
+    /// color so that pixels covered by many quads stand out.
+    pub overdraw: bool,
+}
+
+impl DebugMode {
+    /// The bits of the debug visualizations drawn by the shader.
+    fn shader_bits(self) -> u32 {
+        self.quad_outlines as u32 | (self.overdraw as u32) << 1
+    }
+}
+
+/// A builder of a [`TextRenderer`], see [`TextRenderer::builder`].
+#[derive(Debug, Clone)]
+pub struct TextRendererBuilder {
+    multisample: MultisampleState,
+    depth_stencil: Option<DepthStencilState>,
+    blend_mode: BlendMode,
+    multiview: Option<NonZeroU32>,
+    write_mask: ColorWrites,
+}
+
+impl Default for TextRendererBuilder {
+    fn default() -> Self {
+        Self {
+            multisample: MultisampleState::default(),
+            depth_stencil: None,
+            blend_mode: BlendMode::Alpha,
+            multiview: None,
+            write_mask: ColorWrites::ALL,
+        }
+    }
+}
+
+impl TextRendererBuilder {
+    /// Sets the multisample state of the render target.
+    pub fn multisample(mut self, multisample: MultisampleState) -> Self {
+        self.multisample = multisample;
+        self
+    }
+
+    /// Sets the depth and stencil state of the render target, if it has a depth or stencil
+    /// attachment.
+    pub fn depth_stencil(mut self, depth_stencil: Option<DepthStencilState>) -> Self {
+        self.depth_stencil = depth_stencil;
+        self
+    }
+
+    /// Sets how text is blended with the render target, [`BlendMode::Alpha`] by default.
+    ///
+    /// Glows are always added to the render target. The blend modes other than
+    /// [`BlendMode::Alpha`] average the subpixels of an atlas that uses subpixel antialiasing.
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    /// Sets the number of array layers the pipelines render to at once, such as both eyes of an
+    /// XR headset. Requires the `MULTIVIEW` feature.
+    ///
+    /// Text areas with a [`TextArea::world_transform`] are projected by the projection of each
+    /// view, see [`TextRenderer::set_view_projections`], and all other text is drawn alike in
+    /// every view. [`ShadowStyle`]s and text areas with a knockout [`TextBackground`] aren't drawn
+    /// by multiview renderers, as they are composited from offscreen targets with a single view.
+    pub fn multiview(mut self, multiview: NonZeroU32) -> Self {
+        self.multiview = Some(multiview);
+        self
+    }
+
+    /// Sets the channels of the render target the pipelines write, such as only the alpha
+    /// channel in passes that generate masks. All channels are written by default.
+    ///
+    /// [`ShadowStyle`]s and knockout [`TextBackground`]s are composited with all channels.
+    pub fn write_mask(mut self, write_mask: ColorWrites) -> Self {
+        self.write_mask = write_mask;
+        self
+    }
+
+    /// Creates the pipelines of a `TextRenderer` built with these parameters ahead of time, so
+    /// that their shaders aren't compiled on first use.
+    ///
+    /// The pipelines are kept in the [`Cache`] of the atlas and reused by renderers created with
+    /// the same parameters. Use [`Cache::with_pipeline_cache`] to also persist the compiled
+    /// pipelines across runs. This includes the pipelines that draw packed instances.
+    pub fn precompile(&self, atlas: &TextAtlas, device: &Device) {
+        create_pipelines(
+            atlas,
+            device,
+            self.multisample,
+            self.depth_stencil.clone(),
+            self.blend_mode,
+            self.multiview,
+            self.write_mask,
+            InstanceLayout::Full,
+        );
+        if self.multiview.is_none() && atlas.cache.supports_packed_instances() {
+            create_pipelines(
+                atlas,
+                device,
+                self.multisample,
+                self.depth_stencil.clone(),
+                self.blend_mode,
+                self.multiview,
+                self.write_mask,
+                InstanceLayout::Packed,
+            );
+        }
+    }
+
+    /// Creates the `TextRenderer`.
+    pub fn build(self, atlas: &mut TextAtlas, device: &Device) -> TextRenderer {
+        TextRenderer::with_builder(atlas, device, self)
+    }
 }
 
 impl TextRenderer {
@@ -54,6 +653,27 @@ impl TextRenderer {
         multisample: MultisampleState,
         depth_stencil: Option<DepthStencilState>,
     ) -> Self {
+        Self::builder()
+            .multisample(multisample)
+            .depth_stencil(depth_stencil)
+            .build(atlas, device)
+    }
+
+    /// Returns a builder of a `TextRenderer`, for renderers with other than the default
+    /// [`BlendMode`], multiview or write mask.
+    pub fn builder() -> TextRendererBuilder {
+        TextRendererBuilder::default()
+    }
+
+    fn with_builder(atlas: &mut TextAtlas, device: &Device, builder: TextRendererBuilder) -> Self {
+        let TextRendererBuilder {
+            multisample,
+            depth_stencil,
+            blend_mode,
+            multiview,
+            write_mask,
+        } = builder;
+
         let vertex_buffer_size = next_copy_buffer_size(4096);
         let vertex_buffer = device.create_buffer(&BufferDescriptor {
             label: Some("glyphon vertices"),
@@ -62,17 +682,362 @@ impl TextRenderer {
             mapped_at_creation: false,
         });
 
-        let pipeline = atlas.get_or_create_pipeline(device, multisample, depth_stencil);
+        let (pipeline, glow_pipeline) = create_pipelines(
+            atlas,
+            device,
+            multisample,
+            depth_stencil.clone(),
+            blend_mode,
+            multiview,
+            write_mask,
+            InstanceLayout::Full,
+        );
+        let gradient_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("glyphon gradients"),
+            size: (mem::size_of::<GradientParams>() * MAX_GRADIENTS) as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let transform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("glyphon transforms"),
+            size: (mem::size_of::<TransformParams>() * MAX_TRANSFORMS) as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let clip_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("glyphon clips"),
+            size: (mem::size_of::<ClipParams>() * MAX_CLIPS) as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params = RendererParams::default();
+        let params_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("glyphon renderer params"),
+            contents: renderer_params_bytes(&params),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let pattern_bind_group = atlas.cache.create_pattern_bind_group(
+            device,
+            None,
+            &gradient_buffer,
+            &transform_buffer,
+            &clip_buffer,
+            &params_buffer,
+        );
 
         Self {
             vertex_buffer,
             vertex_buffer_size,
+            vertex_offset: 0,
+            uploaded_vertices: Vec::new(),
+            share_vertices: false,
+            shared_vertices: None,
             pipeline,
+            glow_pipeline,
+            packed_pipelines: None,
+            pulled_pipelines: None,
+            placed_pipelines: None,
+            instance_layout: InstanceLayout::Full,
+            vertex_pulling: false,
+            instances_bind_group: None,
+            packed_vertices: Vec::new(),
+            blend_mode,
+            write_mask,
             glyph_vertices: Vec::new(),
+            glow_vertices: Vec::new(),
+            glow_instances: 0..0,
+            selection_vertices: Vec::new(),
+            drop_shadow_vertices: Vec::new(),
+            outline_vertices: Vec::new(),
+            areas: Vec::new(),
+            area_debug_markers: Vec::new(),
+            pattern_bind_group,
+            gradients: Vec::new(),
+            gradient_buffer,
+            transforms: Vec::new(),
+            transform_buffer,
+            clips: Vec::new(),
+            clip_buffer,
+            params,
+            params_buffer,
+            multisample,
+            depth_stencil,
+            multiview,
+            scissor_clipping: false,
+            debug_mode: DebugMode::default(),
+            shadows: Vec::new(),
+            shadow_blur: None,
+            knockout_vertices: Vec::new(),
+            knockout_areas: Vec::new(),
+            knockout: None,
+            retained_areas: HashMap::new(),
+            next_area_handle: 0,
+            stored_areas: Vec::new(),
+            area_glyph_keys: Vec::new(),
+            glyph_boxes: Vec::new(),
+            glyph_rects: Vec::new(),
+            prepare_stats: PrepareStats::default(),
+            skip_unchanged_areas: false,
+            area_fingerprints: Vec::new(),
+        }
+    }
+
+    /// Sets the texture that fills text areas with a [`TextArea::pattern`], or `None` to unset it.
+    ///
+    /// The texture must have a filterable float sample type and is repeated in both directions.
+    pub fn set_pattern(&mut self, device: &Device, cache: &Cache, pattern: Option<&TextureView>) {
+        self.pattern_bind_group = cache.create_pattern_bind_group(
+            device,
+            pattern,
+            &self.gradient_buffer,
+            &self.transform_buffer,
+            &self.clip_buffer,
+            &self.params_buffer,
+        );
+    }
+
+    /// Sets the projections applied after the [`TextArea::world_transform`] of text areas in each
+    /// view of a multiview render pass, in the order of the views. Views without a projection are
+    /// left unchanged, and the projections of views beyond the fourth are ignored.
+    ///
+    /// The projections default to the identity, in which case the world transforms map to clip
+    /// space on their own. With a renderer that isn't multiview, the first projection applies.
+    pub fn set_view_projections(&mut self, queue: &Queue, projections: &[[[f32; 4]; 4]]) {
+        let count = projections.len().min(MAX_VIEWS);
+        self.params.projections[..count].copy_from_slice(&projections[..count]);
+        self.write_params(queue);
+    }
+
+    /// Sets the factor the brightness of all text of the renderer is scaled by, `1.0` by
+    /// default.
+    ///
+    /// With an atlas in [`ColorMode::Hdr`] and a floating-point render target, this keeps text
+    /// from looking washed out next to bright HDR content, e.g. by scaling it to the SDR white
+    /// level of the display in nits divided by `80.0` for an scRGB target.
+    pub fn set_brightness(&mut self, queue: &Queue, brightness: f32) {
+        let brightness = brightness.max(0.0);
+
+        if self.params.brightness != brightness {
+            self.params.brightness = brightness;
+            self.write_params(queue);
+        }
+    }
+
+    /// Returns the factor the brightness of all text of the renderer is scaled by.
+    pub fn brightness(&self) -> f32 {
+        self.params.brightness
+    }
+
+    fn write_params(&self, queue: &Queue) {
+        queue.write_buffer(&self.params_buffer, 0, renderer_params_bytes(&self.params));
+    }
+
+    /// Sets how the depths of text areas map to the depth buffer, [`DepthConvention::Standard`]
+    /// by default.
+    pub fn set_depth_convention(&mut self, queue: &Queue, depth_convention: DepthConvention) {
+        let reverse_z = (depth_convention == DepthConvention::Reversed) as u32;
+
+        if self.params.reverse_z != reverse_z {
+            self.params.reverse_z = reverse_z;
+            self.write_params(queue);
+        }
+    }
+
+    /// Returns how the depths of text areas map to the depth buffer.
+    pub fn depth_convention(&self) -> DepthConvention {
+        if self.params.reverse_z != 0 {
+            DepthConvention::Reversed
+        } else {
+            DepthConvention::Standard
+        }
+    }
+
+    /// Sets the debug visualizations drawn instead of or over the text, to diagnose clipping or
+    /// fill rate without a GPU debugger. The outlines of the bounds of text areas are added with
+    /// the next call to `prepare`.
+    pub fn set_debug_mode(&mut self, queue: &Queue, debug_mode: DebugMode) {
+        self.debug_mode = debug_mode;
+
+        let debug = debug_mode.shader_bits();
+        if self.params.debug != debug {
+            self.params.debug = debug;
+            self.write_params(queue);
+        }
+    }
+
+    /// Returns the debug visualizations drawn by the renderer.
+    pub fn debug_mode(&self) -> DebugMode {
+        self.debug_mode
+    }
+
+    /// Sets whether text areas without a transform are clipped to their bounds with the scissor
+    /// rect, `false` by default. Takes effect with the next call to `prepare`.
+    ///
+    /// Instead of clipping the instance of each glyph to the bounds of its text area, `render`
+    /// then splits the draw calls by text area and clips each one with the GPU scissor, like
+    /// [`TextRenderer::render_with_scissor`]. This saves work during `prepare` and is cheaper on
+    /// tile-based GPUs. Render bundles can't set the scissor rect, so [`TextRenderer::render_bundle`]
+    /// doesn't clip these text areas.
+    pub fn set_scissor_clipping(&mut self, scissor_clipping: bool) {
+        self.scissor_clipping = scissor_clipping;
+    }
+
+    /// Returns `true` if text areas without a transform are clipped with the scissor rect.
+    pub fn scissor_clipping(&self) -> bool {
+        self.scissor_clipping
+    }
+
+    /// Sets whether the renderer allocates its instances from a vertex buffer shared by all
+    /// renderers of the [`Cache`] of the atlas, instead of from its own vertex buffer, `false` by
+    /// default. Takes effect with the next call to `prepare`.
+    ///
+    /// Apps with many renderers, e.g. one per window or panel, then keep a single buffer that
+    /// grows for all of them, rather than one buffer per renderer that grows on its own.
+    pub fn set_shared_vertices(&mut self, shared: bool) {
+        self.share_vertices = shared;
+    }
+
+    /// Returns `true` if the renderer allocates its instances from the shared vertex buffer of
+    /// its [`Cache`].
+    pub fn shared_vertices(&self) -> bool {
+        self.share_vertices
+    }
+
+    /// Sets whether [`TextRenderer::prepare`] reuses the instances of text areas that haven't
+    /// changed since the last call, `false` by default.
+    ///
+    /// Each text area is compared by a hash of its fields and the identity, scroll, metrics and
+    /// size of its buffer, without walking its glyphs. Changes to the text of a buffer are tracked
+    /// by its [`redraw`](cosmic_text::Buffer::redraw) flag instead: an area is prepared again as
+    /// long as the flag is set, so clear it with
+    /// [`set_redraw(false)`](cosmic_text::Buffer::set_redraw) once the buffer was prepared. This
+    /// keeps a copy of the instances of every text area, so it pays off when most text stays the
+    /// same from frame to frame.
+    pub fn set_skip_unchanged_areas(&mut self, skip: bool) {
+        self.skip_unchanged_areas = skip;
+        if !skip {
+            for (handle, _) in self.area_fingerprints.drain(..) {
+                self.retained_areas.remove(&handle);
+            }
         }
     }
 
+    /// Returns `true` if [`TextRenderer::prepare`] reuses the instances of unchanged text areas.
+    pub fn skip_unchanged_areas(&self) -> bool {
+        self.skip_unchanged_areas
+    }
+
+    /// Sets whether the renderer reads its instances from a storage buffer in the vertex shader
+    /// and draws all of them with a single triangle list, instead of reading them from a vertex
+    /// buffer, `false` by default. Takes effect with the next call to `prepare`.
+    ///
+    /// This requires storage buffers in vertex shaders, which some downlevel devices such as
+    /// WebGL lack. Multiview renderers and shaders replaced without the `vs_pulled` entry point
+    /// keep using a vertex buffer. The instances are kept in a buffer of the renderer's own, even
+    /// with [`TextRenderer::set_shared_vertices`].
+    pub fn set_vertex_pulling(&mut self, enabled: bool) {
+        self.vertex_pulling = enabled;
+    }
+
+    /// Returns `true` if the renderer reads its instances from a storage buffer.
+    pub fn vertex_pulling(&self) -> bool {
+        self.vertex_pulling
+    }
+
+    /// Returns statistics about the last call to prepare the renderer, e.g. to show in a frame
+    /// profiler or to catch glyphs being evicted and rasterized again every frame.
+    pub fn prepare_stats(&self) -> PrepareStats {
+        self.prepare_stats
+    }
+
+    /// Returns the shaped glyph at the given position on screen in physical pixels, as it was
+    /// positioned by the last call to `prepare`. Where text areas overlap, the glyph of the text
+    /// area drawn on top is returned.
+    ///
+    /// Glyphs are hit within their advance and the height of their line, clipped to the bounds
+    /// of their text area. Glyphs of text areas with a [`TextArea::transform`] are hit within the
+    /// bounding box of their transformed box, and glyphs of text areas with a
+    /// [`TextArea::world_transform`] can't be hit.
+    pub fn hit_test(&self, x: f32, y: f32) -> Option<Hit> {
+        let (x, y) = (x.floor() as i32, y.floor() as i32);
+
+        self.areas.iter().rev().find_map(|area| {
+            let range = area.glyph_boxes.start as usize..area.glyph_boxes.end as usize;
+            self.glyph_boxes[range]
+                .iter()
+                .find(|glyph| {
+                    let bounds = glyph.bounds;
+                    bounds.left <= x && x < bounds.right && bounds.top <= y && y < bounds.bottom
+                })
+                .map(|glyph| Hit {
+                    area_index: area.index,
+                    line: glyph.line,
+                    byte_index: glyph.start,
+                    glyph_bounds: glyph.bounds,
+                })
+        })
+    }
+
+    /// Returns the quads and the cache keys of the glyphs drawn for the text area at
+    /// `area_index` in the text areas passed to the last call to `prepare`, in the order they were
+    /// laid out. Custom glyphs come first, and glyphs that were clipped entirely aren't included.
+    ///
+    /// Glyphs of text areas with a [`TextArea::world_transform`] aren't included either, as
+    /// they aren't drawn at a fixed position on screen.
+    pub fn glyph_rects(&self, area_index: usize) -> &[GlyphRect] {
+        self.areas
+            .iter()
+            .find(|area| area.index == area_index)
+            .map_or(&[], |area| {
+                &self.glyph_rects[area.glyph_rects.start as usize..area.glyph_rects.end as usize]
+            })
+    }
+
+    /// Returns the instances prepared by the last call to `prepare` as quads, in the order they
+    /// are drawn, for engines that batch and draw the quads with their own pipelines while
+    /// glyphon shapes, rasterizes and caches the glyphs.
+    ///
+    /// The quads sample the textures of `atlas`, which must be flushed by `prepare` before they
+    /// are drawn. Glows, blurred shadows and knockouts aren't included, and neither are the
+    /// effects the text shader applies to the quads, such as outlines, blurred drop shadows,
+    /// emboldening, slanting, rotation, gradients, patterns, rounded clips and transforms.
+    pub fn quads(&self) -> impl Iterator<Item = GlyphQuad> + '_ {
+        self.areas.iter().flat_map(move |area| {
+            let range = area.instances.start as usize..area.instances.end as usize;
+            self.glyph_vertices[range].iter().map(move |glyph| {
+                let content_type = match glyph.content_type_with_srgb[0] {
+                    0 => Some(ContentType::Color),
+                    1 => Some(ContentType::Mask),
+                    SUBPIXEL_MASK_CONTENT_TYPE => Some(ContentType::SubpixelMask),
+                    SDF_CONTENT_TYPE => Some(ContentType::Sdf),
+                    MSDF_CONTENT_TYPE => Some(ContentType::Msdf),
+                    _ => None,
+                };
+                let size = glyph.dim.map(u32::from);
+
+                GlyphQuad {
+                    area_index: area.index,
+                    position: glyph.pos,
+                    size,
+                    uv: glyph.uv.map(u32::from),
+                    uv_size: size.map(|size| (size as f32 * glyph.texel_scale).round() as u32),
+                    layer: glyph.flags >> LAYER_SHIFT,
+                    content_type,
+                    prebaked: glyph.flags & PREBAKED_FLAG != 0,
+                    color: Color(glyph.color),
+                    opacity: glyph.opacity,
+                    depth: glyph.depth,
+                }
+            })
+        })
+    }
+
     /// Prepares all of the provided text areas for rendering.
+    ///
+    /// With [`TextRenderer::set_skip_unchanged_areas`], text areas that are unchanged since the
+    /// last call reuse the instances they were prepared with. Areas with
+    /// [`decorations`](TextArea::decorations) or a [`glyph_modifier`](TextArea::glyph_modifier)
+    /// can't be compared and are always prepared again.
     pub fn prepare<'a>(
         &mut self,
         device: &Device,
@@ -83,7 +1048,30 @@ impl TextRenderer {
         text_areas: impl IntoIterator<Item = TextArea<'a>>,
         cache: &mut SwashCache,
     ) -> Result<(), PrepareError> {
-        self.prepare_with_depth_and_custom(
+        if !self.skip_unchanged_areas {
+            return self.prepare_with_depth_and_custom(
+                device,
+                queue,
+                font_system,
+                atlas,
+                viewport,
+                text_areas,
+                cache,
+                zero_depth,
+                |_| None,
+            );
+        }
+
+        let text_areas: Vec<_> = text_areas
+            .into_iter()
+            .enumerate()
+            .map(|(index, text_area)| (self.fingerprint_area(index, &text_area), text_area))
+            .collect();
+        for (handle, _) in self.area_fingerprints.drain(text_areas.len()..) {
+            self.retained_areas.remove(&handle);
+        }
+
+        self.prepare_areas(
             device,
             queue,
             font_system,
@@ -93,9 +1081,32 @@ impl TextRenderer {
             cache,
             zero_depth,
             |_| None,
+            HashMap::new(),
         )
     }
 
+    /// Returns the handle the text area at the index is retained under by
+    /// [`TextRenderer::prepare`], marking it dirty if the text area changed since the last call
+    /// or its buffer needs a redraw.
+    fn fingerprint_area(&mut self, index: usize, text_area: &TextArea) -> Option<TextAreaHandle> {
+        while self.area_fingerprints.len() <= index {
+            let handle = self.create_area_handle();
+            self.area_fingerprints.push((handle, None));
+        }
+
+        let (handle, previous) = &mut self.area_fingerprints[index];
+        let fingerprint = area_fingerprint(text_area);
+        if fingerprint.is_none() {
+            self.retained_areas.remove(handle);
+        } else if *previous != fingerprint || text_area.buffer.redraw() {
+            if let Some(area) = self.retained_areas.get_mut(handle) {
+                area.dirty = true;
+            }
+        }
+        *previous = fingerprint;
+        fingerprint.map(|_| *handle)
+    }
+
     /// Prepares all of the provided text areas for rendering.
     pub fn prepare_with_depth<'a>(
         &mut self,
@@ -156,250 +1167,2073 @@ impl TextRenderer {
         viewport: &Viewport,
         text_areas: impl IntoIterator<Item = TextArea<'a>>,
         cache: &mut SwashCache,
-        mut metadata_to_depth: impl FnMut(usize) -> f32,
-        mut rasterize_custom_glyph: impl FnMut(
-            RasterizeCustomGlyphRequest,
-        ) -> Option<RasterizedCustomGlyph>,
+        metadata_to_depth: impl FnMut(usize) -> f32,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
     ) -> Result<(), PrepareError> {
-        self.glyph_vertices.clear();
-
-        let resolution = viewport.resolution();
-
-        for text_area in text_areas {
-            let bounds_min_x = text_area.bounds.left.max(0);
-            let bounds_min_y = text_area.bounds.top.max(0);
-            let bounds_max_x = text_area.bounds.right.min(resolution.width as i32);
-            let bounds_max_y = text_area.bounds.bottom.min(resolution.height as i32);
-
-            for glyph in text_area.custom_glyphs.iter() {
-                let x = text_area.left + (glyph.left * text_area.scale);
-                let y = text_area.top + (glyph.top * text_area.scale);
-                let width = (glyph.width * text_area.scale).round() as u16;
-                let height = (glyph.height * text_area.scale).round() as u16;
-
-                let (x, y, x_bin, y_bin) = if glyph.snap_to_physical_pixel {
-                    (
-                        x.round() as i32,
-                        y.round() as i32,
-                        SubpixelBin::Zero,
-                        SubpixelBin::Zero,
-                    )
-                } else {
-                    let (x, x_bin) = SubpixelBin::new(x);
-                    let (y, y_bin) = SubpixelBin::new(y);
-                    (x, y, x_bin, y_bin)
-                };
-
-                let cache_key = GlyphonCacheKey::Custom(CustomGlyphCacheKey {
-                    glyph_id: glyph.id,
-                    width,
-                    height,
-                    x_bin,
-                    y_bin,
-                });
-
-                let color = glyph.color.unwrap_or(text_area.default_color);
-
-                if let Some(glyph_to_render) = prepare_glyph(
-                    x,
-                    y,
-                    0.0,
-                    color,
-                    glyph.metadata,
-                    cache_key,
-                    atlas,
-                    device,
-                    queue,
-                    cache,
-                    font_system,
-                    text_area.scale,
-                    bounds_min_x,
-                    bounds_min_y,
-                    bounds_max_x,
-                    bounds_max_y,
-                    text_area.shadow,
-                    |_cache, _font_system, rasterize_custom_glyph| -> Option<GetGlyphImageResult> {
-                        if width == 0 || height == 0 {
-                            return None;
-                        }
-
-                        let pw = width + 2 * M;
-                        let ph = height + 2 * M;
-                        let input = RasterizeCustomGlyphRequest {
-                            id: glyph.id,
-                            width: pw,
-                            height: ph,
-                            x_bin,
-                            y_bin,
-                            scale: text_area.scale,
-                        };
-
-                        let output = (rasterize_custom_glyph)(input)?;
-
-                        output.validate(&input, None);
+        self.prepare_areas(
+            device,
+            queue,
+            font_system,
+            atlas,
+            viewport,
+            text_areas.into_iter().map(|text_area| (None, text_area)),
+            cache,
+            metadata_to_depth,
+            rasterize_custom_glyph,
+            HashMap::new(),
+        )
+    }
 
-                        let data = pad_image_data(&output.data, width, height, output.content_type);
+    /// Rasterizes the glyphs of the provided text areas that are missing from the atlas without a
+    /// [`Device`] or [`Queue`], so that rasterizing can move to another thread.
+    ///
+    /// This only reads the atlas, so it can run on a worker thread that shares it while the main
+    /// thread renders. It neither shapes the buffers of the text areas, which is left to the
+    /// caller (e.g. with [`Buffer::shape_until_scroll`]), nor builds their instances:
+    /// [`TextRenderer::commit`] uploads the glyphs and then walks the glyphs of the text areas
+    /// like `prepare` does, only without rasterizing them.
+    ///
+    /// Custom glyphs are rasterized by `commit`, with the `rasterize_custom_glyph` passed to it.
+    ///
+    /// [`Buffer::shape_until_scroll`]: crate::Buffer::shape_until_scroll
+    pub fn rasterize_pending<'a>(
+        font_system: &mut FontSystem,
+        cache: &mut SwashCache,
+        atlas: &TextAtlas,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+    ) -> RasterizedText {
+        trace_span!("glyphon::rasterize_pending");
 
-                        Some(GetGlyphImageResult {
-                            content_type: output.content_type,
-                            top: 0,
-                            left: 0,
-                            width: pw,
-                            height: ph,
-                            data,
-                        })
-                    },
-                    &mut metadata_to_depth,
-                    &mut rasterize_custom_glyph,
-                )? {
-                    self.glyph_vertices.push(glyph_to_render);
-                }
-            }
+        let share_rasterized_glyphs = atlas.mask_atlas.share_rasterized_glyphs;
+        let subpixel = atlas.subpixel;
+        let mut glyphs = HashMap::new();
 
-            let is_run_visible = |run: &cosmic_text::LayoutRun| {
-                let start_y_physical = (text_area.top + (run.line_top * text_area.scale)) as i32;
-                let end_y_physical = start_y_physical + (run.line_height * text_area.scale) as i32;
-                
-                start_y_physical <= text_area.bounds.bottom && text_area.bounds.top <= end_y_physical
+        for text_area in text_areas {
+            let rasterization = text_area.rasterization.unwrap_or_default();
+            // Without the `msdf` feature, glyphs fall back to single-channel fields
+            let msdf = text_area.msdf && cfg!(feature = "msdf");
+            let sdf = text_area.sdf || text_area.msdf;
+            let cull_bounds = match text_area.path {
+                Some(_) => TextBounds::default(),
+                None => text_area.bounds,
             };
 
-            let layout_runs = text_area
-                .buffer
-                .layout_runs()
-                .skip_while(|run| !is_run_visible(run))
-                .take_while(is_run_visible);
-
-            for run in layout_runs {
+            for run in visible_layout_runs(&text_area, cull_bounds, cull_margin(&text_area)[1]) {
                 for glyph in run.glyphs.iter() {
                     let physical_glyph =
                         glyph.physical((text_area.left, text_area.top), text_area.scale);
-
-                    let color = match glyph.color_opt {
-                        Some(some) => some,
-                        None => text_area.default_color,
+                    let cache_key = if sdf {
+                        sdf_glyph(&physical_glyph).2
+                    } else {
+                        physical_glyph.cache_key
                     };
+                    let glyphon_cache_key = if msdf {
+                        GlyphonCacheKey::Msdf(cache_key)
+                    } else if sdf {
+                        GlyphonCacheKey::Sdf(cache_key)
+                    } else {
+                        text_cache_key(cache_key, rasterization)
+                    };
+                    if glyphs.contains_key(&glyphon_cache_key)
+                        || atlas.contains_glyph(&glyphon_cache_key)
+                    {
+                        continue;
+                    }
 
-                    if let Some(glyph_to_render) = prepare_glyph(
-                        physical_glyph.x,
-                        physical_glyph.y,
-                        run.line_y,
-                        color,
-                        glyph.metadata,
-                        GlyphonCacheKey::Text(physical_glyph.cache_key),
-                        atlas,
-                        device,
-                        queue,
+                    if let Some(image) = text_glyph_image(
                         cache,
                         font_system,
-                        text_area.scale,
-                        bounds_min_x,
-                        bounds_min_y,
-                        bounds_max_x,
-                        bounds_max_y,
-                        text_area.shadow,
-                        |cache,
-                         font_system,
-                         _rasterize_custom_glyph|
-                         -> Option<GetGlyphImageResult> {
-                            let image =
-                                cache.get_image_uncached(font_system, physical_glyph.cache_key)?;
-
-                            let content_type = match image.content {
-                                SwashContent::Color => ContentType::Color,
-                                SwashContent::Mask => ContentType::Mask,
-                                SwashContent::SubpixelMask => {
-                                    // Not implemented yet, but don't panic if this happens.
-                                    ContentType::Mask
-                                }
-                            };
-
-                            let ow = image.placement.width as u16;
-                            let oh = image.placement.height as u16;
-
-                            if ow == 0 || oh == 0 {
-                                return Some(GetGlyphImageResult {
-                                    content_type,
-                                    top: image.placement.top as i16,
-                                    left: image.placement.left as i16,
-                                    width: 0,
-                                    height: 0,
-                                    data: Vec::new(),
-                                });
-                            }
-
-                            let pw = ow + 2 * M;
-                            let ph = oh + 2 * M;
-                            let data = pad_image_data(&image.data, ow, oh, content_type);
-
-                            Some(GetGlyphImageResult {
-                                content_type,
-                                top: image.placement.top as i16,
-                                left: image.placement.left as i16,
-                                width: pw,
-                                height: ph,
-                                data,
-                            })
-                        },
-                        &mut metadata_to_depth,
-                        &mut rasterize_custom_glyph,
-                    )? {
-                        self.glyph_vertices.push(glyph_to_render);
+                        cache_key,
+                        rasterization,
+                        share_rasterized_glyphs,
+                        subpixel,
+                        sdf,
+                        msdf,
+                    ) {
+                        glyphs.insert(glyphon_cache_key, image);
                     }
                 }
             }
         }
 
-        let will_render = !self.glyph_vertices.is_empty();
-        if !will_render {
-            return Ok(());
+        RasterizedText { glyphs }
+    }
+
+    /// Prepares the provided text areas for rendering like [`TextRenderer::prepare`], uploading
+    /// the glyphs rasterized by [`TextRenderer::rasterize_pending`] instead of rasterizing
+    /// them again.
+    ///
+    /// The text areas must be the ones that were rasterized, and the atlas the one that was read.
+    /// Glyphs the atlas cached in the meantime are taken from the atlas. `metadata_to_depth` and
+    /// `rasterize_custom_glyph` are used like by
+    /// [`TextRenderer::prepare_with_depth_and_custom`].
+    pub fn commit<'a>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        rasterized: RasterizedText,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        cache: &mut SwashCache,
+        metadata_to_depth: impl FnMut(usize) -> f32,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+    ) -> Result<(), PrepareError> {
+        self.prepare_areas(
+            device,
+            queue,
+            font_system,
+            atlas,
+            viewport,
+            text_areas.into_iter().map(|text_area| (None, text_area)),
+            cache,
+            metadata_to_depth,
+            rasterize_custom_glyph,
+            rasterized.glyphs,
+        )
+    }
+
+    /// Creates a handle for a text area prepared with [`TextRenderer::prepare_retained`].
+    pub fn create_area_handle(&mut self) -> TextAreaHandle {
+        self.next_area_handle += 1;
+        TextAreaHandle(self.next_area_handle)
+    }
+
+    /// Marks the text area of the handle as changed, so that the next call to
+    /// [`TextRenderer::prepare_retained`] prepares it again.
+    pub fn mark_area_dirty(&mut self, handle: TextAreaHandle) {
+        if let Some(area) = self.retained_areas.get_mut(&handle) {
+            area.dirty = true;
+        }
+    }
+
+    /// Drops the instances kept for the text area of the handle.
+    ///
+    /// The instances of retained text areas are kept until they are removed, even while they
+    /// aren't passed to [`TextRenderer::prepare_retained`].
+    pub fn remove_area(&mut self, handle: TextAreaHandle) {
+        self.retained_areas.remove(&handle);
+    }
+
+    /// Adds a text area to the ones the renderer keeps and draws with
+    /// [`TextRenderer::prepare_stored`], after the ones inserted before it.
+    ///
+    /// The renderer keeps a copy of the text area, including its buffer, custom glyphs, gradient
+    /// stops and path, so that unchanged text doesn't have to be passed to every frame. The
+    /// [`decorations`](TextArea::decorations) and [`glyph_modifier`](TextArea::glyph_modifier)
+    /// borrow closures the renderer can't keep, so they are ignored.
+    pub fn insert(&mut self, text_area: TextArea) -> TextId {
+        let id = TextId(self.create_area_handle());
+        self.stored_areas.push(StoredArea::new(id, &text_area));
+        id
+    }
+
+    /// Replaces the text area kept under the id, which is prepared again by the next call to
+    /// [`TextRenderer::prepare_stored`]. Returns `false` if there is no text area with the id.
+    pub fn update(&mut self, id: TextId, text_area: TextArea) -> bool {
+        let Some(stored) = self.stored_areas.iter_mut().find(|stored| stored.id == id) else {
+            return false;
+        };
+        *stored = StoredArea::new(id, &text_area);
+        self.mark_area_dirty(id.0);
+        true
+    }
+
+    /// Stops drawing the text area kept under the id and drops its instances. Returns `false` if
+    /// there is no text area with the id.
+    pub fn remove(&mut self, id: TextId) -> bool {
+        let stored_areas = self.stored_areas.len();
+        self.stored_areas.retain(|stored| stored.id != id);
+        self.retained_areas.remove(&id.0);
+        self.stored_areas.len() < stored_areas
+    }
+
+    /// Prepares all text areas kept with [`TextRenderer::insert`] for rendering, in the order
+    /// they were inserted.
+    ///
+    /// Text areas that weren't updated since the last call reuse the instances they were last
+    /// prepared with, like with [`TextRenderer::prepare_retained`], including how
+    /// `metadata_to_depth` and `rasterize_custom_glyph` are used. When their instances go stale,
+    /// e.g. as the viewport is resized or the atlas evicts or moves glyphs, they are prepared
+    /// again from the copy the renderer keeps.
+    ///
+    /// The index of each text area in [`Hit::area_index`], [`TextRenderer::glyph_rects`] and the
+    /// other queries of prepared areas is its position among the kept text areas.
+    pub fn prepare_stored(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        cache: &mut SwashCache,
+        metadata_to_depth: impl FnMut(usize) -> f32,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+    ) -> Result<(), PrepareError> {
+        let stored_areas = mem::take(&mut self.stored_areas);
+        let result = self.prepare_areas(
+            device,
+            queue,
+            font_system,
+            atlas,
+            viewport,
+            stored_areas
+                .iter()
+                .map(|stored| (Some(stored.id.0), stored.text_area())),
+            cache,
+            metadata_to_depth,
+            rasterize_custom_glyph,
+            HashMap::new(),
+        );
+        self.stored_areas = stored_areas;
+        result
+    }
+
+    /// Prepares the provided text areas for rendering like [`TextRenderer::prepare`], keeping the
+    /// instances of each one under its handle.
+    ///
+    /// Text areas whose handle wasn't marked dirty with [`TextRenderer::mark_area_dirty`] reuse
+    /// the instances they were last prepared with instead of walking their glyphs again, which
+    /// saves most of the work of `prepare` when only a few of many text areas change. Any change
+    /// to a text area, including to its buffer, position or colors, must be marked dirty, or it
+    /// is drawn as it was before.
+    ///
+    /// Text areas are also prepared again when the resolution of the viewport changes or the
+    /// atlas evicts or moves glyphs, so they never sample stale glyphs.
+    ///
+    /// Only the instances that changed since the last `prepare` are written to the GPU. Areas
+    /// keep the ranges of the vertex buffer they were drawn from while the areas before them
+    /// don't change their number of instances, so pass the areas that change most often last.
+    ///
+    /// `metadata_to_depth` and `rasterize_custom_glyph` are used like by
+    /// [`TextRenderer::prepare_with_depth_and_custom`], but only for the text areas that are
+    /// prepared again, so a text area whose depths or custom glyphs change must be marked dirty.
+    pub fn prepare_retained<'a>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        text_areas: impl IntoIterator<Item = (TextAreaHandle, TextArea<'a>)>,
+        cache: &mut SwashCache,
+        metadata_to_depth: impl FnMut(usize) -> f32,
+        rasterize_custom_glyph: impl FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
+    ) -> Result<(), PrepareError> {
+        self.prepare_areas(
+            device,
+            queue,
+            font_system,
+            atlas,
+            viewport,
+            text_areas
+                .into_iter()
+                .map(|(handle, text_area)| (Some(handle), text_area)),
+            cache,
+            metadata_to_depth,
+            rasterize_custom_glyph,
+            HashMap::new(),
+        )
+    }
+
+    fn prepare_areas<'a>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        viewport: &Viewport,
+        text_areas: impl IntoIterator<Item = (Option<TextAreaHandle>, TextArea<'a>)>,
+        cache: &mut SwashCache,
+        mut metadata_to_depth: impl FnMut(usize) -> f32,
+        mut rasterize_custom_glyph: impl FnMut(
+            RasterizeCustomGlyphRequest,
+        ) -> Option<RasterizedCustomGlyph>,
+        mut rasterized_glyphs: HashMap<GlyphonCacheKey, GetGlyphImageResult>,
+    ) -> Result<(), PrepareError> {
+        trace_span!("glyphon::prepare");
+
+        self.glyph_vertices.clear();
+        self.glow_vertices.clear();
+        self.gradients.clear();
+        self.transforms.clear();
+        self.clips.clear();
+        self.areas.clear();
+        self.shadows.clear();
+        self.knockout_vertices.clear();
+        self.knockout_areas.clear();
+        self.glyph_boxes.clear();
+        self.glyph_rects.clear();
+
+        let resolution = viewport.resolution();
+
+        // With a camera, text outside of the viewport may be panned into view without preparing
+        // it again
+        let camera = viewport.has_camera();
+        let screen = if camera {
+            TextBounds::default()
+        } else {
+            TextBounds {
+                left: 0,
+                top: 0,
+                right: resolution.width as i32,
+                bottom: resolution.height as i32,
+            }
+        };
+
+        #[cfg(feature = "rayon")]
+        let text_areas: Vec<_> = text_areas.into_iter().collect();
+        #[cfg(feature = "rayon")]
+        {
+            let missing = rasterize_missing_glyphs(
+                font_system,
+                atlas,
+                cache,
+                screen,
+                &text_areas,
+                &rasterized_glyphs,
+            );
+            rasterized_glyphs.extend(missing);
+        }
+
+        let mut stats = PrepareStats::default();
+        // The number of glyphs looked up in the atlas
+        let mut lookups = 0;
+
+        for (index, (handle, text_area)) in text_areas.into_iter().enumerate() {
+            trace_span!("glyphon::prepare_area", retained = handle.is_some());
+            stats.areas += 1;
+            if let Some(handle) = handle {
+                if self.reuse_retained_area(index, handle, atlas, resolution, camera) {
+                    stats.reused_areas += 1;
+                    continue;
+                }
+                self.area_glyph_keys.clear();
+            }
+            // Areas that lost their gradient, transform or clip to full tables aren't retained,
+            // as they would keep drawing without them
+            let tables_full = self.gradients.len() >= MAX_GRADIENTS
+                || self.transforms.len() >= MAX_TRANSFORMS
+                || self.clips.len() >= MAX_CLIPS;
+            let gradients_start = self.gradients.len();
+            let transforms_start = self.transforms.len();
+            let clips_start = self.clips.len();
+            let knockout_areas_start = self.knockout_areas.len();
+
+            let mut metadata_to_depth = |metadata| metadata_to_depth(metadata) + text_area.depth;
+            let transform = match (text_area.world_transform, text_area.transform) {
+                _ if self.transforms.len() >= MAX_TRANSFORMS => None,
+                (Some(matrix), _) => Some(AreaTransform::World(matrix)),
+                (None, Some(transform)) => Some(AreaTransform::Screen(transform)),
+                (None, None) => None,
+            };
+            // Transformed text is clipped before it is transformed, so its bounds can't be
+            // limited to the screen
+            let area_bounds = match transform {
+                Some(_) => text_area.bounds,
+                None => text_area.bounds.intersect(&screen),
+            };
+            let instances_start = self.glyph_vertices.len() as u32;
+            let glows_start = self.glow_vertices.len() as u32;
+            let shadows_start = self.shadows.len();
+            let knockouts_start = self.knockout_vertices.len();
+            let glyph_boxes_start = self.glyph_boxes.len();
+            let glyph_rects_start = self.glyph_rects.len();
+            let mut flags = pattern_flags(text_area.pattern);
+
+            let gradient = match &text_area.gradient {
+                Some(gradient) if !gradient.stops.is_empty() => {
+                    content_box(&text_area).map(|([x, y], [width, height])| {
+                        let rect = [x as f32, y as f32, width as f32, height as f32];
+                        GradientParams::new(gradient, rect)
+                    })
+                }
+                _ => text_area.glyph_colors.map(GradientParams::glyph_colors),
+            };
+            if let Some(gradient) = gradient {
+                if self.gradients.len() < MAX_GRADIENTS {
+                    self.gradients.push(gradient);
+                    // Gradients are numbered from one, as zero means that there's no gradient
+                    flags |= (self.gradients.len() as u32) << GRADIENT_SHIFT;
+                }
+            }
+
+            for glyph in text_area.custom_glyphs.iter() {
+                let (x, y, custom_cache_key) = custom_glyph_cache_key(&text_area, glyph);
+                let cache_key = GlyphonCacheKey::Custom(custom_cache_key);
+
+                let color = glyph.color.unwrap_or(text_area.default_color);
+                if handle.is_some() {
+                    self.area_glyph_keys.push(cache_key);
+                }
+                stats.glyphs += 1;
+                lookups += 1;
+
+                let params = GlyphParams {
+                    metadata: glyph.metadata,
+                    oblique: None,
+                    ..GlyphParams::new(x, y, color, cache_key).with_area_effects(&text_area)
+                };
+                let context = GlyphContext {
+                    device,
+                    queue,
+                    atlas,
+                    cache,
+                    font_system,
+                };
+                if let Some(prepared) = prepare_glyph(
+                    params,
+                    context,
+                    |_cache, _font_system, rasterize_custom_glyph| {
+                        stats.count_miss(custom_glyph_image(
+                            custom_cache_key,
+                            text_area.scale,
+                            rasterize_custom_glyph,
+                        ))
+                    },
+                    &mut metadata_to_depth,
+                    &mut rasterize_custom_glyph,
+                )? {
+                    let quad = self.push_glyph(prepared, flags, area_bounds, &text_area, 1.0);
+                    self.push_glyph_rect(quad, cache_key, &text_area, transform);
+                }
+            }
+
+            let share_rasterized_glyphs = atlas.mask_atlas.share_rasterized_glyphs;
+
+            let subpixel = atlas.subpixel;
+            let rasterization = text_area.rasterization.unwrap_or_default();
+            let cull_margin = cull_margin(&text_area);
+            // Glyphs on a path can be moved anywhere, so their lines can't be culled
+            let cull_bounds = match text_area.path {
+                Some(_) => TextBounds::default(),
+                None => area_bounds,
+            };
+
+            {
+                trace_span!("glyphon::layout");
+                for run in visible_layout_runs(&text_area, cull_bounds, cull_margin[1]) {
+                    for glyph in run.glyphs.iter() {
+                        stats.glyphs += 1;
+                        let physical_glyph =
+                            glyph.physical((text_area.left, text_area.top), text_area.scale);
+
+                        let color = match glyph.color_opt {
+                            Some(some) => some,
+                            None => text_area.default_color,
+                        };
+
+                        let mut instance = GlyphInstance {
+                            offset: [0.0; 2],
+                            color,
+                            opacity: 1.0,
+                        };
+                        if let Some(glyph_modifier) = text_area.glyph_modifier {
+                            let index = GlyphIndex {
+                                line: run.line_i,
+                                start: glyph.start,
+                                end: glyph.end,
+                            };
+                            glyph_modifier(index, &mut instance);
+                            if instance.opacity <= 0.0 {
+                                continue;
+                            }
+                        }
+
+                        let faux_bold = text_area
+                            .faux_bold
+                            .filter(|_| lacks_bold_face(font_system, &text_area, &run, glyph));
+                        let embolden = faux_bold.unwrap_or(0.0) + text_area.thickness;
+
+                        // Without the `msdf` feature, glyphs fall back to single-channel fields
+                        let msdf = text_area.msdf && cfg!(feature = "msdf");
+                        let sdf = text_area.sdf || text_area.msdf;
+                        let (mut x, mut y, cache_key, texel_scale) = if sdf {
+                            sdf_glyph(&physical_glyph)
+                        } else {
+                            (
+                                physical_glyph.x,
+                                physical_glyph.y,
+                                physical_glyph.cache_key,
+                                1.0,
+                            )
+                        };
+
+                        let (unshifted_x, unshifted_y) = (x, y);
+                        x += instance.offset[0].round() as i32;
+                        y += instance.offset[1].round() as i32;
+
+                        // Moves the center of the glyph on its baseline onto the path
+                        let mut rotation = None;
+                        if let Some(path) = text_area.path {
+                            let distance = (glyph.x + 0.5 * glyph.w) * text_area.scale;
+                            let baseline = text_area.top + run.line_y * text_area.scale;
+                            let Some(([px, py], [dx, dy])) = path.point_at(distance) else {
+                                continue;
+                            };
+                            let offset_x = (px - text_area.left - distance).round();
+                            let offset_y = (py - baseline).round();
+                            x += offset_x as i32;
+                            y += offset_y as i32;
+                            rotation = Some([
+                                dx,
+                                dy,
+                                text_area.left + distance + offset_x,
+                                baseline + offset_y,
+                            ]);
+                        }
+
+                        // Glyphs that lie entirely outside of the bounds are skipped before they are
+                        // rasterized. Glyphs on a path are clipped once they were moved onto it.
+                        if text_area.path.is_none()
+                            && is_glyph_culled(&text_area, glyph, [x, y], area_bounds, cull_margin)
+                        {
+                            stats.culled_glyphs += 1;
+                            continue;
+                        }
+
+                        let glyphon_cache_key = if msdf {
+                            GlyphonCacheKey::Msdf(cache_key)
+                        } else if sdf {
+                            GlyphonCacheKey::Sdf(cache_key)
+                        } else {
+                            text_cache_key(cache_key, rasterization)
+                        };
+                        if handle.is_some() {
+                            self.area_glyph_keys.push(glyphon_cache_key);
+                        }
+                        lookups += 1;
+
+                        let shift = [(x - unshifted_x) as f32, (y - unshifted_y) as f32];
+                        if let Some(bounds) =
+                            glyph_box(&text_area, &run, glyph, shift, area_bounds, transform)
+                        {
+                            self.glyph_boxes.push(GlyphBox {
+                                line: run.line_i,
+                                start: glyph.start,
+                                bounds,
+                            });
+                        }
+
+                        let params = GlyphParams {
+                            line_y: run.line_y,
+                            metadata: glyph.metadata,
+                            embolden,
+                            texel_scale,
+                            ..GlyphParams::new(x, y, instance.color, glyphon_cache_key)
+                                .with_area_effects(&text_area)
+                        };
+                        let context = GlyphContext {
+                            device,
+                            queue,
+                            atlas,
+                            cache,
+                            font_system,
+                        };
+                        if let Some(prepared) = prepare_glyph(
+                            params,
+                            context,
+                            |cache, font_system, _rasterize_custom_glyph| {
+                                let image = match rasterized_glyphs.remove(&glyphon_cache_key) {
+                                    Some(image) => Some(image),
+                                    None => text_glyph_image(
+                                        cache,
+                                        font_system,
+                                        cache_key,
+                                        rasterization,
+                                        share_rasterized_glyphs,
+                                        subpixel,
+                                        sdf,
+                                        msdf,
+                                    ),
+                                };
+                                stats.count_miss(image)
+                            },
+                            &mut metadata_to_depth,
+                            &mut rasterize_custom_glyph,
+                        )? {
+                            let prepared = match rotation {
+                                Some(rotation) => prepared.with_rotation(rotation),
+                                None => prepared,
+                            };
+                            let quad = self.push_glyph(
+                                prepared,
+                                flags,
+                                area_bounds,
+                                &text_area,
+                                instance.opacity,
+                            );
+                            self.push_glyph_rect(quad, glyphon_cache_key, &text_area, transform);
+                        }
+                    }
+
+                    if let Some(decorations) = text_area.decorations {
+                        prepare_decorations(
+                            &text_area,
+                            &run,
+                            decorations,
+                            font_system,
+                            area_bounds,
+                            atlas.color_mode,
+                            &mut metadata_to_depth,
+                            &mut self.glyph_vertices,
+                        );
+                    }
+                }
+            }
+
+            // The background, selection, drop shadows and outlines are drawn behind all glyphs of
+            // the area
+            if let Some(selection) = text_area.selection {
+                prepare_selection(
+                    &text_area,
+                    selection,
+                    area_bounds,
+                    atlas.color_mode,
+                    &mut metadata_to_depth,
+                    &mut self.selection_vertices,
+                );
+            }
+            let background = text_area.background.and_then(|background| {
+                prepare_background(
+                    &text_area,
+                    background,
+                    area_bounds,
+                    atlas.color_mode,
+                    &mut metadata_to_depth,
+                )
+            });
+            // The glyphs of a knockout background were cut out of it instead of being drawn
+            let background = match background {
+                Some(background) if text_area.background.is_some_and(|b| b.knockout) => {
+                    let glyphs = knockouts_start as u32..self.knockout_vertices.len() as u32;
+                    self.knockout_vertices.push(background);
+                    self.knockout_areas.push(KnockoutArea {
+                        background: glyphs.end..glyphs.end + 1,
+                        glyphs,
+                    });
+                    None
+                }
+                background => background,
+            };
+            let start = instances_start as usize;
+            self.glyph_vertices.splice(
+                start..start,
+                background
+                    .into_iter()
+                    .chain(self.selection_vertices.drain(..))
+                    .chain(self.drop_shadow_vertices.drain(..))
+                    .chain(self.outline_vertices.drain(..)),
+            );
+
+            if let Some(caret) = text_area.caret {
+                if let Some(caret_to_render) = prepare_caret(
+                    &text_area,
+                    caret,
+                    area_bounds,
+                    atlas.color_mode,
+                    &mut metadata_to_depth,
+                ) {
+                    self.glyph_vertices.push(caret_to_render);
+                }
+            }
+
+            if self.debug_mode.bounds {
+                self.glyph_vertices.extend(debug_bounds_outline(
+                    area_bounds,
+                    text_area.depth,
+                    atlas.color_mode,
+                ));
+            }
+
+            let mut scissor_bounds = area_bounds;
+            if let Some(transform) = transform {
+                let origin = [text_area.left, text_area.top];
+                let (params, world_bit) = match transform {
+                    AreaTransform::Screen(transform) => {
+                        scissor_bounds =
+                            transformed_bounds(area_bounds, transform, origin).intersect(&screen);
+                        (TransformParams::new(transform, origin), 0)
+                    }
+                    AreaTransform::World(matrix) => {
+                        scissor_bounds = screen;
+                        (TransformParams::world(matrix), WORLD_TRANSFORM_BIT)
+                    }
+                };
+                self.transforms.push(params);
+                // Transforms are numbered from one, as zero means that there's no transform
+                let index = self.transforms.len() as u32 | world_bit;
+                let glyphs = &mut self.glyph_vertices[instances_start as usize..];
+                let glows = &mut self.glow_vertices[glows_start as usize..];
+                let shadows = self.shadows[shadows_start..].iter_mut().map(|(_, g)| g);
+                let knockouts = &mut self.knockout_vertices[knockouts_start..];
+                for glyph in glyphs
+                    .iter_mut()
+                    .chain(glows)
+                    .chain(shadows)
+                    .chain(knockouts)
+                {
+                    glyph.transform |= index;
+                }
+            }
+
+            if let Some(radii) = text_area.corner_radii {
+                if self.clips.len() < MAX_CLIPS {
+                    self.clips.push(ClipParams::new(text_area.bounds, radii));
+                    // Clips are numbered from one, as zero means that there's no clip
+                    let index = (self.clips.len() as u32) << CLIP_SHIFT;
+                    let glyphs = &mut self.glyph_vertices[instances_start as usize..];
+                    let glows = &mut self.glow_vertices[glows_start as usize..];
+                    let shadows = self.shadows[shadows_start..].iter_mut().map(|(_, g)| g);
+                    let knockouts = &mut self.knockout_vertices[knockouts_start..];
+                    for glyph in glyphs
+                        .iter_mut()
+                        .chain(glows)
+                        .chain(shadows)
+                        .chain(knockouts)
+                    {
+                        glyph.transform |= index;
+                    }
+                }
+            }
+
+            self.areas.push(PreparedArea {
+                index,
+                bounds: scissor_bounds,
+                z_order: text_area.z_order,
+                stencil_reference: text_area.stencil_reference,
+                instances: instances_start..self.glyph_vertices.len() as u32,
+                glows: glows_start..self.glow_vertices.len() as u32,
+                glyph_boxes: glyph_boxes_start as u32..self.glyph_boxes.len() as u32,
+                glyph_rects: glyph_rects_start as u32..self.glyph_rects.len() as u32,
+            });
+
+            if let Some(handle) = handle {
+                if tables_full {
+                    self.retained_areas.remove(&handle);
+                    continue;
+                }
+
+                let knockout_area =
+                    self.knockout_areas[knockout_areas_start..]
+                        .first()
+                        .map(|area| {
+                            let start = knockouts_start as u32;
+                            KnockoutArea {
+                                background: area.background.start - start
+                                    ..area.background.end - start,
+                                glyphs: area.glyphs.start - start..area.glyphs.end - start,
+                            }
+                        });
+                self.retained_areas.insert(
+                    handle,
+                    RetainedArea {
+                        dirty: false,
+                        resolution,
+                        atlas_generation: atlas.generation(),
+                        scissor_clipping: self.scissor_clipping,
+                        debug_bounds: self.debug_mode.bounds,
+                        camera,
+                        instances: self.glyph_vertices[instances_start as usize..].to_vec(),
+                        glows: self.glow_vertices[glows_start as usize..].to_vec(),
+                        shadows: self.shadows[shadows_start..].to_vec(),
+                        knockout_vertices: self.knockout_vertices[knockouts_start..].to_vec(),
+                        knockout_area,
+                        gradient: self.gradients.get(gradients_start).copied(),
+                        transform: self.transforms.get(transforms_start).copied(),
+                        clip: self.clips.get(clips_start).copied(),
+                        bounds: scissor_bounds,
+                        z_order: text_area.z_order,
+                        stencil_reference: text_area.stencil_reference,
+                        glyph_keys: mem::take(&mut self.area_glyph_keys),
+                        glyph_boxes: self.glyph_boxes[glyph_boxes_start..].to_vec(),
+                        glyph_rects: self.glyph_rects[glyph_rects_start..].to_vec(),
+                    },
+                );
+            }
+        }
+
+        self.sort_areas();
+
+        if !self.gradients.is_empty() {
+            let gradients = self.gradients.as_slice();
+            let gradients_raw = unsafe {
+                slice::from_raw_parts(
+                    gradients as *const _ as *const u8,
+                    mem::size_of_val(gradients),
+                )
+            };
+            queue.write_buffer(&self.gradient_buffer, 0, gradients_raw);
+        }
+
+        if !self.transforms.is_empty() {
+            let transforms = self.transforms.as_slice();
+            let transforms_raw = unsafe {
+                slice::from_raw_parts(
+                    transforms as *const _ as *const u8,
+                    mem::size_of_val(transforms),
+                )
+            };
+            queue.write_buffer(&self.transform_buffer, 0, transforms_raw);
+        }
+
+        if !self.clips.is_empty() {
+            let clips = self.clips.as_slice();
+            let clips_raw = unsafe {
+                slice::from_raw_parts(clips as *const _ as *const u8, mem::size_of_val(clips))
+            };
+            queue.write_buffer(&self.clip_buffer, 0, clips_raw);
+        }
+
+        // Glows are stored after the instances of all areas
+        let glow_start = self.glyph_vertices.len() as u32;
+        self.glyph_vertices.append(&mut self.glow_vertices);
+        self.glow_instances = glow_start..self.glyph_vertices.len() as u32;
+        for area in &mut self.areas {
+            area.glows = area.glows.start + glow_start..area.glows.end + glow_start;
+        }
+
+        let area_count = self.areas.iter().map(|area| area.index + 1).max();
+        for index in self.area_debug_markers.len()..area_count.unwrap_or(0) {
+            self.area_debug_markers.push([
+                format!("glyphon text area {index}"),
+                format!("glyphon text area {index} glows"),
+            ]);
+        }
+
+        atlas.flush_uploads(device, queue);
+        atlas.update_mipmaps(device, queue);
+
+        // The offscreen targets of shadows and knockouts only have a single view
+        if self.multiview.is_some() {
+            self.shadows.clear();
+            self.knockout_vertices.clear();
+            self.knockout_areas.clear();
+        }
+
+        if !self.shadows.is_empty() && self.shadow_blur.is_none() {
+            self.shadow_blur = Some(ShadowBlur::new(
+                device,
+                atlas,
+                self.multisample,
+                self.depth_stencil.clone(),
+            ));
+        }
+        if let Some(shadow_blur) = &mut self.shadow_blur {
+            shadow_blur.prepare(device, queue, resolution, &mut self.shadows);
+        }
+
+        if !self.knockout_vertices.is_empty() && self.knockout.is_none() {
+            self.knockout = Some(Knockout::new(
+                device,
+                atlas,
+                self.multisample,
+                self.depth_stencil.clone(),
+            ));
+        }
+        if let Some(knockout) = &mut self.knockout {
+            knockout.prepare(
+                device,
+                queue,
+                resolution,
+                &self.knockout_vertices,
+                &mut self.knockout_areas,
+            );
+        }
+
+        stats.atlas_hits = lookups - stats.atlas_misses;
+        stats.instances =
+            self.glyph_vertices.len() + self.shadows.len() + self.knockout_vertices.len();
+        self.prepare_stats = stats;
+
+        let will_render = !self.glyph_vertices.is_empty();
+        if !will_render {
+            return Ok(());
+        }
+
+        // Instances are packed into the compact layout when all of them fit into it, which roughly
+        // halves the bytes uploaded and read per instance
+        self.packed_vertices.clear();
+        self.instance_layout = if self.vertex_pulling
+            && self.multiview.is_none()
+            && atlas.cache.supports_pulled_instances()
+        {
+            InstanceLayout::Pulled
+        } else if self.multiview.is_none()
+            && atlas.cache.supports_packed_instances()
+            && self.glyph_vertices.iter().all(|glyph| {
+                glyph
+                    .pack()
+                    .map(|packed| self.packed_vertices.push(packed))
+                    .is_some()
+            })
+        {
+            InstanceLayout::Packed
+        } else {
+            InstanceLayout::Full
+        };
+        let layout_pipelines = match self.instance_layout {
+            InstanceLayout::Full => None,
+            InstanceLayout::Packed => Some(&mut self.packed_pipelines),
+            InstanceLayout::Pulled => Some(&mut self.pulled_pipelines),
+            InstanceLayout::Placed => Some(&mut self.placed_pipelines),
+        };
+        if let Some(pipelines @ None) = layout_pipelines {
+            *pipelines = Some(create_pipelines(
+                atlas,
+                device,
+                self.multisample,
+                self.depth_stencil.clone(),
+                self.blend_mode,
+                self.multiview,
+                self.write_mask,
+                self.instance_layout,
+            ));
+        }
+        let pulled = self.instance_layout == InstanceLayout::Pulled;
+        if pulled && self.placed_pipelines.is_none() && atlas.cache.supports_placed_instances() {
+            self.placed_pipelines = Some(create_pipelines(
+                atlas,
+                device,
+                self.multisample,
+                self.depth_stencil.clone(),
+                self.blend_mode,
+                self.multiview,
+                self.write_mask,
+                InstanceLayout::Placed,
+            ));
+        }
+
+        let vertices_raw = if self.instance_layout == InstanceLayout::Packed {
+            let vertices = self.packed_vertices.as_slice();
+            unsafe {
+                slice::from_raw_parts(
+                    vertices as *const _ as *const u8,
+                    std::mem::size_of_val(vertices),
+                )
+            }
+        } else {
+            let vertices = self.glyph_vertices.as_slice();
+            unsafe {
+                slice::from_raw_parts(
+                    vertices as *const _ as *const u8,
+                    std::mem::size_of_val(vertices),
+                )
+            }
+        };
+
+        let size = vertices_raw.len() as u64;
+        {
+            trace_span!("glyphon::upload_vertices", bytes = size);
+            // Storage buffers are bound from their start, so pulled instances aren't shared
+            if self.share_vertices && !pulled {
+                let (cache, range) = self
+                    .shared_vertices
+                    .get_or_insert_with(|| (atlas.cache.clone(), 0..0));
+                if !cache.ptr_eq(&atlas.cache) {
+                    cache.free_vertices(mem::replace(range, 0..0));
+                    *cache = atlas.cache.clone();
+                }
+
+                let previous_range = range.clone();
+                self.vertex_buffer = cache.allocate_vertices(device, queue, range, size);
+                self.vertex_offset = range.start;
+                if *range != previous_range {
+                    self.uploaded_vertices.clear();
+                }
+                self.prepare_stats.uploaded_vertex_bytes = write_changed_ranges(
+                    queue,
+                    &self.vertex_buffer,
+                    self.vertex_offset,
+                    &self.uploaded_vertices,
+                    vertices_raw,
+                );
+            } else {
+                // The renderer gets its own buffer again after sharing one
+                let was_shared = match self.shared_vertices.take() {
+                    Some((cache, range)) => {
+                        cache.free_vertices(range);
+                        true
+                    }
+                    None => false,
+                };
+
+                let usage = match pulled {
+                    true => BufferUsages::VERTEX | BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                    false => BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                };
+                if !was_shared
+                    && self.vertex_buffer_size >= size
+                    && self.vertex_buffer.usage().contains(usage)
+                {
+                    self.prepare_stats.uploaded_vertex_bytes = write_changed_ranges(
+                        queue,
+                        &self.vertex_buffer,
+                        0,
+                        &self.uploaded_vertices,
+                        vertices_raw,
+                    );
+                } else {
+                    if !was_shared {
+                        self.vertex_buffer.destroy();
+                    }
+
+                    let (buffer, buffer_size) = create_oversized_buffer(
+                        device,
+                        Some("glyphon vertices"),
+                        vertices_raw,
+                        usage,
+                    );
+
+                    self.vertex_buffer = buffer;
+                    self.vertex_buffer_size = buffer_size;
+                    self.vertex_offset = 0;
+                    self.prepare_stats.uploaded_vertex_bytes = size;
+                }
+            }
+
+            self.uploaded_vertices.clear();
+            self.uploaded_vertices.extend_from_slice(vertices_raw);
+        }
+
+        let stale_bind_group = self
+            .instances_bind_group
+            .as_ref()
+            .is_none_or(|(buffer, _)| *buffer != self.vertex_buffer);
+        if pulled && stale_bind_group {
+            let bind_group = atlas
+                .cache
+                .create_instances_bind_group(device, &self.vertex_buffer);
+            self.instances_bind_group = Some((self.vertex_buffer.clone(), bind_group));
+        }
+
+        Ok(())
+    }
+
+    /// Appends the instances the retained text area at `index` of the text areas being prepared
+    /// was last prepared with, unless it has to be prepared again. Returns whether the instances
+    /// were reused.
+    fn reuse_retained_area(
+        &mut self,
+        index: usize,
+        handle: TextAreaHandle,
+        atlas: &mut TextAtlas,
+        resolution: Resolution,
+        camera: bool,
+    ) -> bool {
+        let Some(area) = self.retained_areas.get(&handle) else {
+            return false;
+        };
+        if area.dirty
+            || area.resolution != resolution
+            || area.atlas_generation != atlas.generation()
+            || area.scissor_clipping != self.scissor_clipping
+            || area.debug_bounds != self.debug_mode.bounds
+            || area.camera != camera
+            || (area.gradient.is_some() && self.gradients.len() >= MAX_GRADIENTS)
+            || (area.transform.is_some() && self.transforms.len() >= MAX_TRANSFORMS)
+            || (area.clip.is_some() && self.clips.len() >= MAX_CLIPS)
+        {
+            return false;
+        }
+
+        for cache_key in &area.glyph_keys {
+            atlas.mark_glyph_in_use(cache_key);
+        }
+
+        // The tables are numbered from one, as zero means that there's no entry
+        let gradient = area.gradient.map(|gradient| {
+            self.gradients.push(gradient);
+            self.gradients.len() as u32
+        });
+        let transform = area.transform.map(|transform| {
+            self.transforms.push(transform);
+            self.transforms.len() as u32
+        });
+        let clip = area.clip.map(|clip| {
+            self.clips.push(clip);
+            self.clips.len() as u32
+        });
+
+        let instances_start = self.glyph_vertices.len();
+        let glows_start = self.glow_vertices.len();
+        let shadows_start = self.shadows.len();
+        let knockouts_start = self.knockout_vertices.len();
+        let glyph_boxes_start = self.glyph_boxes.len();
+        let glyph_rects_start = self.glyph_rects.len();
+        self.glyph_vertices.extend_from_slice(&area.instances);
+        self.glyph_boxes.extend_from_slice(&area.glyph_boxes);
+        self.glyph_rects.extend_from_slice(&area.glyph_rects);
+        self.glow_vertices.extend_from_slice(&area.glows);
+        self.shadows.extend_from_slice(&area.shadows);
+        self.knockout_vertices
+            .extend_from_slice(&area.knockout_vertices);
+
+        let glyphs = &mut self.glyph_vertices[instances_start..];
+        let glows = &mut self.glow_vertices[glows_start..];
+        let shadows = self.shadows[shadows_start..].iter_mut().map(|(_, g)| g);
+        let knockouts = &mut self.knockout_vertices[knockouts_start..];
+        for glyph in glyphs
+            .iter_mut()
+            .chain(glows)
+            .chain(shadows)
+            .chain(knockouts)
+        {
+            remap_retained_glyph(glyph, gradient, transform, clip);
+        }
+
+        if let Some(knockout_area) = &area.knockout_area {
+            let start = knockouts_start as u32;
+            self.knockout_areas.push(KnockoutArea {
+                background: knockout_area.background.start + start
+                    ..knockout_area.background.end + start,
+                glyphs: knockout_area.glyphs.start + start..knockout_area.glyphs.end + start,
+            });
+        }
+
+        self.areas.push(PreparedArea {
+            index,
+            bounds: area.bounds,
+            z_order: area.z_order,
+            stencil_reference: area.stencil_reference,
+            instances: instances_start as u32..self.glyph_vertices.len() as u32,
+            glows: glows_start as u32..self.glow_vertices.len() as u32,
+            glyph_boxes: glyph_boxes_start as u32..self.glyph_boxes.len() as u32,
+            glyph_rects: glyph_rects_start as u32..self.glyph_rects.len() as u32,
+        });
+
+        true
+    }
+
+    /// Rasterizes the glyphs of the provided text areas into the atlas ahead of time, without
+    /// preparing anything to render.
+    ///
+    /// This avoids hitches when text first appears, e.g. by prewarming the glyphs of upcoming
+    /// dialogue during a loading screen. Every line of the buffers is rasterized, regardless of
+    /// the bounds of the text areas. To prewarm a character set, shape it into a [`Buffer`]
+    /// with the fonts and sizes it will be rendered with.
+    ///
+    /// [`Buffer`]: crate::Buffer
+    pub fn prewarm<'a>(
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        text_areas: impl IntoIterator<Item = TextArea<'a>>,
+        cache: &mut SwashCache,
+        mut rasterize_custom_glyph: impl FnMut(
+            RasterizeCustomGlyphRequest,
+        ) -> Option<RasterizedCustomGlyph>,
+    ) -> Result<(), PrepareError> {
+        let share_rasterized_glyphs = atlas.mask_atlas.share_rasterized_glyphs;
+        let subpixel = atlas.subpixel;
+
+        for text_area in text_areas {
+            let rasterization = text_area.rasterization.unwrap_or_default();
+
+            for glyph in text_area.custom_glyphs.iter() {
+                let (x, y, cache_key) = custom_glyph_cache_key(&text_area, glyph);
+
+                let params = GlyphParams {
+                    metadata: glyph.metadata,
+                    scale_factor: text_area.scale,
+                    ..GlyphParams::new(
+                        x,
+                        y,
+                        text_area.default_color,
+                        GlyphonCacheKey::Custom(cache_key),
+                    )
+                };
+                let context = GlyphContext {
+                    device,
+                    queue,
+                    atlas,
+                    cache,
+                    font_system,
+                };
+                prepare_glyph(
+                    params,
+                    context,
+                    |_cache, _font_system, rasterize_custom_glyph| {
+                        custom_glyph_image(cache_key, text_area.scale, rasterize_custom_glyph)
+                    },
+                    zero_depth,
+                    &mut rasterize_custom_glyph,
+                )?;
+            }
+
+            for run in text_area.buffer.layout_runs() {
+                for glyph in run.glyphs.iter() {
+                    let physical_glyph =
+                        glyph.physical((text_area.left, text_area.top), text_area.scale);
+
+                    let params = GlyphParams {
+                        line_y: run.line_y,
+                        metadata: glyph.metadata,
+                        scale_factor: text_area.scale,
+                        ..GlyphParams::new(
+                            physical_glyph.x,
+                            physical_glyph.y,
+                            text_area.default_color,
+                            text_cache_key(physical_glyph.cache_key, rasterization),
+                        )
+                    };
+                    let context = GlyphContext {
+                        device,
+                        queue,
+                        atlas,
+                        cache,
+                        font_system,
+                    };
+                    prepare_glyph(
+                        params,
+                        context,
+                        |cache, font_system, _rasterize_custom_glyph| {
+                            text_glyph_image(
+                                cache,
+                                font_system,
+                                physical_glyph.cache_key,
+                                rasterization,
+                                share_rasterized_glyphs,
+                                subpixel,
+                                false,
+                                false,
+                            )
+                        },
+                        zero_depth,
+                        &mut rasterize_custom_glyph,
+                    )?;
+                }
+            }
+        }
+
+        atlas.flush_uploads(device, queue);
+        atlas.update_mipmaps(device, queue);
+
+        Ok(())
+    }
+
+    /// Prepares the cells of a [`CellGrid`] that changed since it was last prepared, without
+    /// shaping or laying out any text, and uploads their instances into the buffer of the grid.
+    ///
+    /// The glyphs of unchanged cells are kept in the atlas, so call this every frame the grid is
+    /// drawn with [`TextRenderer::render_grid`]. All cells are prepared again when glyphs moved
+    /// within the atlas.
+    pub fn prepare_grid(
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        atlas: &mut TextAtlas,
+        grid: &mut CellGrid,
+        cache: &mut SwashCache,
+    ) -> Result<(), PrepareError> {
+        trace_span!("glyphon::prepare_grid");
+
+        let count = grid.cells.len();
+        if grid.instances.len() != count * INSTANCES_PER_CELL {
+            let empty = cell_quad([0, 0], [0, 0], Color(0), atlas.color_mode, 0.0);
+            grid.instances = vec![empty; count * INSTANCES_PER_CELL];
+            grid.glyph_keys = vec![None; count];
+            grid.all_dirty = true;
+        }
+
+        for cache_key in grid.glyph_keys.iter().flatten() {
+            atlas.mark_glyph_in_use(cache_key);
+        }
+
+        let metrics = grid.metrics;
+        let decorations = font_system
+            .get_font(metrics.font)
+            .map(|font| font.as_swash().metrics(&[]).scale(metrics.font_size));
+
+        // The range of cells whose instances changed
+        let mut changed = count..0;
+        loop {
+            let generation = atlas.generation();
+            if grid.all_dirty || grid.atlas_generation != generation {
+                grid.dirty.fill(true);
+                grid.all_dirty = false;
+            }
+
+            for index in 0..count {
+                if !mem::take(&mut grid.dirty[index]) {
+                    continue;
+                }
+
+                let result = prepare_cell(
+                    grid,
+                    index,
+                    decorations,
+                    atlas,
+                    device,
+                    queue,
+                    cache,
+                    font_system,
+                );
+                if let Err(error) = result {
+                    grid.all_dirty = true;
+                    return Err(error);
+                }
+                changed.start = changed.start.min(index);
+                changed.end = changed.end.max(index + 1);
+            }
+
+            // Preparing cells may have moved the glyphs of the other cells within the atlas
+            grid.atlas_generation = atlas.generation();
+            if grid.atlas_generation == generation {
+                break;
+            }
+        }
+
+        atlas.flush_uploads(device, queue);
+        atlas.update_mipmaps(device, queue);
+
+        if grid.instances.is_empty() {
+            return Ok(());
+        }
+
+        let instances = grid.instances.as_slice();
+        let instances_raw = unsafe {
+            slice::from_raw_parts(
+                instances as *const _ as *const u8,
+                std::mem::size_of_val(instances),
+            )
+        };
+        let size = instances_raw.len() as u64;
+        match &grid.buffer {
+            Some(buffer) if grid.buffer_size >= size => {
+                if changed.is_empty() {
+                    return Ok(());
+                }
+
+                // Backgrounds, glyphs and decorations are uploaded in separate ranges
+                let stride = mem::size_of::<GlyphToRender>();
+                for (first, per_cell) in [(0, 1), (count, 1), (2 * count, 2)] {
+                    let start = (first + changed.start * per_cell) * stride;
+                    let end = (first + changed.end * per_cell) * stride;
+                    queue.write_buffer(buffer, start as u64, &instances_raw[start..end]);
+                }
+            }
+            _ => {
+                if let Some(buffer) = grid.buffer.take() {
+                    buffer.destroy();
+                }
+
+                let (buffer, buffer_size) = create_oversized_buffer(
+                    device,
+                    Some("glyphon cell grid"),
+                    instances_raw,
+                    BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                );
+                grid.buffer = Some(buffer);
+                grid.buffer_size = buffer_size;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Clips a prepared glyph and its shadows to the bounds of its text area and adds them to the
+    /// instances.
+    fn push_glyph(
+        &mut self,
+        prepared: PreparedGlyph,
+        flags: u32,
+        bounds: TextBounds,
+        text_area: &TextArea,
+        opacity: f32,
+    ) -> Option<TextBounds> {
+        let mut prepared = prepared.with_opacity(text_area.opacity * opacity);
+
+        // With scissor clipping, the instances of untransformed areas are clipped when rendering
+        let scissor = self.scissor_clipping
+            && text_area.transform.is_none()
+            && text_area.world_transform.is_none();
+        let clip = |glyph: GlyphToRender| {
+            if scissor {
+                // Instances outside of the scissor rect are still dropped
+                let [x, y] = glyph.pos;
+                let [width, height] = glyph.dim.map(i32::from);
+                let visible = x < bounds.right
+                    && bounds.left < x + width
+                    && y < bounds.bottom
+                    && bounds.top < y + height;
+                visible.then_some(glyph)
+            } else {
+                clip_glyph(glyph, bounds)
+            }
+        };
+
+        if let Some(style) = text_area.color_glyphs {
+            let glyph = &mut prepared.glyph;
+            if glyph.content_type_with_srgb[0] == shader_content_type(ContentType::Color) {
+                let (style, color) = match style {
+                    ColorGlyphStyle::Tint(tint) => (1, tint.0),
+                    ColorGlyphStyle::Grayscale => (2, glyph.color),
+                    ColorGlyphStyle::Silhouette => (3, glyph.color),
+                };
+                glyph.flags |= style << COLOR_GLYPH_STYLE_SHIFT;
+                glyph.color = color;
+            }
+        }
+
+        if let Some(shadow_style) = text_area.shadow_style {
+            let shadow = shadow_style_glyph(prepared.glyph, shadow_style, text_area.scale);
+            if let Some(shadow) = clip_glyph(shadow, bounds) {
+                let radius = (shadow_style.radius * text_area.scale).clamp(0.0, MAX_SHADOW_RADIUS);
+                self.shadows.push((radius, shadow));
+            }
+        }
+
+        if let Some(drop_shadow) = prepared.drop_shadow.and_then(clip) {
+            self.drop_shadow_vertices.push(drop_shadow);
+        }
+
+        if let Some(outline) = prepared.outline.and_then(clip) {
+            self.outline_vertices.push(outline);
+        }
+
+        if let Some(glow) = prepared.glow.and_then(clip) {
+            self.glow_vertices.push(glow);
+        }
+
+        // Knockouts are drawn offscreen without a scissor rect, so they are always clipped here
+        let knockout = text_area.background.is_some_and(|b| b.knockout);
+        let glyph = if knockout {
+            clip_glyph(prepared.glyph, bounds)
+        } else {
+            clip(prepared.glyph)
+        };
+        let glyph = glyph?;
+        let glyph = GlyphToRender {
+            flags: glyph.flags | flags,
+            ..glyph
+        };
+        if knockout {
+            self.knockout_vertices.push(glyph);
+        } else {
+            self.glyph_vertices.push(glyph);
+        }
+
+        let [x, y] = glyph.pos;
+        let [width, height] = glyph.dim.map(i32::from);
+        let quad = TextBounds {
+            left: x,
+            top: y,
+            right: x + width,
+            bottom: y + height,
+        };
+        Some(quad.intersect(&bounds))
+    }
+
+    /// Records the quad of a glyph returned by `push_glyph` on screen.
+    fn push_glyph_rect(
+        &mut self,
+        quad: Option<TextBounds>,
+        cache_key: GlyphonCacheKey,
+        text_area: &TextArea,
+        transform: Option<AreaTransform>,
+    ) {
+        let Some(bounds) = quad.and_then(|quad| screen_bounds(quad, text_area, transform)) else {
+            return;
+        };
+        self.glyph_rects.push(GlyphRect { bounds, cache_key });
+    }
+
+    /// Returns the debug marker of the draw of the glyphs or glows of the text area at `index`.
+    /// Areas left over from a `prepare` that failed may not have one yet.
+    fn area_debug_marker(&self, index: usize, glows: bool) -> &str {
+        self.area_debug_markers
+            .get(index)
+            .map_or("glyphon text area", |markers| &markers[glows as usize])
+    }
+
+    /// Reorders the prepared areas and their instances by `z_order`, keeping the input order of
+    /// areas with the same `z_order`.
+    fn sort_areas(&mut self) {
+        if self.areas.windows(2).all(|w| w[0].z_order <= w[1].z_order) {
+            return;
+        }
+
+        self.areas.sort_by_key(|area| area.z_order);
+
+        let mut sorted = Vec::with_capacity(self.glyph_vertices.len());
+        for area in &mut self.areas {
+            let start = sorted.len() as u32;
+            sorted.extend_from_slice(
+                &self.glyph_vertices[area.instances.start as usize..area.instances.end as usize],
+            );
+            area.instances = start..sorted.len() as u32;
+        }
+
+        self.glyph_vertices = sorted;
+    }
+
+    /// Renders and blurs the shadows of text areas with a [`ShadowStyle`] that were previously
+    /// provided to `prepare`.
+    ///
+    /// This records offscreen passes into `encoder`, so it must be called after `prepare` and
+    /// before the render pass that the text is rendered in. The blurred shadows are then
+    /// composited beneath the text by `render`.
+    pub fn render_shadows(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        encoder: &mut CommandEncoder,
+    ) {
+        if let Some(shadow_blur) = &self.shadow_blur {
+            encoder.push_debug_group("glyphon shadows");
+            shadow_blur.render(encoder, atlas, viewport, &self.pattern_bind_group);
+            encoder.pop_debug_group();
+        }
+    }
+
+    /// Renders the backgrounds of text areas with a knockout [`TextBackground`] that were
+    /// previously provided to `prepare`, with their glyphs cut out.
+    ///
+    /// This records an offscreen pass into `encoder`, so it must be called after `prepare` and
+    /// before the render pass that the text is rendered in. The backgrounds are then composited
+    /// beneath the text by `render`.
+    pub fn render_knockouts(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        encoder: &mut CommandEncoder,
+    ) {
+        if let Some(knockout) = &self.knockout {
+            encoder.push_debug_group("glyphon knockouts");
+            knockout.render(encoder, atlas, viewport, &self.pattern_bind_group);
+            encoder.pop_debug_group();
+        }
+    }
+
+    /// Returns the text and glow pipelines that read the instances in the vertex buffer.
+    fn pipelines(&self) -> (&RenderPipeline, &RenderPipeline) {
+        let pipelines = match self.instance_layout {
+            InstanceLayout::Full => None,
+            InstanceLayout::Packed => self.packed_pipelines.as_ref(),
+            InstanceLayout::Pulled => self.pulled_pipelines.as_ref(),
+            InstanceLayout::Placed => self.placed_pipelines.as_ref(),
+        };
+        match pipelines {
+            Some((pipeline, glow_pipeline)) => (pipeline, glow_pipeline),
+            None => (&self.pipeline, &self.glow_pipeline),
+        }
+    }
+
+    /// Returns the vertices and instances to draw for a range of instances.
+    fn draw_ranges(&self, instances: Range<u32>) -> (Range<u32>, Range<u32>) {
+        match self.instance_layout {
+            // Pulled instances are drawn as six vertices each
+            InstanceLayout::Pulled | InstanceLayout::Placed => {
+                (instances.start * 6..instances.end * 6, 0..1)
+            }
+            _ => (0..4, instances),
+        }
+    }
+
+    /// Renders all layouts that were previously provided to `prepare` like
+    /// [`TextRenderer::render`], measuring the GPU time it takes with the timer.
+    ///
+    /// The call isn't measured if the timer already holds as many measurements as it was created
+    /// for.
+    pub fn render_timed(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass: &mut RenderPass<'_>,
+        timer: &mut GpuTimer,
+    ) -> Result<(), RenderError> {
+        let query = timer.begin(pass);
+        let result = self.render(atlas, viewport, pass);
+        if let Some(query) = query {
+            timer.end(pass, query);
+        }
+        result
+    }
+
+    /// Renders all layouts that were previously provided to `prepare`.
+    pub fn render(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass: &mut RenderPass<'_>,
+    ) -> Result<(), RenderError> {
+        if self.scissor_clipping {
+            return self.render_with_scissor(atlas, viewport, pass, None);
+        }
+
+        if self.glyph_vertices.is_empty() && self.knockout_vertices.is_empty() {
+            return Ok(());
+        }
+
+        pass.push_debug_group("glyphon text");
+
+        if let Some(knockout) = &self.knockout {
+            knockout.composite(pass);
+        }
+
+        if let Some(shadow_blur) = &self.shadow_blur {
+            shadow_blur.composite(pass);
+        }
+
+        pass.set_bind_group(0, &atlas.bind_group, &[]);
+        viewport.bind(pass);
+        pass.set_bind_group(atlas.cache.pattern_group(), &self.pattern_bind_group, &[]);
+        match &self.instances_bind_group {
+            Some((_, bind_group)) if self.instance_layout == InstanceLayout::Pulled => {
+                pass.set_bind_group(atlas.cache.instances_group(), bind_group, &[]);
+            }
+            _ => pass.set_vertex_buffer(0, self.vertex_buffer.slice(self.vertex_offset..)),
+        }
+
+        if self.stencil_enabled() {
+            // Each area is drawn with its own stencil reference
+            let (pipeline, glow_pipeline) = self.pipelines();
+            for (pipeline, glows) in [(glow_pipeline, true), (pipeline, false)] {
+                pass.set_pipeline(pipeline);
+
+                for area in &self.areas {
+                    let instances = if glows {
+                        area.glows.clone()
+                    } else {
+                        area.instances.clone()
+                    };
+                    if !instances.is_empty() {
+                        pass.insert_debug_marker(self.area_debug_marker(area.index, glows));
+                        pass.set_stencil_reference(area.stencil_reference);
+                        let (vertices, instances) = self.draw_ranges(instances);
+                        pass.draw(vertices, instances);
+                    }
+                }
+            }
+
+            pass.pop_debug_group();
+            return Ok(());
+        }
+
+        let (pipeline, glow_pipeline) = self.pipelines();
+        if !self.glow_instances.is_empty() {
+            pass.insert_debug_marker("glyphon glows");
+            pass.set_pipeline(glow_pipeline);
+            let (vertices, instances) = self.draw_ranges(self.glow_instances.clone());
+            pass.draw(vertices, instances);
+        }
+
+        pass.insert_debug_marker("glyphon glyphs");
+        pass.set_pipeline(pipeline);
+        let (vertices, instances) = self.draw_ranges(0..self.glow_instances.start);
+        pass.draw(vertices, instances);
+
+        pass.pop_debug_group();
+        Ok(())
+    }
+
+    /// Renders the prepared text area at the given position in the text areas passed to
+    /// `prepare` once for each placement in `placements` within `range`, in a single draw call,
+    /// e.g. for repeated labels such as grid coordinates or damage numbers.
+    ///
+    /// `placements` is a vertex buffer of [`TextTransform`]s, see
+    /// [`TextTransform::slice_as_bytes`], which transform the positions of the text in physical
+    /// pixels after the transform of the text area. Text areas with a world transform aren't
+    /// placed, and their text is drawn at the same position for every placement. The text isn't
+    /// clipped to the scissor rect of its bounds, and its shadows and knockout backgrounds aren't
+    /// drawn.
+    ///
+    /// This requires a renderer that pulls its instances from a storage buffer, see
+    /// [`TextRenderer::set_vertex_pulling`], and returns [`RenderError::PlacementUnsupported`]
+    /// otherwise, or if the shader was replaced without the `vs_placed` entry point.
+    pub fn render_placed(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass: &mut RenderPass<'_>,
+        area: usize,
+        placements: &Buffer,
+        range: Range<u32>,
+    ) -> Result<(), RenderError> {
+        if self.glyph_vertices.is_empty() || range.is_empty() {
+            return Ok(());
+        }
+
+        let Some((pipeline, glow_pipeline)) = self
+            .placed_pipelines
+            .as_ref()
+            .filter(|_| self.instance_layout == InstanceLayout::Pulled)
+        else {
+            return Err(RenderError::PlacementUnsupported);
+        };
+        let Some(area) = self.areas.iter().find(|prepared| prepared.index == area) else {
+            return Ok(());
+        };
+        let Some((_, bind_group)) = &self.instances_bind_group else {
+            return Ok(());
+        };
+
+        pass.push_debug_group("glyphon placed text");
+        pass.set_bind_group(0, &atlas.bind_group, &[]);
+        viewport.bind(pass);
+        pass.set_bind_group(atlas.cache.pattern_group(), &self.pattern_bind_group, &[]);
+        pass.set_bind_group(atlas.cache.instances_group(), bind_group, &[]);
+        pass.set_vertex_buffer(0, placements.slice(..));
+        if self.stencil_enabled() {
+            pass.set_stencil_reference(area.stencil_reference);
+        }
+
+        for (pipeline, glows) in [(glow_pipeline, true), (pipeline, false)] {
+            let instances = if glows {
+                area.glows.clone()
+            } else {
+                area.instances.clone()
+            };
+            if instances.is_empty() {
+                continue;
+            }
+
+            pass.insert_debug_marker(self.area_debug_marker(area.index, glows));
+            pass.set_pipeline(pipeline);
+            let (vertices, _) = self.draw_ranges(instances);
+            pass.draw(vertices, range.clone());
+        }
+
+        pass.pop_debug_group();
+        Ok(())
+    }
+
+    /// Records all layouts that were previously provided to `prepare` into a render bundle, which
+    /// can be executed in later render passes without recording the draws again, e.g. for UI
+    /// layers that rarely change.
+    ///
+    /// The bundle refers to buffers and offscreen targets that `prepare` may replace, so it must
+    /// be recorded again after each call to `prepare`. On backends with push constants, the
+    /// parameters of the viewport are recorded into the bundle as well, so it must also be
+    /// recorded again after the viewport changes. Render bundles can't set the stencil
+    /// reference, so all text areas are drawn with the reference of the render pass that
+    /// executes the bundle.
+    pub fn render_bundle<'a>(
+        &'a self,
+        atlas: &'a TextAtlas,
+        viewport: &'a Viewport,
+        encoder: &mut RenderBundleEncoder<'a>,
+    ) -> Result<(), RenderError> {
+        if self.glyph_vertices.is_empty() && self.knockout_vertices.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(knockout) = &self.knockout {
+            knockout.composite_bundle(encoder);
+        }
+
+        if let Some(shadow_blur) = &self.shadow_blur {
+            shadow_blur.composite_bundle(encoder);
+        }
+
+        encoder.set_bind_group(0, &atlas.bind_group, &[]);
+        viewport.bind_bundle(encoder);
+        encoder.set_bind_group(atlas.cache.pattern_group(), &self.pattern_bind_group, &[]);
+        match &self.instances_bind_group {
+            Some((_, bind_group)) if self.instance_layout == InstanceLayout::Pulled => {
+                encoder.set_bind_group(atlas.cache.instances_group(), bind_group, &[]);
+            }
+            _ => encoder.set_vertex_buffer(0, self.vertex_buffer.slice(self.vertex_offset..)),
+        }
+
+        let (pipeline, glow_pipeline) = self.pipelines();
+        if !self.glow_instances.is_empty() {
+            encoder.set_pipeline(glow_pipeline);
+            let (vertices, instances) = self.draw_ranges(self.glow_instances.clone());
+            encoder.draw(vertices, instances);
+        }
+
+        encoder.set_pipeline(pipeline);
+        let (vertices, instances) = self.draw_ranges(0..self.glow_instances.start);
+        encoder.draw(vertices, instances);
+
+        Ok(())
+    }
+
+    /// Renders all layouts that were previously provided to `prepare`, clipping each text area to
+    /// the intersection of its `TextBounds` and the given `scissor` rect using the GPU scissor.
+    ///
+    /// This is useful when the caller already clips a region of the render target (e.g. a UI
+    /// panel) and wants text to respect it. Once the text has been drawn, the scissor rect is
+    /// restored to `scissor` (or to the whole viewport if `None`) so the caller's clipping is left
+    /// in place for subsequent draws.
+    pub fn render_with_scissor(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass: &mut RenderPass<'_>,
+        scissor: Option<TextBounds>,
+    ) -> Result<(), RenderError> {
+        if self.glyph_vertices.is_empty() && self.knockout_vertices.is_empty() {
+            return Ok(());
+        }
+
+        let resolution = viewport.resolution();
+        let screen = TextBounds {
+            left: 0,
+            top: 0,
+            right: resolution.width as i32,
+            bottom: resolution.height as i32,
+        };
+        let outer = scissor.unwrap_or(screen).intersect(&screen);
+
+        pass.push_debug_group("glyphon text");
+        set_scissor_rect(pass, outer);
+        if let Some(knockout) = &self.knockout {
+            knockout.composite(pass);
+        }
+        if let Some(shadow_blur) = &self.shadow_blur {
+            shadow_blur.composite(pass);
+        }
+
+        pass.set_bind_group(0, &atlas.bind_group, &[]);
+        viewport.bind(pass);
+        pass.set_bind_group(atlas.cache.pattern_group(), &self.pattern_bind_group, &[]);
+        match &self.instances_bind_group {
+            Some((_, bind_group)) if self.instance_layout == InstanceLayout::Pulled => {
+                pass.set_bind_group(atlas.cache.instances_group(), bind_group, &[]);
+            }
+            _ => pass.set_vertex_buffer(0, self.vertex_buffer.slice(self.vertex_offset..)),
+        }
+
+        let stencil_enabled = self.stencil_enabled();
+
+        // Glows are drawn beneath all text, so they are drawn for all areas first
+        let (pipeline, glow_pipeline) = self.pipelines();
+        for (pipeline, glows) in [(glow_pipeline, true), (pipeline, false)] {
+            pass.set_pipeline(pipeline);
+
+            for area in &self.areas {
+                let instances = if glows {
+                    area.glows.clone()
+                } else {
+                    area.instances.clone()
+                };
+                if instances.is_empty() {
+                    continue;
+                }
+
+                let clip = viewport.camera_bounds(area.bounds).intersect(&outer);
+                if clip.is_empty() {
+                    continue;
+                }
+
+                pass.insert_debug_marker(self.area_debug_marker(area.index, glows));
+                set_scissor_rect(pass, clip);
+                if stencil_enabled {
+                    pass.set_stencil_reference(area.stencil_reference);
+                }
+                let (vertices, instances) = self.draw_ranges(instances);
+                pass.draw(vertices, instances);
+            }
         }
 
-        let vertices = self.glyph_vertices.as_slice();
-        let vertices_raw = unsafe {
-            slice::from_raw_parts(
-                vertices as *const _ as *const u8,
-                std::mem::size_of_val(vertices),
-            )
+        // Restore the caller's scissor rect
+        set_scissor_rect(pass, outer);
+
+        pass.pop_debug_group();
+        Ok(())
+    }
+
+    /// Renders the text areas previously provided to `prepare` whose positions in the prepared
+    /// text areas are within `areas`, so that a single renderer can draw different subsets of the
+    /// prepared text into different render passes (e.g. world-space text and an overlay).
+    ///
+    /// Text areas outside the prepared ones are ignored. If scissor clipping is enabled, each
+    /// text area is clipped to its `TextBounds` like in `render_with_scissor`, and the scissor
+    /// rect is reset to the whole viewport afterwards. Shadows drawn with a [`ShadowStyle`] and
+    /// knockout backgrounds are composited for all text areas at once, so they are only drawn by
+    /// `render` and `render_with_scissor`.
+    pub fn render_range(
+        &self,
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+        pass: &mut RenderPass<'_>,
+        areas: Range<usize>,
+    ) -> Result<(), RenderError> {
+        if self.glyph_vertices.is_empty() || areas.is_empty() {
+            return Ok(());
+        }
+
+        let resolution = viewport.resolution();
+        let screen = TextBounds {
+            left: 0,
+            top: 0,
+            right: resolution.width as i32,
+            bottom: resolution.height as i32,
         };
 
-        if self.vertex_buffer_size >= vertices_raw.len() as u64 {
-            queue.write_buffer(&self.vertex_buffer, 0, vertices_raw);
-        } else {
-            self.vertex_buffer.destroy();
+        pass.push_debug_group("glyphon text range");
+        pass.set_bind_group(0, &atlas.bind_group, &[]);
+        viewport.bind(pass);
+        pass.set_bind_group(atlas.cache.pattern_group(), &self.pattern_bind_group, &[]);
+        match &self.instances_bind_group {
+            Some((_, bind_group)) if self.instance_layout == InstanceLayout::Pulled => {
+                pass.set_bind_group(atlas.cache.instances_group(), bind_group, &[]);
+            }
+            _ => pass.set_vertex_buffer(0, self.vertex_buffer.slice(self.vertex_offset..)),
+        }
 
-            let (buffer, buffer_size) = create_oversized_buffer(
-                device,
-                Some("glyphon vertices"),
-                vertices_raw,
-                BufferUsages::VERTEX | BufferUsages::COPY_DST,
-            );
+        let stencil_enabled = self.stencil_enabled();
 
-            self.vertex_buffer = buffer;
-            self.vertex_buffer_size = buffer_size;
+        // Glows are drawn beneath all text, so they are drawn for all areas first
+        let (pipeline, glow_pipeline) = self.pipelines();
+        for (pipeline, glows) in [(glow_pipeline, true), (pipeline, false)] {
+            pass.set_pipeline(pipeline);
+
+            for area in self.areas.iter().filter(|area| areas.contains(&area.index)) {
+                let instances = if glows {
+                    area.glows.clone()
+                } else {
+                    area.instances.clone()
+                };
+                if instances.is_empty() {
+                    continue;
+                }
+
+                if self.scissor_clipping {
+                    let clip = viewport.camera_bounds(area.bounds).intersect(&screen);
+                    if clip.is_empty() {
+                        continue;
+                    }
+                    set_scissor_rect(pass, clip);
+                }
+
+                pass.insert_debug_marker(self.area_debug_marker(area.index, glows));
+                if stencil_enabled {
+                    pass.set_stencil_reference(area.stencil_reference);
+                }
+                let (vertices, instances) = self.draw_ranges(instances);
+                pass.draw(vertices, instances);
+            }
+        }
+
+        if self.scissor_clipping {
+            set_scissor_rect(pass, screen);
         }
 
+        pass.pop_debug_group();
         Ok(())
     }
 
-    /// Renders all layouts that were previously provided to `prepare`.
-    pub fn render(
+    /// Renders a [`CellGrid`] that was previously provided to
+    /// [`TextRenderer::prepare_grid`], with the blend mode and render pass state of the renderer.
+    pub fn render_grid(
         &self,
         atlas: &TextAtlas,
         viewport: &Viewport,
         pass: &mut RenderPass<'_>,
+        grid: &CellGrid,
     ) -> Result<(), RenderError> {
-        if self.glyph_vertices.is_empty() {
+        let Some(buffer) = &grid.buffer else {
+            return Ok(());
+        };
+        if grid.instances.is_empty() {
             return Ok(());
         }
 
+        pass.push_debug_group("glyphon cell grid");
         pass.set_pipeline(&self.pipeline);
         pass.set_bind_group(0, &atlas.bind_group, &[]);
-        pass.set_bind_group(1, &viewport.bind_group, &[]);
-        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        pass.draw(0..4, 0..self.glyph_vertices.len() as u32);
+        viewport.bind(pass);
+        pass.set_bind_group(atlas.cache.pattern_group(), &self.pattern_bind_group, &[]);
+        pass.set_vertex_buffer(0, buffer.slice(..));
+        pass.draw(0..4, 0..grid.instances.len() as u32);
+        pass.pop_debug_group();
 
         Ok(())
     }
+
+    /// Renders a text buffer into a new texture of the given `resolution` and returns it, for
+    /// text baked into materials, imposters or caches.
+    ///
+    /// The texture has the format of the atlas and can be sampled, rendered to and copied from.
+    /// It is cleared to transparent, and the text is drawn at its top left corner with white as
+    /// the color of glyphs without a color of their own. The commands are submitted to `queue`
+    /// before the texture is returned.
+    pub fn render_to_texture(
+        atlas: &mut TextAtlas,
+        device: &Device,
+        queue: &Queue,
+        font_system: &mut FontSystem,
+        cache: &mut SwashCache,
+        buffer: &cosmic_text::Buffer,
+        resolution: Resolution,
+    ) -> Result<Texture, RenderToTextureError> {
+        let mut renderer = Self::new(atlas, device, MultisampleState::default(), None);
+        let mut viewport = Viewport::new(device, &atlas.cache);
+        viewport.update(queue, resolution);
+
+        renderer.prepare(
+            device,
+            queue,
+            font_system,
+            atlas,
+            &viewport,
+            [TextArea::builder(buffer)
+                .bounds(TextBounds {
+                    left: 0,
+                    top: 0,
+                    right: resolution.width as i32,
+                    bottom: resolution.height as i32,
+                })
+                .build()],
+            cache,
+        )?;
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("glyphon rendered text"),
+            size: Extent3d {
+                width: resolution.width.max(1),
+                height: resolution.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: atlas.format,
+            usage: TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("glyphon render to texture encoder"),
+        });
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("glyphon render to texture pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            renderer.render(atlas, &viewport, &mut pass)?;
+        }
+        queue.submit(Some(encoder.finish()));
+
+        Ok(texture)
+    }
+
+    /// Returns `true` if the renderer was created with a depth-stencil state that tests or writes
+    /// the stencil buffer, so that the stencil reference of each text area applies.
+    fn stencil_enabled(&self) -> bool {
+        self.depth_stencil
+            .as_ref()
+            .is_some_and(|depth_stencil| depth_stencil.stencil.is_enabled())
+    }
 }
 
 #[repr(u16)]
@@ -407,20 +3241,247 @@ impl TextRenderer {
 enum TextColorConversion {
     None = 0,
     ConvertToLinear = 1,
+    /// Decodes the text color and colored glyphs with the transfer function of the atlas.
+    Custom = 2,
+}
+
+impl TextColorConversion {
+    fn for_color_mode(color_mode: ColorMode) -> Self {
+        match color_mode {
+            ColorMode::Accurate | ColorMode::Hdr => TextColorConversion::ConvertToLinear,
+            ColorMode::Web => TextColorConversion::None,
+            ColorMode::Custom(_) => TextColorConversion::Custom,
+        }
+    }
+}
+
+/// The bits of `GlyphToRender::flags` that select the pattern mapping of an instance.
+const PATTERN_SCREEN_FLAG: u32 = 1;
+const PATTERN_GLYPH_FLAG: u32 = 2;
+
+/// Set on instances that sample a mipmapped atlas trilinearly.
+const MIPMAP_FLAG: u32 = 4;
+
+/// Set on mask instances whose coverage is stored in the alpha channel of the color atlas.
+const MERGED_FLAG: u32 = 8;
+
+/// Set on instances that sample a prebaked page instead of the atlas.
+const PREBAKED_FLAG: u32 = 16;
+
+/// Set on instances that draw the blurred drop shadow of a glyph.
+const DROP_SHADOW_FLAG: u32 = 32;
+
+/// Set on instances that draw the outline of a glyph.
+const OUTLINE_FLAG: u32 = 64;
+
+/// Set on instances that draw the glow of a glyph.
+const GLOW_FLAG: u32 = 128;
+
+/// Blends each color channel by the second output of the fragment shader, which holds the
+/// coverage of each subpixel for subpixel masks and the alpha of the color otherwise.
+const SUBPIXEL_BLENDING: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::Src1,
+        dst_factor: BlendFactor::OneMinusSrc1,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent::OVER,
+};
+
+/// Adds the premultiplied color of glows to the render target, leaving its alpha untouched.
+const GLOW_BLENDING: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::Zero,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Add,
+    },
+};
+
+/// The [`UnderlineStyle`] of a decoration quad is stored in these bits of `GlyphToRender::flags`.
+const DECORATION_STYLE_SHIFT: u32 = 8;
+
+/// The [`ColorGlyphStyle`] of a color glyph is stored in the same bits as the style of a
+/// decoration: `1` to tint it by its instance color, `2` for grayscale and `3` for a silhouette
+/// in its instance color.
+const COLOR_GLYPH_STYLE_SHIFT: u32 = DECORATION_STYLE_SHIFT;
+
+/// The 1-based index of the gradient of an instance is stored in these bits of
+/// `GlyphToRender::flags`, or zero if it has no gradient.
+const GRADIENT_SHIFT: u32 = 10;
+
+/// The atlas layer of an instance is stored in the upper bits of `GlyphToRender::flags`.
+const LAYER_SHIFT: u32 = 16;
+
+fn pattern_flags(pattern: Option<PatternMapping>) -> u32 {
+    match pattern {
+        None => 0,
+        Some(PatternMapping::Screen) => PATTERN_SCREEN_FLAG,
+        Some(PatternMapping::Glyph) => PATTERN_GLYPH_FLAG,
+    }
+}
+
+/// The content type of instances that are drawn as a solid quad of their color, without
+/// sampling an atlas.
+const SOLID_QUAD_CONTENT_TYPE: u16 = 2;
+
+/// The content type of instances that draw a subpixel mask from the color atlas.
+const SUBPIXEL_MASK_CONTENT_TYPE: u16 = 3;
+
+/// The content type of instances that draw a signed distance field from the SDF atlas.
+const SDF_CONTENT_TYPE: u16 = 4;
+
+/// The content type of instances that draw a multi-channel signed distance field from the color
+/// atlas.
+const MSDF_CONTENT_TYPE: u16 = 5;
+
+/// Returns the content type the shader uses for glyphs of the given content type.
+fn shader_content_type(content_type: ContentType) -> u16 {
+    match content_type {
+        ContentType::Color => 0,
+        ContentType::Mask => 1,
+        ContentType::SubpixelMask => SUBPIXEL_MASK_CONTENT_TYPE,
+        ContentType::Sdf => SDF_CONTENT_TYPE,
+        ContentType::Msdf => MSDF_CONTENT_TYPE,
+    }
 }
 
+/// The key of a glyph cached in a [`TextAtlas`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub(crate) enum GlyphonCacheKey {
+pub enum GlyphonCacheKey {
+    /// A shaped glyph. Sequences that the shaper composes into a single glyph (such as emoji ZWJ
+    /// sequences or skin-tone modifiers) are keyed by the composed glyph id, so they are cached
+    /// and drawn as one glyph rather than as their individual codepoints.
     Text(cosmic_text::CacheKey),
+    /// A shaped glyph rasterized with other than the default [`GlyphRasterization`] options.
+    Rasterized(cosmic_text::CacheKey, GlyphRasterization),
+    /// The signed distance field of a shaped glyph, rendered at any size. The key is normalized
+    /// to the size distance fields are rasterized at, without a subpixel offset.
+    Sdf(cosmic_text::CacheKey),
+    /// The multi-channel signed distance field of a shaped glyph, keyed like
+    /// [`GlyphonCacheKey::Sdf`].
+    Msdf(cosmic_text::CacheKey),
+    /// A custom glyph.
     Custom(CustomGlyphCacheKey),
 }
 
+/// Points the gradient, transform and clip of a reused instance of a retained text area at the
+/// given 1-based entries of the tables, leaving those the instance doesn't have unset.
+fn remap_retained_glyph(
+    glyph: &mut GlyphToRender,
+    gradient: Option<u32>,
+    transform: Option<u32>,
+    clip: Option<u32>,
+) {
+    const GRADIENT_MASK: u32 = 0x3f << GRADIENT_SHIFT;
+    const TRANSFORM_MASK: u32 = 0xff;
+    const CLIP_MASK: u32 = 0xff << CLIP_SHIFT;
+
+    if let Some(gradient) = gradient {
+        if glyph.flags & GRADIENT_MASK != 0 {
+            glyph.flags = glyph.flags & !GRADIENT_MASK | gradient << GRADIENT_SHIFT;
+        }
+    }
+    if let Some(transform) = transform {
+        glyph.transform = glyph.transform & !TRANSFORM_MASK | transform;
+    }
+    if let Some(clip) = clip {
+        glyph.transform = glyph.transform & !CLIP_MASK | clip << CLIP_SHIFT;
+    }
+}
+
+fn set_scissor_rect(pass: &mut RenderPass<'_>, bounds: TextBounds) {
+    if bounds.is_empty() {
+        pass.set_scissor_rect(0, 0, 0, 0);
+    } else {
+        pass.set_scissor_rect(
+            bounds.left as u32,
+            bounds.top as u32,
+            (bounds.right - bounds.left) as u32,
+            (bounds.bottom - bounds.top) as u32,
+        );
+    }
+}
+
+/// The number of bytes of instances compared at once by `write_changed_ranges`.
+const UPLOAD_CHUNK_SIZE: usize = 4096;
+
+/// Writes the chunks of `data` that differ from `previous`, the current contents of `buffer` from
+/// `offset`, merging adjacent chunks into a single write. Returns the number of bytes written.
+fn write_changed_ranges(
+    queue: &Queue,
+    buffer: &Buffer,
+    offset: u64,
+    previous: &[u8],
+    data: &[u8],
+) -> u64 {
+    let mut changed: Option<Range<usize>> = None;
+    let mut written = 0;
+
+    for start in (0..data.len()).step_by(UPLOAD_CHUNK_SIZE) {
+        let end = (start + UPLOAD_CHUNK_SIZE).min(data.len());
+        if previous.get(start..end) == Some(&data[start..end]) {
+            if let Some(range) = changed.take() {
+                written += range.len() as u64;
+                queue.write_buffer(buffer, offset + range.start as u64, &data[range]);
+            }
+        } else {
+            changed = Some(changed.map_or(start, |range| range.start)..end);
+        }
+    }
+
+    if let Some(range) = changed {
+        written += range.len() as u64;
+        queue.write_buffer(buffer, offset + range.start as u64, &data[range]);
+    }
+
+    written
+}
+
 fn next_copy_buffer_size(size: u64) -> u64 {
     let align_mask = COPY_BUFFER_ALIGNMENT - 1;
     ((size.next_power_of_two() + align_mask) & !align_mask).max(COPY_BUFFER_ALIGNMENT)
 }
 
-fn create_oversized_buffer(
+/// Returns the text and glow pipelines of a renderer, which read instances in the given layout.
+fn create_pipelines(
+    atlas: &TextAtlas,
+    device: &Device,
+    multisample: MultisampleState,
+    depth_stencil: Option<DepthStencilState>,
+    blend_mode: BlendMode,
+    multiview: Option<NonZeroU32>,
+    write_mask: ColorWrites,
+    instances: InstanceLayout,
+) -> (RenderPipeline, RenderPipeline) {
+    let blend = blend_mode.blend_state(atlas.subpixel);
+    let pipeline = atlas.get_or_create_pipeline(
+        device,
+        blend,
+        write_mask,
+        multisample,
+        depth_stencil.clone(),
+        multiview,
+        instances,
+    );
+    let glow_pipeline = atlas.get_or_create_pipeline(
+        device,
+        GLOW_BLENDING,
+        write_mask,
+        multisample,
+        depth_stencil,
+        multiview,
+        instances,
+    );
+
+    (pipeline, glow_pipeline)
+}
+
+pub(crate) fn create_oversized_buffer(
     device: &Device,
     label: Option<&str>,
     contents: &[u8],
@@ -438,37 +3499,669 @@ fn create_oversized_buffer(
     (buffer, size)
 }
 
-fn zero_depth(_: usize) -> f32 {
-    0f32
+fn renderer_params_bytes(params: &RendererParams) -> &[u8] {
+    unsafe {
+        slice::from_raw_parts(
+            params as *const RendererParams as *const u8,
+            mem::size_of::<RendererParams>(),
+        )
+    }
+}
+
+fn zero_depth(_: usize) -> f32 {
+    0f32
+}
+
+struct GetGlyphImageResult {
+    content_type: ContentType,
+    top: i16,
+    left: i16,
+    width: u16,
+    height: u16,
+    data: Vec<u8>,
+}
+
+/// Returns the physical position and cache key of a custom glyph in a text area.
+fn custom_glyph_cache_key(
+    text_area: &TextArea,
+    glyph: &CustomGlyph,
+) -> (i32, i32, CustomGlyphCacheKey) {
+    let x = text_area.left + (glyph.left * text_area.scale);
+    let y = text_area.top + (glyph.top * text_area.scale);
+    let width = (glyph.width * text_area.scale).round() as u16;
+    let height = (glyph.height * text_area.scale).round() as u16;
+
+    let (x, y, x_bin, y_bin) = if glyph.snap_to_physical_pixel {
+        (
+            x.round() as i32,
+            y.round() as i32,
+            SubpixelBin::Zero,
+            SubpixelBin::Zero,
+        )
+    } else {
+        let (x, x_bin) = SubpixelBin::new(x);
+        let (y, y_bin) = SubpixelBin::new(y);
+        (x, y, x_bin, y_bin)
+    };
+
+    let cache_key = CustomGlyphCacheKey {
+        glyph_id: glyph.id,
+        width,
+        height,
+        x_bin,
+        y_bin,
+        content_type_hint: glyph.content_type_hint,
+    };
+
+    (x, y, cache_key)
+}
+
+/// Returns the physical position of a glyph and the key and texel scale of its signed distance
+/// field, which is shared by all sizes and subpixel offsets of the glyph.
+fn sdf_glyph(physical_glyph: &PhysicalGlyph) -> (i32, i32, CacheKey, f32) {
+    let cache_key = physical_glyph.cache_key;
+    let x = (physical_glyph.x as f32 + cache_key.x_bin.as_float()).round() as i32;
+    let y = (physical_glyph.y as f32 + cache_key.y_bin.as_float()).round() as i32;
+    let font_size = f32::from_bits(cache_key.font_size_bits);
+
+    let sdf_cache_key = CacheKey {
+        font_size_bits: SDF_FONT_SIZE.to_bits(),
+        x_bin: SubpixelBin::Zero,
+        y_bin: SubpixelBin::Zero,
+        ..cache_key
+    };
+
+    (x, y, sdf_cache_key, SDF_FONT_SIZE / font_size)
+}
+
+/// The key of a text glyph rasterized with the given options, which is only distinct from the key
+/// of glyphs rasterized like [`SwashCache`] does if the options differ from the defaults.
+fn text_cache_key(
+    cache_key: cosmic_text::CacheKey,
+    rasterization: GlyphRasterization,
+) -> GlyphonCacheKey {
+    if rasterization == GlyphRasterization::default() {
+        GlyphonCacheKey::Text(cache_key)
+    } else {
+        GlyphonCacheKey::Rasterized(cache_key, rasterization)
+    }
+}
+
+fn text_glyph_image(
+    cache: &mut SwashCache,
+    font_system: &mut FontSystem,
+    cache_key: cosmic_text::CacheKey,
+    rasterization: GlyphRasterization,
+    share_rasterized_glyphs: bool,
+    subpixel: bool,
+    sdf: bool,
+    msdf: bool,
+) -> Option<GetGlyphImageResult> {
+    trace_span!("glyphon::rasterize", sdf, msdf);
+
+    let image = if msdf {
+        rasterize_msdf_glyph(cache, font_system, cache_key, share_rasterized_glyphs)?
+    } else if sdf {
+        rasterize_sdf_glyph(cache, font_system, cache_key, share_rasterized_glyphs)?
+    } else {
+        rasterize_text_glyph(
+            cache,
+            font_system,
+            cache_key,
+            rasterization,
+            share_rasterized_glyphs,
+            subpixel,
+        )?
+    };
+
+    Some(glyph_image_result(image, sdf, msdf))
+}
+
+fn glyph_image_result(image: SwashImage, sdf: bool, msdf: bool) -> GetGlyphImageResult {
+    let content_type = match image.content {
+        SwashContent::Color => ContentType::Color,
+        SwashContent::Mask if msdf => ContentType::Msdf,
+        SwashContent::Mask if sdf => ContentType::Sdf,
+        SwashContent::Mask => ContentType::Mask,
+        SwashContent::SubpixelMask => ContentType::SubpixelMask,
+    };
+
+    GetGlyphImageResult {
+        content_type,
+        top: image.placement.top as i16,
+        left: image.placement.left as i16,
+        width: image.placement.width as u16,
+        height: image.placement.height as u16,
+        data: image.data,
+    }
+}
+
+/// Rasterizes the text glyphs of the text areas that are missing from the atlas in parallel, so
+/// that preparing them only allocates and uploads them. Glyphs are culled against the bounds of
+/// their text area clipped to the `screen` like they are while preparing, and go through the image
+/// cache of the [`SwashCache`] like [`rasterize_text_glyph`] if the atlas shares its glyphs.
+///
+/// Glyphs rendered from distance fields are rasterized while they are prepared.
+#[cfg(feature = "rayon")]
+fn rasterize_missing_glyphs(
+    font_system: &mut FontSystem,
+    atlas: &TextAtlas,
+    cache: &mut SwashCache,
+    screen: TextBounds,
+    text_areas: &[(Option<TextAreaHandle>, TextArea)],
+    rasterized_glyphs: &HashMap<GlyphonCacheKey, GetGlyphImageResult>,
+) -> HashMap<GlyphonCacheKey, GetGlyphImageResult> {
+    use rayon::prelude::*;
+
+    trace_span!("glyphon::rasterize_missing_glyphs");
+
+    let share_rasterized_glyphs = atlas.mask_atlas.share_rasterized_glyphs;
+    let subpixel = atlas.subpixel;
+    let mut fonts = HashMap::new();
+    let mut missing = HashMap::new();
+    let mut rasterized = HashMap::new();
+    for (_, text_area) in text_areas {
+        if text_area.sdf || text_area.msdf {
+            continue;
+        }
+
+        let rasterization = text_area.rasterization.unwrap_or_default();
+        let shared =
+            share_rasterized_glyphs && !subpixel && rasterization == GlyphRasterization::default();
+        let area_bounds = match (text_area.world_transform, text_area.transform) {
+            (None, None) => text_area.bounds.intersect(&screen),
+            _ => text_area.bounds,
+        };
+        let cull_margin = cull_margin(text_area);
+        // Glyphs on a path or moved by a glyph modifier can end up anywhere, so they aren't culled
+        let cull = text_area.path.is_none() && text_area.glyph_modifier.is_none();
+        let cull_bounds = match text_area.path {
+            Some(_) => TextBounds::default(),
+            None => area_bounds,
+        };
+        for run in visible_layout_runs(text_area, cull_bounds, cull_margin[1]) {
+            for glyph in run.glyphs.iter() {
+                let physical_glyph =
+                    glyph.physical((text_area.left, text_area.top), text_area.scale);
+                let cache_key = physical_glyph.cache_key;
+                let glyphon_cache_key = text_cache_key(cache_key, rasterization);
+                if missing.contains_key(&glyphon_cache_key)
+                    || rasterized.contains_key(&glyphon_cache_key)
+                    || rasterized_glyphs.contains_key(&glyphon_cache_key)
+                    || atlas.contains_glyph(&glyphon_cache_key)
+                {
+                    continue;
+                }
+                if cull
+                    && is_glyph_culled(
+                        text_area,
+                        glyph,
+                        [physical_glyph.x, physical_glyph.y],
+                        area_bounds,
+                        cull_margin,
+                    )
+                {
+                    continue;
+                }
+
+                if shared {
+                    if let Some(image) = cache.image_cache.get(&cache_key) {
+                        if let Some(image) = image {
+                            rasterized.insert(
+                                glyphon_cache_key,
+                                glyph_image_result(image.clone(), false, false),
+                            );
+                        }
+                        continue;
+                    }
+                }
+
+                // Fonts are loaded up front, as the `FontSystem` can't be shared between threads
+                let font = fonts
+                    .entry(cache_key.font_id)
+                    .or_insert_with(|| font_system.get_font(cache_key.font_id))
+                    .clone();
+                if let Some(font) = font {
+                    missing.insert(glyphon_cache_key, (font, cache_key, rasterization, shared));
+                }
+            }
+        }
+    }
+
+    let images: Vec<_> = missing
+        .into_par_iter()
+        .map_init(
+            ScaleContext::new,
+            |context, (glyphon_cache_key, (font, cache_key, rasterization, shared))| {
+                let image =
+                    rasterize_font_glyph(&font, context, cache_key, rasterization, subpixel);
+                (glyphon_cache_key, cache_key, shared, image)
+            },
+        )
+        .collect();
+
+    for (glyphon_cache_key, cache_key, shared, image) in images {
+        if shared {
+            cache.image_cache.insert(cache_key, image.clone());
+        }
+        if let Some(image) = image {
+            rasterized.insert(glyphon_cache_key, glyph_image_result(image, false, false));
+        }
+    }
+
+    rasterized
+}
+
+/// Hashes the fields of a text area and the identity, scroll, metrics and size of its buffer, so
+/// that [`TextRenderer::prepare`] can tell whether it changed without walking its glyphs. Returns
+/// `None` for text areas with callbacks, which can't be compared.
+fn area_fingerprint(text_area: &TextArea) -> Option<u64> {
+    let TextArea {
+        buffer,
+        left,
+        top,
+        scale,
+        bounds,
+        default_color,
+        opacity,
+        custom_glyphs,
+        shadow,
+        z_order,
+        caret,
+        pattern,
+        drop_shadow,
+        outline,
+        glow,
+        background,
+        gradient,
+        glyph_colors,
+        selection,
+        decorations,
+        shadow_style,
+        transform,
+        world_transform,
+        faux_bold,
+        thickness,
+        oblique,
+        sdf,
+        msdf,
+        corner_radii,
+        stencil_reference,
+        path,
+        glyph_modifier,
+        color_glyphs,
+        rasterization,
+        depth,
+    } = text_area;
+    if decorations.is_some() || glyph_modifier.is_some() {
+        return None;
+    }
+
+    let mut hasher = FxHasher::default();
+    // The buffer is compared by its identity and the state that moves its layout, while changes
+    // to its text are caught by its redraw flag
+    ptr::from_ref(*buffer).hash(&mut hasher);
+    let scroll = buffer.scroll();
+    scroll.line.hash(&mut hasher);
+    [scroll.vertical, scroll.horizontal].fingerprint(&mut hasher);
+    let metrics = buffer.metrics();
+    [metrics.font_size, metrics.line_height].fingerprint(&mut hasher);
+    buffer.size().fingerprint(&mut hasher);
+    left.fingerprint(&mut hasher);
+    top.fingerprint(&mut hasher);
+    scale.fingerprint(&mut hasher);
+    bounds.fingerprint(&mut hasher);
+    default_color.fingerprint(&mut hasher);
+    opacity.fingerprint(&mut hasher);
+    custom_glyphs.fingerprint(&mut hasher);
+    shadow.fingerprint(&mut hasher);
+    z_order.fingerprint(&mut hasher);
+    caret.fingerprint(&mut hasher);
+    pattern.fingerprint(&mut hasher);
+    drop_shadow.fingerprint(&mut hasher);
+    outline.fingerprint(&mut hasher);
+    glow.fingerprint(&mut hasher);
+    background.fingerprint(&mut hasher);
+    gradient.fingerprint(&mut hasher);
+    glyph_colors.fingerprint(&mut hasher);
+    selection.fingerprint(&mut hasher);
+    shadow_style.fingerprint(&mut hasher);
+    transform.fingerprint(&mut hasher);
+    world_transform.fingerprint(&mut hasher);
+    faux_bold.fingerprint(&mut hasher);
+    thickness.fingerprint(&mut hasher);
+    oblique.fingerprint(&mut hasher);
+    sdf.fingerprint(&mut hasher);
+    msdf.fingerprint(&mut hasher);
+    corner_radii.fingerprint(&mut hasher);
+    stencil_reference.fingerprint(&mut hasher);
+    path.fingerprint(&mut hasher);
+    color_glyphs.fingerprint(&mut hasher);
+    rasterization.fingerprint(&mut hasher);
+    depth.fingerprint(&mut hasher);
+
+    Some(hasher.finish())
+}
+
+/// A field of a [`TextArea`] that is hashed into its fingerprint, see `area_fingerprint`.
+///
+/// Floats aren't `Hash`, so they are hashed by their bits.
+trait Fingerprint {
+    fn fingerprint(&self, hasher: &mut FxHasher);
+}
+
+impl Fingerprint for f32 {
+    fn fingerprint(&self, hasher: &mut FxHasher) {
+        self.to_bits().hash(hasher);
+    }
+}
+
+impl Fingerprint for Color {
+    fn fingerprint(&self, hasher: &mut FxHasher) {
+        self.0.hash(hasher);
+    }
+}
+
+impl Fingerprint for Cursor {
+    fn fingerprint(&self, hasher: &mut FxHasher) {
+        (self.line, self.index, self.affinity as u8).hash(hasher);
+    }
+}
+
+impl Fingerprint for PatternMapping {
+    fn fingerprint(&self, hasher: &mut FxHasher) {
+        (*self as u8).hash(hasher);
+    }
+}
+
+impl Fingerprint for ColorGlyphStyle {
+    fn fingerprint(&self, hasher: &mut FxHasher) {
+        mem::discriminant(self).hash(hasher);
+        if let ColorGlyphStyle::Tint(color) = self {
+            color.fingerprint(hasher);
+        }
+    }
+}
+
+impl Fingerprint for GradientKind {
+    fn fingerprint(&self, hasher: &mut FxHasher) {
+        mem::discriminant(self).hash(hasher);
+        match self {
+            GradientKind::Linear { start, end } => (start, end).fingerprint(hasher),
+            GradientKind::Radial { center, radius } => (center, radius).fingerprint(hasher),
+        }
+    }
+}
+
+impl<T: Fingerprint> Fingerprint for Option<T> {
+    fn fingerprint(&self, hasher: &mut FxHasher) {
+        self.is_some().hash(hasher);
+        if let Some(value) = self {
+            value.fingerprint(hasher);
+        }
+    }
+}
+
+impl<T: Fingerprint> Fingerprint for [T] {
+    fn fingerprint(&self, hasher: &mut FxHasher) {
+        self.len().hash(hasher);
+        for value in self {
+            value.fingerprint(hasher);
+        }
+    }
+}
+
+impl<T: Fingerprint, const N: usize> Fingerprint for [T; N] {
+    fn fingerprint(&self, hasher: &mut FxHasher) {
+        self.as_slice().fingerprint(hasher);
+    }
+}
+
+impl<T: Fingerprint + ?Sized> Fingerprint for &T {
+    fn fingerprint(&self, hasher: &mut FxHasher) {
+        (**self).fingerprint(hasher);
+    }
+}
+
+impl<A: Fingerprint, B: Fingerprint> Fingerprint for (A, B) {
+    fn fingerprint(&self, hasher: &mut FxHasher) {
+        self.0.fingerprint(hasher);
+        self.1.fingerprint(hasher);
+    }
+}
+
+/// Implements [`Fingerprint`] for types that are `Hash`.
+macro_rules! fingerprint_by_hash {
+    ($($ty:ty),* $(,)?) => {$(
+        impl Fingerprint for $ty {
+            fn fingerprint(&self, hasher: &mut FxHasher) {
+                self.hash(hasher);
+            }
+        }
+    )*};
+}
+
+fingerprint_by_hash!(bool, u16, u32, i32, usize, ContentType);
+
+/// Implements [`Fingerprint`] for structs by their fields.
+macro_rules! fingerprint_by_fields {
+    ($($ty:ty { $($field:tt),* }),* $(,)?) => {$(
+        impl Fingerprint for $ty {
+            fn fingerprint(&self, hasher: &mut FxHasher) {
+                $(self.$field.fingerprint(hasher);)*
+            }
+        }
+    )*};
+}
+
+fingerprint_by_fields!(
+    TextBounds { left, top, right, bottom },
+    CustomGlyph {
+        id,
+        left,
+        top,
+        width,
+        height,
+        color,
+        snap_to_physical_pixel,
+        content_type_hint,
+        metadata
+    },
+    TextShadow { shadow_intensity, shadow_radius },
+    TextCaret { cursor, width, color, blink_phase, metadata },
+    DropShadow { offset, color, softness },
+    TextOutline { width, color },
+    TextGlow { color, radius, intensity },
+    TextBackground { color, metadata, knockout },
+    TextGradient<'_> { kind, stops },
+    GradientStop { offset, color },
+    GlyphColors { top_left, top_right, bottom_left, bottom_right },
+    TextSelection { start, end, color, metadata },
+    ShadowStyle { radius, color, offset },
+    TextTransform { 0 },
+    CornerRadii { top_left, top_right, bottom_right, bottom_left },
+    TextPath { points, lengths },
+    GlyphRasterization { hinting, stem_darkening },
+);
+
+/// Returns how far the offset shadows of the glyphs of a text area reach beyond their quads,
+/// horizontally and vertically in physical pixels.
+fn cull_margin(text_area: &TextArea) -> [f32; 2] {
+    [
+        text_area.drop_shadow.map(|drop_shadow| drop_shadow.offset),
+        text_area
+            .shadow_style
+            .map(|shadow_style| shadow_style.offset),
+    ]
+    .into_iter()
+    .flatten()
+    .fold([0.0; 2], |[margin_x, margin_y], [x, y]| {
+        [
+            margin_x.max(x.abs() * text_area.scale),
+            margin_y.max(y.abs() * text_area.scale),
+        ]
+    })
 }
 
-struct GetGlyphImageResult {
-    content_type: ContentType,
-    top: i16,
-    left: i16,
-    width: u16,
-    height: u16,
-    data: Vec<u8>,
+/// Returns whether a glyph of a text area at the given physical position lies entirely outside of
+/// the bounds, which are extended by the `margin` from [`cull_margin`].
+fn is_glyph_culled(
+    text_area: &TextArea,
+    glyph: &LayoutGlyph,
+    [x, y]: [i32; 2],
+    bounds: TextBounds,
+    [margin_x, margin_y]: [f32; 2],
+) -> bool {
+    let margin = glyph.font_size * text_area.scale;
+    let left = x as f32 - margin - margin_x;
+    let right = x as f32 + glyph.w * text_area.scale + margin + margin_x;
+    let top = y as f32 - margin - margin_y;
+    let bottom = y as f32 + margin + margin_y;
+
+    right < bounds.left as f32
+        || left > bounds.right as f32
+        || bottom < bounds.top as f32
+        || top > bounds.bottom as f32
 }
 
-fn prepare_glyph<R>(
+/// Returns the layout runs of the buffer of a text area that vertically overlap the given bounds,
+/// which are extended by `margin` physical pixels.
+fn visible_layout_runs<'a>(
+    text_area: &TextArea<'a>,
+    bounds: TextBounds,
+    margin: f32,
+) -> impl Iterator<Item = LayoutRun<'a>> {
+    let (top, scale) = (text_area.top, text_area.scale);
+    let is_run_visible = move |run: &LayoutRun| {
+        // Glyphs can reach beyond their line, e.g. when the line height is smaller than the font
+        // size
+        let margin = margin + run.line_height * scale;
+        let start_y_physical = top + run.line_top * scale - margin;
+        let end_y_physical = top + (run.line_top + run.line_height) * scale + margin;
+
+        start_y_physical <= bounds.bottom as f32 && bounds.top as f32 <= end_y_physical
+    };
+
+    text_area
+        .buffer
+        .layout_runs()
+        .skip_while(move |run| !is_run_visible(run))
+        .take_while(is_run_visible)
+}
+
+fn custom_glyph_image(
+    cache_key: CustomGlyphCacheKey,
+    scale: f32,
+    rasterize_custom_glyph: &mut impl FnMut(
+        RasterizeCustomGlyphRequest,
+    ) -> Option<RasterizedCustomGlyph>,
+) -> Option<GetGlyphImageResult> {
+    trace_span!("glyphon::rasterize_custom");
+
+    let CustomGlyphCacheKey {
+        width,
+        height,
+        content_type_hint,
+        ..
+    } = cache_key;
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let input = RasterizeCustomGlyphRequest {
+        id: cache_key.glyph_id,
+        width,
+        height,
+        x_bin: cache_key.x_bin,
+        y_bin: cache_key.y_bin,
+        scale,
+        content_type_hint,
+    };
+
+    let output = (rasterize_custom_glyph)(input)?;
+
+    output.validate(&input);
+    let output = output.with_content_type_hint(content_type_hint);
+
+    Some(GetGlyphImageResult {
+        content_type: output.content_type,
+        top: 0,
+        left: 0,
+        width,
+        height,
+        data: output.data,
+    })
+}
+
+/// The placement, color and effects of a glyph prepared by `prepare_glyph`.
+struct GlyphParams {
+    /// The physical position of the glyph on its line.
     x: i32,
     y: i32,
+    /// The position of the baseline of the line of the glyph, in logical pixels.
     line_y: f32,
     color: Color,
     metadata: usize,
     cache_key: GlyphonCacheKey,
-    atlas: &mut TextAtlas,
-    device: &Device,
-    queue: &Queue,
-    cache: &mut SwashCache,
-    font_system: &mut FontSystem,
     scale_factor: f32,
-    bounds_min_x: i32,
-    bounds_min_y: i32,
-    bounds_max_x: i32,
-    bounds_max_y: i32,
     shadow: Option<TextShadow>,
+    drop_shadow: Option<DropShadow>,
+    outline: Option<TextOutline>,
+    glow: Option<TextGlow>,
+    embolden: f32,
+    oblique: Option<f32>,
+    /// The atlas texels per physical pixel, see [`GlyphToRender::texel_scale`].
+    texel_scale: f32,
+}
+
+impl GlyphParams {
+    /// Returns the parameters of a glyph at `x` and `y` without any effects.
+    fn new(x: i32, y: i32, color: Color, cache_key: GlyphonCacheKey) -> Self {
+        Self {
+            x,
+            y,
+            line_y: 0.0,
+            color,
+            metadata: 0,
+            cache_key,
+            scale_factor: 1.0,
+            shadow: None,
+            drop_shadow: None,
+            outline: None,
+            glow: None,
+            embolden: 0.0,
+            oblique: None,
+            texel_scale: 1.0,
+        }
+    }
+
+    /// Applies the scale and the effects of a text area to the glyph.
+    fn with_area_effects(self, text_area: &TextArea) -> Self {
+        Self {
+            scale_factor: text_area.scale,
+            shadow: text_area.shadow,
+            drop_shadow: text_area.drop_shadow,
+            outline: text_area.outline,
+            glow: text_area.glow,
+            embolden: text_area.thickness,
+            oblique: text_area.oblique,
+            ..self
+        }
+    }
+}
+
+/// The resources a glyph is rasterized into the atlas with by `prepare_glyph`.
+struct GlyphContext<'a> {
+    device: &'a Device,
+    queue: &'a Queue,
+    atlas: &'a mut TextAtlas,
+    cache: &'a mut SwashCache,
+    font_system: &'a mut FontSystem,
+}
+
+fn prepare_glyph<R>(
+    params: GlyphParams,
+    context: GlyphContext,
     get_glyph_image: impl FnOnce(
         &mut SwashCache,
         &mut FontSystem,
@@ -476,16 +4169,45 @@ fn prepare_glyph<R>(
     ) -> Option<GetGlyphImageResult>,
     mut metadata_to_depth: impl FnMut(usize) -> f32,
     mut rasterize_custom_glyph: R,
-) -> Result<Option<GlyphToRender>, PrepareError>
+) -> Result<Option<PreparedGlyph>, PrepareError>
 where
     R: FnMut(RasterizeCustomGlyphRequest) -> Option<RasterizedCustomGlyph>,
 {
-    let details = if let Some(details) = atlas.mask_atlas.glyph_cache.get(&cache_key) {
+    let GlyphParams {
+        x,
+        y,
+        line_y,
+        color,
+        metadata,
+        cache_key,
+        scale_factor,
+        shadow,
+        drop_shadow,
+        outline,
+        glow,
+        embolden,
+        oblique,
+        texel_scale,
+    } = params;
+    let GlyphContext {
+        device,
+        queue,
+        atlas,
+        cache,
+        font_system,
+    } = context;
+
+    let details = if let Some(details) = atlas.prebaked_glyph(&cache_key) {
+        details
+    } else if let Some(details) = atlas.mask_atlas.glyph_cache.get(&cache_key) {
         atlas.mask_atlas.glyphs_in_use.insert(cache_key);
         details
     } else if let Some(details) = atlas.color_atlas.glyph_cache.get(&cache_key) {
         atlas.color_atlas.glyphs_in_use.insert(cache_key);
         details
+    } else if let Some(details) = atlas.sdf_atlas.glyph_cache.get(&cache_key) {
+        atlas.sdf_atlas.glyphs_in_use.insert(cache_key);
+        details
     } else {
         let Some(image) = (get_glyph_image)(cache, font_system, &mut rasterize_custom_glyph) else {
             return Ok(None);
@@ -495,13 +4217,33 @@ where
 
         let (gpu_cache, atlas_id, inner) = if should_rasterize {
             let mut inner = atlas.inner_for_content_mut(image.content_type);
+            inner.make_room_in_budget(image.width, image.height);
 
             // Find a position in the packer
-            let allocation = loop {
+            let (layer, allocation) = loop {
                 match inner.try_allocate(image.width as usize, image.height as usize) {
                     Some(a) => break a,
                     None => {
-                        if !atlas.grow(
+                        let requested = (image.width as u32, image.height as u32);
+                        // Neither evicting nor growing makes room for a glyph that is larger
+                        // than the atlas can ever be
+                        if !inner.fits_max_size(image.width, image.height) {
+                            let error = AtlasError::AllocationFailed {
+                                requested,
+                                available: inner.max_allocatable(),
+                            };
+                            return Err(inner
+                                .full_error(image.content_type, cache_key, requested, error)
+                                .into());
+                        }
+
+                        // Make room by evicting unused glyphs before growing, if the atlas
+                        // is budgeted
+                        if inner.budget != AtlasBudget::Unbounded && inner.evict_lru() {
+                            continue;
+                        }
+
+                        let grown = atlas.grow(
                             device,
                             queue,
                             font_system,
@@ -509,8 +4251,16 @@ where
                             image.content_type,
                             scale_factor,
                             &mut rasterize_custom_glyph,
-                        ) {
-                            return Err(PrepareError::AtlasFull);
+                        );
+                        match grown {
+                            Ok(()) => {}
+                            Err(error @ AtlasError::MaxSizeReached) => {
+                                return Err(atlas
+                                    .inner_for_content(image.content_type)
+                                    .full_error(image.content_type, cache_key, requested, error)
+                                    .into());
+                            }
+                            Err(error) => return Err(error.into()),
                         }
 
                         inner = atlas.inner_for_content_mut(image.content_type);
@@ -519,34 +4269,25 @@ where
             };
             let atlas_min = allocation.rectangle.min;
 
-            queue.write_texture(
-                TexelCopyTextureInfo {
-                    texture: &inner.texture,
-                    mip_level: 0,
-                    origin: Origin3d {
-                        x: atlas_min.x as u32,
-                        y: atlas_min.y as u32,
-                        z: 0,
-                    },
-                    aspect: TextureAspect::All,
+            inner.stage_upload(
+                Origin3d {
+                    x: atlas_min.x as u32,
+                    y: atlas_min.y as u32,
+                    z: layer as u32,
                 },
+                image.width,
+                image.height,
+                image.content_type,
                 &image.data,
-                TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(image.width as u32 * inner.num_channels() as u32),
-                    rows_per_image: None,
-                },
-                Extent3d {
-                    width: image.width as u32,
-                    height: image.height as u32,
-                    depth_or_array_layers: 1,
-                },
             );
+            inner.used_bytes += inner.glyph_bytes(image.width, image.height);
+            inner.mipmaps_dirty = true;
 
             (
                 GpuCacheStatus::InAtlas {
                     x: atlas_min.x as u16,
                     y: atlas_min.y as u16,
+                    layer,
                     content_type: image.content_type,
                 },
                 Some(allocation.id),
@@ -566,79 +4307,812 @@ where
             atlas_id,
             top: image.top,
             left: image.left,
+            last_used_trim: inner.trims,
         })
     };
 
-    let full_w = details.width + M;
-    let full_h = details.height + M;
+    let (atlas_x, atlas_y, layer, content_type) = match details.gpu_cache {
+        GpuCacheStatus::InAtlas {
+            x,
+            y,
+            layer,
+            content_type,
+        } => (x, y, layer, content_type),
+        GpuCacheStatus::SkipRasterization => return Ok(None),
+    };
+    let (width, height, left, top) = (details.width, details.height, details.left, details.top);
+    let (padding, atlas_flags) = match atlas.prebaked_padding(content_type, &cache_key) {
+        Some(padding) => (padding, PREBAKED_FLAG),
+        None => {
+            let inner = atlas.inner_for_content(content_type);
+            let mut atlas_flags = if inner.mipmaps { MIPMAP_FLAG } else { 0 };
+            if atlas.merged && content_type == ContentType::Mask {
+                atlas_flags |= MERGED_FLAG;
+            }
+            (inner.padding, atlas_flags)
+        }
+    };
+
+    // The quad covers the padding around the glyph, so that effects like shadows can extend into
+    // it. Glyphs rendered from distance fields are scaled from the size they were rasterized at,
+    // and their quad is rounded down so that it doesn't sample beyond the padding.
+    let to_pixels = |texels: i32| (texels as f32 / texel_scale).round() as i32;
+    let full_w = width + 2 * padding;
+    let full_h = height + 2 * padding;
 
-    let mut x = x + details.left as i32 - M as i32;
-    let mut y = (line_y * scale_factor).round() as i32 + y - details.top as i32 - M as i32;
+    let x = x + to_pixels(left as i32 - padding as i32);
+    // The glyph origin lies on the baseline
+    let baseline = (line_y * scale_factor).round() + y as f32;
+    let y = (line_y * scale_factor).round() as i32 + y - to_pixels(top as i32 + padding as i32);
+    // Effects are sized in physical pixels but sample the atlas in texels
+    let scale_factor_texels = scale_factor * texel_scale;
 
-    let (mut atlas_x, mut atlas_y, content_type) = match details.gpu_cache {
-        GpuCacheStatus::InAtlas { x, y, content_type } => (x, y, content_type),
-        GpuCacheStatus::SkipRasterization => return Ok(None),
+    let depth = metadata_to_depth(metadata);
+
+    let glyph = GlyphToRender {
+        pos: [x, y],
+        dim: [
+            (full_w as f32 / texel_scale) as u16,
+            (full_h as f32 / texel_scale) as u16,
+        ],
+        uv: [atlas_x - padding, atlas_y - padding],
+        color: color.0,
+        content_type_with_srgb: [
+            shader_content_type(content_type),
+            TextColorConversion::for_color_mode(atlas.color_mode) as u16,
+        ],
+        depth,
+        shadow_intensity: shadow.map_or(0.0, |s| s.shadow_intensity),
+        // Shadows can't extend beyond the padding without sampling neighboring glyphs
+        shadow_radius: shadow.map_or(0.0, |s| (s.shadow_radius * texel_scale).min(padding as f32)),
+        flags: atlas_flags | (layer as u32) << LAYER_SHIFT,
+        opacity: 1.0,
+        transform: 0,
+        // Glyphs can't be thickened beyond the padding without sampling neighboring glyphs
+        embolden: (embolden * scale_factor_texels).clamp(-(padding as f32), padding as f32),
+        oblique: oblique.map_or([0.0; 2], |angle| [angle.tan(), baseline]),
+        texel_scale,
+        rotation: [1.0, 0.0, 0.0, 0.0],
     };
 
-    let mut width = full_w as i32;
-    let mut height = full_h as i32;
+    let drop_shadow = drop_shadow.map(|drop_shadow| GlyphToRender {
+        pos: [
+            x + (drop_shadow.offset[0] * scale_factor).round() as i32,
+            y + (drop_shadow.offset[1] * scale_factor).round() as i32,
+        ],
+        color: drop_shadow.color.0,
+        shadow_intensity: 0.0,
+        // The blur can't extend beyond the padding without sampling neighboring glyphs
+        shadow_radius: (drop_shadow.softness * scale_factor_texels).clamp(0.0, padding as f32),
+        flags: glyph.flags | DROP_SHADOW_FLAG,
+        ..glyph
+    });
+
+    let outline = outline.map(|outline| GlyphToRender {
+        color: outline.color.0,
+        shadow_intensity: 0.0,
+        // The outline can't extend beyond the padding without sampling neighboring glyphs
+        shadow_radius: (outline.width * scale_factor_texels).clamp(0.0, padding as f32),
+        flags: glyph.flags | OUTLINE_FLAG,
+        ..glyph
+    });
+
+    let glow = glow.map(|glow| GlyphToRender {
+        color: glow.color.0,
+        shadow_intensity: glow.intensity,
+        // The glow can't extend beyond the padding without sampling neighboring glyphs
+        shadow_radius: (glow.radius * scale_factor_texels).clamp(0.0, padding as f32),
+        flags: glyph.flags | GLOW_FLAG,
+        ..glyph
+    });
+
+    Ok(Some(PreparedGlyph {
+        glyph,
+        drop_shadow,
+        outline,
+        glow,
+    }))
+}
+
+/// A glyph instance along with the instances of its effects, before clipping.
+struct PreparedGlyph {
+    glyph: GlyphToRender,
+    drop_shadow: Option<GlyphToRender>,
+    outline: Option<GlyphToRender>,
+    glow: Option<GlyphToRender>,
+}
+
+impl PreparedGlyph {
+    /// Sets the opacity of the glyph and all of its effects.
+    fn with_opacity(self, opacity: f32) -> Self {
+        let set_opacity = |glyph: GlyphToRender| GlyphToRender { opacity, ..glyph };
+
+        Self {
+            glyph: set_opacity(self.glyph),
+            drop_shadow: self.drop_shadow.map(set_opacity),
+            outline: self.outline.map(set_opacity),
+            glow: self.glow.map(set_opacity),
+        }
+    }
+
+    /// Rotates the glyph and all of its effects.
+    fn with_rotation(self, rotation: [f32; 4]) -> Self {
+        let set_rotation = |glyph: GlyphToRender| GlyphToRender { rotation, ..glyph };
+
+        Self {
+            glyph: set_rotation(self.glyph),
+            drop_shadow: self.drop_shadow.map(set_rotation),
+            outline: self.outline.map(set_rotation),
+            glow: self.glow.map(set_rotation),
+        }
+    }
+}
+
+/// Returns the instance that renders the coverage of `glyph` into the offscreen target of a
+/// [`ShadowStyle`], before it is blurred.
+fn shadow_style_glyph(
+    glyph: GlyphToRender,
+    shadow_style: ShadowStyle,
+    scale: f32,
+) -> GlyphToRender {
+    GlyphToRender {
+        pos: [
+            glyph.pos[0] + (shadow_style.offset[0] * scale).round() as i32,
+            glyph.pos[1] + (shadow_style.offset[1] * scale).round() as i32,
+        ],
+        color: shadow_style.color.0,
+        shadow_intensity: 0.0,
+        shadow_radius: 0.0,
+        flags: glyph.flags | DROP_SHADOW_FLAG,
+        ..glyph
+    }
+}
+
+/// Clips an instance to `bounds`. Returns `None` if it lies entirely outside of `bounds`.
+fn clip_glyph(glyph: GlyphToRender, bounds: TextBounds) -> Option<GlyphToRender> {
+    let (pos, [width, height], _) = clip_quad(
+        glyph.pos,
+        [glyph.dim[0] as i32, glyph.dim[1] as i32],
+        glyph.uv,
+        bounds,
+    )?;
+
+    // The atlas position moves by the clipped distance in texels
+    let uv = [0, 1].map(|i| {
+        let shift = (pos[i] - glyph.pos[i]) as f32 * glyph.texel_scale;
+        glyph.uv[i] + shift.round() as u16
+    });
+
+    Some(GlyphToRender {
+        pos,
+        dim: [width as u16, height as u16],
+        uv,
+        ..glyph
+    })
+}
+
+/// Clips a quad at `pos` with size `dim` to `bounds`, shifting its atlas position `uv` along
+/// with the clipped edges. Returns `None` if the quad lies entirely outside of `bounds`.
+fn clip_quad(
+    pos: [i32; 2],
+    dim: [i32; 2],
+    uv: [u16; 2],
+    bounds: TextBounds,
+) -> Option<([i32; 2], [i32; 2], [u16; 2])> {
+    let [mut x, mut y] = pos;
+    let [mut width, mut height] = dim;
+    let [mut atlas_x, mut atlas_y] = uv;
 
     // Starts beyond right edge or ends beyond left edge
     let max_x = x + width;
-    if x > bounds_max_x || max_x < bounds_min_x {
-        return Ok(None);
+    if x > bounds.right || max_x < bounds.left {
+        return None;
     }
 
     // Starts beyond bottom edge or ends beyond top edge
     let max_y = y + height;
-    if y > bounds_max_y || max_y < bounds_min_y {
-        return Ok(None);
+    if y > bounds.bottom || max_y < bounds.top {
+        return None;
     }
 
     // Clip left ege
-    if x < bounds_min_x {
-        let right_shift = bounds_min_x - x;
+    if x < bounds.left {
+        let right_shift = bounds.left - x;
 
-        x = bounds_min_x;
-        width = max_x - bounds_min_x;
+        x = bounds.left;
+        width = max_x - bounds.left;
         atlas_x += right_shift as u16;
     }
 
     // Clip right edge
-    if x + width > bounds_max_x {
-        width = bounds_max_x - x;
+    if x + width > bounds.right {
+        width = bounds.right - x;
     }
 
     // Clip top edge
-    if y < bounds_min_y {
-        let bottom_shift = bounds_min_y - y;
+    if y < bounds.top {
+        let bottom_shift = bounds.top - y;
 
-        y = bounds_min_y;
-        height = max_y - bounds_min_y;
+        y = bounds.top;
+        height = max_y - bounds.top;
         atlas_y += bottom_shift as u16;
     }
 
     // Clip bottom edge
-    if y + height > bounds_max_y {
-        height = bounds_max_y - y;
+    if y + height > bounds.bottom {
+        height = bounds.bottom - y;
     }
 
-    let depth = metadata_to_depth(metadata);
+    Some(([x, y], [width, height], [atlas_x, atlas_y]))
+}
 
-    Ok(Some(GlyphToRender {
-        pos: [x, y],
-        dim: [width as u16, height as u16],
-        uv: [atlas_x, atlas_y],
+/// Returns the horizontal offset of `cursor` within `run`, or `None` if the cursor is on a
+/// different line.
+fn cursor_x(run: &LayoutRun, cursor: Cursor) -> Option<f32> {
+    if run.line_i != cursor.line {
+        return None;
+    }
+
+    for glyph in run.glyphs.iter() {
+        if cursor.index < glyph.start || cursor.index >= glyph.end {
+            continue;
+        }
+
+        // Place cursors inside of a multi-character cluster (such as a ligature) proportionally
+        let offset =
+            glyph.w * (cursor.index - glyph.start) as f32 / (glyph.end - glyph.start) as f32;
+
+        return Some(if glyph.level.is_rtl() {
+            glyph.x + glyph.w - offset
+        } else {
+            glyph.x + offset
+        });
+    }
+
+    // The cursor is after the last glyph of the run
+    Some(match run.glyphs.last() {
+        Some(glyph) if glyph.level.is_rtl() => glyph.x,
+        Some(glyph) => glyph.x + glyph.w,
+        None => 0.0,
+    })
+}
+
+/// Returns an untextured quad at `pos` with size `dim` filled with `color`, such as a background or
+/// a caret.
+fn solid_quad(
+    (pos, dim): ([i32; 2], [i32; 2]),
+    color: Color,
+    depth: f32,
+    flags: u32,
+    color_mode: ColorMode,
+) -> GlyphToRender {
+    GlyphToRender {
+        pos,
+        dim: dim.map(|size| size.clamp(0, u16::MAX as i32) as u16),
+        uv: [0, 0],
         color: color.0,
         content_type_with_srgb: [
-            content_type as u16,
-            match atlas.color_mode {
-                ColorMode::Accurate => TextColorConversion::ConvertToLinear,
-                ColorMode::Web => TextColorConversion::None,
-            } as u16,
+            SOLID_QUAD_CONTENT_TYPE,
+            TextColorConversion::for_color_mode(color_mode) as u16,
         ],
         depth,
-        shadow_intensity: shadow.map_or(0.0, |s| s.shadow_intensity),
-        shadow_radius: shadow.map_or(0.0, |s| s.shadow_radius),
-    }))
+        shadow_radius: 0.0,
+        shadow_intensity: 0.0,
+        flags,
+        opacity: 1.0,
+        transform: 0,
+        embolden: 0.0,
+        oblique: [0.0; 2],
+        texel_scale: 1.0,
+        rotation: [1.0, 0.0, 0.0, 0.0],
+    }
+}
+
+fn prepare_background(
+    text_area: &TextArea,
+    background: TextBackground,
+    bounds: TextBounds,
+    color_mode: ColorMode,
+    mut metadata_to_depth: impl FnMut(usize) -> f32,
+) -> Option<GlyphToRender> {
+    let ([x, y], [width, height]) = content_box(text_area)?;
+    let ([x, y], [width, height], _) = clip_quad([x, y], [width, height], [0, 0], bounds)?;
+
+    let depth = metadata_to_depth(background.metadata);
+    Some(GlyphToRender {
+        opacity: text_area.opacity,
+        ..solid_quad(
+            ([x, y], [width, height]),
+            background.color,
+            depth,
+            0,
+            color_mode,
+        )
+    })
+}
+
+/// Prepares the instances of the cell of a [`CellGrid`] at the given index.
+fn prepare_cell(
+    grid: &mut CellGrid,
+    index: usize,
+    decorations: Option<swash::Metrics>,
+    atlas: &mut TextAtlas,
+    device: &Device,
+    queue: &Queue,
+    cache: &mut SwashCache,
+    font_system: &mut FontSystem,
+) -> Result<(), PrepareError> {
+    let cell = grid.cells[index];
+    let count = grid.cells.len();
+    let metrics = grid.metrics;
+    let color_mode = atlas.color_mode;
+    let (column, row) = (index % grid.columns(), index / grid.columns());
+
+    let left = (grid.position[0] + column as f32 * metrics.cell_width).round() as i32;
+    let top = (grid.position[1] + row as f32 * metrics.cell_height).round() as i32;
+    let right = (grid.position[0] + (column + 1) as f32 * metrics.cell_width).round() as i32;
+    let bottom = (grid.position[1] + (row + 1) as f32 * metrics.cell_height).round() as i32;
+    let (fg, bg) = if cell.flags.inverse {
+        (cell.bg, cell.fg)
+    } else {
+        (cell.fg, cell.bg)
+    };
+
+    let empty = cell_quad([0, 0], [0, 0], Color(0), color_mode, 0.0);
+    grid.instances[index] = if bg.a() > 0 {
+        cell_quad(
+            [left, top],
+            [right - left, bottom - top],
+            bg,
+            color_mode,
+            0.0,
+        )
+    } else {
+        empty
+    };
+
+    grid.instances[count + index] = empty;
+    grid.glyph_keys[index] = None;
+    if cell.glyph_id != 0 {
+        let (cache_key, _, _) = CacheKey::new(
+            metrics.font,
+            cell.glyph_id,
+            metrics.font_size,
+            (0.0, 0.0),
+            CacheKeyFlags::empty(),
+        );
+        let glyphon_cache_key = GlyphonCacheKey::Text(cache_key);
+        let share_rasterized_glyphs = atlas.mask_atlas.share_rasterized_glyphs;
+        let subpixel = atlas.subpixel;
+
+        let params = GlyphParams {
+            line_y: metrics.baseline,
+            embolden: if cell.flags.bold {
+                metrics.faux_bold
+            } else {
+                0.0
+            },
+            oblique: cell.flags.italic.then_some(ITALIC_OBLIQUE),
+            ..GlyphParams::new(left, top, fg, glyphon_cache_key)
+        };
+        let context = GlyphContext {
+            device,
+            queue,
+            atlas,
+            cache,
+            font_system,
+        };
+        let prepared = prepare_glyph(
+            params,
+            context,
+            |cache, font_system, _rasterize_custom_glyph| {
+                text_glyph_image(
+                    cache,
+                    font_system,
+                    cache_key,
+                    GlyphRasterization::default(),
+                    share_rasterized_glyphs,
+                    subpixel,
+                    false,
+                    false,
+                )
+            },
+            zero_depth,
+            |_| None,
+        )?;
+        if let Some(prepared) = prepared {
+            grid.instances[count + index] = prepared.glyph;
+            grid.glyph_keys[index] = Some(glyphon_cache_key);
+        }
+    }
+
+    // Offsets are measured upwards from the baseline to the top of the stroke
+    let strokes = decorations.map_or([None; 2], |decorations| {
+        [
+            cell.flags
+                .underline
+                .then_some((decorations.underline_offset, decorations.stroke_size)),
+            cell.flags
+                .strikethrough
+                .then_some((decorations.strikeout_offset, decorations.stroke_size)),
+        ]
+    });
+    for (slot, stroke) in strokes.into_iter().enumerate() {
+        grid.instances[2 * count + 2 * index + slot] = match stroke {
+            Some((offset, stroke_size)) => {
+                let thickness = stroke_size.round().max(1.0) as i32;
+                let y = (top as f32 + metrics.baseline - offset).round() as i32;
+                cell_quad(
+                    [left, y],
+                    [right - left, thickness],
+                    fg,
+                    color_mode,
+                    thickness as f32,
+                )
+            }
+            None => empty,
+        };
+    }
+
+    Ok(())
+}
+
+/// Returns a solid quad of a [`CellGrid`], such as the background of a cell or a decoration
+/// stroke of the given thickness.
+fn cell_quad(
+    pos: [i32; 2],
+    dim: [i32; 2],
+    color: Color,
+    color_mode: ColorMode,
+    thickness: f32,
+) -> GlyphToRender {
+    GlyphToRender {
+        shadow_radius: thickness,
+        ..solid_quad((pos, dim), color, 0.0, 0, color_mode)
+    }
+}
+
+/// Returns the quads that outline `bounds` from the inside, one pixel wide.
+fn debug_bounds_outline(
+    bounds: TextBounds,
+    depth: f32,
+    color_mode: ColorMode,
+) -> impl Iterator<Item = GlyphToRender> {
+    let TextBounds {
+        left,
+        top,
+        right,
+        bottom,
+    } = bounds;
+    let edges = [
+        ([left, top], [right - left, 1]),
+        ([left, bottom - 1], [right - left, 1]),
+        ([left, top], [1, bottom - top]),
+        ([right - 1, top], [1, bottom - top]),
+    ];
+
+    edges
+        .into_iter()
+        .filter(move |_| !bounds.is_empty())
+        .map(move |rect| solid_quad(rect, Color::rgb(255, 0, 255), depth, 0, color_mode))
+}
+
+/// Returns `true` if `glyph` is requested to be bold by the attributes of its line, but its font
+/// face isn't.
+fn lacks_bold_face(
+    font_system: &FontSystem,
+    text_area: &TextArea,
+    run: &LayoutRun,
+    glyph: &LayoutGlyph,
+) -> bool {
+    let weight = text_area
+        .buffer
+        .lines
+        .get(run.line_i)
+        .map_or(Weight::NORMAL, |line| {
+            line.attrs_list().get_span(glyph.start).weight
+        });
+
+    weight >= Weight::SEMIBOLD
+        && font_system
+            .db()
+            .face(glyph.font_id)
+            .is_some_and(|face| face.weight < Weight::SEMIBOLD)
+}
+
+/// Returns the box of a shaped glyph on screen, spanning its advance and the height of its line,
+/// or `None` if it's clipped or has a world transform.
+fn glyph_box(
+    text_area: &TextArea,
+    run: &LayoutRun,
+    glyph: &LayoutGlyph,
+    shift: [f32; 2],
+    bounds: TextBounds,
+    transform: Option<AreaTransform>,
+) -> Option<TextBounds> {
+    let left = text_area.left + glyph.x * text_area.scale + shift[0];
+    let top = text_area.top + run.line_top * text_area.scale + shift[1];
+    let glyph_bounds = TextBounds {
+        left: left.round() as i32,
+        top: top.round() as i32,
+        right: (left + glyph.w * text_area.scale).round() as i32,
+        bottom: (top + run.line_height * text_area.scale).round() as i32,
+    }
+    .intersect(&bounds);
+    if glyph_bounds.is_empty() {
+        return None;
+    }
+
+    screen_bounds(glyph_bounds, text_area, transform)
+}
+
+/// Returns the bounding box on screen of `bounds` within a text area with the given transform,
+/// or `None` if the text area has a world transform.
+fn screen_bounds(
+    bounds: TextBounds,
+    text_area: &TextArea,
+    transform: Option<AreaTransform>,
+) -> Option<TextBounds> {
+    match transform {
+        None => Some(bounds),
+        Some(AreaTransform::Screen(transform)) => Some(transformed_bounds(
+            bounds,
+            transform,
+            [text_area.left, text_area.top],
+        )),
+        Some(AreaTransform::World(_)) => None,
+    }
+}
+
+/// Returns the bounding box of `bounds` after applying `transform` around `origin`.
+fn transformed_bounds(
+    bounds: TextBounds,
+    transform: TextTransform,
+    origin: [f32; 2],
+) -> TextBounds {
+    let corners = [
+        [bounds.left, bounds.top],
+        [bounds.right, bounds.top],
+        [bounds.left, bounds.bottom],
+        [bounds.right, bounds.bottom],
+    ]
+    .map(|[x, y]| {
+        let [x, y] = transform.transform_point([x as f32 - origin[0], y as f32 - origin[1]]);
+        [x + origin[0], y + origin[1]]
+    });
+
+    let (mut left, mut top) = (f32::INFINITY, f32::INFINITY);
+    let (mut right, mut bottom) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+    for [x, y] in corners {
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x);
+        bottom = bottom.max(y);
+    }
+
+    // Float to int casts saturate, so unbounded text areas stay unbounded
+    TextBounds {
+        left: left.floor() as i32,
+        top: top.floor() as i32,
+        right: right.ceil() as i32,
+        bottom: bottom.ceil() as i32,
+    }
+}
+
+/// Returns the physical position and size of the box spanning all laid-out lines of a text area,
+/// from the leftmost to the rightmost glyph. Returns `None` if the text area has no glyphs.
+fn content_box(text_area: &TextArea) -> Option<([i32; 2], [i32; 2])> {
+    let mut content: Option<(f32, f32, f32, f32)> = None;
+    for run in text_area.buffer.layout_runs() {
+        let (mut left, mut right) = (f32::MAX, f32::MIN);
+        for glyph in run.glyphs.iter() {
+            left = left.min(glyph.x);
+            right = right.max(glyph.x + glyph.w);
+        }
+        if left > right {
+            continue;
+        }
+
+        let top = run.line_top;
+        let bottom = run.line_top + run.line_height;
+        content = Some(match content {
+            Some((l, t, r, b)) => (l.min(left), t.min(top), r.max(right), b.max(bottom)),
+            None => (left, top, right, bottom),
+        });
+    }
+
+    let (left, top, right, bottom) = content?;
+    let x = (text_area.left + left * text_area.scale).round() as i32;
+    let y = (text_area.top + top * text_area.scale).round() as i32;
+    let width = (text_area.left + right * text_area.scale).round() as i32 - x;
+    let height = (text_area.top + bottom * text_area.scale).round() as i32 - y;
+
+    Some(([x, y], [width, height]))
+}
+
+/// Adds a solid quad for each run covered by `selection`.
+fn prepare_selection(
+    text_area: &TextArea,
+    selection: TextSelection,
+    bounds: TextBounds,
+    color_mode: ColorMode,
+    mut metadata_to_depth: impl FnMut(usize) -> f32,
+    vertices: &mut Vec<GlyphToRender>,
+) {
+    let (start, end) = if (selection.start.line, selection.start.index)
+        <= (selection.end.line, selection.end.index)
+    {
+        (selection.start, selection.end)
+    } else {
+        (selection.end, selection.start)
+    };
+
+    for run in text_area.buffer.layout_runs() {
+        if run.line_i < start.line {
+            continue;
+        }
+        if run.line_i > end.line {
+            break;
+        }
+
+        let Some((left, width)) = run.highlight(start, end) else {
+            continue;
+        };
+
+        let x = (text_area.left + left * text_area.scale).round() as i32;
+        let y = (text_area.top + run.line_top * text_area.scale).round() as i32;
+        let width = (text_area.left + (left + width) * text_area.scale).round() as i32 - x;
+        let height =
+            (text_area.top + (run.line_top + run.line_height) * text_area.scale).round() as i32 - y;
+
+        let Some(([x, y], [width, height], _)) = clip_quad([x, y], [width, height], [0, 0], bounds)
+        else {
+            continue;
+        };
+
+        let depth = metadata_to_depth(selection.metadata);
+        vertices.push(GlyphToRender {
+            opacity: text_area.opacity,
+            ..solid_quad(
+                ([x, y], [width, height]),
+                selection.color,
+                depth,
+                0,
+                color_mode,
+            )
+        });
+    }
+}
+
+/// Adds a solid quad for each underline and strikethrough of `run`.
+fn prepare_decorations(
+    text_area: &TextArea,
+    run: &LayoutRun,
+    decorations: &dyn Fn(usize) -> TextDecoration,
+    font_system: &mut FontSystem,
+    bounds: TextBounds,
+    color_mode: ColorMode,
+    mut metadata_to_depth: impl FnMut(usize) -> f32,
+    vertices: &mut Vec<GlyphToRender>,
+) {
+    let mut glyphs = run.glyphs.iter().peekable();
+    while let Some(first) = glyphs.next() {
+        let decoration = decorations(first.metadata);
+        // Lines without their own color follow the color of each span of glyphs
+        let same_line = |glyph: &&LayoutGlyph| {
+            decorations(glyph.metadata) == decoration
+                && (decoration.color.is_some() || glyph.color_opt == first.color_opt)
+        };
+        let mut right = first.x + first.w;
+        while let Some(glyph) = glyphs.next_if(same_line) {
+            right = right.max(glyph.x + glyph.w);
+        }
+
+        if !decoration.underline && !decoration.strikethrough {
+            continue;
+        }
+        let Some(font) = font_system.get_font(first.font_id) else {
+            continue;
+        };
+
+        // Offsets are measured upwards from the baseline to the top of the stroke
+        let metrics = font.as_swash().metrics(&[]).scale(first.font_size);
+        let strokes = [
+            (
+                decoration.underline,
+                metrics.underline_offset,
+                decoration.underline_style,
+            ),
+            (
+                decoration.strikethrough,
+                metrics.strikeout_offset,
+                UnderlineStyle::Solid,
+            ),
+        ];
+
+        let color = decoration
+            .color
+            .or(first.color_opt)
+            .unwrap_or(text_area.default_color);
+        let x = (text_area.left + first.x * text_area.scale).round() as i32;
+        let width = (text_area.left + right * text_area.scale).round() as i32 - x;
+        let thickness = (metrics.stroke_size * text_area.scale).round().max(1.0) as i32;
+
+        for (_, offset, style) in strokes.into_iter().filter(|(enabled, ..)| *enabled) {
+            let y = (text_area.top + (run.line_y - offset) * text_area.scale).round() as i32;
+
+            // Wavy lines oscillate by one thickness above and below the straight stroke
+            let (y, height) = match style {
+                UnderlineStyle::Wavy => (y - thickness, 3 * thickness),
+                _ => (y, thickness),
+            };
+
+            // Keep the position shifted by clipping, so that styles line up with the unclipped quad
+            let Some(([x, y], [width, height], uv)) =
+                clip_quad([x, y], [width, height], [0, 0], bounds)
+            else {
+                continue;
+            };
+
+            let depth = metadata_to_depth(first.metadata);
+            let flags = (style as u32) << DECORATION_STYLE_SHIFT;
+            vertices.push(GlyphToRender {
+                uv,
+                // The thickness of the stroke, which styles are drawn with
+                shadow_radius: thickness as f32,
+                opacity: text_area.opacity,
+                ..solid_quad(([x, y], [width, height]), color, depth, flags, color_mode)
+            });
+        }
+    }
+}
+
+fn prepare_caret(
+    text_area: &TextArea,
+    caret: TextCaret,
+    bounds: TextBounds,
+    color_mode: ColorMode,
+    mut metadata_to_depth: impl FnMut(usize) -> f32,
+) -> Option<GlyphToRender> {
+    if caret
+        .blink_phase
+        .is_some_and(|phase| phase.rem_euclid(1.0) >= 0.5)
+    {
+        return None;
+    }
+
+    // A line can wrap into several runs, so prefer the run that contains the cursor and fall
+    // back to the end of the last run of the line
+    let mut caret_run = None;
+    for run in text_area.buffer.layout_runs() {
+        if run.line_i != caret.cursor.line {
+            continue;
+        }
+
+        let contains_cursor = run
+            .glyphs
+            .iter()
+            .any(|glyph| glyph.start <= caret.cursor.index && caret.cursor.index < glyph.end);
+
+        caret_run = Some(run);
+        if contains_cursor {
+            break;
+        }
+    }
+
+    let run = caret_run?;
+    let x = cursor_x(&run, caret.cursor)?;
+
+    let x = (text_area.left + x * text_area.scale).round() as i32;
+    let y = (text_area.top + run.line_top * text_area.scale).round() as i32;
+    let width = (caret.width * text_area.scale).round().max(1.0) as i32;
+    let height = (run.line_height * text_area.scale).round() as i32;
+
+    let ([x, y], [width, height], _) = clip_quad([x, y], [width, height], [0, 0], bounds)?;
+
+    let depth = metadata_to_depth(caret.metadata);
+    Some(GlyphToRender {
+        opacity: text_area.opacity,
+        ..solid_quad(([x, y], [width, height]), caret.color, depth, 0, color_mode)
+    })
 }
@@ -1,6 +1,9 @@
-use crate::{Cache, Params, Resolution};
+use crate::{Cache, Params, Resolution, TextBounds};
 use std::{mem, slice};
-use wgpu::{BindGroup, Buffer, BufferDescriptor, BufferUsages, Device, Queue};
+use wgpu::{
+    BindGroup, Buffer, BufferDescriptor, BufferUsages, Device, Queue, RenderBundleEncoder,
+    RenderPass, ShaderStages,
+};
 
 /// Controls the visible area of all text for a given renderer. Any text outside of the visible
 /// area will be clipped.
@@ -11,8 +14,9 @@ use wgpu::{BindGroup, Buffer, BufferDescriptor, BufferUsages, Device, Queue};
 #[derive(Debug)]
 pub struct Viewport {
     params: Params,
-    params_buffer: Buffer,
-    pub(crate) bind_group: BindGroup,
+    /// The buffer holding `params` and its bind group, or `None` if the cache passes the
+    /// parameters through push constants.
+    uniforms: Option<(Buffer, BindGroup)>,
 }
 
 impl Viewport {
@@ -23,35 +27,34 @@ impl Viewport {
                 width: 0,
                 height: 0,
             },
+            edge_feather: 0.0,
+            gamma: 1.0,
+            contrast: 0.0,
+            zoom: 1.0,
+            offset: [0.0; 2],
         };
 
-        let params_buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("glyphon params"),
-            size: mem::size_of::<Params>() as u64,
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let uniforms = (!cache.push_constants()).then(|| {
+            let params_buffer = device.create_buffer(&BufferDescriptor {
+                label: Some("glyphon params"),
+                size: mem::size_of::<Params>() as u64,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
 
-        let bind_group = cache.create_uniforms_bind_group(device, &params_buffer);
+            let bind_group = cache.create_uniforms_bind_group(device, &params_buffer);
 
-        Self {
-            params,
-            params_buffer,
-            bind_group,
-        }
+            (params_buffer, bind_group)
+        });
+
+        Self { params, uniforms }
     }
 
     /// Updates the `Viewport` with the given `resolution`.
     pub fn update(&mut self, queue: &Queue, resolution: Resolution) {
         if self.params.screen_resolution != resolution {
             self.params.screen_resolution = resolution;
-
-            queue.write_buffer(&self.params_buffer, 0, unsafe {
-                slice::from_raw_parts(
-                    &self.params as *const Params as *const u8,
-                    mem::size_of::<Params>(),
-                )
-            });
+            self.write_params(queue);
         }
     }
 
@@ -59,4 +62,147 @@ impl Viewport {
     pub fn resolution(&self) -> Resolution {
         self.params.screen_resolution
     }
+
+    /// Sets the width in pixels of the feathered edge applied to glyphs, or `0.0` to disable it
+    /// (the default).
+    ///
+    /// Feathering smooths the hard or blurry edges of glyphs that are magnified by
+    /// [`TextArea::scale`](crate::TextArea::scale), which is useful for zoom animations.
+    pub fn set_edge_feather(&mut self, queue: &Queue, edge_feather: f32) {
+        let edge_feather = edge_feather.max(0.0);
+
+        if self.params.edge_feather != edge_feather {
+            self.params.edge_feather = edge_feather;
+            self.write_params(queue);
+        }
+    }
+
+    /// Returns the width in pixels of the feathered edge applied to glyphs.
+    pub fn edge_feather(&self) -> f32 {
+        self.params.edge_feather
+    }
+
+    /// Sets the gamma applied to the coverage of mask glyphs depending on the luminance of their
+    /// color, `1.0` (the default) to disable it.
+    ///
+    /// Blending coverage in the wrong color space makes light text on a dark background look
+    /// thinner than dark text on a light background. A gamma above `1.0`, such as `1.8`, thickens
+    /// light text to compensate.
+    pub fn set_gamma(&mut self, queue: &Queue, gamma: f32) {
+        let gamma = gamma.max(f32::EPSILON);
+
+        if self.params.gamma != gamma {
+            self.params.gamma = gamma;
+            self.write_params(queue);
+        }
+    }
+
+    /// Returns the gamma applied to the coverage of mask glyphs.
+    pub fn gamma(&self) -> f32 {
+        self.params.gamma
+    }
+
+    /// Sets how much the coverage of mask glyphs is boosted towards fully opaque, from `0.0` (the
+    /// default) to `1.0`.
+    ///
+    /// A small contrast such as `0.2` sharpens the antialiased edges of small text.
+    pub fn set_contrast(&mut self, queue: &Queue, contrast: f32) {
+        let contrast = contrast.clamp(0.0, 1.0);
+
+        if self.params.contrast != contrast {
+            self.params.contrast = contrast;
+            self.write_params(queue);
+        }
+    }
+
+    /// Returns how much the coverage of mask glyphs is boosted towards fully opaque.
+    pub fn contrast(&self) -> f32 {
+        self.params.contrast
+    }
+
+    /// Sets the camera the text is viewed through: `offset` is the position in physical pixels
+    /// that is drawn at the top left corner of the `Viewport`, and the text is scaled by `zoom`
+    /// around it. The default is no offset and a zoom of `1.0`.
+    ///
+    /// The camera is applied in the vertex shader, so panning or zooming doesn't require
+    /// preparing the text again. While a camera is set, text areas are only clipped to their own
+    /// bounds when they are prepared, rather than to the `Viewport`, so that text panned into
+    /// view is drawn. Text areas with a [`TextArea::world_transform`] aren't affected, and the
+    /// positions of [`TextRenderer::hit_test`] and [`TextRenderer::glyph_rects`] are before the
+    /// camera is applied.
+    ///
+    /// [`TextArea::world_transform`]: crate::TextArea::world_transform
+    /// [`TextRenderer::hit_test`]: crate::TextRenderer::hit_test
+    /// [`TextRenderer::glyph_rects`]: crate::TextRenderer::glyph_rects
+    pub fn set_camera(&mut self, queue: &Queue, offset: [f32; 2], zoom: f32) {
+        let zoom = zoom.max(f32::EPSILON);
+
+        if self.params.offset != offset || self.params.zoom != zoom {
+            self.params.offset = offset;
+            self.params.zoom = zoom;
+            self.write_params(queue);
+        }
+    }
+
+    /// Returns the offset and the zoom of the camera the text is viewed through.
+    pub fn camera(&self) -> ([f32; 2], f32) {
+        (self.params.offset, self.params.zoom)
+    }
+
+    /// Returns `true` if a camera other than the default one is set.
+    pub(crate) fn has_camera(&self) -> bool {
+        self.params.offset != [0.0; 2] || self.params.zoom != 1.0
+    }
+
+    /// Returns the bounds on screen of `bounds` in the coordinates of the text, which contain
+    /// every pixel they cover once the camera is applied.
+    pub(crate) fn camera_bounds(&self, bounds: TextBounds) -> TextBounds {
+        if !self.has_camera() {
+            return bounds;
+        }
+
+        let [x, y] = self.params.offset;
+        let zoom = self.params.zoom;
+        TextBounds {
+            left: ((bounds.left as f32 - x) * zoom).floor() as i32,
+            top: ((bounds.top as f32 - y) * zoom).floor() as i32,
+            right: ((bounds.right as f32 - x) * zoom).ceil() as i32,
+            bottom: ((bounds.bottom as f32 - y) * zoom).ceil() as i32,
+        }
+    }
+
+    /// Sets the parameters of the `Viewport` on `pass`, either as push constants or by binding
+    /// their uniform buffer.
+    pub(crate) fn bind(&self, pass: &mut RenderPass<'_>) {
+        match &self.uniforms {
+            Some((_, bind_group)) => pass.set_bind_group(1, bind_group, &[]),
+            None => pass.set_push_constants(ShaderStages::VERTEX_FRAGMENT, 0, self.params_bytes()),
+        }
+    }
+
+    /// Records the parameters of the `Viewport` into a render bundle, like `bind`.
+    pub(crate) fn bind_bundle<'a>(&'a self, encoder: &mut RenderBundleEncoder<'a>) {
+        match &self.uniforms {
+            Some((_, bind_group)) => encoder.set_bind_group(1, bind_group, &[]),
+            None => {
+                encoder.set_push_constants(ShaderStages::VERTEX_FRAGMENT, 0, self.params_bytes())
+            }
+        }
+    }
+
+    fn params_bytes(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                &self.params as *const Params as *const u8,
+                mem::size_of::<Params>(),
+            )
+        }
+    }
+
+    fn write_params(&self, queue: &Queue) {
+        // Push constants are set from `params` when the text is rendered
+        if let Some((params_buffer, _)) = &self.uniforms {
+            queue.write_buffer(params_buffer, 0, self.params_bytes());
+        }
+    }
 }
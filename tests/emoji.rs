@@ -0,0 +1,70 @@
+use cosmic_text::{fontdb, Attrs, Buffer, Family, FontSystem, Metrics, Shaping, SwashCache};
+use glyphon::{Cache, ColorMode, Resolution, TextArea, TextAtlas, TextRenderer, Viewport};
+use pollster::block_on;
+use wgpu::{MultisampleState, TextureFormat};
+
+/// A font that draws U+1F468 MAN, U+1F469 WOMAN and their ZWJ sequence, which it substitutes
+/// with a ligature, from COLR layers.
+static EMOJI_FONT: &[u8] = include_bytes!("fonts/GlyphonEmojiTest.ttf");
+
+/// A ZWJ sequence is cached as a single color glyph and drawn by a single instance, rather than
+/// one per code point.
+#[test]
+#[ignore = "requires a GPU, run with a backend like `--features wgpu/vulkan -- --ignored`"]
+fn zwj_sequence_prepares_one_color_glyph() {
+    let instance = wgpu::Instance::default();
+    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+        .expect("no adapter is available");
+    let (device, queue) = block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))
+        .expect("failed to create device");
+
+    let mut font_system =
+        FontSystem::new_with_locale_and_db("en-US".into(), fontdb::Database::new());
+    font_system.db_mut().load_font_data(EMOJI_FONT.to_vec());
+    let mut swash_cache = SwashCache::new();
+    let cache = Cache::new(&device);
+    let mut viewport = Viewport::new(&device, &cache);
+    viewport.update(
+        &queue,
+        Resolution {
+            width: 1000,
+            height: 1000,
+        },
+    );
+    let mut atlas = TextAtlas::with_color_mode(
+        &device,
+        &queue,
+        &cache,
+        TextureFormat::Bgra8Unorm,
+        ColorMode::Accurate,
+    );
+    let mut text_renderer =
+        TextRenderer::new(&mut atlas, &device, MultisampleState::default(), None);
+
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(30.0, 42.0));
+    buffer.set_size(&mut font_system, None, None);
+    buffer.set_text(
+        &mut font_system,
+        "\u{1F468}\u{200D}\u{1F469}",
+        &Attrs::new().family(Family::Name("Glyphon Emoji Test")),
+        Shaping::Advanced,
+    );
+    buffer.shape_until_scroll(&mut font_system, false);
+
+    text_renderer
+        .prepare(
+            &device,
+            &queue,
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea::new(&buffer)],
+            &mut swash_cache,
+        )
+        .unwrap();
+
+    let stats = atlas.stats();
+    assert_eq!(stats.color.glyphs, 1);
+    assert_eq!(stats.mask.glyphs, 0);
+    assert_eq!(text_renderer.prepare_stats().instances, 1);
+}
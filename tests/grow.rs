@@ -0,0 +1,103 @@
+use cosmic_text::{fontdb, Attrs, Buffer, Family, FontSystem, Metrics, Shaping, SwashCache};
+use glyphon::{
+    AtlasConfig, Cache, ColorMode, Resolution, TextArea, TextAtlas, TextRenderer, Viewport,
+};
+use pollster::block_on;
+use wgpu::{MultisampleState, TextureFormat};
+
+static INTER_BOLD: &[u8] = include_bytes!("../examples/Inter-Bold.ttf");
+
+fn font_system_with_inter(locale: String, db: fontdb::Database) -> FontSystem {
+    let mut font_system = FontSystem::new_with_locale_and_db(locale, db);
+    font_system.db_mut().load_font_data(INTER_BOLD.to_vec());
+    font_system
+}
+
+fn shape(font_system: &mut FontSystem, text: &str, font_size: f32) -> Buffer {
+    let mut buffer = Buffer::new(font_system, Metrics::new(font_size, font_size * 1.2));
+    buffer.set_size(font_system, None, None);
+    buffer.set_text(
+        font_system,
+        text,
+        &Attrs::new().family(Family::Name("Inter")),
+        Shaping::Advanced,
+    );
+    buffer.shape_until_scroll(font_system, false);
+    buffer
+}
+
+/// Growing the atlas after the font of some cached glyphs was removed from the `FontSystem`
+/// drops those glyphs instead of panicking while rasterizing them again.
+#[test]
+#[ignore = "requires a GPU, run with a backend like `--features wgpu/vulkan -- --ignored`"]
+fn grow_after_removing_font() {
+    let instance = wgpu::Instance::default();
+    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+        .expect("no adapter is available");
+    let (device, queue) = block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))
+        .expect("failed to create device");
+
+    let mut font_system = font_system_with_inter("en-US".into(), fontdb::Database::new());
+    let mut swash_cache = SwashCache::new();
+    let cache = Cache::new(&device);
+    let mut viewport = Viewport::new(&device, &cache);
+    viewport.update(
+        &queue,
+        Resolution {
+            width: 1000,
+            height: 1000,
+        },
+    );
+    let mut atlas = TextAtlas::with_config(
+        &device,
+        &queue,
+        &cache,
+        TextureFormat::Bgra8Unorm,
+        ColorMode::Accurate,
+        AtlasConfig {
+            initial_size: 64,
+            ..AtlasConfig::default()
+        },
+    );
+    let mut text_renderer =
+        TextRenderer::new(&mut atlas, &device, MultisampleState::default(), None);
+
+    // Cache a few glyphs of the font
+    let buffer = shape(&mut font_system, "glyphon", 16.0);
+    text_renderer
+        .prepare(
+            &device,
+            &queue,
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea::new(&buffer)],
+            &mut swash_cache,
+        )
+        .unwrap();
+    let initial_width = atlas.stats().mask.width;
+
+    // Remove the font along with the fonts the `FontSystem` loaded from it, and load it again
+    // under a new ID
+    let (locale, mut db) = font_system.into_locale_and_db();
+    let face_id = db.faces().next().unwrap().id;
+    db.remove_face(face_id);
+    let mut font_system = font_system_with_inter(locale, db);
+    assert!(font_system.db().face(face_id).is_none());
+
+    // Cache larger glyphs than fit into the atlas, which grows it
+    let buffer = shape(&mut font_system, "ABCDEFGHIJKLMNOPQRSTUVWXYZ", 48.0);
+    text_renderer
+        .prepare(
+            &device,
+            &queue,
+            &mut font_system,
+            &mut atlas,
+            &viewport,
+            [TextArea::new(&buffer)],
+            &mut swash_cache,
+        )
+        .unwrap();
+
+    assert!(atlas.stats().mask.width > initial_width);
+}